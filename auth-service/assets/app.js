@@ -32,6 +32,53 @@ signupLoginLink.addEventListener("click", (e) => {
 
 // -----------------------------------------------------
 
+// Login, signup and password resets need a solved challenge: a counter whose SHA-256 after the challenge starts with
+// `difficulty` zero bits. The server raises the difficulty for clients it keeps rate limiting.
+async function solveChallenge() {
+    const response = await fetch('/auth/challenge');
+    const { challenge, difficulty } = await response.json();
+    const encoder = new TextEncoder();
+
+    for (let counter = 0; ; counter++) {
+        const solution = counter.toString();
+        const hash = new Uint8Array(await crypto.subtle.digest('SHA-256', encoder.encode(challenge + solution)));
+        if (leadingZeroBits(hash) >= difficulty) {
+            return { 'X-PoW-Challenge': challenge, 'X-PoW-Solution': solution };
+        }
+    }
+}
+
+function leadingZeroBits(hash) {
+    let bits = 0;
+    for (const byte of hash) {
+        if (byte !== 0) {
+            return bits + Math.clz32(byte) - 24;
+        }
+        bits += 8;
+    }
+    return bits;
+}
+
+// What to change about a refused password, in the words of the user
+const passwordWeaknesses = {
+    too_short: "use more characters",
+    missing_uppercase: "add an uppercase letter",
+    missing_lowercase: "add a lowercase letter",
+    too_predictable: "avoid repeated or sequential characters and mix in other kinds",
+    common: "it is one of the most common passwords",
+    breached: "it has appeared in a data breach",
+    contains_email: "do not include your email",
+};
+
+function errorMessage(data) {
+    if (data.reasons === undefined || data.reasons.length === 0) {
+        return data.error;
+    }
+    return `${data.error}: ${data.reasons.map(reason => passwordWeaknesses[reason] || reason).join(", ")}`;
+}
+
+// -----------------------------------------------------
+
 const loginForm = document.getElementById("login-form");
 const loginButton = document.getElementById("login-form-submit");
 const loginErrAlter = document.getElementById("login-err-alert");
@@ -42,13 +89,14 @@ loginButton.addEventListener("click", (e) => {
     const email = loginForm.email.value;
     const password = loginForm.password.value;
 
-    fetch('/auth/login', {
+    solveChallenge().then(proofOfWork => fetch('/auth/login', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
+            ...proofOfWork,
         },
         body: JSON.stringify({ email, password }),
-    }).then(response => {
+    })).then(response => {
         if (response.status === 206) {
             TwoFAForm.email.value = email;
             response.json().then(data => {
@@ -67,6 +115,19 @@ loginButton.addEventListener("click", (e) => {
             loginForm.password.value = "";
             loginErrAlter.style.display = "none";
             alert("You have successfully logged in.");
+        } else if (response.status === 403) {
+            // Also sent for a disabled, suspended or deleted account, with an error instead
+            response.json().then(data => {
+                if (data.error) {
+                    loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                    loginErrAlter.style.display = "block";
+                    return;
+                }
+                loginForm.email.value = "";
+                loginForm.password.value = "";
+                loginErrAlter.style.display = "none";
+                showPasswordExpired();
+            });
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -92,13 +153,14 @@ signupButton.addEventListener("click", (e) => {
     const password = signupForm.password.value;
     const requires2FA = signupForm.twoFA.checked;
 
-    fetch('/auth/signup', {
+    solveChallenge().then(proofOfWork => fetch('/auth/signup', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
+            ...proofOfWork,
         },
         body: JSON.stringify({ email, password, requires2FA }),
-    }).then(response => {
+    })).then(response => {
         if (response.ok) {
             signupForm.email.value = "";
             signupForm.password.value = "";
@@ -110,7 +172,7 @@ signupButton.addEventListener("click", (e) => {
             signupSection.style.display = "none";
         } else {
             response.json().then(data => {
-                let error_msg = data.error;
+                let error_msg = errorMessage(data);
                 if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
                     signupErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
                     signupErrAlter.style.display = "block";
@@ -132,17 +194,33 @@ TwoFAButton.addEventListener("click", (e) => {
     const email = TwoFAForm.email.value;
     const loginAttemptId = TwoFAForm.login_attempt_id.value;
     const TwoFACode = TwoFAForm.email_code.value;
+    const rememberDevice = TwoFAForm.remember_device.checked;
 
     fetch('/auth/verify-2fa', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
         },
-        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode }),
+        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode, rememberDevice }),
     }).then(response => {
-        if (response.ok) {
+        if (response.status === 403) {
+            response.json().then(data => {
+                if (data.error) {
+                    TwoFAErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                    TwoFAErrAlter.style.display = "block";
+                    return;
+                }
+                TwoFAForm.email.value = "";
+                TwoFAForm.email_code.value = "";
+                TwoFAForm.remember_device.checked = false;
+                TwoFAForm.login_attempt_id.value = "";
+                TwoFAErrAlter.style.display = "none";
+                showPasswordExpired();
+            });
+        } else if (response.ok) {
             TwoFAForm.email.value = "";
             TwoFAForm.email_code.value = "";
+            TwoFAForm.remember_device.checked = false;
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
             alert("You have successfully logged in.");
@@ -161,4 +239,274 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+// -----------------------------------------------------
+
+const magicLinkButton = document.getElementById("magic-link-submit");
+const loginInfoAlert = document.getElementById("login-info-alert");
+
+magicLinkButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = loginForm.email.value;
+
+    fetch('/auth/magic-link', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        response.json().then(data => {
+            if (response.ok) {
+                loginErrAlter.style.display = "none";
+                loginInfoAlert.textContent = data.message;
+                loginInfoAlert.style.display = "block";
+            } else {
+                loginInfoAlert.style.display = "none";
+                loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                loginErrAlter.style.display = "block";
+            }
+        });
+    });
+});
+
+// Opened from an emailed login link, the link only works in the browser that asked for it
+const magicToken = new URLSearchParams(window.location.search).get("magic_token");
+
+if (magicToken !== null) {
+    // Keep the token out of the history once it has been used
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    fetch('/auth/verify-magic-link', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token: magicToken }),
+    }).then(response => {
+        if (response.status === 206) {
+            // The 2FA form needs the email, which is the subject of the token
+            const claims = JSON.parse(atob(magicToken.split(".")[1].replace(/-/g, "+").replace(/_/g, "/")));
+            TwoFAForm.email.value = claims.sub;
+            response.json().then(data => {
+                TwoFAForm.login_attempt_id.value = data.loginAttemptId;
+            });
+
+            loginSection.style.display = "none";
+            twoFASection.style.display = "block";
+            signupSection.style.display = "none";
+        } else if (response.status === 200) {
+            alert("You have successfully logged in.");
+        } else {
+            response.json().then(data => {
+                // Same as a password login, a 403 without an error means the password expired
+                if (response.status === 403 && !data.error) {
+                    showPasswordExpired();
+                    return;
+                }
+                loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                loginErrAlter.style.display = "block";
+            });
+        }
+    });
+}
+
+// Back from an identity provider for a user with 2FA, the callback passes the login attempt in the fragment
+const oidcLogin = new URLSearchParams(window.location.hash.substring(1));
+
+if (oidcLogin.has("loginAttemptId")) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    TwoFAForm.email.value = oidcLogin.get("email");
+    TwoFAForm.login_attempt_id.value = oidcLogin.get("loginAttemptId");
+
+    loginSection.style.display = "none";
+    twoFASection.style.display = "block";
+    signupSection.style.display = "none";
+}
+
+const changeEmailToken = new URLSearchParams(window.location.search).get("change_email_token");
+
+if (changeEmailToken !== null) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    fetch('/auth/confirm-email-change', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token: changeEmailToken }),
+    }).then(response => {
+        if (response.ok) {
+            loginInfoAlert.textContent = "Your email was changed, log in with the new one.";
+            loginInfoAlert.style.display = "block";
+        } else {
+            response.json().then(data => {
+                loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                loginErrAlter.style.display = "block";
+            });
+        }
+    });
+}
+
+// Opened from the "this wasn't me" link of a new device alert
+const secureAccountToken = new URLSearchParams(window.location.search).get("secure_account_token");
+const secureAccountSection = document.getElementById("secure-account-section");
+const secureAccountForm = document.getElementById("secure-account-form");
+const secureAccountButton = document.getElementById("secure-account-form-submit");
+const secureAccountErrAlert = document.getElementById("secure-account-err-alert");
+
+if (secureAccountToken !== null) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    loginSection.style.display = "none";
+    twoFASection.style.display = "none";
+    signupSection.style.display = "none";
+    secureAccountSection.style.display = "block";
+
+    secureAccountButton.addEventListener("click", (e) => {
+        e.preventDefault();
+
+        fetch('/auth/secure-account', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify({ token: secureAccountToken, newPassword: secureAccountForm.new_password.value }),
+        }).then(response => {
+            if (response.ok) {
+                secureAccountForm.new_password.value = "";
+                secureAccountSection.style.display = "none";
+                loginSection.style.display = "block";
+                loginInfoAlert.textContent = "Every session was logged out, log in with your new password.";
+                loginInfoAlert.style.display = "block";
+            } else {
+                response.json().then(data => {
+                    secureAccountErrAlert.innerHTML = `<span><strong>Error: </strong>${errorMessage(data)}</span>`;
+                    secureAccountErrAlert.style.display = "block";
+                });
+            }
+        });
+    });
+}
+
+const passwordResetButton = document.getElementById("password-reset-submit");
+
+// Asking for a reset link needs a solved challenge, like login and signup
+passwordResetButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = loginForm.email.value;
+
+    solveChallenge().then(proofOfWork => fetch('/auth/password-reset', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+            ...proofOfWork,
+        },
+        body: JSON.stringify({ email }),
+    })).then(response => {
+        response.json().then(data => {
+            if (response.ok) {
+                loginErrAlter.style.display = "none";
+                loginInfoAlert.textContent = data.message;
+                loginInfoAlert.style.display = "block";
+            } else {
+                loginInfoAlert.style.display = "none";
+                loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                loginErrAlter.style.display = "block";
+            }
+        });
+    });
+});
+
+// Opened from an emailed password reset link
+const passwordResetToken = new URLSearchParams(window.location.search).get("password_reset_token");
+const passwordResetSection = document.getElementById("password-reset-section");
+const passwordResetForm = document.getElementById("password-reset-form");
+const passwordResetFormButton = document.getElementById("password-reset-form-submit");
+const passwordResetErrAlert = document.getElementById("password-reset-err-alert");
+
+if (passwordResetToken !== null) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    loginSection.style.display = "none";
+    twoFASection.style.display = "none";
+    signupSection.style.display = "none";
+    passwordResetSection.style.display = "block";
+
+    passwordResetFormButton.addEventListener("click", (e) => {
+        e.preventDefault();
+
+        fetch('/auth/confirm-password-reset', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify({ token: passwordResetToken, newPassword: passwordResetForm.new_password.value }),
+        }).then(response => {
+            if (response.ok) {
+                passwordResetForm.new_password.value = "";
+                passwordResetSection.style.display = "none";
+                loginSection.style.display = "block";
+                loginInfoAlert.textContent = "Your password was changed, log in with the new one.";
+                loginInfoAlert.style.display = "block";
+            } else {
+                response.json().then(data => {
+                    passwordResetErrAlert.innerHTML = `<span><strong>Error: </strong>${errorMessage(data)}</span>`;
+                    passwordResetErrAlert.style.display = "block";
+                });
+            }
+        });
+    });
+}
+
+// A login with an expired password only gets to change it
+const passwordExpiredSection = document.getElementById("password-expired-section");
+const passwordExpiredForm = document.getElementById("password-expired-form");
+const passwordExpiredButton = document.getElementById("password-expired-form-submit");
+const passwordExpiredErrAlert = document.getElementById("password-expired-err-alert");
+
+function showPasswordExpired() {
+    loginSection.style.display = "none";
+    twoFASection.style.display = "none";
+    signupSection.style.display = "none";
+    passwordExpiredSection.style.display = "block";
+}
+
+passwordExpiredButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    fetch('/auth/change-password', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({
+            currentPassword: passwordExpiredForm.current_password.value,
+            newPassword: passwordExpiredForm.new_password.value,
+        }),
+    }).then(response => {
+        if (response.ok) {
+            passwordExpiredForm.current_password.value = "";
+            passwordExpiredForm.new_password.value = "";
+            passwordExpiredErrAlert.style.display = "none";
+            passwordExpiredSection.style.display = "none";
+            loginSection.style.display = "block";
+            alert("Your password was changed and you are logged in.");
+        } else {
+            response.json().then(data => {
+                passwordExpiredErrAlert.innerHTML = `<span><strong>Error: </strong>${errorMessage(data)}</span>`;
+                passwordExpiredErrAlert.style.display = "block";
+            });
+        }
+    });
+});
+
+// Back from an identity provider with an expired password, the cookie set only opens /change-password
+if (oidcLogin.has("passwordChangeRequired")) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    showPasswordExpired();
+}
@@ -0,0 +1,197 @@
+// Concurrent signup, login and 2FA throughput against the real stores.
+// Needs the Postgres and Redis from config/test.toml, run with:
+//
+//     cargo bench --bench store_throughput
+//
+// BENCH_USERS and BENCH_CONCURRENCY change the workload.
+
+use std::{collections::HashMap, env, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use auth_service::{
+    app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType, UserStoreType},
+    domain::data_stores::LoginAttemptId,
+    get_postgres_pool, get_redis_pool,
+    services::data_stores::{
+        mock_email_client::MockEmailClient, mock_sms_client::MockSmsClient,
+        postgres_login_fingerprint_store::PostgresLoginFingerprintStore,
+        postgres_trusted_device_store::PostgresTrustedDeviceStore, postgres_user_store::PostgresUserStore,
+        PasswordHashingExecutor, RedisBannedTokenStore,
+        RedisIdempotencyStore, RedisTwoFACodeStore,
+    },
+    routes::TwoFactorAuthResponse,
+    utils::{password_policy::PasswordPolicy, AuthSettings},
+    Application,
+};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Connection, Executor, PgConnection};
+use tokio::{sync::{oneshot, RwLock}, task::JoinSet};
+use uuid::Uuid;
+
+const PASSWORD: &str = "Password123";
+
+#[tokio::main]
+async fn main() {
+    env::set_var("RUN_ENV", "test");
+    let auth_settings = AuthSettings::new();
+
+    let users: usize = env::var("BENCH_USERS").ok().and_then(|v| v.parse().ok()).unwrap_or(256);
+    let concurrency: usize = env::var("BENCH_CONCURRENCY").ok().and_then(|v| v.parse().ok()).unwrap_or(32);
+
+    let db_name = format!("bench_{}", Uuid::new_v4().simple());
+    let database_url = auth_settings.database.url.expose_secret().clone();
+    create_database(&database_url, &db_name).await;
+
+    let pg_pool = get_postgres_pool(&Secret::new(format!("{}/{}", database_url, db_name)))
+        .await
+        .expect("Failed to create Postgres connection pool");
+    sqlx::migrate!().run(&pg_pool).await.expect("Failed to migrate the database");
+
+    let redis_pool = get_redis_pool(&auth_settings.redis).await.expect("Failed to create Redis connection pool");
+
+    let password_hashing = PasswordHashingExecutor::new(&auth_settings.password_hashing)
+        .expect("Invalid password hashing settings");
+    let trusted_device_store = Arc::new(PostgresTrustedDeviceStore::new(pg_pool.clone()));
+    let login_fingerprint_store = Arc::new(PostgresLoginFingerprintStore::new(pg_pool.clone()));
+    let user_store: UserStoreType = Arc::new(PostgresUserStore::new(pg_pool, password_hashing)
+        .with_enumeration_protection(auth_settings.enumeration_protection.enabled));
+    let banned_token_store: BannedTokenStoreType =
+        Arc::new(RedisBannedTokenStore::new(redis_pool.clone(), auth_settings.redis.ttl_millis));
+    let two_fa_code_store: TwoFACodeStoreType = Arc::new(RedisTwoFACodeStore::new(redis_pool.clone(), auth_settings.two_fa.max_attempts_per_user));
+    let idempotency_store = Arc::new(RedisIdempotencyStore::new(redis_pool, auth_settings.idempotency.ttl_millis));
+
+    let app_state = AppState::new(
+        user_store,
+        banned_token_store,
+        Arc::clone(&two_fa_code_store),
+        idempotency_store,
+        trusted_device_store,
+        login_fingerprint_store,
+        Arc::new(RwLock::new(MockEmailClient)),
+        Arc::new(RwLock::new(MockSmsClient)),
+        Arc::new(PasswordPolicy::new(&auth_settings.password_policy).expect("Invalid password policy settings")),
+        auth_settings.clone(),
+    );
+
+    let app = Application::build(app_state, &auth_settings.http.address, &auth_settings.grpc.address)
+        .await
+        .expect("Failed to build app");
+    let address = format!("http://{}/auth", app.address);
+
+    let (tx_ready, rx_ready) = oneshot::channel();
+    tokio::spawn(app.run(Some(tx_ready)));
+    rx_ready.await.expect("App did not start");
+
+    let client = reqwest::Client::new();
+    let emails: Arc<Vec<String>> = Arc::new((0..users).map(|_| format!("{}@example.com", Uuid::new_v4())).collect());
+
+    println!("{} users, {} concurrent clients", users, concurrency);
+
+    report("signup", users, run(concurrency, &emails, |email| {
+        let (client, address) = (client.clone(), address.clone());
+        async move {
+            let body = serde_json::json!({ "email": email, "password": PASSWORD, "requires2FA": true });
+            client.post(format!("{}/signup", address)).json(&body).send().await.map(|r| r.status().as_u16() == 201)
+        }
+    }).await);
+
+    // Codes are looked up by the login attempt each login answered with
+    let login_attempts: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+
+    report("login", users, run(concurrency, &emails, |email| {
+        let (client, address, login_attempts) = (client.clone(), address.clone(), Arc::clone(&login_attempts));
+        async move {
+            let body = serde_json::json!({ "email": email, "password": PASSWORD });
+            let response = client.post(format!("{}/login", address)).json(&body).send().await?;
+            if response.status().as_u16() != 206 {
+                return Ok(false);
+            }
+            let login_attempt_id = response.json::<TwoFactorAuthResponse>().await?.login_attempt_id;
+            login_attempts.lock().expect("Lock poisoned").insert(email, login_attempt_id);
+            Ok(true)
+        }
+    }).await);
+
+    report("verify-2fa", users, run(concurrency, &emails, |email| {
+        let (client, address, two_fa_code_store) = (client.clone(), address.clone(), Arc::clone(&two_fa_code_store));
+        let login_attempt_id = login_attempts.lock().expect("Lock poisoned").get(&email).cloned();
+        async move {
+            // Its login failed, which was already counted
+            let Some(login_attempt_id) = login_attempt_id else {
+                return Ok(false);
+            };
+            let login_attempt_id = LoginAttemptId::parse(Secret::new(login_attempt_id)).expect("Invalid login attempt id");
+            let (_, code) = two_fa_code_store.get_code(&login_attempt_id).await.expect("No 2FA code stored");
+            let body = serde_json::json!({
+                "email": email,
+                "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+                "2FACode": code.as_ref().expose_secret(),
+            });
+            client.post(format!("{}/verify-2fa", address)).json(&body).send().await.map(|r| r.status().as_u16() == 200)
+        }
+    }).await);
+
+    drop_database(&database_url, &db_name).await;
+}
+
+// Splits the emails between `concurrency` clients that each send their requests one after the other
+async fn run<F, Fut>(concurrency: usize, emails: &Arc<Vec<String>>, request: F) -> (Duration, usize)
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = reqwest::Result<bool>> + Send + 'static,
+{
+    let start = Instant::now();
+    let mut clients = JoinSet::new();
+
+    for client_index in 0..concurrency {
+        let requests: Vec<Fut> = emails
+            .iter()
+            .skip(client_index)
+            .step_by(concurrency)
+            .map(|email| request(email.clone()))
+            .collect();
+
+        clients.spawn(async move {
+            let mut failures = 0;
+            for request in requests {
+                if !matches!(request.await, Ok(true)) {
+                    failures += 1;
+                }
+            }
+            failures
+        });
+    }
+
+    let mut failures = 0;
+    while let Some(result) = clients.join_next().await {
+        failures += result.expect("Bench client panicked");
+    }
+
+    (start.elapsed(), failures)
+}
+
+fn report(name: &str, requests: usize, (elapsed, failures): (Duration, usize)) {
+    println!(
+        "{:<12} {:>8.1} req/s  ({} requests in {:.2?}, {} failed)",
+        name,
+        requests as f64 / elapsed.as_secs_f64(),
+        requests,
+        elapsed,
+        failures
+    );
+}
+
+async fn create_database(database_url: &str, db_name: &str) {
+    let mut connection = PgConnection::connect(database_url).await.expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, db_name).as_str())
+        .await
+        .expect("Failed to create database");
+}
+
+async fn drop_database(database_url: &str, db_name: &str) {
+    let mut connection = PgConnection::connect(database_url).await.expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE);"#, db_name).as_str())
+        .await
+        .expect("Failed to drop database");
+}
@@ -0,0 +1,4 @@
+ALTER TABLE users
+   DROP COLUMN IF EXISTS two_fa_method,
+   DROP COLUMN IF EXISTS phone_number_verified,
+   DROP COLUMN IF EXISTS phone_number;
@@ -0,0 +1,14 @@
+-- Existing passwords count as changed when the migration runs
+ALTER TABLE users
+   ADD COLUMN IF NOT EXISTS password_changed_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())::BIGINT,
+   ADD COLUMN IF NOT EXISTS password_max_age_seconds BIGINT;
+
+-- Ids grow with every change, several changes can share a second
+CREATE TABLE IF NOT EXISTS password_history(
+   id BIGSERIAL PRIMARY KEY,
+   user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   password_hash TEXT NOT NULL,
+   changed_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS password_history_user_id_idx ON password_history (user_id, id);
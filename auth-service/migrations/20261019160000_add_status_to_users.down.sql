@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS users_pending_deletion_idx;
+ALTER TABLE users
+   DROP COLUMN IF EXISTS status_until,
+   DROP COLUMN IF EXISTS status;
@@ -0,0 +1,6 @@
+-- status_until is when a suspension ends or a pending deletion is due
+ALTER TABLE users
+   ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'active',
+   ADD COLUMN IF NOT EXISTS status_until BIGINT;
+
+CREATE INDEX IF NOT EXISTS users_pending_deletion_idx ON users (status_until) WHERE status = 'pending_deletion';
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS users(
+   id TEXT NOT NULL PRIMARY KEY,
+   email TEXT NOT NULL UNIQUE,
+   password_hash TEXT NOT NULL,
+   requires_2fa BOOLEAN NOT NULL DEFAULT FALSE,
+   phone_number TEXT,
+   phone_number_verified BOOLEAN NOT NULL DEFAULT FALSE,
+   two_fa_method TEXT NOT NULL DEFAULT 'email'
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS trusted_devices(
+   id TEXT NOT NULL PRIMARY KEY,
+   user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   name TEXT NOT NULL,
+   created_at INTEGER NOT NULL,
+   last_used_at INTEGER NOT NULL,
+   expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS trusted_devices_user_id_idx ON trusted_devices (user_id);
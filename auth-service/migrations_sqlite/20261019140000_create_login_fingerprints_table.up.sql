@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS login_fingerprints(
+   user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   fingerprint TEXT NOT NULL,
+   first_seen_at INTEGER NOT NULL,
+   last_seen_at INTEGER NOT NULL,
+   PRIMARY KEY (user_id, fingerprint)
+);
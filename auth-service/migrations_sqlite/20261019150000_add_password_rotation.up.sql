@@ -0,0 +1,14 @@
+-- Defaults have to be constant here, existing passwords count as changed when the migration runs
+ALTER TABLE users ADD COLUMN password_changed_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN password_max_age_seconds INTEGER;
+UPDATE users SET password_changed_at = CAST(strftime('%s', 'now') AS INTEGER);
+
+-- Ids grow with every change, several changes can share a second
+CREATE TABLE IF NOT EXISTS password_history(
+   id INTEGER PRIMARY KEY,
+   user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+   password_hash TEXT NOT NULL,
+   changed_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS password_history_user_id_idx ON password_history (user_id, id);
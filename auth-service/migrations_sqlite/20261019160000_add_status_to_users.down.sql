@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS users_pending_deletion_idx;
+ALTER TABLE users DROP COLUMN status_until;
+ALTER TABLE users DROP COLUMN status;
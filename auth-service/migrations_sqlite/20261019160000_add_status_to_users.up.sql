@@ -0,0 +1,5 @@
+-- status_until is when a suspension ends or a pending deletion is due
+ALTER TABLE users ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+ALTER TABLE users ADD COLUMN status_until INTEGER;
+
+CREATE INDEX IF NOT EXISTS users_pending_deletion_idx ON users (status_until) WHERE status = 'pending_deletion';
@@ -1,30 +1,76 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::{domain::{data_stores::{banned_token_store::BannedTokenStore, two_fa_code_store::TwoFACodeStore, user_store::UserStore}, EmailClient}, utils::AuthSettings};
+use crate::{
+    domain::{data_stores::{banned_token_store::BannedTokenStore, idempotency_store::IdempotencyStore, login_fingerprint_store::LoginFingerprintStore, rate_limit_store::RateLimitStore, trusted_device_store::TrustedDeviceStore, two_fa_code_store::TwoFACodeStore, user_store::UserStore}, Clock, EmailClient, SmsClient, SystemClock},
+    services::data_stores::{capturing_email_client::Mailbox, hashmap_rate_limit_store::HashmapRateLimitStore, oidc_client::OidcClient},
+    utils::{password_policy::PasswordPolicy, AuthSettings}
+};
 
 // Using a type alias to improve readability!
-pub type UserStoreType = Arc<RwLock<dyn UserStore + Sync + Send >>;
-pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Sync + Send >>;
-pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Sync + Send >>;
+// Stores handle concurrent access themselves, so requests share them without a lock
+pub type UserStoreType = Arc<dyn UserStore + Sync + Send >;
+pub type BannedTokenStoreType = Arc<dyn BannedTokenStore + Sync + Send >;
+pub type TwoFACodeStoreType = Arc<dyn TwoFACodeStore + Sync + Send >;
+pub type IdempotencyStoreType = Arc<dyn IdempotencyStore + Sync + Send >;
+pub type TrustedDeviceStoreType = Arc<dyn TrustedDeviceStore + Sync + Send >;
+pub type LoginFingerprintStoreType = Arc<dyn LoginFingerprintStore + Sync + Send >;
+pub type RateLimitStoreType = Arc<dyn RateLimitStore + Sync + Send >;
 pub type EmailClientType = Arc<RwLock<dyn EmailClient + Sync + Send >>;
+pub type SmsClientType = Arc<RwLock<dyn SmsClient + Sync + Send >>;
+pub type OidcClientType = Arc<OidcClient>;
+pub type PasswordPolicyType = Arc<PasswordPolicy>;
+pub type ClockType = Arc<dyn Clock>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
-    pub two_fa_code_store: TwoFACodeStoreType, 
+    pub two_fa_code_store: TwoFACodeStoreType,
+    pub idempotency_store: IdempotencyStoreType,
+    pub trusted_device_store: TrustedDeviceStoreType,
+    pub login_fingerprint_store: LoginFingerprintStoreType,
+    // Limits each instance on its own unless given a shared store
+    pub rate_limit_store: RateLimitStoreType,
     pub email_client: EmailClientType,
+    pub sms_client: SmsClientType,
+    pub oidc_client: OidcClientType,
+    pub password_policy: PasswordPolicyType,
     pub auth_settings: AuthSettings,
+    // Every expiry is measured against it, give the stores the same one
+    pub clock: ClockType,
+    // Only set when the email client captures what it sends (dev and tests)
+    pub mailbox: Option<Mailbox>,
 }
 
 impl AppState {
+    // Every store is required, none of them has a safe default to fall back on
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        user_store: UserStoreType, 
-        banned_token_store: BannedTokenStoreType, 
+        user_store: UserStoreType,
+        banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
+        idempotency_store: IdempotencyStoreType,
+        trusted_device_store: TrustedDeviceStoreType,
+        login_fingerprint_store: LoginFingerprintStoreType,
         email_client: EmailClientType,
+        sms_client: SmsClientType,
+        password_policy: PasswordPolicyType,
         auth_settings: AuthSettings) -> Self {
-        Self { user_store, banned_token_store, two_fa_code_store, email_client, auth_settings }
+        // Only talks to the providers listed in the settings, so it is built from them
+        let oidc_client = Arc::new(OidcClient::new(auth_settings.oidc.clone()));
+        Self { user_store, banned_token_store, two_fa_code_store, idempotency_store, trusted_device_store, login_fingerprint_store, rate_limit_store: Arc::new(HashmapRateLimitStore::default()), email_client, sms_client, oidc_client, password_policy, auth_settings, clock: Arc::new(SystemClock), mailbox: None }
+    }
+
+    pub fn with_clock(self, clock: ClockType) -> Self {
+        Self { clock, ..self }
+    }
+
+    pub fn with_rate_limit_store(self, rate_limit_store: RateLimitStoreType) -> Self {
+        Self { rate_limit_store, ..self }
+    }
+
+    pub fn with_mailbox(self, mailbox: Mailbox) -> Self {
+        Self { mailbox: Some(mailbox), ..self }
     }
 }
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+
+// Whether the user may log in. Only pending deletion ever ends on its own, with the account purged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccountStatus {
+    #[default]
+    Active,
+    // Set by an admin, until they lift it
+    Disabled,
+    SuspendedUntil(DateTime<Utc>),
+    // The user deleted their account, its data is purged once the grace period is over
+    PendingDeletion { delete_at: DateTime<Utc> },
+}
+
+impl AccountStatus {
+    // A suspension that ran out counts as active, nobody has to lift it
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        match self {
+            AccountStatus::Active => true,
+            AccountStatus::SuspendedUntil(until) => *until <= now,
+            AccountStatus::Disabled | AccountStatus::PendingDeletion { .. } => false,
+        }
+    }
+
+    // Stored as a name plus the time it runs until, in Unix seconds
+    pub fn parse(s: &str, until: Option<i64>) -> Result<Self> {
+        let until = || {
+            until
+                .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+                .ok_or_else(|| eyre!("Account status '{}' without a valid time", s))
+        };
+
+        match s {
+            "active" => Ok(AccountStatus::Active),
+            "disabled" => Ok(AccountStatus::Disabled),
+            "suspended" => Ok(AccountStatus::SuspendedUntil(until()?)),
+            "pending_deletion" => Ok(AccountStatus::PendingDeletion { delete_at: until()? }),
+            other => Err(eyre!("Unknown account status '{}'", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Disabled => "disabled",
+            AccountStatus::SuspendedUntil(_) => "suspended",
+            AccountStatus::PendingDeletion { .. } => "pending_deletion",
+        }
+    }
+
+    pub fn until(&self) -> Option<DateTime<Utc>> {
+        match self {
+            AccountStatus::SuspendedUntil(until) => Some(*until),
+            AccountStatus::PendingDeletion { delete_at } => Some(*delete_at),
+            AccountStatus::Active | AccountStatus::Disabled => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn account_status_round_trips_through_str() {
+        let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+        for status in [
+            AccountStatus::Active,
+            AccountStatus::Disabled,
+            AccountStatus::SuspendedUntil(now),
+            AccountStatus::PendingDeletion { delete_at: now },
+        ] {
+            let until = status.until().map(|until| until.timestamp());
+            assert_eq!(AccountStatus::parse(status.as_str(), until).unwrap(), status);
+        }
+        assert!(AccountStatus::parse("suspended", None).is_err());
+        assert!(AccountStatus::parse("banished", None).is_err());
+    }
+
+    #[test]
+    fn suspension_ends_on_its_own() {
+        let now = Utc::now();
+        let status = AccountStatus::SuspendedUntil(now + Duration::days(1));
+
+        assert!(!status.is_active(now));
+        assert!(status.is_active(now + Duration::days(1)));
+        assert!(!AccountStatus::PendingDeletion { delete_at: now }.is_active(now + Duration::days(1)));
+    }
+}
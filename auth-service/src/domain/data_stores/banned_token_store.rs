@@ -1,13 +1,33 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use color_eyre::eyre::Report;
-use secrecy::Secret;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
+use crate::domain::UserId;
+
+// Tokens are still accepted for a while past their expiry, see jsonwebtoken's Validation::leeway
+pub const JWT_LEEWAY_SECONDS: i64 = 60;
+
+// Tokens are banned by their jti claim. A ban is kept until the token's exp plus JWT_LEEWAY_SECONDS,
+// and a revocation for the token TTL plus JWT_LEEWAY_SECONDS, past that the tokens they cover
+// have expired anyway.
 #[mockall::automock]
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn add_token(&mut self, token: Secret<String> ) -> Result<(), BannedTokenStoreError>;
+    // `expires_at` is the exp claim of the token, tokens already past it are not stored
+    async fn add_token(&self, jti: &str, expires_at: usize) -> Result<(), BannedTokenStoreError>;
+
+    // Bans the token unless it already is, true when this call banned it. Of calls racing with
+    // the same jti only one gets true, which is what makes a token single use. A token already
+    // past its expiry is not stored and gets false.
+    async fn add_token_if_absent(&self, jti: &str, expires_at: usize) -> Result<bool, BannedTokenStoreError>;
+
+    async fn contains_token(&self, jti: &str) -> Result<bool, BannedTokenStoreError>;
 
-    async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError>;
+    // Every token of the user issued before the timestamp stops being valid, e.g. after a password change
+    async fn revoke_tokens_before(&self, user_id: &UserId, timestamp: usize) -> Result<(), BannedTokenStoreError>;
+
+    async fn tokens_revoked_before(&self, user_id: &UserId) -> Result<Option<usize>, BannedTokenStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -24,3 +44,8 @@ impl PartialEq for BannedTokenStoreError {
         )
     }
 }
+
+// Stores key bans on this rather than the jti itself
+pub fn hash_jti(jti: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(jti.as_bytes()))
+}
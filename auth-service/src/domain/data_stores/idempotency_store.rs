@@ -0,0 +1,111 @@
+use color_eyre::eyre::{eyre, Report, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Remembers the response given to a request sent with an Idempotency-Key header, so a client
+// retrying after a lost response gets it again instead of running the request twice
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait IdempotencyStore {
+    // Claims the key for a request, unless an earlier request with it is running or finished
+    async fn start(&self, key: &IdempotencyKey, fingerprint: &str) -> Result<IdempotencyStatus, IdempotencyStoreError>;
+    async fn complete(&self, key: &IdempotencyKey, response: SavedResponse) -> Result<(), IdempotencyStoreError>;
+    // Frees the key after a failure the client may retry
+    async fn release(&self, key: &IdempotencyKey) -> Result<(), IdempotencyStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum IdempotencyStoreError {
+    #[error("Idempotency key already used for another request")]
+    KeyMismatch,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for IdempotencyStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::KeyMismatch, Self::KeyMismatch)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdempotencyStatus {
+    Started,
+    InProgress,
+    Completed(SavedResponse),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+// What a key holds, the fingerprint tells a retry apart from another request reusing the key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    pub fingerprint: String,
+    pub response: Option<SavedResponse>,
+}
+
+impl IdempotencyRecord {
+    pub fn status(self, fingerprint: &str) -> Result<IdempotencyStatus, IdempotencyStoreError> {
+        if self.fingerprint != fingerprint {
+            return Err(IdempotencyStoreError::KeyMismatch);
+        }
+
+        Ok(match self.response {
+            Some(response) => IdempotencyStatus::Completed(response),
+            None => IdempotencyStatus::InProgress,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey(String);
+
+impl IdempotencyKey {
+    pub fn parse(key: &str) -> Result<Self> {
+        if key.is_empty() || key.len() > 255 {
+            return Err(eyre!("Idempotency key must be between 1 and 255 characters long"));
+        }
+        if !key.chars().all(|c| c.is_ascii_graphic()) {
+            return Err(eyre!("Idempotency key has characters other than printable ASCII"));
+        }
+        Ok(IdempotencyKey(key.to_owned()))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_idempotency_key() {
+        assert!(IdempotencyKey::parse("8e03978e-40d5-43e8-bc93-6894a57f9324").is_ok());
+        assert!(IdempotencyKey::parse("").is_err());
+        assert!(IdempotencyKey::parse(&"a".repeat(256)).is_err());
+        assert!(IdempotencyKey::parse("with space").is_err());
+    }
+
+    #[test]
+    fn test_record_status() {
+        let response = SavedResponse { status: 201, body: "{}".to_owned() };
+        let running = IdempotencyRecord { fingerprint: "a".to_owned(), response: None };
+        let finished = IdempotencyRecord { fingerprint: "a".to_owned(), response: Some(response.clone()) };
+
+        assert_eq!(running.clone().status("a").unwrap(), IdempotencyStatus::InProgress);
+        assert_eq!(finished.status("a").unwrap(), IdempotencyStatus::Completed(response));
+        assert_eq!(running.status("b").unwrap_err(), IdempotencyStoreError::KeyMismatch);
+    }
+}
@@ -0,0 +1,83 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::domain::UserId;
+
+// Where each user logged in from before, so a login from somewhere new can be told apart
+#[async_trait::async_trait]
+pub trait LoginFingerprintStore {
+    // Remembers the fingerprint for the user and tells whether it was seen before
+    async fn record_fingerprint(
+        &self,
+        user_id: &UserId,
+        fingerprint: &LoginFingerprint,
+        seen_at: DateTime<Utc>,
+    ) -> Result<FingerprintStatus, LoginFingerprintStoreError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FingerprintStatus {
+    // The user had no fingerprint yet, e.g. the first login after signing up
+    FirstLogin,
+    Known,
+    New,
+}
+
+#[derive(Debug, Error)]
+pub enum LoginFingerprintStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for LoginFingerprintStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// The address and browser a login came from, a change in either counts as a new device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginFingerprint {
+    pub ip_address: String,
+    pub user_agent: String,
+}
+
+impl LoginFingerprint {
+    pub fn new(ip_address: String, user_agent: String) -> Self {
+        LoginFingerprint { ip_address, user_agent }
+    }
+
+    // Stores keep this rather than the address and browser themselves
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.ip_address.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.user_agent.as_bytes());
+        URL_SAFE_NO_PAD.encode(hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprints_hash_address_and_browser() {
+        let fingerprint = LoginFingerprint::new("203.0.113.7".to_owned(), "Firefox".to_owned());
+
+        assert_eq!(fingerprint.hash(), fingerprint.clone().hash());
+        assert_ne!(fingerprint.hash(), LoginFingerprint::new("203.0.113.8".to_owned(), "Firefox".to_owned()).hash());
+        assert_ne!(fingerprint.hash(), LoginFingerprint::new("203.0.113.7".to_owned(), "Chrome".to_owned()).hash());
+        // The separator keeps the two parts from running into each other
+        assert_ne!(
+            LoginFingerprint::new("1".to_owned(), "23".to_owned()).hash(),
+            LoginFingerprint::new("12".to_owned(), "3".to_owned()).hash()
+        );
+    }
+}
@@ -1,5 +1,13 @@
 pub mod banned_token_store;
 pub use banned_token_store::*;
+pub mod idempotency_store;
+pub use idempotency_store::*;
+pub mod login_fingerprint_store;
+pub use login_fingerprint_store::*;
+pub mod rate_limit_store;
+pub use rate_limit_store::*;
+pub mod trusted_device_store;
+pub use trusted_device_store::*;
 pub mod two_fa_code_store;
 pub use two_fa_code_store::*;
 pub mod user_store;
@@ -0,0 +1,119 @@
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::Report;
+use serde::Deserialize;
+use thiserror::Error;
+
+// Token buckets for rate limiting, shared by every instance of the service
+#[async_trait::async_trait]
+pub trait RateLimitStore {
+    // Takes a token from the bucket under the key, the bucket starts full
+    async fn take_token(&self, key: &str, bucket: &TokenBucket) -> Result<RateLimitDecision, RateLimitStoreError>;
+    // How many tokens the bucket under the key is missing, without taking any
+    async fn tokens_taken(&self, key: &str, bucket: &TokenBucket) -> Result<u32, RateLimitStoreError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    Allowed,
+    // The bucket is empty, the next token comes back after retry_after
+    Limited { retry_after: Duration },
+}
+
+#[derive(Debug, Error)]
+pub enum RateLimitStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RateLimitStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Stores only keep when a bucket is full again. Taking a token pushes that back by refill_millis,
+// the bucket is empty once it is capacity tokens away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct TokenBucket {
+    // Requests allowed in a burst
+    pub capacity: u32,
+    // One token comes back every refill_millis
+    pub refill_millis: i64,
+}
+
+impl TokenBucket {
+    pub fn refill_interval(&self) -> Duration {
+        Duration::milliseconds(self.refill_millis)
+    }
+
+    // Without a stored time, or one already past, the bucket is full
+    pub fn take(&self, full_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Result<DateTime<Utc>, Duration> {
+        let full_at = full_at.filter(|full_at| *full_at > now).unwrap_or(now) + self.refill_interval();
+        self.check(full_at, now).map(|_| full_at)
+    }
+
+    // Tokens that have not come back yet, counting the one on its way as missing
+    pub fn taken(&self, full_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> u32 {
+        let Some(wait) = full_at.map(|full_at| full_at - now).filter(|wait| *wait > Duration::zero()) else {
+            return 0;
+        };
+        let taken = (wait.num_milliseconds() + self.refill_millis - 1) / self.refill_millis;
+        taken.min(self.capacity as i64) as u32
+    }
+
+    // Whether the token that pushed the bucket to full_at was there to take, or how long until it is
+    pub fn check(&self, full_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<(), Duration> {
+        let wait = full_at - now - self.refill_interval() * self.capacity as i32;
+        if wait > Duration::zero() {
+            return Err(wait);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUCKET: TokenBucket = TokenBucket { capacity: 3, refill_millis: 1000 };
+
+    #[test]
+    fn test_bucket_allows_bursts_up_to_capacity() {
+        let now = Utc::now();
+        let mut full_at = None;
+
+        for _ in 0..BUCKET.capacity {
+            full_at = Some(BUCKET.take(full_at, now).expect("Bucket should have a token"));
+        }
+
+        assert_eq!(BUCKET.take(full_at, now), Err(Duration::seconds(1)));
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let now = Utc::now();
+        let mut full_at = None;
+        for _ in 0..BUCKET.capacity {
+            full_at = BUCKET.take(full_at, now).ok();
+        }
+
+        assert_eq!(BUCKET.take(full_at, now + Duration::milliseconds(400)), Err(Duration::milliseconds(600)));
+        assert!(BUCKET.take(full_at, now + Duration::seconds(1)).is_ok());
+        // Left alone long enough it is full again, and no fuller than its capacity
+        assert_eq!(BUCKET.take(full_at, now + Duration::hours(1)), Ok(now + Duration::hours(1) + Duration::seconds(1)));
+    }
+
+    #[test]
+    fn test_bucket_counts_tokens_taken() {
+        let now = Utc::now();
+        let full_at = BUCKET.take(BUCKET.take(None, now).ok(), now).ok();
+
+        assert_eq!(BUCKET.taken(None, now), 0);
+        assert_eq!(BUCKET.taken(full_at, now), 2);
+        assert_eq!(BUCKET.taken(full_at, now + Duration::milliseconds(1500)), 1);
+        assert_eq!(BUCKET.taken(full_at, now + Duration::seconds(2)), 0);
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::{Context, Report, Result};
+use sqlx::{prelude::FromRow, Row};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::domain::UserId;
+
+// Browsers a user chose to remember after a 2FA login, their logins skip the code until the
+// device expires or is revoked. Revoking removes the device, so a token for it stops working.
+#[async_trait::async_trait]
+pub trait TrustedDeviceStore {
+    // Also drops the user's devices that expired by the time this one was created
+    async fn add_device(&self, device: TrustedDevice) -> Result<(), TrustedDeviceStoreError>;
+    async fn get_device(&self, id: &TrustedDeviceId) -> Result<TrustedDevice, TrustedDeviceStoreError>;
+    // Newest first
+    async fn get_devices(&self, user_id: &UserId) -> Result<Vec<TrustedDevice>, TrustedDeviceStoreError>;
+    async fn mark_used(&self, id: &TrustedDeviceId, used_at: DateTime<Utc>) -> Result<(), TrustedDeviceStoreError>;
+    // Only the device's owner can revoke it, anyone else gets DeviceNotFound
+    async fn revoke_device(&self, user_id: &UserId, id: &TrustedDeviceId) -> Result<(), TrustedDeviceStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum TrustedDeviceStoreError {
+    #[error("Trusted device not found")]
+    DeviceNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for TrustedDeviceStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::DeviceNotFound, Self::DeviceNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustedDevice {
+    pub id: TrustedDeviceId,
+    pub user_id: UserId,
+    // Taken from the User-Agent, only to tell the devices apart when listing them
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TrustedDevice {
+    pub fn new(user_id: UserId, name: String, now: DateTime<Utc>, ttl: Duration) -> Self {
+        // Stores keep whole seconds
+        let now = DateTime::from_timestamp(now.timestamp(), 0).unwrap_or(now);
+        TrustedDevice {
+            id: TrustedDeviceId::default(),
+            user_id,
+            name,
+            created_at: now,
+            last_used_at: now,
+            expires_at: now + ttl,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    // Times are stored as Unix seconds
+    fn from_columns(
+        id: TrustedDeviceId,
+        user_id: UserId,
+        name: String,
+        created_at: i64,
+        last_used_at: i64,
+        expires_at: i64,
+    ) -> Result<Self, sqlx::Error> {
+        let timestamp = |seconds: i64| {
+            DateTime::from_timestamp(seconds, 0)
+                .ok_or_else(|| sqlx::Error::Decode(format!("Timestamp out of range '{}'", seconds).into()))
+        };
+
+        Ok(TrustedDevice {
+            id,
+            user_id,
+            name,
+            created_at: timestamp(created_at)?,
+            last_used_at: timestamp(last_used_at)?,
+            expires_at: timestamp(expires_at)?,
+        })
+    }
+}
+
+impl<'r> FromRow<'r, sqlx::postgres::PgRow> for TrustedDevice {
+    fn from_row(row: &'r sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
+        TrustedDevice::from_columns(
+            TrustedDeviceId::from(row.try_get::<Uuid, _>("id")?),
+            UserId::from(row.try_get::<Uuid, _>("user_id")?),
+            row.try_get("name")?,
+            row.try_get("created_at")?,
+            row.try_get("last_used_at")?,
+            row.try_get("expires_at")?,
+        )
+    }
+}
+
+// SQLite has no UUID type, the ids are stored as text
+impl<'r> FromRow<'r, sqlx::sqlite::SqliteRow> for TrustedDevice {
+    fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let raw_id = row.try_get::<String, _>("id")?;
+        let id = TrustedDeviceId::parse(&raw_id)
+            .map_err(|_| sqlx::Error::Decode(format!("Trusted device id had the wrong format '{}'", raw_id).into()))?;
+        let raw_user_id = row.try_get::<String, _>("user_id")?;
+        let user_id = UserId::parse(&raw_user_id)
+            .map_err(|_| sqlx::Error::Decode(format!("User id had the wrong format '{}'", raw_user_id).into()))?;
+
+        TrustedDevice::from_columns(
+            id,
+            user_id,
+            row.try_get("name")?,
+            row.try_get("created_at")?,
+            row.try_get("last_used_at")?,
+            row.try_get("expires_at")?,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrustedDeviceId(Uuid);
+
+impl TrustedDeviceId {
+    pub fn parse(id: &str) -> Result<Self> {
+        let id = Uuid::parse_str(id).wrap_err("Invalid trusted device id")?;
+        Ok(TrustedDeviceId(id))
+    }
+}
+
+impl Default for TrustedDeviceId {
+    fn default() -> Self {
+        TrustedDeviceId(Uuid::new_v4())
+    }
+}
+
+impl From<Uuid> for TrustedDeviceId {
+    fn from(id: Uuid) -> Self {
+        TrustedDeviceId(id)
+    }
+}
+
+impl AsRef<Uuid> for TrustedDeviceId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl fmt::Display for TrustedDeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_expires_after_its_ttl() {
+        let now = Utc::now();
+        let device = TrustedDevice::new(UserId::default(), "Firefox".to_owned(), now, Duration::days(30));
+
+        assert!(!device.is_expired(now));
+        assert!(device.is_expired(now + Duration::days(30)));
+        assert_eq!(TrustedDeviceId::parse(&device.id.to_string()).unwrap(), device.id);
+    }
+}
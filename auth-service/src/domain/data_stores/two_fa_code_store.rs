@@ -1,25 +1,55 @@
+use std::hash::Hash;
+
 use color_eyre::eyre::{eyre, Context, Report, Result};
 use secrecy::{ExposeSecret, Secret};
 use thiserror::Error;
 use rand::Rng;
 use uuid::Uuid;
 
-use crate::domain::email::Email;
+use crate::domain::{email::Email, PhoneNumber};
+
+// How long a code can be used after it is sent
+pub const TWO_FA_CODE_TTL_SECONDS: i64 = 600;
 
-// This trait represents the interface all concrete 2FA code stores should implement
+// Pending logins a user can have at once, when not configured
+pub const DEFAULT_MAX_ATTEMPTS_PER_USER: usize = 5;
+
+// This trait represents the interface all concrete 2FA code stores should implement.
+// Codes are keyed by login attempt, so a user can log in from several devices at once. Past the
+// per-user cap the user's oldest attempt is dropped. A code is gone once TWO_FA_CODE_TTL_SECONDS
+// have passed, and removing a missing code is not an error.
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
         login_attempt_id: LoginAttemptId,
+        email: Email,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(&self, login_attempt_id: &LoginAttemptId) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError>;
+    // Removes the login attempt and returns its code, of concurrent takes only one finds it
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError>;
+    // Phone number verifications keep their codes here too, along with the number the code was
+    // texted to. get_code does not find them, so they can not stand in for a login attempt, and
+    // remove_code removes either kind.
+    async fn add_phone_number_code(
+        &self,
+        verification_id: LoginAttemptId,
+        email: Email,
+        phone_number: PhoneNumber,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
+    async fn get_phone_number_code(
+        &self,
+        verification_id: &LoginAttemptId,
+    ) -> Result<(Email, PhoneNumber, TwoFACode), TwoFACodeStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -56,6 +86,14 @@ impl PartialEq for LoginAttemptId {
     }
 }
 
+impl Hash for LoginAttemptId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.expose_secret().hash(state);
+    }
+}
+
+impl Eq for LoginAttemptId {}
+
 impl Default for LoginAttemptId {
     fn default() -> Self {
         LoginAttemptId(Secret::new(Uuid::new_v4().to_string()))
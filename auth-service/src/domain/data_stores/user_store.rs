@@ -1,17 +1,43 @@
-use crate::domain::{email::Email, password::Password, User, UserHashed};
+use chrono::{DateTime, Duration, Utc};
+use crate::domain::{email::Email, AccountStatus, password::Password, PhoneNumber, TwoFAMethod, User, UserHashed, UserId};
 use color_eyre::eyre::Report;
 use thiserror::Error;
 
 #[mockall::automock]
 #[async_trait::async_trait]
 pub trait UserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError>;
 
-    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    async fn delete_user(&self, id: &UserId) -> Result<(), UserStoreError>;
 
     async fn get_user(&self, email: &Email) -> Result<UserHashed, UserStoreError>;
 
+    async fn get_user_by_id(&self, id: &UserId) -> Result<UserHashed, UserStoreError>;
+
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError>;
+
+    // Stores the number as unverified, falling back to email 2FA until it gets verified
+    async fn set_phone_number(&self, id: &UserId, phone_number: PhoneNumber) -> Result<(), UserStoreError>;
+
+    // Only verifies the number if it is still the one stored for the user
+    async fn verify_phone_number(&self, id: &UserId, phone_number: &PhoneNumber) -> Result<(), UserStoreError>;
+
+    async fn set_two_fa_method(&self, id: &UserId, two_fa_method: TwoFAMethod) -> Result<(), UserStoreError>;
+
+    // Fails with PasswordReused if the password is one of the last history_size passwords of the user,
+    // the current one included. The replaced one is remembered for the next changes.
+    async fn update_password(&self, id: &UserId, password: Password, changed_at: DateTime<Utc>, history_size: usize) -> Result<(), UserStoreError>;
+
+    // Set by an admin, the user has to change a password older than max_age before doing anything else
+    async fn set_password_max_age(&self, id: &UserId, max_age: Option<Duration>) -> Result<(), UserStoreError>;
+
+    async fn set_status(&self, id: &UserId, status: AccountStatus) -> Result<(), UserStoreError>;
+
+    // Hard deletes the users whose deletion is due by now, returns how many went
+    async fn purge_deleted_users(&self, now: DateTime<Utc>) -> Result<u64, UserStoreError>;
+
+    // Fails with UserAlreadyExists if another user already has the new email
+    async fn update_email(&self, id: &UserId, new_email: Email) -> Result<(), UserStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -22,6 +48,12 @@ pub enum UserStoreError {
     UserNotFound,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Phone number not verified")]
+    PhoneNumberNotVerified,
+    #[error("Password was used before")]
+    PasswordReused,
+    #[error("Too many password hashes in progress")]
+    Overloaded,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -33,6 +65,9 @@ impl PartialEq for UserStoreError {
             (Self::UserAlreadyExists, Self::UserAlreadyExists)
                 | (Self::UserNotFound, Self::UserNotFound)
                 | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::PhoneNumberNotVerified, Self::PhoneNumberNotVerified)
+                | (Self::PasswordReused, Self::PasswordReused)
+                | (Self::Overloaded, Self::Overloaded)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
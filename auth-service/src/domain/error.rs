@@ -1,6 +1,8 @@
 use color_eyre::eyre::Report;
 use thiserror::Error;
 
+use crate::domain::PasswordWeakness;
+
 #[derive(Debug,Error)]
 pub enum AuthAPIError {
     #[error("Invalid credentials")]
@@ -19,6 +21,40 @@ pub enum AuthAPIError {
     UserNotFound,
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Invalid phone number")]
+    InvalidPhoneNumber,
+    #[error("Phone number not verified")]
+    PhoneNumberNotVerified,
+    #[error("Unknown identity provider")]
+    UnknownIdentityProvider,
+    #[error("Email not verified by the identity provider")]
+    UnverifiedEmail,
+    #[error("Invalid idempotency key")]
+    InvalidIdempotencyKey,
+    #[error("Idempotency key already used for another request")]
+    IdempotencyKeyReused,
+    #[error("Request with this idempotency key in progress")]
+    RequestInProgress,
+    #[error("Trusted device not found")]
+    TrustedDeviceNotFound,
+    #[error("Reauthentication required")]
+    ReauthenticationRequired,
+    #[error("Weak password")]
+    WeakPassword(Vec<PasswordWeakness>),
+    #[error("Account disabled")]
+    AccountDisabled,
+    #[error("Account suspended")]
+    AccountSuspended,
+    #[error("Account pending deletion")]
+    AccountPendingDeletion,
+    #[error("Password used recently")]
+    PasswordReused,
+    #[error("Proof of work required")]
+    ProofOfWorkRequired,
+    #[error("Too many requests")]
+    TooManyRequests { retry_after_seconds: u64 },
+    #[error("Service unavailable")]
+    ServiceUnavailable,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
\ No newline at end of file
@@ -1,13 +1,25 @@
 pub mod user;
+pub mod account_status;
+pub use account_status::*;
 pub mod error;
 pub mod data_stores;
+pub mod clock;
+pub use clock::*;
 pub mod email;
 pub use email::*;
 pub mod password;
 pub use password::*;
+pub mod phone_number;
+pub use phone_number::*;
+pub mod two_fa_method;
+pub use two_fa_method::*;
+pub mod user_id;
+pub use user_id::*;
 
 pub use user::*;
 pub use error::*;
 
 pub mod email_client;
 pub use email_client::*;
+pub mod sms_client;
+pub use sms_client::*;
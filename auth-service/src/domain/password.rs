@@ -1,5 +1,8 @@
 use color_eyre::eyre::{eyre, Result};
 use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
 
 #[derive(Debug, Clone)]
 pub struct Password(Secret<String>);
@@ -22,9 +25,38 @@ impl Password {
 }
 
 fn validate_password(s: &Secret<String>) -> bool { // Updated!
-    s.expose_secret().len() >= 8 && 
-        s.expose_secret().chars().any(|c| c.is_uppercase()) && 
-        s.expose_secret().chars().any(|c| c.is_lowercase())
+    weaknesses(s).is_empty()
+}
+
+// Why a password was refused, sent back to the client so it can tell the user what to change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordWeakness {
+    TooShort,
+    MissingUppercase,
+    MissingLowercase,
+    // Too few characters, from too few kinds, or in repeated or sequential runs
+    TooPredictable,
+    Common,
+    Breached,
+    ContainsEmail,
+}
+
+// The rules every password follows, the password policy adds its own for new ones
+pub fn weaknesses(s: &Secret<String>) -> Vec<PasswordWeakness> {
+    let password = s.expose_secret();
+    let mut weaknesses = Vec::new();
+
+    if password.len() < MIN_PASSWORD_LENGTH {
+        weaknesses.push(PasswordWeakness::TooShort);
+    }
+    if !password.chars().any(|c| c.is_uppercase()) {
+        weaknesses.push(PasswordWeakness::MissingUppercase);
+    }
+    if !password.chars().any(|c| c.is_lowercase()) {
+        weaknesses.push(PasswordWeakness::MissingLowercase);
+    }
+    weaknesses
 }
 
 impl AsRef<Secret<String>> for Password {
@@ -37,7 +69,7 @@ impl AsRef<Secret<String>> for Password {
 #[cfg(test)]
 mod tests {
 
-    use super::Password;
+    use super::{weaknesses, Password, PasswordWeakness};
     use secrecy::Secret;
 
     #[test]
@@ -71,4 +103,13 @@ mod tests {
         }
         
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn weaknesses_should_list_every_broken_rule() {
+        assert_eq!(
+            weaknesses(&Secret::new("short".to_string())),
+            vec![PasswordWeakness::TooShort, PasswordWeakness::MissingUppercase]
+        );
+        assert_eq!(weaknesses(&Secret::new("Exactly8".to_string())), vec![]);
+    }
+}
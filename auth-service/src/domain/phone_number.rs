@@ -0,0 +1,85 @@
+use std::hash::Hash;
+
+use color_eyre::eyre::{eyre, Result};
+use regex::Regex;
+use secrecy::{ExposeSecret, Secret};
+
+// Phone number in E.164 format, e.g. +34600123456
+#[derive(Debug, Clone)]
+pub struct PhoneNumber(Secret<String>);
+
+impl PartialEq for PhoneNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Hash for PhoneNumber {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.expose_secret().hash(state);
+    }
+}
+
+impl Eq for PhoneNumber {}
+
+impl PhoneNumber {
+    pub fn parse(s: Secret<String>) -> Result<PhoneNumber> {
+        // Leading '+', a country code that cannot start with 0 and at most 15 digits in total
+        let re = Regex::new(r"^\+[1-9]\d{1,14}$").unwrap();
+        if re.is_match(s.expose_secret()) {
+            Ok(Self(s))
+        }
+        else {
+            Err(eyre!("Phone number is not in E.164 format"))
+        }
+    }
+}
+
+impl AsRef<Secret<String>> for PhoneNumber {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PhoneNumber;
+
+    use secrecy::Secret;
+
+    #[test]
+    fn good_phone_numbers_should_be_parsed() {
+
+        let raw_phone_numbers = [
+            "+34600123456",
+            "+14155552671",
+            "+442071838750",
+            "+123456789012345"
+        ];
+
+        for raw_phone_number in raw_phone_numbers {
+            assert!(PhoneNumber::parse(Secret::new(raw_phone_number.to_string())).is_ok());
+        }
+
+    }
+
+    #[test]
+    fn bad_phone_numbers_should_not_be_parsed() {
+
+        let raw_phone_numbers = [
+            "600123456",
+            "+0600123456",
+            "+34 600 123 456",
+            "+34-600-123-456",
+            "+1234567890123456",
+            "+",
+            "+34abc",
+            ""
+        ];
+
+        for raw_phone_number in raw_phone_numbers {
+            assert!(PhoneNumber::parse(Secret::new(raw_phone_number.to_string())).is_err());
+        }
+
+    }
+}
@@ -0,0 +1,13 @@
+use super::PhoneNumber;
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+
+// This trait represents the interface all concrete SMS clients should implement
+#[async_trait::async_trait]
+pub trait SmsClient {
+    async fn send_sms(
+        &self,
+        recipient: &PhoneNumber,
+        content: &Secret<String>,
+    ) -> Result<()>;
+}
@@ -0,0 +1,41 @@
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+// Channel used to deliver the 2FA code to users with requires_2fa
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAMethod {
+    #[default]
+    Email,
+    Sms,
+}
+
+impl TwoFAMethod {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "email" => Ok(TwoFAMethod::Email),
+            "sms" => Ok(TwoFAMethod::Sms),
+            other => Err(eyre!("Unknown 2FA method '{}'", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TwoFAMethod::Email => "email",
+            TwoFAMethod::Sms => "sms",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoFAMethod;
+
+    #[test]
+    fn two_fa_method_round_trips_through_str() {
+        for method in [TwoFAMethod::Email, TwoFAMethod::Sms] {
+            assert_eq!(TwoFAMethod::parse(method.as_str()).unwrap(), method);
+        }
+        assert!(TwoFAMethod::parse("pigeon").is_err());
+    }
+}
@@ -1,48 +1,157 @@
+use chrono::{DateTime, Duration, Utc};
 use sqlx::prelude::FromRow;
 use secrecy::Secret;
 use sqlx::Row;
 
-use crate::domain::{email::Email, password::Password};
+use uuid::Uuid;
+
+use crate::domain::{account_status::AccountStatus, email::Email, password::Password, phone_number::PhoneNumber, two_fa_method::TwoFAMethod, user_id::UserId};
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct User {
+    pub id: UserId,
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    pub phone_number: Option<PhoneNumber>,
+    pub phone_number_verified: bool,
+    pub two_fa_method: TwoFAMethod,
+    pub password_changed_at: DateTime<Utc>,
+    // Set by an admin for accounts that must rotate their password, None never expires
+    pub password_max_age: Option<Duration>,
+    pub status: AccountStatus,
 }
 
 impl User {
-    pub fn new(email: Email, password: Password, requires_2fa: bool) -> User {
+    // now is when the password was set, from the app's clock
+    pub fn new(email: Email, password: Password, requires_2fa: bool, now: DateTime<Utc>) -> User {
+        // Stores keep whole seconds
+        let now = DateTime::from_timestamp(now.timestamp(), 0).unwrap_or(now);
         User {
+            id: UserId::default(),
             email,
             password,
             requires_2fa,
+            phone_number: None,
+            phone_number_verified: false,
+            two_fa_method: TwoFAMethod::Email,
+            password_changed_at: now,
+            password_max_age: None,
+            status: AccountStatus::Active,
         }
     }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct UserHashed  {
+    pub id: UserId,
     pub email: Email,
     pub password_hash: Password,
     pub requires_2fa: bool,
+    pub phone_number: Option<PhoneNumber>,
+    pub phone_number_verified: bool,
+    pub two_fa_method: TwoFAMethod,
+    pub password_changed_at: DateTime<Utc>,
+    pub password_max_age: Option<Duration>,
+    pub status: AccountStatus,
+}
+
+impl UserHashed {
+    // The phone number 2FA codes can be sent to, only once the user proved they own it
+    pub fn verified_phone_number(&self) -> Option<&PhoneNumber> {
+        self.phone_number.as_ref().filter(|_| self.phone_number_verified)
+    }
+
+    // Logging in still works, but only to change the password
+    pub fn password_expired(&self, now: DateTime<Utc>) -> bool {
+        self.password_max_age
+            .is_some_and(|max_age| self.password_changed_at + max_age <= now)
+    }
 }
 
 impl<'r> FromRow<'r, sqlx::postgres::PgRow> for UserHashed {
     fn from_row(row: &'r sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
-        let email: Email = {
-            let raw_email = row.try_get::<String, _>("email")?;
-            Email::parse(Secret::new(raw_email.clone())).map_err(|_| sqlx::Error::Decode(format!("Email had the wrong format '{}'", raw_email).into()))?
-        };
-        let password_hash = {
-            let raw_password = Secret::new(row.try_get::<String, _>("password_hash")?);
-            Password::parse(raw_password).map_err(|_| sqlx::Error::Decode(format!("Password was not validated").into()))?
+        let id = UserId::from(row.try_get::<Uuid, _>("id")?);
+        UserHashed::from_columns(
+            id,
+            row.try_get("email")?,
+            row.try_get("password_hash")?,
+            row.try_get("requires_2fa")?,
+            row.try_get("phone_number")?,
+            row.try_get("phone_number_verified")?,
+            row.try_get("two_fa_method")?,
+            row.try_get("password_changed_at")?,
+            row.try_get("password_max_age_seconds")?,
+            row.try_get("status")?,
+            row.try_get("status_until")?,
+        )
+    }
+}
+
+// SQLite has no UUID type, the id is stored as text
+impl<'r> FromRow<'r, sqlx::sqlite::SqliteRow> for UserHashed {
+    fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
+        let id = {
+            let raw_id = row.try_get::<String, _>("id")?;
+            UserId::parse(&raw_id).map_err(|_| sqlx::Error::Decode(format!("User id had the wrong format '{}'", raw_id).into()))?
         };
-        let requires_2fa: bool = row.try_get::<bool, _>("requires_2fa")?;
+        UserHashed::from_columns(
+            id,
+            row.try_get("email")?,
+            row.try_get("password_hash")?,
+            row.try_get("requires_2fa")?,
+            row.try_get("phone_number")?,
+            row.try_get("phone_number_verified")?,
+            row.try_get("two_fa_method")?,
+            row.try_get("password_changed_at")?,
+            row.try_get("password_max_age_seconds")?,
+            row.try_get("status")?,
+            row.try_get("status_until")?,
+        )
+    }
+}
+
+impl UserHashed {
+    // Times are stored as Unix seconds
+    #[allow(clippy::too_many_arguments)]
+    fn from_columns(
+        id: UserId,
+        raw_email: String,
+        raw_password_hash: String,
+        requires_2fa: bool,
+        raw_phone_number: Option<String>,
+        phone_number_verified: bool,
+        raw_two_fa_method: String,
+        raw_password_changed_at: i64,
+        raw_password_max_age_seconds: Option<i64>,
+        raw_status: String,
+        raw_status_until: Option<i64>,
+    ) -> Result<Self, sqlx::Error> {
+        let email = Email::parse(Secret::new(raw_email.clone()))
+            .map_err(|_| sqlx::Error::Decode(format!("Email had the wrong format '{}'", raw_email).into()))?;
+        let password_hash = Password::parse(Secret::new(raw_password_hash))
+            .map_err(|_| sqlx::Error::Decode("Password was not validated".into()))?;
+        let phone_number = raw_phone_number
+            .map(|raw_phone_number| PhoneNumber::parse(Secret::new(raw_phone_number)))
+            .transpose()
+            .map_err(|_| sqlx::Error::Decode("Phone number had the wrong format".into()))?;
+        let two_fa_method = TwoFAMethod::parse(&raw_two_fa_method).map_err(|e| sqlx::Error::Decode(e.into()))?;
+        let password_changed_at = DateTime::from_timestamp(raw_password_changed_at, 0)
+            .ok_or_else(|| sqlx::Error::Decode("Password change time was out of range".into()))?;
+        let password_max_age = raw_password_max_age_seconds.map(Duration::seconds);
+        let status = AccountStatus::parse(&raw_status, raw_status_until).map_err(|e| sqlx::Error::Decode(e.into()))?;
+
         Ok(UserHashed {
+            id,
             email,
             password_hash,
             requires_2fa,
+            phone_number,
+            phone_number_verified,
+            two_fa_method,
+            password_changed_at,
+            password_max_age,
+            status,
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+use color_eyre::eyre::{Context, Result};
+use uuid::Uuid;
+
+// Stable identifier of a user, unlike the email it never changes and is safe to put in tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UserId(Uuid);
+
+impl UserId {
+    pub fn parse(id: &str) -> Result<Self> {
+        let id = Uuid::parse_str(id).wrap_err("Invalid user id")?;
+        Ok(UserId(id))
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        UserId(Uuid::new_v4())
+    }
+}
+
+impl From<Uuid> for UserId {
+    fn from(id: Uuid) -> Self {
+        UserId(id)
+    }
+}
+
+impl AsRef<Uuid> for UserId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserId;
+
+    #[test]
+    fn user_id_round_trips_through_string() {
+        let id = UserId::default();
+        assert_eq!(UserId::parse(&id.to_string()).unwrap(), id);
+        assert!(UserId::parse("guillem@letsgetrusty.com").is_err());
+    }
+}
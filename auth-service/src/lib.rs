@@ -1,16 +1,22 @@
 use anyhow::Result;
 use secrecy::{ExposeSecret, Secret};
 use axum::{
-    http::StatusCode, 
-    // middleware, 
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    http::{header::RETRY_AFTER, StatusCode}, 
+    middleware, 
     response::{IntoResponse, Response}, 
-    routing::{delete, post}, 
+    routing::{delete, get, post}, 
     serve::Serve, 
     Json, 
     Router
 };
-use domain::AuthAPIError;
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use domain::{AuthAPIError, PasswordWeakness};
+use sqlx::{
+    postgres::PgPoolOptions,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    PgPool, SqlitePool,
+};
+use std::{net::SocketAddr, str::FromStr};
 use tower_http::services::ServeDir;
 use tokio::try_join;
 use tokio::sync::oneshot;
@@ -18,11 +24,14 @@ use serde::{Deserialize, Serialize};
 use std::error::Error;
 use redis::{Client, RedisResult};
 use crate::{
-    app_state::AppState, 
+    app_state::{AppState, ClockType, UserStoreType},
     auth::auth_grpc_service_server::AuthGrpcServiceServer,
     presentation::grpc_auth_service_impl::AuthGrpcServiceImpl, 
+    services::data_stores::RedisConnectionPool,
+    utils::{account_deletion::purge_deleted_accounts, proof_of_work::require_proof_of_work, rate_limit::rate_limit, RedisSettings},
     // roles_assignment::roles_middleware::auth_middleware
 }; 
+use axum::middleware::AddExtension;
 use tower_http::trace::TraceLayer;
 use utils::tracing::{make_span_with_request_id, on_request, on_response};
 
@@ -40,12 +49,15 @@ pub mod auth {
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<tokio::net::TcpListener, Router, Router>,
+    server: Serve<tokio::net::TcpListener, IntoMakeServiceWithConnectInfo<Router, SocketAddr>, AddExtension<Router, ConnectInfo<SocketAddr>>>,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
     grpc_router: tonic::transport::server::Router,
     pub grpc_address: String,
+    user_store: UserStoreType,
+    clock: ClockType,
+    purge_interval: std::time::Duration,
 }
 
 impl Application {
@@ -54,7 +66,7 @@ impl Application {
         //Grpc router
         let listener = tokio::net::TcpListener::bind(grpc_address).await?;
         let grpc_address = listener.local_addr()?.to_string();
-        let auth_service = AuthGrpcServiceImpl::new(app_state.banned_token_store.clone(), app_state.auth_settings.http.jwt_token.clone());
+        let auth_service = AuthGrpcServiceImpl::new(app_state.user_store.clone(), app_state.banned_token_store.clone(), app_state.auth_settings.http.jwt_token.clone(), app_state.clock.clone());
         let grpc_router =  tonic::transport::Server::builder()
             .add_service(AuthGrpcServiceServer::new(auth_service));
 
@@ -73,8 +85,9 @@ impl Application {
         //     .allow_origin(allowed_origins);
 
         //Http router
-        let router_internal = Router::new()
+        let mut router_internal = Router::new()
             .fallback_service(ServeDir::new("assets"))
+            .route("/challenge", get(routes::challenge))
             .route("/signup", post(routes::signup))
             .route("/login", post(routes::login))
             .route("/logout", post(routes::logout))
@@ -82,7 +95,32 @@ impl Application {
             .route("/verify-token", post(routes::verify_token_html))
             .route("/delete-account", delete(routes::delete_account))
             .route("/refresh-token", post(routes::refresh_token))
+            .route("/reauthenticate", post(routes::reauthenticate))
+            .route("/phone-number", post(routes::set_phone_number))
+            .route("/verify-phone-number", post(routes::verify_phone_number))
+            .route("/2fa-method", post(routes::set_two_fa_method))
+            .route("/magic-link", post(routes::request_magic_link))
+            .route("/verify-magic-link", post(routes::verify_magic_link))
+            .route("/oidc/{provider}/authorize", get(routes::oidc_authorize))
+            .route("/oidc/{provider}/callback", get(routes::oidc_callback))
+            .route("/change-password", post(routes::change_password))
+            .route("/change-email", post(routes::change_email))
+            .route("/confirm-email-change", post(routes::confirm_email_change))
+            .route("/secure-account", post(routes::secure_account))
+            .route("/password-reset", post(routes::request_password_reset))
+            .route("/confirm-password-reset", post(routes::confirm_password_reset))
+            .route("/trusted-devices", get(routes::list_trusted_devices))
+            .route("/trusted-devices/{id}", delete(routes::revoke_trusted_device));
+
+        if app_state.auth_settings.dev.mailbox && app_state.mailbox.is_some() {
+            tracing::warn!("📬 Dev mailbox exposed at /auth/dev/mailbox, do not use in production");
+            router_internal = router_internal.route("/dev/mailbox", get(routes::dev_mailbox));
+        }
+
+        let router_internal = router_internal
             .with_state(app_state.clone())
+            .layer(middleware::from_fn_with_state(app_state.clone(), require_proof_of_work))
+            .layer(middleware::from_fn_with_state(app_state.clone(), rate_limit))
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(make_span_with_request_id)
@@ -96,9 +134,20 @@ impl Application {
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Rate limiting needs the address each request comes from
+        let server = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>());
 
-        Ok(Application { server, address, grpc_router, grpc_address })
+        let purge_interval = std::time::Duration::from_millis(app_state.auth_settings.account_deletion.purge_interval_millis);
+
+        Ok(Application {
+            server,
+            address,
+            grpc_router,
+            grpc_address,
+            user_store: app_state.user_store,
+            clock: app_state.clock,
+            purge_interval,
+        })
     }
 
     pub async fn run(self, tx_ready: Option<oneshot::Sender<()>>) -> Result<()> {
@@ -127,19 +176,35 @@ impl Application {
                     .map_err(|e| anyhow::Error::new(e).context("HTTP server failed")) 
             };
 
-        try_join!(grpc_server_async, http_server_async).map(|_| ())
+        // Purges only while the servers run, an app that was built but never run starts nothing
+        let purge = tokio::spawn(purge_deleted_accounts(self.user_store, self.clock, self.purge_interval));
+
+        let result = try_join!(grpc_server_async, http_server_async).map(|_| ());
+        purge.abort();
+        result
     }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    // What to change, for errors about something the user chose
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<PasswordWeakness>,
 }
 
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self);
+        let retry_after_seconds = match self {
+            AuthAPIError::TooManyRequests { retry_after_seconds } => Some(retry_after_seconds),
+            _ => None,
+        };
+        let reasons = match &self {
+            AuthAPIError::WeakPassword(reasons) => reasons.clone(),
+            _ => Vec::new(),
+        };
         let (status, error_message) = match self {
             AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "Incorrect credentials"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
@@ -152,11 +217,32 @@ impl IntoResponse for AuthAPIError {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             }
             AuthAPIError::Unauthorized => (StatusCode::UNAUTHORIZED, "Not authorized to do this operation"),
+            AuthAPIError::InvalidPhoneNumber => (StatusCode::BAD_REQUEST, "Invalid phone number"),
+            AuthAPIError::PhoneNumberNotVerified => (StatusCode::CONFLICT, "Phone number not verified"),
+            AuthAPIError::UnknownIdentityProvider => (StatusCode::NOT_FOUND, "Unknown identity provider"),
+            AuthAPIError::UnverifiedEmail => (StatusCode::FORBIDDEN, "Email not verified by the identity provider"),
+            AuthAPIError::InvalidIdempotencyKey => (StatusCode::BAD_REQUEST, "Invalid idempotency key"),
+            AuthAPIError::IdempotencyKeyReused => (StatusCode::UNPROCESSABLE_ENTITY, "Idempotency key already used for another request"),
+            AuthAPIError::RequestInProgress => (StatusCode::CONFLICT, "A request with this idempotency key is still in progress"),
+            AuthAPIError::TrustedDeviceNotFound => (StatusCode::NOT_FOUND, "Trusted device not found"),
+            AuthAPIError::ReauthenticationRequired => (StatusCode::FORBIDDEN, "Reauthentication required"),
+            AuthAPIError::WeakPassword(_) => (StatusCode::BAD_REQUEST, "Password is too weak"),
+            AuthAPIError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+            AuthAPIError::AccountSuspended => (StatusCode::FORBIDDEN, "Account suspended, try again later"),
+            AuthAPIError::AccountPendingDeletion => (StatusCode::FORBIDDEN, "Account scheduled for deletion"),
+            AuthAPIError::PasswordReused => (StatusCode::BAD_REQUEST, "Password was used recently, choose another one"),
+            AuthAPIError::ProofOfWorkRequired => (StatusCode::PRECONDITION_REQUIRED, "Solve a challenge from /auth/challenge first"),
+            AuthAPIError::TooManyRequests { .. } => (StatusCode::TOO_MANY_REQUESTS, "Too many requests, try again later"),
+            AuthAPIError::ServiceUnavailable => (StatusCode::SERVICE_UNAVAILABLE, "Service temporarily unavailable, try again later"),
         };
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
+            reasons,
         });
-        (status, body).into_response()
+        match retry_after_seconds {
+            Some(seconds) => (status, [(RETRY_AFTER, seconds.to_string())], body).into_response(),
+            None => (status, body).into_response(),
+        }
     }
 }
 
@@ -164,11 +250,24 @@ pub async fn get_postgres_pool(url: &Secret<String>) -> Result<PgPool, sqlx::Err
     PgPoolOptions::new().max_connections(5).connect(url.expose_secret()).await
 }
 
+pub async fn get_sqlite_pool(url: &Secret<String>) -> Result<SqlitePool, sqlx::Error> {
+    let url = url.expose_secret();
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    // Each connection to an in-memory database gets a database of its own, so they share one connection
+    let max_connections = if url.contains(":memory:") || url.contains("mode=memory") { 1 } else { 5 };
+    SqlitePoolOptions::new().max_connections(max_connections).connect_with(options).await
+}
+
 pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
     let redis_url = format!("redis://{}/", redis_hostname);
     redis::Client::open(redis_url)
 }
 
+pub async fn get_redis_pool(redis_settings: &RedisSettings) -> RedisResult<RedisConnectionPool> {
+    let client = get_redis_client(redis_settings.host_name.clone())?;
+    RedisConnectionPool::new(client, redis_settings).await
+}
+
 fn log_error_chain(e: &(dyn Error + 'static)) {
     let separator =
         "\n-----------------------------------------------------------------------------------\n";
@@ -1,32 +1,63 @@
-use auth_service::app_state::AppState;
-use auth_service::domain::Email;
+use auth_service::app_state::{
+    AppState, BannedTokenStoreType, ClockType, EmailClientType, IdempotencyStoreType, LoginFingerprintStoreType, RateLimitStoreType, SmsClientType, TrustedDeviceStoreType, TwoFACodeStoreType, UserStoreType,
+};
+use auth_service::domain::{Email, PhoneNumber, SystemClock};
+use auth_service::services::data_stores::capturing_email_client::{CapturingEmailClient, Mailbox};
+use auth_service::services::data_stores::mock_email_client::MockEmailClient;
+use auth_service::services::data_stores::mock_sms_client::MockSmsClient;
+use auth_service::services::data_stores::twilio_sms_client::TwilioSmsClient;
 use auth_service::services::data_stores::postmark_email_client::PostmarkEmailClient;
-use auth_service::services::data_stores::{RedisBannedTokenStore, RedisTwoFACodeStore};
+use auth_service::services::data_stores::{RedisBannedTokenStore, RedisConnectionPool, RedisIdempotencyStore, RedisRateLimitStore, RedisTwoFACodeStore};
+use auth_service::services::data_stores::{
+    postgres_login_fingerprint_store::PostgresLoginFingerprintStore, postgres_trusted_device_store::PostgresTrustedDeviceStore,
+    postgres_user_store::PostgresUserStore, sqlite_login_fingerprint_store::SqliteLoginFingerprintStore,
+    sqlite_trusted_device_store::SqliteTrustedDeviceStore, sqlite_user_store::SqliteUserStore, PasswordHashingExecutor
+};
 use auth_service::services::data_stores::{
-    postgres_user_store::PostgresUserStore
+    hashmap_idempotency_store::HashmapIdempotencyStore, hashmap_login_fingerprint_store::HashmapLoginFingerprintStore,
+    hashmap_rate_limit_store::HashmapRateLimitStore,
+    hashmap_trusted_device_store::HashmapTrustedDeviceStore,
+    hashmap_two_fa_code_store::HashmapTwoFACodeStore,
+    hashmap_user_store::HashmapUserStore, hashset_banned_token_store::HashsetBannedTokenStore,
 };
-use auth_service::utils::{init_tracing, AuthSettings, EmailSettings};
-use auth_service::{get_postgres_pool, get_redis_client, Application};
+use auth_service::utils::password_policy::PasswordPolicy;
+use auth_service::utils::{init_tracing, AuthSettings, EmailSettings, RedisSettings, RunProfile, SmsSettings};
+use auth_service::{get_postgres_pool, get_redis_pool, get_sqlite_pool, Application};
 use reqwest::Client;
 use secrecy::Secret;
-use sqlx::PgPool;
+use sqlx::{PgPool, SqlitePool};
 use tokio::sync::RwLock;
 use std::sync::Arc;
 
+type Stores = (
+    UserStoreType, BannedTokenStoreType, TwoFACodeStoreType, IdempotencyStoreType, TrustedDeviceStoreType, LoginFingerprintStoreType,
+    RateLimitStoreType,
+);
+
 #[tokio::main]
 async fn main() {
     color_eyre::install().expect("Failed to install color_eyre");
     init_tracing().expect("Failed to initialize tracing");
     let auth_settings = AuthSettings::new();
-    let redis_connection = Arc::new(RwLock::new(configure_redis(auth_settings.redis.host_name.clone())));
-    let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(Arc::clone(&redis_connection), auth_settings.redis.ttl_millis)));
-    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection)));
-    let email_client = Arc::new(RwLock::new(configure_postmark_email_client(auth_settings.email.clone())));
-    let pg_pool = configure_postgresql(&auth_settings.database.url).await;
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+    let clock: ClockType = Arc::new(SystemClock);
+    let (user_store, banned_token_store, two_fa_code_store, idempotency_store, trusted_device_store, login_fingerprint_store, rate_limit_store) = match auth_settings.profile {
+        RunProfile::Services => configure_stores(&auth_settings, &clock).await,
+        RunProfile::Memory => configure_memory_stores(&auth_settings, &clock),
+    };
+    let mailbox = Mailbox::default();
+    let email_client = configure_email_client(&auth_settings, mailbox.clone());
+    let sms_client = configure_sms_client(&auth_settings);
+    // Loads its blocklist once, it is too big to read on every signup
+    let password_policy = Arc::new(PasswordPolicy::new(&auth_settings.password_policy).expect("Invalid password policy settings"));
     let http_address = auth_settings.http.address.clone();
     let grpc_address = auth_settings.grpc.address.clone();
-    let app_state = AppState::new(user_store, banned_token_store, two_fa_code_store, email_client, auth_settings);
+    let dev_mailbox = auth_settings.dev.mailbox;
+    let mut app_state = AppState::new(user_store, banned_token_store, two_fa_code_store, idempotency_store, trusted_device_store, login_fingerprint_store, email_client, sms_client, password_policy, auth_settings)
+        .with_rate_limit_store(rate_limit_store)
+        .with_clock(clock);
+    if dev_mailbox {
+        app_state = app_state.with_mailbox(mailbox);
+    }
 
     let app = Application::build(app_state, &http_address, &grpc_address)
         .await
@@ -35,6 +66,75 @@ async fn main() {
     app.run(None).await.expect("Failed to run app");
 }
 
+async fn configure_stores(
+    auth_settings: &AuthSettings,
+    clock: &ClockType,
+) -> Stores {
+    let redis_pool = configure_redis(&auth_settings.redis).await;
+    let (user_store, trusted_device_store, login_fingerprint_store) = configure_database_stores(auth_settings).await;
+    (
+        user_store,
+        Arc::new(RedisBannedTokenStore::new(redis_pool.clone(), auth_settings.redis.ttl_millis).with_clock(clock.clone())),
+        Arc::new(RedisTwoFACodeStore::new(redis_pool.clone(), auth_settings.two_fa.max_attempts_per_user).with_clock(clock.clone())),
+        Arc::new(RedisIdempotencyStore::new(redis_pool.clone(), auth_settings.idempotency.ttl_millis)),
+        trusted_device_store,
+        login_fingerprint_store,
+        Arc::new(RedisRateLimitStore::new(redis_pool).with_clock(clock.clone())),
+    )
+}
+
+fn configure_memory_stores(
+    auth_settings: &AuthSettings,
+    clock: &ClockType,
+) -> Stores {
+    tracing::warn!("🧠 Running in memory, every account is lost when the process stops");
+    (
+        Arc::new(HashmapUserStore::default()),
+        Arc::new(HashsetBannedTokenStore::new(auth_settings.redis.ttl_millis).with_clock(clock.clone())),
+        Arc::new(HashmapTwoFACodeStore::new(auth_settings.two_fa.max_attempts_per_user).with_clock(clock.clone())),
+        Arc::new(HashmapIdempotencyStore::default()),
+        Arc::new(HashmapTrustedDeviceStore::default()),
+        Arc::new(HashmapLoginFingerprintStore::default()),
+        Arc::new(HashmapRateLimitStore::default().with_clock(clock.clone())),
+    )
+}
+
+// Trusted devices and login fingerprints reference their users, so they all live in the same database
+async fn configure_database_stores(auth_settings: &AuthSettings) -> (UserStoreType, TrustedDeviceStoreType, LoginFingerprintStoreType) {
+    let password_hashing = PasswordHashingExecutor::new(&auth_settings.password_hashing)
+        .expect("Invalid password hashing settings");
+    let enumeration_protection = auth_settings.enumeration_protection.enabled;
+
+    if auth_settings.database.is_sqlite() {
+        let sqlite_pool = configure_sqlite(&auth_settings.database.url).await;
+        (
+            Arc::new(SqliteUserStore::new(sqlite_pool.clone(), password_hashing).with_enumeration_protection(enumeration_protection)),
+            Arc::new(SqliteTrustedDeviceStore::new(sqlite_pool.clone())),
+            Arc::new(SqliteLoginFingerprintStore::new(sqlite_pool)),
+        )
+    } else {
+        let pg_pool = configure_postgresql(&auth_settings.database.url).await;
+        (
+            Arc::new(PostgresUserStore::new(pg_pool.clone(), password_hashing).with_enumeration_protection(enumeration_protection)),
+            Arc::new(PostgresTrustedDeviceStore::new(pg_pool.clone())),
+            Arc::new(PostgresLoginFingerprintStore::new(pg_pool)),
+        )
+    }
+}
+
+async fn configure_sqlite(database_url: &Secret<String>) -> SqlitePool {
+    let sqlite_pool = get_sqlite_pool(database_url)
+        .await
+        .expect("Failed to create SQLite connection pool!");
+
+    sqlx::migrate!("./migrations_sqlite")
+        .run(&sqlite_pool)
+        .await
+        .expect("Failed to run migrations");
+
+    sqlite_pool
+}
+
 async fn configure_postgresql(database_url:&Secret<String>) -> PgPool {
     let pg_pool = get_postgres_pool(database_url)
         .await
@@ -48,11 +148,19 @@ async fn configure_postgresql(database_url:&Secret<String>) -> PgPool {
     pg_pool
 }
 
-fn configure_redis(redis_host_name:String) -> redis::Connection {
-    get_redis_client(redis_host_name)
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
+async fn configure_redis(redis_settings: &RedisSettings) -> RedisConnectionPool {
+    get_redis_pool(redis_settings)
+        .await
+        .expect("Failed to create Redis connection pool")
+}
+
+// With the dev mailbox enabled no email leaves the process, they are only captured in memory
+fn configure_email_client(auth_settings: &AuthSettings, mailbox: Mailbox) -> EmailClientType {
+    if auth_settings.dev.mailbox {
+        Arc::new(RwLock::new(CapturingEmailClient::new(MockEmailClient, mailbox)))
+    } else {
+        Arc::new(RwLock::new(configure_postmark_email_client(auth_settings.email.clone())))
+    }
 }
 
 fn configure_postmark_email_client(email_settings: EmailSettings) -> PostmarkEmailClient {
@@ -68,4 +176,28 @@ fn configure_postmark_email_client(email_settings: EmailSettings) -> PostmarkEma
         email_settings.postmark_auth_token,
         http_client,
     )
-}
\ No newline at end of file
+}
+// SMS are only logged while developing with the dev mailbox
+fn configure_sms_client(auth_settings: &AuthSettings) -> SmsClientType {
+    if auth_settings.dev.mailbox {
+        Arc::new(RwLock::new(MockSmsClient))
+    } else {
+        Arc::new(RwLock::new(configure_twilio_sms_client(auth_settings.sms.clone())))
+    }
+}
+
+fn configure_twilio_sms_client(sms_settings: SmsSettings) -> TwilioSmsClient {
+    let timeout = std::time::Duration::from_millis(sms_settings.timeout_milliseconds);
+    let http_client = Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    TwilioSmsClient::new(
+        sms_settings.base_url,
+        PhoneNumber::parse(Secret::new(sms_settings.sender)).expect("SMS sender must be in E.164 format"),
+        sms_settings.account_sid,
+        sms_settings.auth_token,
+        http_client,
+    )
+}
@@ -1,19 +1,21 @@
 use secrecy::Secret;
 use tonic::{Request as TonicRequest, Response as TonicResponse};
 use tonic::Status;
-use crate::app_state::BannedTokenStoreType;
+use crate::app_state::{BannedTokenStoreType, ClockType, UserStoreType};
 use crate::auth::auth_grpc_service_server::AuthGrpcService;
 use crate::auth::{VerifyTokenRequest, VerifyTokenResponse};
 use crate::routes::verify_token_grpc;
 
 pub struct AuthGrpcServiceImpl {
+    user_store: UserStoreType,
     banned_token_store: BannedTokenStoreType,
-    jwt_token: Secret<String>
+    jwt_token: Secret<String>,
+    clock: ClockType,
 }
 
 impl AuthGrpcServiceImpl {
-    pub fn new(banned_token_store: BannedTokenStoreType, jwt_token: Secret<String>) -> Self {
-        Self { banned_token_store, jwt_token }
+    pub fn new(user_store: UserStoreType, banned_token_store: BannedTokenStoreType, jwt_token: Secret<String>, clock: ClockType) -> Self {
+        Self { user_store, banned_token_store, jwt_token, clock }
     }
 }
 
@@ -23,7 +25,7 @@ impl AuthGrpcService for AuthGrpcServiceImpl {
         &self,
         request: TonicRequest<VerifyTokenRequest>
     ) -> Result<TonicResponse<VerifyTokenResponse>, Status> {
-        let token_status = verify_token_grpc(self.banned_token_store.clone(), Secret::new(request.into_inner().token), self.jwt_token.clone()).await.into();
+        let token_status = verify_token_grpc(self.user_store.clone(), self.banned_token_store.clone(), Secret::new(request.into_inner().token), self.jwt_token.clone(), self.clock.as_ref()).await.into();
 
         let reply = VerifyTokenResponse { token_status };
         Ok(TonicResponse::new(reply))
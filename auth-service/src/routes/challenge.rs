@@ -0,0 +1,43 @@
+use axum::{extract::{Request, State}, http::StatusCode, response::IntoResponse, Json};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::AuthAPIError,
+    utils::{
+        proof_of_work::{challenge_difficulty, generate_challenge},
+        rate_limit::request_client_ip,
+    },
+};
+
+// The client solves it and sends both along with its request to a protected route
+#[tracing::instrument(name = "Challenge", skip_all)]
+pub async fn challenge(
+    State(state): State<AppState>,
+    request: Request,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let ip_address = request_client_ip(&request, &state.auth_settings.rate_limit.trusted_proxies);
+    let difficulty = challenge_difficulty(&state, ip_address).await;
+
+    let challenge = generate_challenge(
+        difficulty,
+        state.auth_settings.http.jwt_token.clone(),
+        state.auth_settings.proof_of_work.ttl_millis,
+        state.clock.as_ref(),
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(ChallengeResponse {
+        challenge: challenge.expose_secret().to_owned(),
+        difficulty,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChallengeResponse {
+    pub challenge: String,
+    pub difficulty: u32,
+}
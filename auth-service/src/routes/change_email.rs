@@ -0,0 +1,144 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, email::Email, password::Password, AuthAPIError, UserId},
+    utils::{
+        change_email::{generate_change_email_token, validate_change_email_token},
+        step_up::RecentlyAuthenticated,
+    },
+};
+
+// The email is what the account is recovered through, same window as for the password
+const MAX_AUTH_AGE_SECONDS: i64 = 10 * 60;
+
+// Nothing changes until the link sent to the new address is followed
+#[tracing::instrument(name = "ChangeEmail", skip_all)]
+pub async fn change_email(
+    State(state): State<AppState>,
+    RecentlyAuthenticated { user_id, .. }: RecentlyAuthenticated<MAX_AUTH_AGE_SECONDS>,
+    Json(request): Json<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let new_email = Email::parse(Secret::new(request.new_email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user_store = &state.user_store;
+
+    let email = user_store
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|_| AuthAPIError::UserNotFound)?
+        .email;
+
+    user_store
+        .validate_user(&email, &password)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::Overloaded => AuthAPIError::ServiceUnavailable,
+            _ => AuthAPIError::IncorrectCredentials,
+        })?;
+
+    if user_store.get_user(&new_email).await.is_ok() {
+        return Err(AuthAPIError::UserAlreadyExists);
+    }
+
+    let change_email_settings = &state.auth_settings.change_email;
+
+    let token = generate_change_email_token(
+        &user_id,
+        &new_email,
+        state.auth_settings.http.jwt_token.clone(),
+        change_email_settings.ttl_millis,
+        state.clock.as_ref(),
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let content = Secret::new(format!(
+        "Follow this link to use this address for your account, it expires in {} minutes: {}?change_email_token={}",
+        change_email_settings.ttl_millis / 60000,
+        change_email_settings.base_url,
+        token.expose_secret()
+    ));
+
+    state.email_client
+        .read()
+        .await
+        .send_email(&new_email, "Confirm your new email", &content)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(ChangeEmailResponse {
+        message: "Confirmation sent to the new email".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Moves the account to the new email and lets the old address know
+#[tracing::instrument(name = "ConfirmEmailChange", skip_all)]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Json(request): Json<ConfirmEmailChangeRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let claims = validate_change_email_token(
+        state.banned_token_store.clone(),
+        &request.token,
+        state.auth_settings.http.jwt_token.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let new_email = Email::parse(Secret::new(claims.new_email)).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_store = &state.user_store;
+
+    let email = user_store
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?
+        .email;
+
+    user_store
+        .update_email(&user_id, new_email.clone())
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserAlreadyExists => AuthAPIError::UserAlreadyExists,
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let notice = Secret::new(format!(
+        "The email of your account was changed to {}. If it was not you, contact support.",
+        new_email.as_ref().expose_secret()
+    ));
+
+    state.email_client
+        .read()
+        .await
+        .send_email(&email, "Your email was changed", &notice)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    #[serde(rename = "newEmail")]
+    pub new_email: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeEmailResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: Secret<String>,
+}
@@ -0,0 +1,95 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, password::Password, AuthAPIError},
+    routes::ensure_account_active,
+    utils::{auth::{generate_auth_cookie, AuthMethod}, password_rotation::PasswordChangeAllowed, HttpSettings},
+};
+
+// The current password is asked for as well, this keeps out a session left open somewhere
+const MAX_AUTH_AGE_SECONDS: i64 = 10 * 60;
+
+// Logs out every other session of the user, the caller gets a fresh auth cookie. Also where a login
+// with an expired password ends up, holding a password change token instead of an auth token.
+#[tracing::instrument(name = "ChangePassword", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    PasswordChangeAllowed { user_id, password_change_token }: PasswordChangeAllowed<MAX_AUTH_AGE_SECONDS>,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let current_password = Password::parse(request.current_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user_store = &state.user_store;
+
+    let user = user_store
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|_| AuthAPIError::UserNotFound)?;
+
+    user_store
+        .validate_user(&user.email, &current_password)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::Overloaded => AuthAPIError::ServiceUnavailable,
+            _ => AuthAPIError::IncorrectCredentials,
+        })?;
+
+    // The caller gets a fresh auth cookie, which an inactive account must not
+    ensure_account_active(&user, state.clock.now())?;
+
+    let new_password = state.password_policy
+        .parse(request.new_password, &user.email)
+        .await
+        .map_err(AuthAPIError::WeakPassword)?;
+
+    // Used up only once the new password was accepted, of concurrent changes with it only one goes through
+    if let Some(token) = password_change_token {
+        token.use_once(state.banned_token_store.clone())
+            .await
+            .map_err(|_| AuthAPIError::InvalidToken)?;
+    }
+
+    user_store
+        .update_password(&user_id, new_password, state.clock.now(), state.auth_settings.password_rotation.history_size)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            UserStoreError::PasswordReused => AuthAPIError::PasswordReused,
+            UserStoreError::Overloaded => AuthAPIError::ServiceUnavailable,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // Token timestamps are in seconds, a session started within this same second survives
+    let now: usize = state.clock
+        .now()
+        .timestamp()
+        .try_into()
+        .map_err(|e: std::num::TryFromIntError| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state.banned_token_store
+        .revoke_tokens_before(&user_id, now)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = state.auth_settings.http;
+    let token_ttl_millis = state.auth_settings.redis.ttl_millis;
+
+    let auth_cookie =
+        generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, state.clock.as_ref())
+            .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((jar.add(auth_cookie), StatusCode::OK))
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
@@ -1,33 +1,58 @@
-use axum::{extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse, Json};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use color_eyre::eyre::eyre;
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 use crate::{
     app_state::AppState,
-    domain::{email::Email, AuthAPIError},
+    domain::{data_stores::UserStoreError, email::Email, AccountStatus, AuthAPIError},
+    utils::step_up::RecentlyAuthenticated,
 };
 
-pub async fn delete_account(State(state): State<AppState>, headers: HeaderMap, Json(request): Json<DeleteAccountRequest>) -> Result<impl IntoResponse, AuthAPIError> {
-    if let Some(auth) = headers.get("authorization") {
-        let _auth_str = auth.to_str().unwrap_or("");
-        // // to implement when he have login
-        // // we could also use a service that determines if a user can use a route, 
-        // // like AuthService (not to be confused with the auth-app)
-        // if(isAdmin(auth_str)){
-        //  return Err(AuthAPIError::Unauthorized)
-        //}
-    };
+// There is no undoing this, the user must have logged in or reauthenticated just before
+const MAX_AUTH_AGE_SECONDS: i64 = 5 * 60;
 
+pub async fn delete_account(
+    State(state): State<AppState>,
+    RecentlyAuthenticated { user_id, .. }: RecentlyAuthenticated<MAX_AUTH_AGE_SECONDS>,
+    Json(request): Json<DeleteAccountRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
     let email = match Email::parse(Secret::new(request.email)) {
         Ok(email) => email,
         _ => return Err(AuthAPIError::InvalidCredentials),
     };
 
-    let mut user_store = state.user_store.write().await;
+    let user_store = &state.user_store;
+
+    let user = user_store.get_user(&email).await.map_err(|_| AuthAPIError::UserNotFound)?;
 
-    if user_store.delete_user(&email).await.is_err() {
-        return Err(AuthAPIError::UserNotFound);
+    // The email confirms which account goes, only the logged in user's own can
+    if user.id != user_id {
+        return Err(AuthAPIError::Unauthorized);
     }
 
+    // The account stops working now, its data stays until the purge job removes it after the grace period
+    let now = state.clock.now();
+    let grace_period = chrono::Duration::try_milliseconds(state.auth_settings.account_deletion.grace_period_millis)
+        .ok_or(AuthAPIError::UnexpectedError(eyre!("failed to create deletion grace period time delta")))?;
+
+    user_store
+        .set_status(&user.id, AccountStatus::PendingDeletion { delete_at: now + grace_period })
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // Every session ends, the one deleting the account too
+    let revoked_before: usize = (now.timestamp() + 1)
+        .try_into()
+        .map_err(|e: std::num::TryFromIntError| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state.banned_token_store
+        .revoke_tokens_before(&user.id, revoked_before)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
     let response = Json(DeleteAccountResponse {
         message: "User deleted successfully!".to_string(),
     });
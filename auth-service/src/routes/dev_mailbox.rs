@@ -0,0 +1,88 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    http::{header::ACCEPT, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email},
+    services::data_stores::capturing_email_client::CapturedEmail,
+};
+
+// Dev-only view of the emails captured in memory. Browsers get an HTML page,
+// clients sending `Accept: application/json` (e.g. the API tests) get the raw messages.
+#[tracing::instrument(name = "DevMailbox", skip_all)]
+pub async fn dev_mailbox(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<MailboxQuery>,
+) -> Result<Response, AuthAPIError> {
+    let mailbox = match state.mailbox {
+        Some(mailbox) => mailbox,
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    let captured_emails = match query.recipient {
+        Some(recipient) => {
+            let recipient = Email::parse(Secret::new(recipient)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+            mailbox.messages_for(&recipient).await
+        }
+        None => mailbox.messages().await,
+    };
+
+    let messages: Vec<MailboxMessage> = captured_emails.into_iter().map(MailboxMessage::from).collect();
+
+    if wants_json(&headers) {
+        return Ok(Json(messages).into_response());
+    }
+
+    let page = MailboxTemplate { messages }
+        .render()
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(Html(page).into_response())
+}
+
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(|accept| accept.contains("application/json"))
+        .unwrap_or(false)
+}
+
+#[derive(Deserialize)]
+pub struct MailboxQuery {
+    pub recipient: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct MailboxMessage {
+    pub recipient: String,
+    pub subject: String,
+    pub content: String,
+    #[serde(rename = "sentAt")]
+    pub sent_at: String,
+}
+
+impl From<CapturedEmail> for MailboxMessage {
+    fn from(email: CapturedEmail) -> Self {
+        MailboxMessage {
+            recipient: email.recipient.as_ref().expose_secret().to_owned(),
+            subject: email.subject,
+            content: email.content.expose_secret().to_owned(),
+            sent_at: email.sent_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "dev_mailbox.html")]
+struct MailboxTemplate {
+    messages: Vec<MailboxMessage>,
+}
@@ -1,81 +1,233 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use askama::Template;
+use chrono::{DateTime, Utc};
+use std::net::SocketAddr;
+
+use axum::{extract::{ConnectInfo, State}, http::{HeaderMap, StatusCode}, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
+use color_eyre::eyre::Result;
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     app_state::AppState, 
-    domain::{data_stores::two_fa_code_store::{LoginAttemptId, TwoFACode}, email::Email, password::Password, AuthAPIError},
-    utils::{auth::generate_auth_cookie, HttpSettings},
+    domain::{data_stores::{two_fa_code_store::{LoginAttemptId, TwoFACode}, FingerprintStatus, TrustedDeviceStoreError, UserStoreError}, email::Email, password::Password, AccountStatus, AuthAPIError, Clock, TwoFAMethod, UserHashed, UserId},
+    utils::{
+        auth::{generate_auth_cookie, AuthMethod},
+        login_alert::{generate_secure_account_token, login_fingerprint, LoginAlertTemplate},
+        password_rotation::generate_password_change_cookie,
+        trusted_device::validate_trusted_device_token,
+        HttpSettings,
+    },
 };
 
 #[tracing::instrument(name = "Login", skip_all)]
 pub async fn login(
     State(state): State<AppState>, 
     jar: CookieJar,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(request): Json<LoginRequest>
 ) ->  Result<(CookieJar, impl IntoResponse), AuthAPIError> {
     let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
     let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
-    let user_store = &state.user_store.read().await;
+    let user_store = &state.user_store;
 
-    user_store.validate_user(&email, &password).await.map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    user_store.validate_user(&email, &password).await.map_err(|e| match e {
+        UserStoreError::Overloaded => AuthAPIError::ServiceUnavailable,
+        _ => AuthAPIError::IncorrectCredentials,
+    })?;
 
     let user = user_store.get_user(&email).await.map_err(|_| AuthAPIError::IncorrectCredentials)?;
 
-    match user.requires_2fa {
-        true => handle_2fa(&user.email, &state, jar).await,
+    ensure_account_active(&user, state.clock.now())?;
+
+    let requires_2fa = user.requires_2fa && !is_trusted_device(&user, &state, &jar).await;
+
+    match requires_2fa {
+        true => handle_2fa(&user, &state, jar).await,
         false => {
-            let HttpSettings { address: _, jwt_token, jwt_cookie_name} = state.auth_settings.http;
-            let token_ttl_millis = state.auth_settings.redis.ttl_millis;
-            handle_no_2fa(&user.email, jar, jwt_token, jwt_cookie_name, token_ttl_millis).await
+            let response = start_session(&user, &[AuthMethod::Password], &state, jar).await?;
+            alert_on_new_device(&user, peer, &headers, &state).await;
+            Ok(response)
         },
     }
     
 }
 
+// Only called once the credentials were checked, so the status is never told to someone who does not own the account
+pub(crate) fn ensure_account_active(user: &UserHashed, now: DateTime<Utc>) -> Result<(), AuthAPIError> {
+    match user.status {
+        status if status.is_active(now) => Ok(()),
+        AccountStatus::SuspendedUntil(_) => Err(AuthAPIError::AccountSuspended),
+        AccountStatus::PendingDeletion { .. } => Err(AuthAPIError::AccountPendingDeletion),
+        AccountStatus::Active | AccountStatus::Disabled => Err(AuthAPIError::AccountDisabled),
+    }
+}
+
+// A browser remembered after a 2FA login skips the code while its device is neither expired nor revoked.
+// Anything wrong with the cookie only means the user goes through 2FA again.
+#[tracing::instrument(name = "IsTrustedDevice", skip_all)]
+async fn is_trusted_device(user: &UserHashed, state: &AppState, jar: &CookieJar) -> bool {
+    let Some(cookie) = jar.get(&state.auth_settings.trusted_device.cookie_name) else {
+        return false;
+    };
+
+    let claims = match validate_trusted_device_token(cookie.value(), state.auth_settings.http.jwt_token.clone(), state.clock.as_ref()) {
+        Ok(claims) => claims,
+        Err(_) => return false,
+    };
+
+    let (Ok(user_id), Ok(device_id)) = (claims.user_id(), claims.device_id()) else {
+        return false;
+    };
+    if user_id != user.id {
+        return false;
+    }
+
+    let now = state.clock.now();
+    match state.trusted_device_store.get_device(&device_id).await {
+        Ok(device) if device.user_id == user.id && !device.is_expired(now) => {
+            if let Err(e) = state.trusted_device_store.mark_used(&device_id, now).await {
+                tracing::warn!(error = %e, "Could not record the trusted device use");
+            }
+            true
+        },
+        Ok(_) | Err(TrustedDeviceStoreError::DeviceNotFound) => false,
+        Err(e) => {
+            tracing::warn!(error = %e, "Could not look up the trusted device");
+            false
+        },
+    }
+}
+
 #[tracing::instrument(name = "Handle2FA", skip_all)]
-async fn handle_2fa(
-    email: &Email,
+pub(crate) async fn handle_2fa(
+    user: &UserHashed,
     state: &AppState, 
     jar: CookieJar,
 ) ->  Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
+    let login_attempt_id = start_2fa(user, state).await?;
+
+    let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+        message: "2FA required".to_owned(),
+        login_attempt_id: login_attempt_id.as_ref().expose_secret().to_string(), 
+    }));
+
+    Ok((jar, (StatusCode::PARTIAL_CONTENT, response)))
+}
+
+// Stores a new 2FA code for the user and sends it, the login finishes at /verify-2fa
+#[tracing::instrument(name = "Start2FA", skip_all)]
+pub(crate) async fn start_2fa(user: &UserHashed, state: &AppState) -> Result<LoginAttemptId, AuthAPIError> {
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
 
     let _ = state.two_fa_code_store
-                .write()
-                .await
-                .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+                .add_code(login_attempt_id.clone(), user.email.clone(), two_fa_code.clone())
                 .await
                 .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
 
-    let _ = state.email_client
+    // A user only gets SMS once their number is verified, email stays as the fallback
+    match (user.two_fa_method, user.verified_phone_number()) {
+        (TwoFAMethod::Sms, Some(phone_number)) => {
+            state.sms_client
                 .read()
                 .await
-                .send_email(email, "Verify code for login", two_fa_code.as_ref())
+                .send_sms(phone_number, two_fa_code.as_ref())
                 .await
-                .map_err(|e| AuthAPIError::UnexpectedError(e))?;
+                .map_err(AuthAPIError::UnexpectedError)?;
+        },
+        _ => {
+            state.email_client
+                .read()
+                .await
+                .send_email(&user.email, "Verify code for login", two_fa_code.as_ref())
+                .await
+                .map_err(AuthAPIError::UnexpectedError)?;
+        },
+    }
 
-    let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
-        message: "2FA required".to_owned(),
-        login_attempt_id: login_attempt_id.as_ref().expose_secret().to_string(), 
-    }));
+    Ok(login_attempt_id)
+}
 
-    Ok((jar, (StatusCode::PARTIAL_CONTENT, response)))
+// Emails the user when a login succeeds from somewhere their account was not used from before.
+// Best effort, the login goes through whether or not the alert could be sent.
+#[tracing::instrument(name = "AlertOnNewDevice", skip_all)]
+pub(crate) async fn alert_on_new_device(user: &UserHashed, peer: SocketAddr, headers: &HeaderMap, state: &AppState) {
+    if let Err(e) = send_new_device_alert(user, peer, headers, state).await {
+        tracing::warn!(error = %e, "Could not alert the user of a login from a new device");
+    }
+}
+
+async fn send_new_device_alert(user: &UserHashed, peer: SocketAddr, headers: &HeaderMap, state: &AppState) -> Result<()> {
+    let fingerprint = login_fingerprint(peer.ip(), headers, &state.auth_settings.rate_limit.trusted_proxies);
+    let now = state.clock.now();
+
+    let status = state.login_fingerprint_store
+        .record_fingerprint(&user.id, &fingerprint, now)
+        .await?;
+
+    // The first login has nothing to be compared with
+    if status != FingerprintStatus::New {
+        return Ok(());
+    }
+
+    let login_alert_settings = &state.auth_settings.login_alert;
+
+    let token = generate_secure_account_token(
+        &user.id,
+        state.auth_settings.http.jwt_token.clone(),
+        login_alert_settings.ttl_millis,
+        state.clock.as_ref(),
+    )?;
+
+    let content = LoginAlertTemplate {
+        logged_in_at: now.format("%Y-%m-%d %H:%M UTC").to_string(),
+        ip_address: &fingerprint.ip_address,
+        user_agent: &fingerprint.user_agent,
+        link_ttl_hours: login_alert_settings.ttl_millis / 3_600_000,
+        secure_account_link: format!("{}?secure_account_token={}", login_alert_settings.base_url, token.expose_secret()),
+    }
+    .render()?;
+
+    state.email_client
+        .read()
+        .await
+        .send_email(&user.email, "New login to your account", &Secret::new(content))
+        .await
+}
+
+// Where every first factor ends up when no 2FA code is asked for, so an expired password is caught
+// however the user logged in. After 2FA, verify_2fa makes the same check.
+#[tracing::instrument(name = "StartSession", skip_all)]
+pub(crate) async fn start_session(
+    user: &UserHashed,
+    amr: &[AuthMethod],
+    state: &AppState,
+    jar: CookieJar,
+) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
+    if user.password_expired(state.clock.now()) {
+        return handle_password_expired(&user.id, state, jar);
+    }
+
+    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = state.auth_settings.http.clone();
+    let token_ttl_millis = state.auth_settings.redis.ttl_millis;
+    handle_no_2fa(&user.id, amr, jar, jwt_token, jwt_cookie_name, token_ttl_millis, state.clock.as_ref()).await
 }
 
 #[tracing::instrument(name = "HandleNo2FA", skip_all)]
-async fn handle_no_2fa(
-    email: &Email,
+pub(crate) async fn handle_no_2fa(
+    user_id: &UserId,
+    amr: &[AuthMethod],
     jar: CookieJar,
     jwt_secret:Secret<String>,
     jwt_cookie_name:String,
-    token_ttl_millis: i64
+    token_ttl_millis: i64,
+    clock: &dyn Clock,
 ) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
     let auth_cookie = 
-        generate_auth_cookie(&email, jwt_secret, jwt_cookie_name, token_ttl_millis)
+        generate_auth_cookie(user_id, amr, jwt_secret, jwt_cookie_name, token_ttl_millis, clock)
             .map_err(|e| AuthAPIError::UnexpectedError(e))?;
 
     let updated_jar = jar.add(auth_cookie);
@@ -85,6 +237,29 @@ async fn handle_no_2fa(
     Ok((updated_jar, (StatusCode::OK, json_body)))
 }
 
+// The password was right but is older than the user's max age. The cookie only lets the user
+// through /change-password, the session starts there.
+#[tracing::instrument(name = "HandlePasswordExpired", skip_all)]
+pub(crate) fn handle_password_expired(
+    user_id: &UserId,
+    state: &AppState,
+    jar: CookieJar,
+) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
+    let password_change_cookie = generate_password_change_cookie(
+        user_id,
+        state.auth_settings.http.jwt_token.clone(),
+        state.auth_settings.http.jwt_cookie_name.clone(),
+        state.auth_settings.password_rotation.change_token_ttl_millis,
+        state.clock.as_ref(),
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(LoginResponse::PasswordChangeRequired(PasswordChangeRequiredResponse {
+        message: "Password change required".to_owned(),
+    }));
+
+    Ok((jar.add(password_change_cookie), (StatusCode::FORBIDDEN, response)))
+}
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
@@ -92,13 +267,14 @@ pub struct LoginRequest {
     pub password: Secret<String>,
 }
 
-// The login route can return 2 possible success responses.
+// The login route can return 3 possible responses once the credentials are right.
 // This enum models each response!
 #[derive(Debug, Serialize)]
 #[serde(untagged)]
 pub enum LoginResponse {
     RegularAuth,
     TwoFactorAuth(TwoFactorAuthResponse),
+    PasswordChangeRequired(PasswordChangeRequiredResponse),
 }
 
 // If a user requires 2FA, this JSON body should be returned!
@@ -107,4 +283,10 @@ pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
-}
\ No newline at end of file
+}
+
+// If the password of the user expired, this JSON body is returned along with a 403
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordChangeRequiredResponse {
+    pub message: String,
+}
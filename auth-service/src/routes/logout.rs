@@ -34,10 +34,10 @@ pub async fn logout(
     let token = Secret::new(cookie.value().to_owned());
 
 
-    validate_token(state.banned_token_store.clone(), &token, jwt_token).await.map_err(|_| AuthAPIError::InvalidToken)?;
+    let claims = validate_token(state.banned_token_store.clone(), &token, jwt_token, state.clock.as_ref()).await.map_err(|_| AuthAPIError::InvalidToken)?;
 
-    let mut banned_token_store = state.banned_token_store.write().await;
-    let _ = banned_token_store.add_token(Secret::new(cookie.clone().value().to_owned())).await;
+    let banned_token_store = &state.banned_token_store;
+    let _ = banned_token_store.add_token(&claims.jti, claims.exp).await;
 
 
     //This method generates the cookie with no token but with the flags like HttpOnly, SameSite...
@@ -0,0 +1,126 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, email::Email, AuthAPIError},
+    routes::{ensure_account_active, handle_2fa, start_session, LoginResponse},
+    utils::{
+        auth::AuthMethod,
+        magic_link::{
+            create_magic_link_nonce_cookie, generate_browser_nonce, generate_magic_link_token,
+            validate_magic_link_token, MAGIC_LINK_NONCE_COOKIE_NAME,
+        },
+    },
+};
+
+// Always answers the same way so the route can not be used to find out which emails have an account
+#[tracing::instrument(name = "RequestMagicLink", skip_all)]
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<MagicLinkRequest>,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let browser_nonce = generate_browser_nonce();
+    let updated_jar = jar.add(create_magic_link_nonce_cookie(&browser_nonce));
+
+    let user = state.user_store.get_user(&email).await;
+
+    match user {
+        Ok(_) => send_magic_link(&state, &email, &browser_nonce).await?,
+        Err(UserStoreError::UserNotFound) => tracing::debug!("Magic link requested for an unknown email"),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(MagicLinkResponse {
+        message: "If the email has an account, a login link was sent to it".to_owned(),
+    });
+
+    Ok((updated_jar, (StatusCode::OK, response)))
+}
+
+#[tracing::instrument(name = "SendMagicLink", skip_all)]
+async fn send_magic_link(state: &AppState, email: &Email, browser_nonce: &Secret<String>) -> Result<(), AuthAPIError> {
+    let magic_link_settings = &state.auth_settings.magic_link;
+
+    let token = generate_magic_link_token(
+        email,
+        browser_nonce,
+        state.auth_settings.http.jwt_token.clone(),
+        magic_link_settings.ttl_millis,
+        state.clock.as_ref(),
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let content = Secret::new(format!(
+        "Open this link in the same browser to log in, it can only be used once and expires in {} minutes: {}?magic_token={}",
+        magic_link_settings.ttl_millis / 60000,
+        magic_link_settings.base_url,
+        token.expose_secret()
+    ));
+
+    state.email_client
+        .read()
+        .await
+        .send_email(email, "Your login link", &content)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+// Consumes the link and continues exactly like a password login would
+#[tracing::instrument(name = "VerifyMagicLink", skip_all)]
+pub async fn verify_magic_link(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<VerifyMagicLinkRequest>,
+) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
+    let browser_nonce = jar
+        .get(MAGIC_LINK_NONCE_COOKIE_NAME)
+        .map(|cookie| Secret::new(cookie.value().to_owned()))
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    let claims = validate_magic_link_token(
+        state.banned_token_store.clone(),
+        &request.token,
+        &browser_nonce,
+        state.auth_settings.http.jwt_token.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let email = Email::parse(Secret::new(claims.sub)).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user = state.user_store
+        .get_user(&email)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    ensure_account_active(&user, state.clock.now())?;
+
+    let jar = jar.remove(Cookie::build(MAGIC_LINK_NONCE_COOKIE_NAME).path("/"));
+
+    match user.requires_2fa {
+        true => handle_2fa(&user, &state, jar).await,
+        false => start_session(&user, &[AuthMethod::EmailLink], &state, jar).await,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyMagicLinkRequest {
+    pub token: Secret<String>,
+}
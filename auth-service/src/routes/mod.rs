@@ -1,16 +1,40 @@
+mod challenge;
+mod change_email;
+mod change_password;
 mod delete_account;
+mod dev_mailbox;
 mod login;
 mod logout;
+mod magic_link;
+mod oidc;
+mod password_reset;
+mod phone_number;
 mod signup;
+mod two_fa_method;
+mod reauthenticate;
 mod refresh_token;
+mod secure_account;
+mod trusted_devices;
 mod verify_2fa;
 mod verify_token;
 
 // re-export items from sub-modules
+pub use challenge::*;
+pub use change_email::*;
+pub use change_password::*;
 pub use delete_account::*;
+pub use dev_mailbox::*;
 pub use login::*;
 pub use logout::*;
+pub use magic_link::*;
+pub use oidc::*;
+pub use password_reset::*;
+pub use phone_number::*;
+pub use reauthenticate::*;
 pub use refresh_token::*;
+pub use secure_account::*;
 pub use signup::*;
+pub use trusted_devices::*;
+pub use two_fa_method::*;
 pub use verify_2fa::*;
 pub use verify_token::*;
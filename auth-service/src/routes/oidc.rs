@@ -0,0 +1,148 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Redirect,
+    Json,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, email::Email, password::Password, AuthAPIError, User, UserHashed},
+    routes::{ensure_account_active, start_2fa, start_session, LoginResponse},
+    utils::{
+        auth::AuthMethod,
+        oidc::{create_oidc_flow_cookie, validate_oidc_flow_cookie, OidcFlow, OIDC_FLOW_COOKIE_NAME},
+    },
+};
+
+// Sends the browser to the provider's login page, remembering state, nonce and PKCE verifier in a cookie
+#[tracing::instrument(name = "OidcAuthorize", skip_all)]
+pub async fn oidc_authorize(
+    State(state): State<AppState>,
+    Path(provider_name): Path<String>,
+    jar: CookieJar,
+) -> Result<(CookieJar, Redirect), AuthAPIError> {
+    let provider = state.oidc_client
+        .provider(&provider_name)
+        .ok_or(AuthAPIError::UnknownIdentityProvider)?;
+
+    let flow = OidcFlow::new(provider.name.clone());
+
+    let authorization_url = state.oidc_client
+        .authorization_url(provider, &flow.state, &flow.nonce, &flow.code_challenge())
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let flow_cookie = create_oidc_flow_cookie(&flow, state.auth_settings.http.jwt_token.clone(), state.clock.as_ref())
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((jar.add(flow_cookie), Redirect::to(authorization_url.as_str())))
+}
+
+#[tracing::instrument(name = "OidcCallback", skip_all)]
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    Path(provider_name): Path<String>,
+    Query(query): Query<OidcCallbackQuery>,
+    jar: CookieJar,
+) -> Result<(CookieJar, Redirect), AuthAPIError> {
+    let provider = state.oidc_client
+        .provider(&provider_name)
+        .ok_or(AuthAPIError::UnknownIdentityProvider)?;
+
+    if let Some(error) = query.error {
+        tracing::debug!("Identity provider {} answered with error {}", provider.name, error);
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    let flow_cookie = jar.get(OIDC_FLOW_COOKIE_NAME).ok_or(AuthAPIError::MissingToken)?;
+
+    let flow = validate_oidc_flow_cookie(&Secret::new(flow_cookie.value().to_owned()), state.auth_settings.http.jwt_token.clone(), state.clock.as_ref())
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let (code, callback_state) = query.code.zip(query.state).ok_or(AuthAPIError::InvalidCredentials)?;
+
+    // The callback must come from the login this browser started, with the provider it started it at
+    if flow.provider != provider.name || callback_state.expose_secret() != flow.state.expose_secret() {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let id_token = state.oidc_client
+        .exchange_code(provider, &code, &flow.code_verifier)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    let claims = state.oidc_client
+        .validate_id_token(provider, &id_token, &flow.nonce, state.clock.as_ref())
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Linking by email is only safe when the provider vouches for it
+    let email = match (claims.email, claims.email_verified) {
+        (Some(email), true) => Email::parse(Secret::new(email)).map_err(|_| AuthAPIError::UnverifiedEmail)?,
+        _ => return Err(AuthAPIError::UnverifiedEmail),
+    };
+
+    let user = get_or_create_user(&state, email).await?;
+
+    ensure_account_active(&user, state.clock.now())?;
+
+    let jar = jar.remove(Cookie::build(OIDC_FLOW_COOKIE_NAME).path("/"));
+    let post_login_redirect = state.oidc_client.post_login_redirect();
+
+    if user.requires_2fa {
+        let login_attempt_id = start_2fa(&user, &state).await?;
+
+        // The page picks these up from the fragment and shows the 2FA form
+        let fragment = form_urlencoded::Serializer::new(String::new())
+            .append_pair("loginAttemptId", login_attempt_id.as_ref().expose_secret())
+            .append_pair("email", user.email.as_ref().expose_secret())
+            .finish();
+
+        return Ok((jar, Redirect::to(&format!("{}#{}", post_login_redirect, fragment))));
+    }
+
+    let (jar, (_, Json(response))) = start_session(&user, &[AuthMethod::Federated], &state, jar).await?;
+
+    // The cookie set for an expired password only opens /change-password, the page shows that form
+    match response {
+        LoginResponse::PasswordChangeRequired(_) => Ok((jar, Redirect::to(&format!("{}#passwordChangeRequired=true", post_login_redirect)))),
+        _ => Ok((jar, Redirect::to(post_login_redirect))),
+    }
+}
+
+// Existing accounts are linked by email, otherwise a new one is created for the provider's user
+#[tracing::instrument(name = "GetOrCreateOidcUser", skip_all)]
+async fn get_or_create_user(state: &AppState, email: Email) -> Result<UserHashed, AuthAPIError> {
+    let user_store = &state.user_store;
+
+    match user_store.get_user(&email).await {
+        Ok(user) => return Ok(user),
+        Err(UserStoreError::UserNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // Nobody knows this password, the account logs in through the provider or a magic link
+    let password = Password::parse(Secret::new(format!("Oidc-{}", Uuid::new_v4())))
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    // Another callback for the same email may have created it in the meantime, that account is used then
+    match user_store.add_user(User::new(email.clone(), password, false, state.clock.now())).await {
+        Ok(()) | Err(UserStoreError::UserAlreadyExists) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    user_store
+        .get_user(&email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<Secret<String>>,
+    pub state: Option<Secret<String>>,
+    pub error: Option<String>,
+}
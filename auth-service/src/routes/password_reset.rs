@@ -0,0 +1,91 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, email::Email, AuthAPIError, UserId},
+    routes::replace_password,
+    utils::password_reset::{generate_password_reset_token, validate_password_reset_token},
+};
+
+// Always answers the same way so the route can not be used to find out which emails have an account
+#[tracing::instrument(name = "RequestPasswordReset", skip_all)]
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    match state.user_store.get_user(&email).await {
+        Ok(user) => send_password_reset_link(&state, &email, &user.id).await?,
+        Err(UserStoreError::UserNotFound) => tracing::debug!("Password reset requested for an unknown email"),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(PasswordResetResponse {
+        message: "If the email has an account, a password reset link was sent to it".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "SendPasswordResetLink", skip_all)]
+async fn send_password_reset_link(state: &AppState, email: &Email, user_id: &UserId) -> Result<(), AuthAPIError> {
+    let password_reset_settings = &state.auth_settings.password_reset;
+
+    let token = generate_password_reset_token(
+        user_id,
+        state.auth_settings.http.jwt_token.clone(),
+        password_reset_settings.ttl_millis,
+        state.clock.as_ref(),
+    )
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let content = Secret::new(format!(
+        "Open this link to choose a new password, it can only be used once and expires in {} minutes: {}?password_reset_token={}",
+        password_reset_settings.ttl_millis / 60000,
+        password_reset_settings.base_url,
+        token.expose_secret()
+    ));
+
+    state.email_client
+        .read()
+        .await
+        .send_email(email, "Reset your password", &content)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+// Sets the new password and logs out every session, like securing the account after a login alert
+#[tracing::instrument(name = "ConfirmPasswordReset", skip_all)]
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<ConfirmPasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = validate_password_reset_token(&request.token, state.auth_settings.http.jwt_token.clone(), state.clock.as_ref())
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&token.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    replace_password(&state, token, &user_id, request.new_password).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmPasswordResetRequest {
+    pub token: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
@@ -0,0 +1,133 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        data_stores::{two_fa_code_store::{LoginAttemptId, TwoFACode}, UserStoreError},
+        AuthAPIError, PhoneNumber,
+    },
+    utils::{auth::get_authenticated_user_id, step_up::RecentlyAuthenticated},
+};
+
+// Whoever sets the number receives the codes for the account from then on
+const MAX_AUTH_AGE_SECONDS: i64 = 10 * 60;
+
+// Saves the number as unverified and texts it a code, the number can only
+// receive 2FA codes once the code is sent back to /verify-phone-number
+#[tracing::instrument(name = "SetPhoneNumber", skip_all)]
+pub async fn set_phone_number(
+    State(state): State<AppState>,
+    RecentlyAuthenticated { user_id, .. }: RecentlyAuthenticated<MAX_AUTH_AGE_SECONDS>,
+    Json(request): Json<SetPhoneNumberRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let phone_number = PhoneNumber::parse(request.phone_number).map_err(|_| AuthAPIError::InvalidPhoneNumber)?;
+
+    let user_store = &state.user_store;
+
+    let email = user_store
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|_| AuthAPIError::UserNotFound)?
+        .email;
+
+    user_store
+        .set_phone_number(&user_id, phone_number.clone())
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // The verification reuses the 2FA code store, the id plays the role of a login attempt id
+    let verification_id = LoginAttemptId::default();
+    let code = TwoFACode::default();
+
+    state.two_fa_code_store
+        .add_phone_number_code(verification_id.clone(), email, phone_number.clone(), code.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state.sms_client
+        .read()
+        .await
+        .send_sms(&phone_number, code.as_ref())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(SetPhoneNumberResponse {
+        message: "Verification code sent".to_owned(),
+        verification_id: verification_id.as_ref().expose_secret().to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "VerifyPhoneNumber", skip_all)]
+pub async fn verify_phone_number(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<VerifyPhoneNumberRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = get_authenticated_user_id(&jar, state.banned_token_store.clone(), &state.auth_settings.http, state.clock.as_ref()).await?;
+
+    let verification_id = LoginAttemptId::parse(request.verification_id).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user = state.user_store
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|_| AuthAPIError::UserNotFound)?;
+
+    let two_fa_code_store = &state.two_fa_code_store;
+
+    let (sent_to_email, phone_number, sent_code) = two_fa_code_store
+        .get_phone_number_code(&verification_id)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    if (sent_to_email, sent_code) != (user.email, code) {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    // Only the number the code was texted to gets verified, and only while it is still the pending one
+    if user.phone_number.as_ref() != Some(&phone_number) {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    state.user_store
+        .verify_phone_number(&user_id, &phone_number)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            UserStoreError::InvalidCredentials => AuthAPIError::IncorrectCredentials,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let _ = two_fa_code_store.remove_code(&verification_id).await;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct SetPhoneNumberRequest {
+    #[serde(rename = "phoneNumber")]
+    pub phone_number: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetPhoneNumberResponse {
+    pub message: String,
+    #[serde(rename = "verificationId")]
+    pub verification_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyPhoneNumberRequest {
+    #[serde(rename = "verificationId")]
+    pub verification_id: Secret<String>,
+    pub code: Secret<String>,
+}
@@ -0,0 +1,61 @@
+use axum::{extract::State, http::StatusCode, response::{IntoResponse, Response}, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, password::Password, AuthAPIError},
+    routes::{ensure_account_active, handle_password_expired},
+    utils::{auth::{generate_auth_cookie, get_authenticated_user_id, AuthMethod}, HttpSettings},
+};
+
+// Checks the password again for the logged in user, the new auth cookie lets them through
+// routes that need a recent authentication without having to log out first
+#[tracing::instrument(name = "Reauthenticate", skip_all)]
+pub async fn reauthenticate(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ReauthenticateRequest>,
+) -> Result<(CookieJar, Response), AuthAPIError> {
+    let user_id = get_authenticated_user_id(&jar, state.banned_token_store.clone(), &state.auth_settings.http, state.clock.as_ref()).await?;
+
+    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user_store = &state.user_store;
+
+    let user = user_store
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|_| AuthAPIError::UserNotFound)?;
+
+    user_store
+        .validate_user(&user.email, &password)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::Overloaded => AuthAPIError::ServiceUnavailable,
+            _ => AuthAPIError::IncorrectCredentials,
+        })?;
+
+    // Same checks as a login, a session must not outlive the account status or the password
+    ensure_account_active(&user, state.clock.now())?;
+
+    if user.password_expired(state.clock.now()) {
+        let (jar, response) = handle_password_expired(&user.id, &state, jar)?;
+        return Ok((jar, response.into_response()));
+    }
+
+    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = state.auth_settings.http;
+    let token_ttl_millis = state.auth_settings.redis.ttl_millis;
+
+    let auth_cookie =
+        generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, state.clock.as_ref())
+            .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((jar.add(auth_cookie), StatusCode::OK.into_response()))
+}
+
+#[derive(Deserialize)]
+pub struct ReauthenticateRequest {
+    pub password: Secret<String>,
+}
@@ -1,12 +1,11 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
 use axum_extra::extract::CookieJar;
-use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
-use secrecy::{ExposeSecret, Secret};
+use secrecy::Secret;
 use crate::{
     app_state::AppState, 
-    domain::{email::Email, AuthAPIError}, 
-    routes::VerifyTokenSummary, 
-    utils::{auth::{generate_auth_cookie, validate_token, Claims}, HttpSettings}
+    domain::{AuthAPIError, UserId}, 
+    routes::ensure_account_active,
+    utils::{auth::{generate_refreshed_auth_cookie, validate_token}, HttpSettings}
 };
 
 #[tracing::instrument(name = "RefeshToken", skip_all)]
@@ -16,24 +15,19 @@ pub async fn refresh_token(State(state): State<AppState>, jar: CookieJar) -> Res
     let old_cookie = jar.get(&jwt_cookie_name).ok_or(AuthAPIError::MissingToken)?;
 
 
-    let _ = match VerifyTokenSummary::new(validate_token(state.banned_token_store, &Secret::new(old_cookie.value().to_string()), jwt_token.clone()).await) {
-        VerifyTokenSummary::Valid => Ok(()),
-        VerifyTokenSummary::Invalid => Err(AuthAPIError::InvalidToken),
-    }?;
-
-    let email_raw =             
-            decode::<Claims>(
-                &old_cookie.value().to_string(),
-                &DecodingKey::from_secret(&jwt_token.expose_secret().as_bytes()),
-                &Validation::new(Algorithm::HS256))
-            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
-            .claims
-            .sub;
-    let email = Email::parse(Secret::new(email_raw)).map_err(|_| AuthAPIError::InvalidToken)?;
+    let claims = validate_token(state.banned_token_store.clone(), &Secret::new(old_cookie.value().to_string()), jwt_token.clone(), state.clock.as_ref())
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // A session outlives neither its account nor the account being disabled
+    let user = state.user_store.get_user_by_id(&user_id).await.map_err(|_| AuthAPIError::InvalidToken)?;
+    ensure_account_active(&user, state.clock.now())?;
     let token_ttl_millis = state.auth_settings.redis.ttl_millis;
 
     let new_cookie = 
-        generate_auth_cookie(&email, jwt_token, jwt_cookie_name, token_ttl_millis)
+        generate_refreshed_auth_cookie(&user_id, &claims, jwt_token, jwt_cookie_name, token_ttl_millis, state.clock.as_ref())
             .map_err(|_| AuthAPIError::IncorrectCredentials)?;
 
     let jar = jar.add(new_cookie);
@@ -0,0 +1,83 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use secrecy::Secret;
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, AuthAPIError, UserId},
+    utils::{login_alert::validate_secure_account_token, scoped_token::ScopedToken},
+};
+
+// Followed from the "this wasn't me" link of a new device alert. Every session of the user is
+// logged out and the password replaced, so whoever logged in has to start over.
+#[tracing::instrument(name = "SecureAccount", skip_all)]
+pub async fn secure_account(
+    State(state): State<AppState>,
+    Json(request): Json<SecureAccountRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = validate_secure_account_token(&request.token, state.auth_settings.http.jwt_token.clone(), state.clock.as_ref())
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&token.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    replace_password(&state, token, &user_id, request.new_password).await?;
+
+    Ok(StatusCode::OK)
+}
+
+// Shared by the links that set a new password without the old one. The token is used up only once
+// the password was accepted, so a refused one can be retried with the same link.
+#[tracing::instrument(name = "ReplacePassword", skip_all)]
+pub(crate) async fn replace_password<T>(
+    state: &AppState,
+    token: ScopedToken<T>,
+    user_id: &UserId,
+    new_password: Secret<String>,
+) -> Result<(), AuthAPIError> {
+    let email = state.user_store
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?
+        .email;
+
+    let new_password = state.password_policy
+        .parse(new_password, &email)
+        .await
+        .map_err(AuthAPIError::WeakPassword)?;
+
+    token.use_once(state.banned_token_store.clone())
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    state.user_store
+        .update_password(user_id, new_password, state.clock.now(), state.auth_settings.password_rotation.history_size)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            UserStoreError::PasswordReused => AuthAPIError::PasswordReused,
+            UserStoreError::Overloaded => AuthAPIError::ServiceUnavailable,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // Token timestamps are in seconds, same as after a password change
+    let now: usize = state.clock
+        .now()
+        .timestamp()
+        .try_into()
+        .map_err(|e: std::num::TryFromIntError| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state.banned_token_store
+        .revoke_tokens_before(user_id, now)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+#[derive(Deserialize)]
+pub struct SecureAccountRequest {
+    pub token: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
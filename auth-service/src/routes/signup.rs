@@ -1,27 +1,100 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
 use crate::{
     app_state::AppState,
-    domain::{email::Email, password::Password, AuthAPIError, User},
+    domain::{
+        data_stores::{IdempotencyKey, IdempotencyStatus, IdempotencyStoreError, SavedResponse, UserStoreError},
+        email::Email, AuthAPIError, User,
+    },
 };
 
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
 #[tracing::instrument(name = "Signup", skip_all)]
-pub async fn signup(State(state): State<AppState>, Json(request): Json<SignupRequest>) -> Result<impl IntoResponse, AuthAPIError>  {
-    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+pub async fn signup(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SignupRequest>,
+) -> Result<Response, AuthAPIError> {
+    let Some(idempotency_key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(create_user(&state, request).await.into_response());
+    };
 
-    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let idempotency_key = idempotency_key
+        .to_str()
+        .map_err(|_| AuthAPIError::InvalidIdempotencyKey)
+        .and_then(|key| IdempotencyKey::parse(key).map_err(|_| AuthAPIError::InvalidIdempotencyKey))?;
 
-    let user = User::new(email, password, request.requires_2fa);
+    // The password stays out of it, it would be kept in the store for the whole TTL
+    let fingerprint = URL_SAFE_NO_PAD.encode(Sha256::digest(format!("signup\n{}\n{}", request.email, request.requires_2fa)));
 
-    let mut user_store = state.user_store.write().await;
+    let idempotency_store = &state.idempotency_store;
 
-    if user_store.get_user(&user.email).await.is_ok() {
-        return Err(AuthAPIError::UserAlreadyExists);
+    match idempotency_store.start(&idempotency_key, &fingerprint).await {
+        Ok(IdempotencyStatus::Started) => (),
+        Ok(IdempotencyStatus::InProgress) => return Err(AuthAPIError::RequestInProgress),
+        Ok(IdempotencyStatus::Completed(saved)) => return Ok(replay(saved)),
+        Err(IdempotencyStoreError::KeyMismatch) => return Err(AuthAPIError::IdempotencyKeyReused),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
     }
 
-    if let Err(e) = user_store.add_user(user).await { //At this point all errors are unexpected
-        return Err(AuthAPIError::UnexpectedError(e.into()));
+    let response = create_user(&state, request).await.into_response();
+
+    // Server errors are worth retrying, everything else is the final answer for this key
+    if response.status().is_server_error() {
+        if let Err(e) = idempotency_store.release(&idempotency_key).await {
+            tracing::warn!(error = %e, "Could not release idempotency key");
+        }
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    let saved = SavedResponse {
+        status: parts.status.as_u16(),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+
+    idempotency_store
+        .complete(&idempotency_key, saved)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+#[tracing::instrument(name = "CreateUser", skip_all)]
+async fn create_user(state: &AppState, request: SignupRequest) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let password = state.password_policy
+        .parse(request.password, &email)
+        .await
+        .map_err(AuthAPIError::WeakPassword)?;
+
+    let user = User::new(email.clone(), password, request.requires_2fa, state.clock.now());
+
+    // The unique email makes the insert fail for a taken one, there is no check before it to race with
+    match state.user_store.add_user(user).await {
+        Ok(()) => (),
+        // Always answers 201, only the owner of a taken email hears about it
+        Err(UserStoreError::UserAlreadyExists) if state.auth_settings.enumeration_protection.enabled => {
+            notify_existing_owner(state, email)
+        }
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::Overloaded) => return Err(AuthAPIError::ServiceUnavailable),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
     }
 
     let response = Json(SignupResponse {
@@ -31,6 +104,33 @@ pub async fn signup(State(state): State<AppState>, Json(request): Json<SignupReq
     Ok((StatusCode::CREATED, response))
 }
 
+fn replay(saved: SavedResponse) -> Response {
+    let status = StatusCode::from_u16(saved.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, [(header::CONTENT_TYPE, "application/json")], saved.body).into_response()
+}
+
+// Sent in the background, waiting on the email provider would make this answer slower than a real signup
+fn notify_existing_owner(state: &AppState, email: Email) {
+    let email_client = state.email_client.clone();
+
+    tokio::spawn(async move {
+        let content = Secret::new(
+            "Someone tried to create an account with this email, but you already have one. \
+            You can log in with your password or ask for a login link. If it was not you, you can ignore this email."
+                .to_owned(),
+        );
+
+        if let Err(e) = email_client
+            .read()
+            .await
+            .send_email(&email, "Sign up attempt with your email", &content)
+            .await
+        {
+            tracing::warn!(error = %e, "Could not notify the owner of an existing account");
+        }
+    });
+}
+
 #[derive(Deserialize)]
 pub struct SignupRequest {
     pub email: String,
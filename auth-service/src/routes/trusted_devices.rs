@@ -0,0 +1,92 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        data_stores::{TrustedDevice, TrustedDeviceId, TrustedDeviceStoreError},
+        AuthAPIError,
+    },
+    utils::{auth::get_authenticated_user_id, trusted_device::validate_trusted_device_token},
+};
+
+#[tracing::instrument(name = "ListTrustedDevices", skip_all)]
+pub async fn list_trusted_devices(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = get_authenticated_user_id(&jar, state.banned_token_store.clone(), &state.auth_settings.http, state.clock.as_ref()).await?;
+
+    let devices = state.trusted_device_store
+        .get_devices(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // Flags the browser making the request, so the user knows which entry they are on
+    let current_device_id = jar
+        .get(&state.auth_settings.trusted_device.cookie_name)
+        .and_then(|cookie| validate_trusted_device_token(cookie.value(), state.auth_settings.http.jwt_token.clone(), state.clock.as_ref()).ok())
+        .and_then(|claims| claims.device_id().ok());
+
+    let now = state.clock.now();
+    let devices: Vec<TrustedDeviceResponse> = devices
+        .into_iter()
+        .filter(|device| !device.is_expired(now))
+        .map(|device| TrustedDeviceResponse::new(device, current_device_id))
+        .collect();
+
+    Ok(Json(devices))
+}
+
+#[tracing::instrument(name = "RevokeTrustedDevice", skip_all)]
+pub async fn revoke_trusted_device(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = get_authenticated_user_id(&jar, state.banned_token_store.clone(), &state.auth_settings.http, state.clock.as_ref()).await?;
+
+    let device_id = TrustedDeviceId::parse(&device_id).map_err(|_| AuthAPIError::TrustedDeviceNotFound)?;
+
+    state.trusted_device_store
+        .revoke_device(&user_id, &device_id)
+        .await
+        .map_err(|e| match e {
+            TrustedDeviceStoreError::DeviceNotFound => AuthAPIError::TrustedDeviceNotFound,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct TrustedDeviceResponse {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: String,
+    pub current: bool,
+}
+
+impl TrustedDeviceResponse {
+    fn new(device: TrustedDevice, current_device_id: Option<TrustedDeviceId>) -> Self {
+        TrustedDeviceResponse {
+            id: device.id.to_string(),
+            name: device.name,
+            created_at: device.created_at.to_rfc3339(),
+            last_used_at: device.last_used_at.to_rfc3339(),
+            expires_at: device.expires_at.to_rfc3339(),
+            current: current_device_id == Some(device.id),
+        }
+    }
+}
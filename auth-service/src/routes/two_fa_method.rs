@@ -0,0 +1,34 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, AuthAPIError, TwoFAMethod},
+    utils::step_up::RecentlyAuthenticated,
+};
+
+// Switching how codes are delivered is as sensitive as changing the phone number
+const MAX_AUTH_AGE_SECONDS: i64 = 10 * 60;
+
+#[tracing::instrument(name = "SetTwoFAMethod", skip_all)]
+pub async fn set_two_fa_method(
+    State(state): State<AppState>,
+    RecentlyAuthenticated { user_id, .. }: RecentlyAuthenticated<MAX_AUTH_AGE_SECONDS>,
+    Json(request): Json<SetTwoFAMethodRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    state.user_store
+        .set_two_fa_method(&user_id, request.method)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+            UserStoreError::PhoneNumberNotVerified => AuthAPIError::PhoneNumberNotVerified,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct SetTwoFAMethodRequest {
+    pub method: TwoFAMethod,
+}
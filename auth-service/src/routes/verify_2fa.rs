@@ -1,19 +1,30 @@
-use axum::extract::State;
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header::USER_AGENT, HeaderMap};
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use secrecy::Secret;
 use serde::Deserialize;
 
 use crate::app_state::AppState;
-use crate::domain::data_stores::two_fa_code_store::{LoginAttemptId, TwoFACode};
+use crate::domain::data_stores::two_fa_code_store::{LoginAttemptId, TwoFACode, TwoFACodeStoreError};
+use crate::domain::data_stores::TrustedDevice;
 use crate::domain::{AuthAPIError, Email};
-use crate::utils::auth::generate_auth_cookie;
+use crate::utils::auth::{generate_auth_cookie, AuthMethod};
+use crate::utils::trusted_device::generate_trusted_device_cookie;
 use crate::utils::HttpSettings;
+use crate::routes::{alert_on_new_device, ensure_account_active, handle_password_expired};
+
+// Shown when the user lists their trusted devices, browsers sending no User-Agent get this
+const UNKNOWN_DEVICE_NAME: &str = "Unknown device";
 
 #[tracing::instrument(name = "Verify2FA", skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>,
     jar: CookieJar,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(request): Json<Verify2FARequest>,
 ) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
     let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidCredentials)?;
@@ -22,28 +33,69 @@ pub async fn verify_2fa(
 
     let two_fa_code = TwoFACode::parse(request.two_fa_code).map_err(|_| AuthAPIError::InvalidCredentials)?; 
 
-    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+    let two_fa_code_store = &state.two_fa_code_store;
 
+    // Taken before anything else, so concurrent requests with the same code get one session at most.
+    // A wrong code uses the attempt up too, the user logs in again for a new one.
     let code_tuple = 
-        two_fa_code_store.get_code(&email)
+        two_fa_code_store.take_code(&login_attempt_id)
             .await
-            .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+            .map_err(|e| match e {
+                TwoFACodeStoreError::LoginAttemptIdNotFound => AuthAPIError::IncorrectCredentials,
+                e => AuthAPIError::UnexpectedError(e.into()),
+            })?;
 
-    if code_tuple != (login_attempt_id, two_fa_code) {
+    if code_tuple != (email.clone(), two_fa_code) {
         return Err(AuthAPIError::IncorrectCredentials.into());
     } 
 
-    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = state.auth_settings.http;
+    let user = state.user_store
+        .get_user(&email)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    // The account may have been disabled since the code was sent
+    ensure_account_active(&user, state.clock.now())?;
+
+    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = state.auth_settings.http.clone();
 
     let token_ttl_millis = state.auth_settings.redis.ttl_millis;
 
+    // The first factor may have been the password or a magic link, the code is what was checked here
+    let amr = [AuthMethod::OneTimeCode, AuthMethod::MultiFactor];
+
+    // Only the password can be changed, no device is remembered for a password that is no longer good
+    if user.password_expired(state.clock.now()) {
+        let (updated_jar, response) = handle_password_expired(&user.id, &state, jar)?;
+        return Ok((updated_jar, response.into_response()));
+    }
+
     let auth_cookie = 
-        generate_auth_cookie(&email, jwt_token, jwt_cookie_name, token_ttl_millis)
+        generate_auth_cookie(&user.id, &amr, jwt_token.clone(), jwt_cookie_name, token_ttl_millis, state.clock.as_ref())
             .map_err(|e| AuthAPIError::UnexpectedError(e))?;
+    let mut updated_jar = jar.add(auth_cookie);
+
+    if request.remember_device {
+        let device_name = headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or(UNKNOWN_DEVICE_NAME)
+            .to_owned();
+        let ttl = chrono::Duration::milliseconds(state.auth_settings.trusted_device.ttl_millis);
+        let device = TrustedDevice::new(user.id, device_name, state.clock.now(), ttl);
+
+        let device_cookie = generate_trusted_device_cookie(&device, jwt_token, state.auth_settings.trusted_device.cookie_name.clone())
+            .map_err(AuthAPIError::UnexpectedError)?;
+
+        state.trusted_device_store
+            .add_device(device)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
 
-    let updated_jar = jar.add(auth_cookie);
+        updated_jar = updated_jar.add(device_cookie);
+    }
 
-    let _ = two_fa_code_store.remove_code(&email).await;
+    alert_on_new_device(&user, peer, &headers, &state).await;
 
     Ok((updated_jar, StatusCode::OK.into_response()))
 }
@@ -55,4 +107,7 @@ pub struct Verify2FARequest {
     pub login_attempt_id: Secret<String>,
     #[serde(rename = "2FACode")]
     pub two_fa_code: Secret<String>,
+    // Sets a trusted device cookie so the next logins from this browser skip 2FA
+    #[serde(default, rename = "rememberDevice")]
+    pub remember_device: bool,
 }
\ No newline at end of file
@@ -1,11 +1,12 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use secrecy::Secret;
 use serde::Deserialize;
 
 use crate::{
-    app_state::{AppState, BannedTokenStoreType}, 
+    app_state::{AppState, BannedTokenStoreType, UserStoreType}, 
     auth::verify_token_response::VerifyTokenStatus, 
+    domain::{Clock, UserId},
     utils::auth::{validate_token, Claims}
 };
 
@@ -32,18 +33,39 @@ impl VerifyTokenSummary {
         }
     }
 }
+
+// Other services trust a valid token, so it stops being one once its account is disabled or deleted
+async fn validate_token_of_active_user(
+    user_store: UserStoreType,
+    banned_token_store: BannedTokenStoreType,
+    token: &Secret<String>,
+    jwt_token: Secret<String>,
+    clock: &dyn Clock,
+) -> Result<Claims> {
+    let claims = validate_token(banned_token_store, token, jwt_token, clock).await?;
+
+    let user_id = UserId::parse(&claims.sub)?;
+    let user = user_store.get_user_by_id(&user_id).await?;
+    if !user.status.is_active(clock.now()) {
+        return Err(eyre!("account is {}", user.status.as_str()));
+    }
+
+    Ok(claims)
+}
+
 #[tracing::instrument(name = "VerifyTokenHtml", skip_all)]
 pub async fn verify_token_html(State(state): State<AppState>, Json(request): Json<VerifyTokenRequest>) -> impl IntoResponse {
     let jwt_token = state.auth_settings.http.jwt_token;
-    match VerifyTokenSummary::new(validate_token(state.banned_token_store, &request.token, jwt_token).await) {
+    let validation = validate_token_of_active_user(state.user_store, state.banned_token_store, &request.token, jwt_token, state.clock.as_ref()).await;
+    match VerifyTokenSummary::new(validation) {
         VerifyTokenSummary::Valid => StatusCode::OK.into_response(),
         VerifyTokenSummary::Invalid => StatusCode::UNAUTHORIZED.into_response(),
     }
 }
 
 #[tracing::instrument(name = "VerifyTokenGrpc", skip_all)]
-pub async fn verify_token_grpc(banned_token_store: BannedTokenStoreType, token:Secret<String>, jwt_token: Secret<String>) -> VerifyTokenStatus {
-    match VerifyTokenSummary::new(validate_token(banned_token_store, &token, jwt_token).await) {
+pub async fn verify_token_grpc(user_store: UserStoreType, banned_token_store: BannedTokenStoreType, token:Secret<String>, jwt_token: Secret<String>, clock: &dyn Clock) -> VerifyTokenStatus {
+    match VerifyTokenSummary::new(validate_token_of_active_user(user_store, banned_token_store, &token, jwt_token, clock).await) {
         VerifyTokenSummary::Valid => VerifyTokenStatus::Valid,
         VerifyTokenSummary::Invalid => VerifyTokenStatus::Invalid,
     }
@@ -0,0 +1,172 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::RwLock;
+
+use crate::domain::{Email, EmailClient};
+
+// Oldest messages are dropped once the mailbox holds this many
+const MAILBOX_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct CapturedEmail {
+    pub recipient: Email,
+    pub subject: String,
+    pub content: Secret<String>,
+    pub sent_at: DateTime<Utc>,
+}
+
+// In-memory record of every email sent through a CapturingEmailClient.
+// Only meant for local development and tests, it holds codes in plain text!
+#[derive(Clone, Default)]
+pub struct Mailbox {
+    messages: Arc<RwLock<VecDeque<CapturedEmail>>>,
+}
+
+impl Mailbox {
+    async fn push(&self, email: CapturedEmail) {
+        let mut messages = self.messages.write().await;
+        if messages.len() == MAILBOX_CAPACITY {
+            messages.pop_front();
+        }
+        messages.push_back(email);
+    }
+
+    // Newest first
+    pub async fn messages(&self) -> Vec<CapturedEmail> {
+        self.messages.read().await.iter().rev().cloned().collect()
+    }
+
+    pub async fn messages_for(&self, recipient: &Email) -> Vec<CapturedEmail> {
+        self.messages
+            .read()
+            .await
+            .iter()
+            .rev()
+            .filter(|email| email.recipient == *recipient)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn last_message_for(&self, recipient: &Email) -> Option<CapturedEmail> {
+        self.messages
+            .read()
+            .await
+            .iter()
+            .rev()
+            .find(|email| email.recipient == *recipient)
+            .cloned()
+    }
+}
+
+// Decorates any email client so every successfully sent email also lands in a Mailbox
+pub struct CapturingEmailClient<C> {
+    inner: C,
+    mailbox: Mailbox,
+}
+
+impl<C> CapturingEmailClient<C> {
+    pub fn new(inner: C, mailbox: Mailbox) -> Self {
+        Self { inner, mailbox }
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: EmailClient + Send + Sync> EmailClient for CapturingEmailClient<C> {
+    #[tracing::instrument(name = "Capturing email", skip_all)]
+    async fn send_email(&self, recipient: &Email, subject: &str, content: &Secret<String>) -> Result<()> {
+        self.inner.send_email(recipient, subject, content).await?;
+
+        self.mailbox
+            .push(CapturedEmail {
+                recipient: recipient.clone(),
+                subject: subject.to_owned(),
+                content: Secret::new(content.expose_secret().to_owned()),
+                sent_at: Utc::now(),
+            })
+            .await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre::eyre;
+
+    use crate::services::data_stores::mock_email_client::MockEmailClient;
+
+    use super::*;
+
+    struct FailingEmailClient;
+
+    #[async_trait::async_trait]
+    impl EmailClient for FailingEmailClient {
+        async fn send_email(&self, _: &Email, _: &str, _: &Secret<String>) -> Result<()> {
+            Err(eyre!("Email provider is down"))
+        }
+    }
+
+    fn email(raw: &str) -> Email {
+        Email::parse(Secret::new(raw.to_owned())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_captures_sent_email() {
+        let mailbox = Mailbox::default();
+        let email_client = CapturingEmailClient::new(MockEmailClient, mailbox.clone());
+        let recipient = email("guillem@letsgetrusty.com");
+
+        let outcome = email_client.send_email(&recipient, "Subject", &Secret::new("123456".to_owned())).await;
+
+        assert!(outcome.is_ok());
+        let captured = mailbox.last_message_for(&recipient).await.expect("Email was not captured");
+        assert_eq!(captured.subject, "Subject");
+        assert_eq!(captured.content.expose_secret(), "123456");
+    }
+
+    #[tokio::test]
+    async fn test_last_message_is_the_newest_for_the_recipient() {
+        let mailbox = Mailbox::default();
+        let email_client = CapturingEmailClient::new(MockEmailClient, mailbox.clone());
+        let recipient = email("guillem@letsgetrusty.com");
+        let other_recipient = email("other@letsgetrusty.com");
+
+        let _ = email_client.send_email(&recipient, "First", &Secret::new("111111".to_owned())).await;
+        let _ = email_client.send_email(&recipient, "Second", &Secret::new("222222".to_owned())).await;
+        let _ = email_client.send_email(&other_recipient, "Third", &Secret::new("333333".to_owned())).await;
+
+        assert_eq!(mailbox.last_message_for(&recipient).await.unwrap().subject, "Second");
+        assert_eq!(mailbox.messages_for(&recipient).await.len(), 2);
+        assert_eq!(mailbox.messages().await.first().unwrap().subject, "Third");
+    }
+
+    #[tokio::test]
+    async fn test_failed_email_is_not_captured() {
+        let mailbox = Mailbox::default();
+        let email_client = CapturingEmailClient::new(FailingEmailClient, mailbox.clone());
+        let recipient = email("guillem@letsgetrusty.com");
+
+        let outcome = email_client.send_email(&recipient, "Subject", &Secret::new("123456".to_owned())).await;
+
+        assert!(outcome.is_err());
+        assert!(mailbox.last_message_for(&recipient).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mailbox_drops_oldest_message_when_full() {
+        let mailbox = Mailbox::default();
+        let email_client = CapturingEmailClient::new(MockEmailClient, mailbox.clone());
+        let recipient = email("guillem@letsgetrusty.com");
+
+        for i in 0..=MAILBOX_CAPACITY {
+            let _ = email_client.send_email(&recipient, &i.to_string(), &Secret::new("123456".to_owned())).await;
+        }
+
+        let messages = mailbox.messages().await;
+        assert_eq!(messages.len(), MAILBOX_CAPACITY);
+        assert_eq!(messages.last().unwrap().subject, "1");
+    }
+}
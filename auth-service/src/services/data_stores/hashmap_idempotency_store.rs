@@ -0,0 +1,70 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use color_eyre::eyre::eyre;
+use tokio::sync::RwLock;
+
+use crate::domain::data_stores::idempotency_store::{
+    IdempotencyKey, IdempotencyRecord, IdempotencyStatus, IdempotencyStore, IdempotencyStoreError, SavedResponse,
+};
+
+#[derive(Default)]
+pub struct HashmapIdempotencyStore {
+    records: RwLock<HashMap<IdempotencyKey, IdempotencyRecord>>,
+}
+
+#[async_trait::async_trait]
+impl IdempotencyStore for HashmapIdempotencyStore {
+    async fn start(&self, key: &IdempotencyKey, fingerprint: &str) -> Result<IdempotencyStatus, IdempotencyStoreError> {
+        match self.records.write().await.entry(key.clone()) {
+            Entry::Occupied(entry) => entry.get().clone().status(fingerprint),
+            Entry::Vacant(entry) => {
+                entry.insert(IdempotencyRecord { fingerprint: fingerprint.to_owned(), response: None });
+                Ok(IdempotencyStatus::Started)
+            }
+        }
+    }
+
+    async fn complete(&self, key: &IdempotencyKey, response: SavedResponse) -> Result<(), IdempotencyStoreError> {
+        self.records.write().await.get_mut(key)
+            .ok_or(IdempotencyStoreError::UnexpectedError(eyre!("Idempotency key was not started")))
+            .map(|record| record.response = Some(response))
+    }
+
+    async fn release(&self, key: &IdempotencyKey) -> Result<(), IdempotencyStoreError> {
+        self.records.write().await.remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_start_claims_the_key_once() {
+        let idempotency_store = HashmapIdempotencyStore::default();
+        let key = IdempotencyKey::parse("key").unwrap();
+        assert_eq!(idempotency_store.start(&key, "signup").await, Ok(IdempotencyStatus::Started));
+        assert_eq!(idempotency_store.start(&key, "signup").await, Ok(IdempotencyStatus::InProgress));
+        assert_eq!(idempotency_store.start(&key, "other").await, Err(IdempotencyStoreError::KeyMismatch));
+    }
+
+    #[tokio::test]
+    async fn test_completed_key_returns_the_response() {
+        let idempotency_store = HashmapIdempotencyStore::default();
+        let key = IdempotencyKey::parse("key").unwrap();
+        let response = SavedResponse { status: 201, body: "{}".to_owned() };
+        let _ = idempotency_store.start(&key, "signup").await;
+        assert!(idempotency_store.complete(&key, response.clone()).await.is_ok());
+        assert_eq!(idempotency_store.start(&key, "signup").await, Ok(IdempotencyStatus::Completed(response)));
+    }
+
+    #[tokio::test]
+    async fn test_released_key_can_be_started_again() {
+        let idempotency_store = HashmapIdempotencyStore::default();
+        let key = IdempotencyKey::parse("key").unwrap();
+        let _ = idempotency_store.start(&key, "signup").await;
+        assert!(idempotency_store.release(&key).await.is_ok());
+        assert_eq!(idempotency_store.start(&key, "signup").await, Ok(IdempotencyStatus::Started));
+    }
+}
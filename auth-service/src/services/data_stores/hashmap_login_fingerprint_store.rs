@@ -0,0 +1,37 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{FingerprintStatus, LoginFingerprint, LoginFingerprintStore, LoginFingerprintStoreError},
+    UserId,
+};
+
+#[derive(Debug, Default)]
+pub struct HashmapLoginFingerprintStore {
+    fingerprints: RwLock<HashMap<UserId, HashSet<String>>>,
+}
+
+#[async_trait::async_trait]
+impl LoginFingerprintStore for HashmapLoginFingerprintStore {
+    async fn record_fingerprint(
+        &self,
+        user_id: &UserId,
+        fingerprint: &LoginFingerprint,
+        _seen_at: DateTime<Utc>,
+    ) -> Result<FingerprintStatus, LoginFingerprintStoreError> {
+        let mut fingerprints = self.fingerprints.write().await;
+        let seen = fingerprints.entry(*user_id).or_default();
+
+        let first_login = seen.is_empty();
+        if !seen.insert(fingerprint.hash()) {
+            return Ok(FingerprintStatus::Known);
+        }
+
+        Ok(match first_login {
+            true => FingerprintStatus::FirstLogin,
+            false => FingerprintStatus::New,
+        })
+    }
+}
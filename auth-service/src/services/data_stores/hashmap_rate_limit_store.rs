@@ -0,0 +1,104 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::domain::{
+    data_stores::{RateLimitDecision, RateLimitStore, RateLimitStoreError, TokenBucket},
+    Clock, SystemClock,
+};
+
+// A full bucket is the same as no bucket. Requests overwrite their own, the others are swept once this
+// many new buckets were added, so the map never holds much more than the buckets still filling up.
+const SWEEP_EVERY_NEW_BUCKETS: usize = 1024;
+
+// Only limits the instance it runs in, see RedisRateLimitStore for several of them
+pub struct HashmapRateLimitStore {
+    buckets: Mutex<Buckets>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    // When each bucket is full again
+    full_at: HashMap<String, DateTime<Utc>>,
+    new_since_sweep: usize,
+}
+
+impl Buckets {
+    fn insert(&mut self, key: &str, full_at: DateTime<Utc>, now: DateTime<Utc>) {
+        if self.full_at.insert(key.to_owned(), full_at).is_some() {
+            return;
+        }
+
+        self.new_since_sweep += 1;
+        if self.new_since_sweep >= SWEEP_EVERY_NEW_BUCKETS {
+            self.full_at.retain(|_, full_at| *full_at > now);
+            self.new_since_sweep = 0;
+        }
+    }
+}
+
+impl HashmapRateLimitStore {
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+}
+
+impl Default for HashmapRateLimitStore {
+    fn default() -> Self {
+        Self { buckets: Mutex::default(), clock: Arc::new(SystemClock) }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for HashmapRateLimitStore {
+    async fn take_token(&self, key: &str, bucket: &TokenBucket) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let now = self.clock.now();
+        let mut buckets = self.buckets.lock().await;
+
+        match bucket.take(buckets.full_at.get(key).copied(), now) {
+            Ok(full_at) => {
+                buckets.insert(key, full_at, now);
+                Ok(RateLimitDecision::Allowed)
+            }
+            Err(retry_after) => Ok(RateLimitDecision::Limited { retry_after }),
+        }
+    }
+
+    async fn tokens_taken(&self, key: &str, bucket: &TokenBucket) -> Result<u32, RateLimitStoreError> {
+        let buckets = self.buckets.lock().await;
+        Ok(bucket.taken(buckets.full_at.get(key).copied(), self.clock.now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::domain::ManualClock;
+
+    const BUCKET: TokenBucket = TokenBucket { capacity: 3, refill_millis: 1000 };
+
+    #[tokio::test]
+    async fn test_full_buckets_are_swept_as_new_ones_come() {
+        let clock = Arc::new(ManualClock::default());
+        let store = HashmapRateLimitStore::default().with_clock(clock.clone());
+
+        let _ = store.take_token("refilled", &BUCKET).await;
+        clock.advance(Duration::seconds(1));
+
+        // The last new bucket before the sweep is taken below
+        for i in 2..SWEEP_EVERY_NEW_BUCKETS {
+            let _ = store.take_token(&i.to_string(), &BUCKET).await;
+            assert!(store.buckets.lock().await.full_at.contains_key("refilled"));
+        }
+
+        let _ = store.take_token("last", &BUCKET).await;
+
+        let buckets = store.buckets.lock().await;
+        assert!(!buckets.full_at.contains_key("refilled"));
+        assert!(buckets.full_at.contains_key("last"));
+    }
+}
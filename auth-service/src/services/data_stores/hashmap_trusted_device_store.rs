@@ -0,0 +1,56 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{TrustedDevice, TrustedDeviceId, TrustedDeviceStore, TrustedDeviceStoreError},
+    UserId,
+};
+
+#[derive(Debug, Default)]
+pub struct HashmapTrustedDeviceStore {
+    devices: RwLock<HashMap<TrustedDeviceId, TrustedDevice>>,
+}
+
+#[async_trait::async_trait]
+impl TrustedDeviceStore for HashmapTrustedDeviceStore {
+    async fn add_device(&self, device: TrustedDevice) -> Result<(), TrustedDeviceStoreError> {
+        let mut devices = self.devices.write().await;
+        devices.retain(|_, stored| stored.user_id != device.user_id || !stored.is_expired(device.created_at));
+        devices.insert(device.id, device);
+        Ok(())
+    }
+
+    async fn get_device(&self, id: &TrustedDeviceId) -> Result<TrustedDevice, TrustedDeviceStoreError> {
+        self.devices.read().await.get(id).cloned().ok_or(TrustedDeviceStoreError::DeviceNotFound)
+    }
+
+    async fn get_devices(&self, user_id: &UserId) -> Result<Vec<TrustedDevice>, TrustedDeviceStoreError> {
+        let mut devices: Vec<TrustedDevice> = self.devices.read().await
+            .values()
+            .filter(|device| device.user_id == *user_id)
+            .cloned()
+            .collect();
+        devices.sort_by_key(|device| Reverse(device.created_at));
+        Ok(devices)
+    }
+
+    async fn mark_used(&self, id: &TrustedDeviceId, used_at: DateTime<Utc>) -> Result<(), TrustedDeviceStoreError> {
+        let mut devices = self.devices.write().await;
+        let device = devices.get_mut(id).ok_or(TrustedDeviceStoreError::DeviceNotFound)?;
+        device.last_used_at = used_at;
+        Ok(())
+    }
+
+    async fn revoke_device(&self, user_id: &UserId, id: &TrustedDeviceId) -> Result<(), TrustedDeviceStoreError> {
+        let mut devices = self.devices.write().await;
+        match devices.get(id) {
+            Some(device) if device.user_id == *user_id => {
+                devices.remove(id);
+                Ok(())
+            }
+            _ => Err(TrustedDeviceStoreError::DeviceNotFound),
+        }
+    }
+}
@@ -1,42 +1,139 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use color_eyre::eyre::eyre;
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::RwLock;
 
 use crate::domain::{
-    data_stores::two_fa_code_store::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    data_stores::two_fa_code_store::{
+        LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, DEFAULT_MAX_ATTEMPTS_PER_USER,
+        TWO_FA_CODE_TTL_SECONDS,
+    },
     email::Email,
+    Clock, PhoneNumber, SystemClock,
 };
 
-#[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: RwLock<HashMap<LoginAttemptId, StoredCode>>,
+    max_attempts_per_user: usize,
+    clock: Arc<dyn Clock>,
+}
+
+struct StoredCode {
+    email: Email,
+    // Only set for phone number verifications
+    phone_number: Option<PhoneNumber>,
+    code: TwoFACode,
+    expires_at: DateTime<Utc>,
+}
+
+impl Default for HashmapTwoFACodeStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ATTEMPTS_PER_USER)
+    }
+}
+
+impl HashmapTwoFACodeStore {
+    pub fn new(max_attempts_per_user: usize) -> Self {
+        Self {
+            codes: RwLock::default(),
+            max_attempts_per_user: max_attempts_per_user.max(1),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
         login_attempt_id: LoginAttemptId,
+        email: Email,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>{
-        let _ = self.codes.insert(email, (login_attempt_id, code));
+        let now = self.clock.now();
+        let mut codes = self.codes.write().await;
+        // Nothing else drops expired codes
+        codes.retain(|_, stored| stored.expires_at > now);
+
+        // Every code lives as long, so the one expiring first is the oldest
+        let mut attempts: Vec<_> = codes.iter()
+            .filter(|(_, stored)| stored.email == email && stored.phone_number.is_none())
+            .map(|(id, stored)| (stored.expires_at, id.clone()))
+            .collect();
+        attempts.sort_by_key(|(expires_at, _)| *expires_at);
+        let excess = (attempts.len() + 1).saturating_sub(self.max_attempts_per_user);
+        for (_, id) in attempts.into_iter().take(excess) {
+            codes.remove(&id);
+        }
+
+        let expires_at = now + Duration::seconds(TWO_FA_CODE_TTL_SECONDS);
+        codes.insert(login_attempt_id, StoredCode { email, phone_number: None, code, expires_at });
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        self.codes.remove(email)
-            .ok_or(TwoFACodeStoreError::UnexpectedError(eyre!("Could not remove the code from the store")))
-            .map(|_| ())
+    async fn remove_code(&self, login_attempt_id: &LoginAttemptId) -> Result<(), TwoFACodeStoreError> {
+        self.codes.write().await.remove(login_attempt_id);
+        Ok(())
     }
 
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        self.codes.get(email)
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError> {
+        let now = self.clock.now();
+        self.codes.read().await.get(login_attempt_id)
+            .filter(|stored| stored.expires_at > now && stored.phone_number.is_none())
+            .map(|stored| (stored.email.clone(), stored.code.clone()))
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    }
+
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError> {
+        let now = self.clock.now();
+        let mut codes = self.codes.write().await;
+        match codes.get(login_attempt_id) {
+            Some(stored) if stored.expires_at > now && stored.phone_number.is_none() => (),
+            _ => return Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+        codes.remove(login_attempt_id)
+            .map(|stored| (stored.email, stored.code))
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    }
+
+    async fn add_phone_number_code(
+        &self,
+        verification_id: LoginAttemptId,
+        email: Email,
+        phone_number: PhoneNumber,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let now = self.clock.now();
+        let mut codes = self.codes.write().await;
+        codes.retain(|_, stored| stored.expires_at > now);
+
+        let expires_at = now + Duration::seconds(TWO_FA_CODE_TTL_SECONDS);
+        codes.insert(verification_id, StoredCode { email, phone_number: Some(phone_number), code, expires_at });
+        Ok(())
+    }
+
+    async fn get_phone_number_code(
+        &self,
+        verification_id: &LoginAttemptId,
+    ) -> Result<(Email, PhoneNumber, TwoFACode), TwoFACodeStoreError> {
+        let now = self.clock.now();
+        self.codes.read().await.get(verification_id)
+            .filter(|stored| stored.expires_at > now)
+            .and_then(|stored| {
+                let phone_number = stored.phone_number.clone()?;
+                Some((stored.email.clone(), phone_number, stored.code.clone()))
+            })
             .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
-            .map(|reference| reference.clone())
     }
 }
 
@@ -46,41 +143,44 @@ mod tests {
 
     use super::*;
 
+    fn email() -> Email {
+        Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap()
+    }
+
     #[tokio::test]
     async fn test_add_code() {
-        let mut two_fa_code_store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
+        let two_fa_code_store = HashmapTwoFACodeStore::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
-        assert!(two_fa_code_store.add_code(email, login_attempt_id, code).await.is_ok());
+        assert!(two_fa_code_store.add_code(login_attempt_id, email(), code).await.is_ok());
     }
 
     #[tokio::test]
     async fn test_get_existing_code() {
-        let mut two_fa_code_store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
+        let two_fa_code_store = HashmapTwoFACodeStore::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
-        let _ = two_fa_code_store.add_code(email.clone(), login_attempt_id.clone(), code.clone()).await;
-        assert_eq!(two_fa_code_store.get_code(&email).await, Ok((login_attempt_id, code)))
+        let _ = two_fa_code_store.add_code(login_attempt_id.clone(), email(), code.clone()).await;
+        assert_eq!(two_fa_code_store.get_code(&login_attempt_id).await, Ok((email(), code)))
     }
 
 
     #[tokio::test]
     async fn test_get_non_existing_code() {
         let two_fa_code_store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
-        assert_eq!(two_fa_code_store.get_code(&email).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound))
+        assert_eq!(
+            two_fa_code_store.get_code(&LoginAttemptId::default()).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        )
     }
 
     #[tokio::test]
     async fn test_remove_code() {
-        let mut two_fa_code_store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
+        let two_fa_code_store = HashmapTwoFACodeStore::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
-        let _ = two_fa_code_store.add_code(email.clone(), login_attempt_id, code).await;
-        assert!(two_fa_code_store.remove_code(&email).await.is_ok());
-        assert_eq!(two_fa_code_store.get_code(&email).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound))
+        let _ = two_fa_code_store.add_code(login_attempt_id.clone(), email(), code).await;
+        assert!(two_fa_code_store.remove_code(&login_attempt_id).await.is_ok());
+        assert_eq!(two_fa_code_store.get_code(&login_attempt_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound))
     }
-}
\ No newline at end of file
+}
@@ -1,44 +1,67 @@
 use std::collections::HashMap;
 
-use crate::domain::{data_stores::user_store::{UserStore, UserStoreError}, email::Email, password::Password, User, UserHashed};
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::RwLock;
+
+use crate::domain::{data_stores::user_store::{UserStore, UserStoreError}, email::Email, AccountStatus, password::Password, PhoneNumber, TwoFAMethod, User, UserHashed, UserId};
 
 #[derive(Debug, Default)]
 pub struct HashmapUserStore {
-    users: HashMap<Email, User>,
+    users: RwLock<HashMap<UserId, User>>,
+    // Passwords a user had before the current one, newest first
+    password_history: RwLock<HashMap<UserId, Vec<Password>>>,
+}
+
+fn find_by_email<'a>(users: &'a HashMap<UserId, User>, email: &Email) -> Option<&'a User> {
+    users.values().find(|user| user.email == *email)
+}
+
+//Here we don't hash the password, therefore password is equal to password_hash
+impl From<&User> for UserHashed {
+    fn from(user: &User) -> Self {
+        UserHashed {
+            id: user.id,
+            email: user.email.clone(),
+            password_hash: user.password.clone(),
+            requires_2fa: user.requires_2fa,
+            phone_number: user.phone_number.clone(),
+            phone_number_verified: user.phone_number_verified,
+            two_fa_method: user.two_fa_method,
+            password_changed_at: user.password_changed_at,
+            password_max_age: user.password_max_age,
+            status: user.status,
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for HashmapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        if self.users.contains_key(&user.email) {
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        if users.contains_key(&user.id) || find_by_email(&users, &user.email).is_some() {
             Err(UserStoreError::UserAlreadyExists)
         } else {
-            self.users.insert(user.email.clone(), user);
+            users.insert(user.id, user);
             Ok(())
         }
     }
 
-    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
-        if self.users.contains_key(email) {
-            self.users.remove(email);
-            Ok(())
-        } else {
-            Err(UserStoreError::UserNotFound)
+    async fn delete_user(&self, id: &UserId) -> Result<(), UserStoreError> {
+        match self.users.write().await.remove(id) {
+            Some(_) => {
+                self.password_history.write().await.remove(id);
+                Ok(())
+            },
+            None => Err(UserStoreError::UserNotFound),
         }
     }
 
-    //Here we don't hash the password, therefore password is equal to password_hash
     async fn get_user(&self, email: &Email) -> Result<UserHashed, UserStoreError> {
-        match self.users.get(email) {
-            Some(User { email, password, requires_2fa}) => 
-                Ok(
-                    UserHashed { 
-                        email: email.clone(),
-                        password_hash: password.clone(), 
-                        requires_2fa: requires_2fa.clone() 
-                }),
-            None => Err(UserStoreError::UserNotFound),
-        }
+        find_by_email(&*self.users.read().await, email).map(UserHashed::from).ok_or(UserStoreError::UserNotFound)
+    }
+
+    async fn get_user_by_id(&self, id: &UserId) -> Result<UserHashed, UserStoreError> {
+        self.users.read().await.get(id).map(UserHashed::from).ok_or(UserStoreError::UserNotFound)
     }
 
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
@@ -48,6 +71,92 @@ impl UserStore for HashmapUserStore {
             Err(error) => Err(error), //we return the same UserNotFound error required
         }
     }
+
+    async fn set_phone_number(&self, id: &UserId, phone_number: PhoneNumber) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.phone_number = Some(phone_number);
+        user.phone_number_verified = false;
+        user.two_fa_method = TwoFAMethod::Email;
+        Ok(())
+    }
+
+    async fn verify_phone_number(&self, id: &UserId, phone_number: &PhoneNumber) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        if user.phone_number.as_ref() != Some(phone_number) {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+        user.phone_number_verified = true;
+        Ok(())
+    }
+
+    async fn set_two_fa_method(&self, id: &UserId, two_fa_method: TwoFAMethod) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        if two_fa_method == TwoFAMethod::Sms && !(user.phone_number.is_some() && user.phone_number_verified) {
+            return Err(UserStoreError::PhoneNumberNotVerified);
+        }
+        user.two_fa_method = two_fa_method;
+        Ok(())
+    }
+
+    async fn update_password(&self, id: &UserId, password: Password, changed_at: DateTime<Utc>, history_size: usize) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        let mut password_history = self.password_history.write().await;
+        let history = password_history.entry(*id).or_default();
+
+        if std::iter::once(&user.password).chain(history.iter()).take(history_size).any(|used| *used == password) {
+            return Err(UserStoreError::PasswordReused);
+        }
+
+        history.insert(0, std::mem::replace(&mut user.password, password));
+        history.truncate(history_size.saturating_sub(1));
+        // Stores keep whole seconds
+        user.password_changed_at = DateTime::from_timestamp(changed_at.timestamp(), 0).unwrap_or(changed_at);
+        Ok(())
+    }
+
+    async fn set_password_max_age(&self, id: &UserId, max_age: Option<Duration>) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.password_max_age = max_age;
+        Ok(())
+    }
+
+    async fn set_status(&self, id: &UserId, status: AccountStatus) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        let user = users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.status = status;
+        Ok(())
+    }
+
+    async fn purge_deleted_users(&self, now: DateTime<Utc>) -> Result<u64, UserStoreError> {
+        let mut users = self.users.write().await;
+        let due: Vec<UserId> = users
+            .values()
+            .filter(|user| matches!(user.status, AccountStatus::PendingDeletion { delete_at } if delete_at <= now))
+            .map(|user| user.id)
+            .collect();
+
+        let mut password_history = self.password_history.write().await;
+        for id in &due {
+            users.remove(id);
+            password_history.remove(id);
+        }
+        Ok(due.len() as u64)
+    }
+
+    async fn update_email(&self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().await;
+        if find_by_email(&users, &new_email).is_some_and(|user| user.id != *id) {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+        let user = users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.email = new_email;
+        Ok(())
+    }
 }
 
 
@@ -58,11 +167,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_user() {
-        let mut hashmap_user_store = HashmapUserStore::default();
+        let hashmap_user_store = HashmapUserStore::default();
         let user = User::new(
             Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap(),
             Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap(),
             false,
+            Utc::now(),
         );
         let same_user = user.clone();
         assert!(hashmap_user_store.add_user(user).await.is_ok());
@@ -74,10 +184,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_user() {
-        let mut hashmap_user_store = HashmapUserStore::default();
+        let hashmap_user_store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
         let other_email = Email::parse(Secret::new("other_person@letsgetrusty.com".to_owned())).unwrap();
-        let user = User::new(email.clone(), Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap(), false);
+        let user = User::new(email.clone(), Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap(), false, Utc::now());
 
         let _ = hashmap_user_store.add_user(user).await;
         assert!(hashmap_user_store.get_user(&email).await.is_ok());
@@ -87,14 +197,32 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_get_user_by_id() {
+        let hashmap_user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
+        let user = User::new(email.clone(), Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap(), false, Utc::now());
+        let id = user.id;
+
+        let _ = hashmap_user_store.add_user(user).await;
+        assert_eq!(hashmap_user_store.get_user_by_id(&id).await.unwrap().email, email);
+        assert_eq!(
+            hashmap_user_store.get_user_by_id(&UserId::default()).await,
+            Err(UserStoreError::UserNotFound)
+        );
+
+        assert!(hashmap_user_store.delete_user(&id).await.is_ok());
+        assert_eq!(hashmap_user_store.get_user(&email).await, Err(UserStoreError::UserNotFound));
+    }
+
     #[tokio::test]
     async fn test_validate_user() {
-        let mut hashmap_user_store = HashmapUserStore::default();
+        let hashmap_user_store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
         let password = Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap();
         let other_email = Email::parse(Secret::new("other_person@letsgetrusty.com".to_owned())).unwrap();
         let other_password = Password::parse(Secret::new("OtherPassword456".to_string())).unwrap();
-        let user = User::new(email.clone(), password.clone(), false);
+        let user = User::new(email.clone(), password.clone(), false, Utc::now());
 
         let _ = hashmap_user_store.add_user(user).await;
         assert!(hashmap_user_store.validate_user(&email, &password).await.is_ok());
@@ -107,4 +235,79 @@ mod tests {
             Err(UserStoreError::UserNotFound)
         );
     }
+
+    #[tokio::test]
+    async fn test_phone_number_verification() {
+        let hashmap_user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
+        let phone_number = PhoneNumber::parse(Secret::new("+34600123456".to_owned())).unwrap();
+        let other_phone_number = PhoneNumber::parse(Secret::new("+34600654321".to_owned())).unwrap();
+        let user = User::new(email.clone(), Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap(), true, Utc::now());
+        let id = user.id;
+
+        let _ = hashmap_user_store.add_user(user).await;
+        assert!(hashmap_user_store.set_phone_number(&id, phone_number.clone()).await.is_ok());
+        assert_eq!(
+            hashmap_user_store.set_two_fa_method(&id, TwoFAMethod::Sms).await,
+            Err(UserStoreError::PhoneNumberNotVerified)
+        );
+        assert_eq!(
+            hashmap_user_store.verify_phone_number(&id, &other_phone_number).await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+        assert!(hashmap_user_store.verify_phone_number(&id, &phone_number).await.is_ok());
+        assert!(hashmap_user_store.set_two_fa_method(&id, TwoFAMethod::Sms).await.is_ok());
+
+        let user = hashmap_user_store.get_user(&email).await.unwrap();
+        assert_eq!(user.verified_phone_number(), Some(&phone_number));
+        assert_eq!(user.two_fa_method, TwoFAMethod::Sms);
+
+        // Changing the number needs a new verification and falls back to email
+        let _ = hashmap_user_store.set_phone_number(&id, other_phone_number).await;
+        let user = hashmap_user_store.get_user(&email).await.unwrap();
+        assert_eq!(user.verified_phone_number(), None);
+        assert_eq!(user.two_fa_method, TwoFAMethod::Email);
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let hashmap_user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap();
+        let new_password = Password::parse(Secret::new("RustIsSecure456".to_string())).unwrap();
+        let user = User::new(email.clone(), password.clone(), false, Utc::now());
+        let id = user.id;
+
+        let _ = hashmap_user_store.add_user(user).await;
+        assert!(hashmap_user_store.update_password(&id, new_password.clone(), Utc::now(), 5).await.is_ok());
+        assert!(hashmap_user_store.validate_user(&email, &new_password).await.is_ok());
+        assert_eq!(
+            hashmap_user_store.validate_user(&email, &password).await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_email() {
+        let hashmap_user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("guillem@letsgetrusty.com".to_owned())).unwrap();
+        let new_email = Email::parse(Secret::new("guillem@rust.com".to_owned())).unwrap();
+        let taken_email = Email::parse(Secret::new("other_person@letsgetrusty.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("RustIsSecure123".to_string())).unwrap();
+
+        let user = User::new(email.clone(), password.clone(), false, Utc::now());
+        let id = user.id;
+
+        let _ = hashmap_user_store.add_user(user).await;
+        let _ = hashmap_user_store.add_user(User::new(taken_email.clone(), password.clone(), false, Utc::now())).await;
+
+        assert_eq!(
+            hashmap_user_store.update_email(&id, taken_email).await,
+            Err(UserStoreError::UserAlreadyExists)
+        );
+        assert!(hashmap_user_store.update_email(&id, new_email.clone()).await.is_ok());
+        assert_eq!(hashmap_user_store.get_user(&email).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(hashmap_user_store.get_user(&new_email).await.unwrap().id, id);
+        assert_eq!(hashmap_user_store.get_user_by_id(&id).await.unwrap().email, new_email);
+    }
 }
@@ -1,23 +1,94 @@
-use std::collections::HashSet;
+use std::{collections::{hash_map::Entry, HashMap}, sync::Arc};
 
-use secrecy::{ExposeSecret, Secret};
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::eyre;
+use tokio::sync::RwLock;
 
-use crate::domain::data_stores::banned_token_store::{BannedTokenStore, BannedTokenStoreError};
+use crate::domain::{
+    data_stores::banned_token_store::{hash_jti, BannedTokenStore, BannedTokenStoreError, JWT_LEEWAY_SECONDS},
+    Clock, SystemClock, UserId,
+};
+
+// Same as redis.ttl_millis in config/default.toml
+const DEFAULT_TOKEN_TTL_MILLIS: i64 = 600_000;
 
-#[derive(Debug, Default)]
 pub struct HashsetBannedTokenStore {
-    tokens: HashSet<String>,
+    // Hash of the banned jti to when the ban can be forgotten
+    tokens: RwLock<HashMap<String, DateTime<Utc>>>,
+    revoked_before: RwLock<HashMap<UserId, (usize, DateTime<Utc>)>>,
+    revocation_ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl HashsetBannedTokenStore {
+    pub fn new(token_ttl_millis: i64) -> Self {
+        Self {
+            tokens: RwLock::default(),
+            revoked_before: RwLock::default(),
+            revocation_ttl: Duration::milliseconds(token_ttl_millis) + Duration::seconds(JWT_LEEWAY_SECONDS),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+}
+
+impl Default for HashsetBannedTokenStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_TOKEN_TTL_MILLIS)
+    }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore{
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
-        self.tokens.insert(token.expose_secret().clone());
+    async fn add_token(&self, jti: &str, expires_at: usize) -> Result<(), BannedTokenStoreError> {
+        self.add_token_if_absent(jti, expires_at).await.map(|_| ())
+    }
+
+    async fn add_token_if_absent(&self, jti: &str, expires_at: usize) -> Result<bool, BannedTokenStoreError> {
+        let expires_at = i64::try_from(expires_at)
+            .ok()
+            .and_then(|expires_at| DateTime::from_timestamp(expires_at + JWT_LEEWAY_SECONDS, 0))
+            .ok_or_else(|| BannedTokenStoreError::UnexpectedError(eyre!("invalid token expiry {}", expires_at)))?;
+        let now = self.clock.now();
+        // Already expired, nothing left to ban
+        if expires_at <= now {
+            return Ok(false);
+        }
+
+        let mut tokens = self.tokens.write().await;
+        // Nothing else drops expired bans
+        tokens.retain(|_, expires_at| *expires_at > now);
+        match tokens.entry(hash_jti(jti)) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert(expires_at);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn contains_token(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        let now = self.clock.now();
+        Ok(self.tokens.read().await.get(&hash_jti(jti)).is_some_and(|expires_at| *expires_at > now))
+    }
+
+    async fn revoke_tokens_before(&self, user_id: &UserId, timestamp: usize) -> Result<(), BannedTokenStoreError> {
+        let now = self.clock.now();
+        let mut revoked_before = self.revoked_before.write().await;
+        revoked_before.retain(|_, (_, expires_at)| *expires_at > now);
+        revoked_before.insert(*user_id, (timestamp, now + self.revocation_ttl));
         Ok(())
     }
 
-    async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        Ok(self.tokens.contains(token.expose_secret()))
+    async fn tokens_revoked_before(&self, user_id: &UserId) -> Result<Option<usize>, BannedTokenStoreError> {
+        let now = self.clock.now();
+        Ok(self.revoked_before.read().await
+            .get(user_id)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(timestamp, _)| *timestamp))
     }
 }
 
@@ -27,27 +98,50 @@ mod tests {
 
     use super::*;
 
+    fn in_ten_minutes() -> usize {
+        (Utc::now().timestamp() + 600) as usize
+    }
+
     #[tokio::test]
     async fn test_add_token() {
-        let mut hashset_banned_user_store = HashsetBannedTokenStore::default();
-        let token = Secret::new("token_to_add".to_owned());
-        assert!(hashset_banned_user_store.add_token(token).await.is_ok());
+        let hashset_banned_user_store = HashsetBannedTokenStore::default();
+        assert!(hashset_banned_user_store.add_token("jti_to_add", in_ten_minutes()).await.is_ok());
     }
 
     #[tokio::test]
     async fn test_get_existing_token() {
-        let mut hashset_banned_user_store = HashsetBannedTokenStore::default();
-        let token = Secret::new("token_to_add".to_owned());
-        let _ =hashset_banned_user_store.add_token(token.clone()).await;
+        let hashset_banned_user_store = HashsetBannedTokenStore::default();
+        let _ = hashset_banned_user_store.add_token("jti_to_add", in_ten_minutes()).await;
 
-        assert!(hashset_banned_user_store.contains_token(&token).await == Ok(true))
+        assert!(hashset_banned_user_store.contains_token("jti_to_add").await == Ok(true))
     }
 
     #[tokio::test]
     async fn test_get_non_existing_token() {
         let hashset_banned_user_store = HashsetBannedTokenStore::default();
-        let token = Secret::new("not_added_token".to_owned());
 
-        assert!(hashset_banned_user_store.contains_token(&token).await == Ok(false))
+        assert!(hashset_banned_user_store.contains_token("not_added_jti").await == Ok(false))
+    }
+
+    #[tokio::test]
+    async fn test_only_keeps_the_jti_hash() {
+        let hashset_banned_user_store = HashsetBannedTokenStore::default();
+        let _ = hashset_banned_user_store.add_token("jti_to_add", in_ten_minutes()).await;
+
+        let tokens = hashset_banned_user_store.tokens.read().await;
+        assert!(tokens.contains_key(&hash_jti("jti_to_add")));
+        assert!(!tokens.contains_key("jti_to_add"));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_revoke_tokens_before() {
+        let hashset_banned_user_store = HashsetBannedTokenStore::default();
+        let user_id = UserId::default();
+
+        assert!(hashset_banned_user_store.tokens_revoked_before(&user_id).await == Ok(None));
+
+        let _ = hashset_banned_user_store.revoke_tokens_before(&user_id, 1000).await;
+
+        assert!(hashset_banned_user_store.tokens_revoked_before(&user_id).await == Ok(Some(1000)))
+    }
+}
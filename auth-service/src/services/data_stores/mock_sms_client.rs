@@ -0,0 +1,23 @@
+use crate::domain::{PhoneNumber, SmsClient};
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+
+pub struct MockSmsClient;
+
+#[async_trait::async_trait]
+impl SmsClient for MockSmsClient {
+    async fn send_sms(
+        &self,
+        recipient: &PhoneNumber,
+        content: &Secret<String>,
+    ) -> Result<()> {
+        // Our mock SMS client will simply log the recipient and content to standard output
+        tracing::debug!(
+            "Sending SMS to {} with content: {}",
+            recipient.as_ref().expose_secret(),
+            content.expose_secret()
+        );
+
+        Ok(())
+    }
+}
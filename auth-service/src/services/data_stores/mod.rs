@@ -1,10 +1,31 @@
+pub mod capturing_email_client;
 pub mod hashmap_user_store;
 pub mod hashset_banned_token_store;
 pub mod hashmap_two_fa_code_store;
+pub mod hashmap_idempotency_store;
+pub mod hashmap_login_fingerprint_store;
+pub mod hashmap_rate_limit_store;
+pub mod hashmap_trusted_device_store;
 pub mod mock_email_client;
+pub mod mock_sms_client;
+pub mod oidc_client;
+pub mod password_hashing_executor;
+pub use password_hashing_executor::*;
+pub mod postgres_login_fingerprint_store;
+pub mod postgres_trusted_device_store;
 pub mod postgres_user_store;
 pub mod postmark_email_client;
+pub mod redis_connection_pool;
+pub use redis_connection_pool::*;
 pub mod redis_banned_token_store;
 pub use redis_banned_token_store::*;
+pub mod redis_idempotency_store;
+pub use redis_idempotency_store::*;
+pub mod redis_rate_limit_store;
+pub use redis_rate_limit_store::*;
 pub mod redis_two_fa_code_store;
-pub use redis_two_fa_code_store::*;
\ No newline at end of file
+pub use redis_two_fa_code_store::*;
+pub mod sqlite_login_fingerprint_store;
+pub mod sqlite_trusted_device_store;
+pub mod sqlite_user_store;
+pub mod twilio_sms_client;
\ No newline at end of file
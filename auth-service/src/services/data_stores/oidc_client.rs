@@ -0,0 +1,301 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::{decode, decode_header, jwk::{Jwk, JwkSet}, Algorithm, DecodingKey, Validation};
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::{
+    domain::Clock,
+    utils::{OidcProviderSettings, OidcSettings},
+};
+
+// A token naming a key the cached set lacks refetches it at most this often, so made up kids can not
+// send every login to the provider
+const JWKS_REFETCH_INTERVAL_SECONDS: i64 = 60;
+
+// Relying party side of the OpenID Connect authorization code flow, for every configured provider
+pub struct OidcClient {
+    http_client: Client,
+    settings: OidcSettings,
+    // Signing keys of each provider by name
+    jwks_cache: RwLock<HashMap<String, CachedJwks>>,
+}
+
+#[derive(Clone)]
+struct CachedJwks {
+    jwks: Arc<JwkSet>,
+    fetched_at: DateTime<Utc>,
+}
+
+impl OidcClient {
+    pub fn new(settings: OidcSettings) -> Self {
+        let http_client = Client::builder()
+            .timeout(std::time::Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self { http_client, settings, jwks_cache: RwLock::default() }
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&OidcProviderSettings> {
+        self.settings.providers.iter().find(|provider| provider.name == name)
+    }
+
+    pub fn post_login_redirect(&self) -> &str {
+        &self.settings.post_login_redirect
+    }
+
+    pub fn redirect_uri(&self, provider: &OidcProviderSettings) -> String {
+        format!("{}/{}/callback", self.settings.redirect_base_url, provider.name)
+    }
+
+    pub fn authorization_url(
+        &self,
+        provider: &OidcProviderSettings,
+        state: &Secret<String>,
+        nonce: &Secret<String>,
+        code_challenge: &str,
+    ) -> Result<Url> {
+        Url::parse_with_params(
+            &provider.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", provider.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri(provider).as_str()),
+                ("scope", provider.scopes.as_str()),
+                ("state", state.expose_secret().as_str()),
+                ("nonce", nonce.expose_secret().as_str()),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .wrap_err("failed to build authorization url")
+    }
+
+    #[tracing::instrument(name = "ExchangeOidcCode", skip_all)]
+    pub async fn exchange_code(
+        &self,
+        provider: &OidcProviderSettings,
+        code: &Secret<String>,
+        code_verifier: &Secret<String>,
+    ) -> Result<Secret<String>> {
+        let redirect_uri = self.redirect_uri(provider);
+
+        let request_body = [
+            ("grant_type", "authorization_code"),
+            ("code", code.expose_secret().as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("client_id", provider.client_id.as_str()),
+            ("client_secret", provider.client_secret.expose_secret().as_str()),
+            ("code_verifier", code_verifier.expose_secret().as_str()),
+        ];
+
+        let response = self
+            .http_client
+            .post(&provider.token_endpoint)
+            .form(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await
+            .wrap_err("failed to deserialize token response")?;
+
+        Ok(response.id_token)
+    }
+
+    // Checks signature, issuer, audience, expiry and nonce of the id_token
+    #[tracing::instrument(name = "ValidateIdToken", skip_all)]
+    pub async fn validate_id_token(
+        &self,
+        provider: &OidcProviderSettings,
+        id_token: &Secret<String>,
+        nonce: &Secret<String>,
+        clock: &dyn Clock,
+    ) -> Result<IdTokenClaims> {
+        let header = decode_header(id_token.expose_secret()).wrap_err("failed to decode id_token header")?;
+
+        // Symmetric algorithms would let anyone holding the client secret forge tokens
+        if !matches!(header.alg, Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 | Algorithm::ES256 | Algorithm::ES384 | Algorithm::EdDSA) {
+            return Err(eyre!("unsupported id_token algorithm {:?}", header.alg));
+        }
+
+        let jwk = self.find_jwk(provider, header.kid.as_deref(), clock).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_issuer(&[&provider.issuer]);
+
+        let claims = decode::<IdTokenClaims>(
+            id_token.expose_secret(),
+            &DecodingKey::from_jwk(&jwk)?,
+            &validation,
+        )
+        .map(|data| data.claims)
+        .wrap_err("failed to decode id_token")?;
+
+        if claims.nonce.as_deref() != Some(nonce.expose_secret().as_str()) {
+            return Err(eyre!("id_token nonce does not match"));
+        }
+
+        Ok(claims)
+    }
+
+    // Serves the key from the cached set while it is fresh, providers rotate keys by adding them first
+    async fn find_jwk(&self, provider: &OidcProviderSettings, kid: Option<&str>, clock: &dyn Clock) -> Result<Jwk> {
+        let now = clock.now();
+        let cached = self.jwks_cache.read().await.get(&provider.name).cloned();
+
+        if let Some(cached) = cached {
+            if now < cached.fetched_at + Duration::milliseconds(self.settings.jwks_cache_ttl_millis) {
+                if let Some(jwk) = select_jwk(&cached.jwks, kid)? {
+                    return Ok(jwk);
+                }
+
+                if now < cached.fetched_at + Duration::seconds(JWKS_REFETCH_INTERVAL_SECONDS) {
+                    return Err(eyre!("no JWK matches the id_token"));
+                }
+            }
+        }
+
+        let jwks = Arc::new(self.fetch_jwks(provider).await?);
+        self.jwks_cache
+            .write()
+            .await
+            .insert(provider.name.clone(), CachedJwks { jwks: jwks.clone(), fetched_at: now });
+
+        select_jwk(&jwks, kid)?.ok_or(eyre!("no JWK matches the id_token"))
+    }
+
+    #[tracing::instrument(name = "FetchOidcJwks", skip_all)]
+    async fn fetch_jwks(&self, provider: &OidcProviderSettings) -> Result<JwkSet> {
+        self.http_client
+            .get(&provider.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await
+            .wrap_err("failed to deserialize provider JWKS")
+    }
+}
+
+// Without a kid only a set of one key tells which key signed the token
+fn select_jwk(jwks: &JwkSet, kid: Option<&str>) -> Result<Option<Jwk>> {
+    match (kid, jwks.keys.as_slice()) {
+        (Some(kid), _) => Ok(jwks.find(kid).cloned()),
+        (None, [jwk]) => Ok(Some(jwk.clone())),
+        (None, keys) => Err(eyre!("id_token has no kid and the provider has {} keys", keys.len())),
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Secret<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub nonce: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::domain::SystemClock;
+
+    fn provider(base_url: &str) -> OidcProviderSettings {
+        OidcProviderSettings {
+            name: "mock".to_owned(),
+            issuer: base_url.to_owned(),
+            client_id: "client_id".to_owned(),
+            client_secret: Secret::new("client_secret".to_owned()),
+            authorization_endpoint: format!("{}/authorize", base_url),
+            token_endpoint: format!("{}/token", base_url),
+            jwks_uri: format!("{}/jwks", base_url),
+            scopes: "openid email".to_owned(),
+        }
+    }
+
+    fn oidc_client(base_url: &str) -> OidcClient {
+        OidcClient::new(OidcSettings {
+            redirect_base_url: "http://127.0.0.1/auth/oidc".to_owned(),
+            post_login_redirect: "/auth/".to_owned(),
+            timeout_milliseconds: 200,
+            jwks_cache_ttl_millis: 60 * 60 * 1000,
+            providers: vec![provider(base_url)],
+        })
+    }
+
+    #[test]
+    fn test_authorization_url_carries_pkce_state_and_nonce() {
+        let client = oidc_client("http://idp.example.com");
+        let provider = client.provider("mock").unwrap();
+        let url = client
+            .authorization_url(provider, &Secret::new("state".to_owned()), &Secret::new("nonce".to_owned()), "challenge")
+            .unwrap();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+        assert!(query.contains(&("state".to_owned(), "state".to_owned())));
+        assert!(query.contains(&("nonce".to_owned(), "nonce".to_owned())));
+        assert!(query.contains(&("code_challenge_method".to_owned(), "S256".to_owned())));
+        assert!(query.contains(&("redirect_uri".to_owned(), "http://127.0.0.1/auth/oidc/mock/callback".to_owned())));
+    }
+
+    #[test]
+    fn test_unknown_provider() {
+        let client = oidc_client("http://idp.example.com");
+        assert!(client.provider("unknown").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_exchange_code_fails_if_the_provider_returns_400() {
+        let mock_server = MockServer::start().await;
+        let client = oidc_client(&mock_server.uri());
+
+        Mock::given(path("/token"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let provider = client.provider("mock").unwrap();
+        let outcome = client
+            .exchange_code(provider, &Secret::new("code".to_owned()), &Secret::new("verifier".to_owned()))
+            .await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_id_token_rejects_symmetric_algorithms() {
+        let mock_server = MockServer::start().await;
+        let client = oidc_client(&mock_server.uri());
+
+        let id_token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &serde_json::json!({ "sub": "1", "aud": "client_id", "iss": mock_server.uri(), "exp": usize::MAX, "nonce": "nonce" }),
+            &jsonwebtoken::EncodingKey::from_secret(b"client_secret"),
+        )
+        .unwrap();
+
+        let provider = client.provider("mock").unwrap();
+        let outcome = client
+            .validate_id_token(provider, &Secret::new(id_token), &Secret::new("nonce".to_owned()), &SystemClock)
+            .await;
+
+        assert!(outcome.is_err());
+    }
+}
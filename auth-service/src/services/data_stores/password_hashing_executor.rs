@@ -0,0 +1,203 @@
+use std::{sync::Arc, time::Duration};
+
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use color_eyre::eyre::{eyre, Report, Result};
+use secrecy::{ExposeSecret, Secret};
+use thiserror::Error;
+use tokio::sync::Semaphore;
+
+use crate::{domain::data_stores::UserStoreError, utils::PasswordHashingSettings};
+
+#[derive(Debug, Error)]
+pub enum PasswordHashingError {
+    #[error("Too many password hashes in progress")]
+    Overloaded,
+    #[error("Password does not match")]
+    Mismatch,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+// Argon2 is slow on purpose, so a flood of logins must not take every core. Hashes run on the
+// blocking pool behind a semaphore, and a caller that waits too long for a slot gets Overloaded
+// instead of queueing forever.
+#[derive(Clone)]
+pub struct PasswordHashingExecutor {
+    params: Params,
+    dummy_hash: Arc<Secret<String>>,
+    permits: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+impl PasswordHashingExecutor {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self> {
+        let params = Params::new(settings.memory_kib, settings.iterations, settings.parallelism, None)
+            .map_err(|e| eyre!(e))?;
+
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let dummy_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password(b"dummy password", &salt)
+            .map_err(|e| eyre!(e))?
+            .to_string();
+
+        Ok(Self {
+            params,
+            dummy_hash: Arc::new(Secret::new(dummy_hash)),
+            permits: Arc::new(Semaphore::new(settings.max_concurrency.max(1))),
+            queue_timeout: Duration::from_millis(settings.queue_timeout_millis),
+        })
+    }
+
+    #[tracing::instrument(name = "Computing password hash", skip_all)]
+    pub async fn hash(&self, password: Secret<String>) -> Result<Secret<String>, PasswordHashingError> {
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone());
+
+        let password_hash = self
+            .run(move || {
+                let salt = SaltString::generate(&mut rand::thread_rng());
+                argon2
+                    .hash_password(password.expose_secret().as_bytes(), &salt)
+                    .map(|password_hash| password_hash.to_string())
+                    .map_err(|e| PasswordHashingError::UnexpectedError(eyre!(e)))
+            })
+            .await?;
+
+        Ok(Secret::new(password_hash))
+    }
+
+    #[tracing::instrument(name = "Verify password hash", skip_all)]
+    pub async fn verify(
+        &self,
+        expected_password_hash: &Secret<String>,
+        password_candidate: &Secret<String>,
+    ) -> Result<(), PasswordHashingError> {
+        let expected_password_hash = expected_password_hash.expose_secret().to_owned();
+        let password_candidate = password_candidate.expose_secret().to_owned();
+
+        self.run(move || {
+            let expected_hash = PasswordHash::new(&expected_password_hash)
+                .map_err(|e| PasswordHashingError::UnexpectedError(eyre!(e)))?;
+
+            // The parameters come from the stored hash, so older hashes still verify
+            Argon2::default()
+                .verify_password(password_candidate.as_bytes(), &expected_hash)
+                .map_err(|_| PasswordHashingError::Mismatch)
+        })
+        .await
+    }
+
+    // Costs the same as checking a real password, for callers that must not reveal the user is unknown
+    #[tracing::instrument(name = "Verify dummy password hash", skip_all)]
+    pub async fn verify_dummy(&self, password_candidate: &Secret<String>) -> Result<(), PasswordHashingError> {
+        match self.verify(&self.dummy_hash, password_candidate).await {
+            Ok(()) | Err(PasswordHashingError::Mismatch) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // True when the hash was made with other parameters than the configured ones
+    pub fn needs_rehash(&self, password_hash: &Secret<String>) -> bool {
+        let Ok(password_hash) = PasswordHash::new(password_hash.expose_secret()) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&password_hash) else {
+            return true;
+        };
+
+        password_hash.algorithm != Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+
+    async fn run<T, F>(&self, job: F) -> Result<T, PasswordHashingError>
+    where
+        F: FnOnce() -> Result<T, PasswordHashingError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = tokio::time::timeout(self.queue_timeout, Arc::clone(&self.permits).acquire_owned())
+            .await
+            .map_err(|_| PasswordHashingError::Overloaded)?
+            .map_err(|e| PasswordHashingError::UnexpectedError(e.into()))?;
+
+        // The permit moves into the task, so a caller that goes away does not free the slot early
+        tokio::task::spawn_blocking(move || {
+            let result = job();
+            drop(permit);
+            result
+        })
+        .await
+        .map_err(|e| PasswordHashingError::UnexpectedError(e.into()))?
+    }
+}
+
+impl From<PasswordHashingError> for UserStoreError {
+    fn from(error: PasswordHashingError) -> Self {
+        match error {
+            PasswordHashingError::Overloaded => UserStoreError::Overloaded,
+            PasswordHashingError::Mismatch => UserStoreError::InvalidCredentials,
+            PasswordHashingError::UnexpectedError(e) => UserStoreError::UnexpectedError(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(memory_kib: u32, max_concurrency: usize) -> PasswordHashingSettings {
+        PasswordHashingSettings {
+            memory_kib,
+            iterations: 1,
+            parallelism: 1,
+            max_concurrency,
+            queue_timeout_millis: 50,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hash_and_verify() {
+        let executor = PasswordHashingExecutor::new(&settings(1024, 2)).unwrap();
+        let password = Secret::new("Password123".to_owned());
+
+        let password_hash = executor.hash(password.clone()).await.unwrap();
+
+        assert!(executor.verify(&password_hash, &password).await.is_ok());
+        assert!(matches!(
+            executor.verify(&password_hash, &Secret::new("Password456".to_owned())).await,
+            Err(PasswordHashingError::Mismatch)
+        ));
+        assert!(!executor.needs_rehash(&password_hash));
+        assert!(executor.verify_dummy(&password).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_needs_rehash_when_params_change() {
+        let old_executor = PasswordHashingExecutor::new(&settings(1024, 2)).unwrap();
+        let new_executor = PasswordHashingExecutor::new(&settings(2048, 2)).unwrap();
+
+        let password_hash = old_executor.hash(Secret::new("Password123".to_owned())).await.unwrap();
+
+        assert!(new_executor.needs_rehash(&password_hash));
+        // Old hashes keep verifying until they are replaced
+        assert!(new_executor
+            .verify(&password_hash, &Secret::new("Password123".to_owned()))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_overloaded_when_no_slot_frees_up() {
+        let executor = PasswordHashingExecutor::new(&settings(1024, 1)).unwrap();
+        let _busy = Arc::clone(&executor.permits).acquire_owned().await.unwrap();
+
+        assert!(matches!(
+            executor.hash(Secret::new("Password123".to_owned())).await,
+            Err(PasswordHashingError::Overloaded)
+        ));
+    }
+}
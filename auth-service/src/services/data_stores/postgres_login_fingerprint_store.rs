@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::domain::{
+    data_stores::{FingerprintStatus, LoginFingerprint, LoginFingerprintStore, LoginFingerprintStoreError},
+    UserId,
+};
+
+pub struct PostgresLoginFingerprintStore {
+    pool: PgPool,
+}
+
+impl PostgresLoginFingerprintStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginFingerprintStore for PostgresLoginFingerprintStore {
+    #[tracing::instrument(name = "Recording login fingerprint in PostgreSQL", skip_all)]
+    async fn record_fingerprint(
+        &self,
+        user_id: &UserId,
+        fingerprint: &LoginFingerprint,
+        seen_at: DateTime<Utc>,
+    ) -> Result<FingerprintStatus, LoginFingerprintStoreError> {
+        let fingerprint = fingerprint.hash();
+
+        let mut transaction = self.pool
+            .begin()
+            .await
+            .map_err(|e| LoginFingerprintStoreError::UnexpectedError(e.into()))?;
+
+        let updated = sqlx::query("UPDATE login_fingerprints SET last_seen_at = $3 WHERE user_id = $1 AND fingerprint = $2")
+            .bind(user_id.as_ref())
+            .bind(&fingerprint)
+            .bind(seen_at.timestamp())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| LoginFingerprintStoreError::UnexpectedError(e.into()))?;
+
+        if updated.rows_affected() > 0 {
+            transaction
+                .commit()
+                .await
+                .map_err(|e| LoginFingerprintStoreError::UnexpectedError(e.into()))?;
+            return Ok(FingerprintStatus::Known);
+        }
+
+        let has_fingerprints: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM login_fingerprints WHERE user_id = $1)")
+            .bind(user_id.as_ref())
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(|e| LoginFingerprintStoreError::UnexpectedError(e.into()))?;
+
+        // A concurrent login from the same place may have inserted it in the meantime
+        let inserted = sqlx::query(
+            "INSERT INTO login_fingerprints (user_id, fingerprint, first_seen_at, last_seen_at) VALUES ($1, $2, $3, $3) ON CONFLICT (user_id, fingerprint) DO NOTHING"
+        )
+        .bind(user_id.as_ref())
+        .bind(&fingerprint)
+        .bind(seen_at.timestamp())
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| LoginFingerprintStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| LoginFingerprintStoreError::UnexpectedError(e.into()))?;
+
+        Ok(match (inserted.rows_affected(), has_fingerprints) {
+            (0, _) => FingerprintStatus::Known,
+            (_, false) => FingerprintStatus::FirstLogin,
+            (_, true) => FingerprintStatus::New,
+        })
+    }
+}
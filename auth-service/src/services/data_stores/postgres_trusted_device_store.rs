@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::domain::{
+    data_stores::{TrustedDevice, TrustedDeviceId, TrustedDeviceStore, TrustedDeviceStoreError},
+    UserId,
+};
+
+pub struct PostgresTrustedDeviceStore {
+    pool: PgPool,
+}
+
+impl PostgresTrustedDeviceStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl TrustedDeviceStore for PostgresTrustedDeviceStore {
+    #[tracing::instrument(name = "Adding trusted device to PostgreSQL", skip_all)]
+    async fn add_device(&self, device: TrustedDevice) -> Result<(), TrustedDeviceStoreError> {
+        let mut transaction = self.pool
+            .begin()
+            .await
+            .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query("DELETE FROM trusted_devices WHERE user_id = $1 AND expires_at <= $2")
+            .bind(device.user_id.as_ref())
+            .bind(device.created_at.timestamp())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query(
+            "INSERT INTO trusted_devices (id, user_id, name, created_at, last_used_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6)"
+        )
+        .bind(device.id.as_ref())
+        .bind(device.user_id.as_ref())
+        .bind(&device.name)
+        .bind(device.created_at.timestamp())
+        .bind(device.last_used_at.timestamp())
+        .bind(device.expires_at.timestamp())
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Retrieving trusted device from PostgreSQL", skip_all)]
+    async fn get_device(&self, id: &TrustedDeviceId) -> Result<TrustedDevice, TrustedDeviceStoreError> {
+        sqlx::query_as::<_, TrustedDevice>(
+            "SELECT id, user_id, name, created_at, last_used_at, expires_at FROM trusted_devices WHERE id = $1"
+        )
+        .bind(id.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?
+        .ok_or(TrustedDeviceStoreError::DeviceNotFound)
+    }
+
+    #[tracing::instrument(name = "Retrieving trusted devices of a user from PostgreSQL", skip_all)]
+    async fn get_devices(&self, user_id: &UserId) -> Result<Vec<TrustedDevice>, TrustedDeviceStoreError> {
+        sqlx::query_as::<_, TrustedDevice>(
+            "SELECT id, user_id, name, created_at, last_used_at, expires_at FROM trusted_devices WHERE user_id = $1 ORDER BY created_at DESC"
+        )
+        .bind(user_id.as_ref())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Marking trusted device used in PostgreSQL", skip_all)]
+    async fn mark_used(&self, id: &TrustedDeviceId, used_at: DateTime<Utc>) -> Result<(), TrustedDeviceStoreError> {
+        let result = sqlx::query("UPDATE trusted_devices SET last_used_at = $2 WHERE id = $1")
+            .bind(id.as_ref())
+            .bind(used_at.timestamp())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(TrustedDeviceStoreError::DeviceNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Revoking trusted device in PostgreSQL", skip_all)]
+    async fn revoke_device(&self, user_id: &UserId, id: &TrustedDeviceId) -> Result<(), TrustedDeviceStoreError> {
+        let result = sqlx::query("DELETE FROM trusted_devices WHERE id = $1 AND user_id = $2")
+            .bind(id.as_ref())
+            .bind(user_id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| TrustedDeviceStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(TrustedDeviceStoreError::DeviceNotFound),
+            _ => Ok(()),
+        }
+    }
+}
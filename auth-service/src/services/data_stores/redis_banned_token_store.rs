@@ -1,56 +1,140 @@
 use std::sync::Arc;
+
 use color_eyre::eyre::Context;
 
-use redis::{Commands, Connection};
-use secrecy::{ExposeSecret, Secret};
-use tokio::sync::RwLock;
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
 
-use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
+use crate::{
+    domain::{
+        data_stores::{hash_jti, BannedTokenStore, BannedTokenStoreError, JWT_LEEWAY_SECONDS},
+        Clock, SystemClock, UserId,
+    },
+    services::data_stores::RedisConnectionPool,
+};
 
 pub struct RedisBannedTokenStore {
-    conn: Arc<RwLock<Connection>>,
+    pool: RedisConnectionPool,
     token_ttl_millis: i64,
+    clock: Arc<dyn Clock>,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>, token_ttl_millis: i64) -> Self {
-        Self { conn,  token_ttl_millis }
+    pub fn new(pool: RedisConnectionPool, token_ttl_millis: i64) -> Self {
+        Self { pool, token_ttl_millis, clock: Arc::new(SystemClock) }
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    // Past the token TTL every token issued before a revocation has expired anyway
+    fn revocation_ttl_seconds(&self) -> Result<u64, BannedTokenStoreError> {
+        (self.token_ttl_millis / 1000 + JWT_LEEWAY_SECONDS)
+            .try_into()
+            .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64") 
+            .map_err(BannedTokenStoreError::UnexpectedError)
     }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
     #[tracing::instrument(name = "AddToken", skip_all)]
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
-        let key = get_key(&token);
-        let mut connection: tokio::sync::RwLockWriteGuard<'_, Connection> = self.conn.write().await;
-        let ttl_seconds: u64 = 
-            (self.token_ttl_millis / 1000)
-                .try_into()
-                .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64") 
-                .map_err(|e| BannedTokenStoreError::UnexpectedError(e))?;
-
-        connection
-            .set_ex(key, true, ttl_seconds)
+    async fn add_token(&self, jti: &str, expires_at: usize) -> Result<(), BannedTokenStoreError> {
+        self.add_token_if_absent(jti, expires_at).await.map(|_| ())
+    }
+
+    #[tracing::instrument(name = "AddTokenIfAbsent", skip_all)]
+    async fn add_token_if_absent(&self, jti: &str, expires_at: usize) -> Result<bool, BannedTokenStoreError> {
+        let key = get_key(jti);
+        // The ban lives as long as the token, and holds its expiry so it ends by our clock and not only by Redis'
+        let expires_at: i64 = expires_at
+            .try_into()
+            .wrap_err("failed to cast token expiry to i64")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+        let expires_at = expires_at + JWT_LEEWAY_SECONDS;
+        let ttl_seconds = expires_at - self.clock.now().timestamp();
+        // Already expired, nothing left to ban
+        if ttl_seconds <= 0 {
+            return Ok(false);
+        }
+
+        // NX makes the check and the ban a single step
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(ttl_seconds as usize));
+
+        let banned: Option<String> = self.pool
+            .get()
+            .set_options(key, expires_at, options)
+            .await
             .wrap_err("failed to set banned token in Redis") 
-            .map_err(|e| BannedTokenStoreError::UnexpectedError(e))
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(banned.is_some())
     }
 
     #[tracing::instrument(name = "ContainsToken", skip_all)]
-    async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        let key = get_key(&token);
-        let mut connection = self.conn.write().await;
-        
-        connection
-            .exists(&key)
-                .wrap_err("failed to check if token exists in Redis")
-                .map_err(BannedTokenStoreError::UnexpectedError)
+    async fn contains_token(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        let key = get_key(jti);
+
+        let expires_at: Option<i64> = self.pool
+            .get()
+            .get(&key)
+            .await
+            .wrap_err("failed to check if token exists in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(expires_at.is_some_and(|expires_at| expires_at > self.clock.now().timestamp()))
+    }
+
+    #[tracing::instrument(name = "RevokeTokensBefore", skip_all)]
+    async fn revoke_tokens_before(&self, user_id: &UserId, timestamp: usize) -> Result<(), BannedTokenStoreError> {
+        let key = get_revoked_before_key(user_id);
+        let ttl_seconds = self.revocation_ttl_seconds()?;
+        let expires_at = self.clock.now().timestamp() + self.token_ttl_millis / 1000 + JWT_LEEWAY_SECONDS;
+        let revocation = serde_json::to_string(&(timestamp, expires_at))
+            .wrap_err("failed to serialize revocation")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        self.pool
+            .get()
+            .set_ex(key, revocation, ttl_seconds)
+            .await
+            .wrap_err("failed to set revocation timestamp in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "TokensRevokedBefore", skip_all)]
+    async fn tokens_revoked_before(&self, user_id: &UserId) -> Result<Option<usize>, BannedTokenStoreError> {
+        let key = get_revoked_before_key(user_id);
+
+        let revocation: Option<String> = self.pool
+            .get()
+            .get(&key)
+            .await
+            .wrap_err("failed to get revocation timestamp from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        let Some(revocation) = revocation else {
+            return Ok(None);
+        };
+        let (timestamp, expires_at): (usize, i64) = serde_json::from_str(&revocation)
+            .wrap_err("failed to parse revocation")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok((expires_at > self.clock.now().timestamp()).then_some(timestamp))
     }
 }
 
 // We are using a key prefix to prevent collisions and organize data!
-const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
+const BANNED_TOKEN_KEY_PREFIX: &str = "banned_jti:";
+
+fn get_key(jti: &str) -> String {
+    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, hash_jti(jti))
+}
+
+const REVOKED_BEFORE_KEY_PREFIX: &str = "tokens_revoked_before:";
 
-fn get_key(token: &Secret<String>) -> String {
-    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token.expose_secret())
+fn get_revoked_before_key(user_id: &UserId) -> String {
+    format!("{}{}", REVOKED_BEFORE_KEY_PREFIX, user_id)
 }
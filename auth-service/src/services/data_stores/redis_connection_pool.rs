@@ -0,0 +1,54 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use redis::{aio::ConnectionManager, Client, RedisResult};
+
+use crate::utils::RedisSettings;
+
+// Reconnection backoff, see ConnectionManager::new_with_backoff
+const RECONNECT_BACKOFF_EXPONENT_BASE: u64 = 2;
+const RECONNECT_BACKOFF_FACTOR_MILLIS: u64 = 100;
+
+// Each manager multiplexes requests over one async connection and reconnects on its own
+// when it drops, so callers never lock and several managers only spread the load.
+#[derive(Clone)]
+pub struct RedisConnectionPool {
+    connections: Arc<Vec<ConnectionManager>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl RedisConnectionPool {
+    pub async fn new(client: Client, redis_settings: &RedisSettings) -> RedisResult<Self> {
+        let mut connections = Vec::with_capacity(redis_settings.pool_size);
+
+        for _ in 0..redis_settings.pool_size.max(1) {
+            let connection = ConnectionManager::new_with_backoff_and_timeouts(
+                client.clone(),
+                RECONNECT_BACKOFF_EXPONENT_BASE,
+                RECONNECT_BACKOFF_FACTOR_MILLIS,
+                redis_settings.reconnect_retries,
+                Duration::from_millis(redis_settings.response_timeout_millis),
+                Duration::from_millis(redis_settings.connection_timeout_millis),
+            )
+            .await?;
+
+            connections.push(connection);
+        }
+
+        Ok(Self {
+            connections: Arc::new(connections),
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    // Cheap handle on the next connection, round robin
+    pub fn get(&self) -> ConnectionManager {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+        self.connections[index].clone()
+    }
+}
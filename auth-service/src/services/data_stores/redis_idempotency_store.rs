@@ -0,0 +1,113 @@
+use color_eyre::eyre::Context;
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+
+use crate::{
+    domain::data_stores::{
+        IdempotencyKey, IdempotencyRecord, IdempotencyStatus, IdempotencyStore, IdempotencyStoreError, SavedResponse,
+    },
+    services::data_stores::RedisConnectionPool,
+};
+
+pub struct RedisIdempotencyStore {
+    pool: RedisConnectionPool,
+    ttl_millis: i64,
+}
+
+impl RedisIdempotencyStore {
+    pub fn new(pool: RedisConnectionPool, ttl_millis: i64) -> Self {
+        Self { pool, ttl_millis }
+    }
+
+    fn ttl_seconds(&self) -> Result<u64, IdempotencyStoreError> {
+        (self.ttl_millis / 1000)
+            .try_into()
+            .wrap_err("failed to cast idempotency TTL to u64")
+            .map_err(IdempotencyStoreError::UnexpectedError)
+    }
+}
+
+#[async_trait::async_trait]
+impl IdempotencyStore for RedisIdempotencyStore {
+    #[tracing::instrument(name = "StartIdempotentRequest", skip_all)]
+    async fn start(&self, key: &IdempotencyKey, fingerprint: &str) -> Result<IdempotencyStatus, IdempotencyStoreError> {
+        let key = get_key(key);
+        let record = to_json(&IdempotencyRecord { fingerprint: fingerprint.to_owned(), response: None })?;
+        // A request that dies midway only holds its key for a short while
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(IN_PROGRESS_TTL_SECONDS));
+
+        let mut connection = self.pool.get();
+
+        let claimed: Option<String> = connection
+            .set_options(&key, record, options)
+            .await
+            .wrap_err("failed to claim idempotency key in Redis")
+            .map_err(IdempotencyStoreError::UnexpectedError)?;
+
+        if claimed.is_some() {
+            return Ok(IdempotencyStatus::Started);
+        }
+
+        let stored: Option<String> = connection
+            .get(&key)
+            .await
+            .wrap_err("failed to get idempotency key from Redis")
+            .map_err(IdempotencyStoreError::UnexpectedError)?;
+
+        match stored {
+            Some(stored) => serde_json::from_str::<IdempotencyRecord>(&stored)
+                .wrap_err("failed to deserialize idempotency record")
+                .map_err(IdempotencyStoreError::UnexpectedError)?
+                .status(fingerprint),
+            // Expired since the claim failed, the client can simply retry
+            None => Ok(IdempotencyStatus::InProgress),
+        }
+    }
+
+    #[tracing::instrument(name = "CompleteIdempotentRequest", skip_all)]
+    async fn complete(&self, key: &IdempotencyKey, response: SavedResponse) -> Result<(), IdempotencyStoreError> {
+        let key = get_key(key);
+        let mut connection = self.pool.get();
+
+        let stored: String = connection
+            .get(&key)
+            .await
+            .wrap_err("failed to get idempotency key from Redis")
+            .map_err(IdempotencyStoreError::UnexpectedError)?;
+
+        let mut record: IdempotencyRecord = serde_json::from_str(&stored)
+            .wrap_err("failed to deserialize idempotency record")
+            .map_err(IdempotencyStoreError::UnexpectedError)?;
+        record.response = Some(response);
+
+        connection
+            .set_ex(&key, to_json(&record)?, self.ttl_seconds()?)
+            .await
+            .wrap_err("failed to save idempotent response in Redis")
+            .map_err(IdempotencyStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "ReleaseIdempotencyKey", skip_all)]
+    async fn release(&self, key: &IdempotencyKey) -> Result<(), IdempotencyStoreError> {
+        self.pool
+            .get()
+            .del(get_key(key))
+            .await
+            .wrap_err("failed to delete idempotency key from Redis")
+            .map_err(IdempotencyStoreError::UnexpectedError)
+    }
+}
+
+fn to_json(record: &IdempotencyRecord) -> Result<String, IdempotencyStoreError> {
+    serde_json::to_string(record)
+        .wrap_err("failed to serialize idempotency record")
+        .map_err(IdempotencyStoreError::UnexpectedError)
+}
+
+const IN_PROGRESS_TTL_SECONDS: usize = 60;
+const IDEMPOTENCY_KEY_PREFIX: &str = "idempotency_key:";
+
+fn get_key(key: &IdempotencyKey) -> String {
+    format!("{}{}", IDEMPOTENCY_KEY_PREFIX, key.as_ref())
+}
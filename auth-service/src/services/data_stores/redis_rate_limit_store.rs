@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context};
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+
+use crate::{
+    domain::{
+        data_stores::{RateLimitDecision, RateLimitStore, RateLimitStoreError, TokenBucket},
+        Clock, SystemClock,
+    },
+    services::data_stores::{hashmap_rate_limit_store::HashmapRateLimitStore, RedisConnectionPool},
+};
+
+// Buckets are shared by every instance. While Redis is unreachable each instance limits on its own
+// instead, better than letting everything through or turning everyone away.
+pub struct RedisRateLimitStore {
+    pool: RedisConnectionPool,
+    fallback: HashmapRateLimitStore,
+    clock: Arc<dyn Clock>,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(pool: RedisConnectionPool) -> Self {
+        Self { pool, fallback: HashmapRateLimitStore::default(), clock: Arc::new(SystemClock) }
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { fallback: self.fallback.with_clock(clock.clone()), clock, ..self }
+    }
+
+    // The bucket is kept as the millisecond it is full again. Redis has no atomic read and update
+    // short of a script, but while the key exists its time is ahead of now, so INCRBY alone takes the
+    // token and the key expiring frees buckets that filled up.
+    async fn take_token_in_redis(&self, key: &str, bucket: &TokenBucket) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let key = get_key(key);
+        let now = self.clock.now();
+        let refill_millis = bucket.refill_millis;
+        let mut connection = self.pool.get();
+
+        // Full bucket, the first token starts it
+        if self.restart(&key, now, bucket).await? {
+            return Ok(RateLimitDecision::Allowed);
+        }
+
+        let full_at_millis: i64 = connection
+            .incr(&key, refill_millis)
+            .await
+            .wrap_err("failed to take token from Redis")
+            .map_err(RateLimitStoreError::UnexpectedError)?;
+        let full_at = from_millis(full_at_millis)?;
+
+        // Filled up by our clock although Redis has not expired it yet
+        if full_at - bucket.refill_interval() <= now {
+            self.overwrite(&key, now, bucket).await?;
+            return Ok(RateLimitDecision::Allowed);
+        }
+
+        if let Err(retry_after) = bucket.check(full_at, now) {
+            // There was no token to take, give back the time
+            let _: i64 = connection
+                .decr(&key, refill_millis)
+                .await
+                .wrap_err("failed to give token back to Redis")
+                .map_err(RateLimitStoreError::UnexpectedError)?;
+            return Ok(RateLimitDecision::Limited { retry_after });
+        }
+
+        let _: bool = connection
+            .pexpire(&key, (full_at - now).num_milliseconds())
+            .await
+            .wrap_err("failed to set rate limit expiry in Redis")
+            .map_err(RateLimitStoreError::UnexpectedError)?;
+
+        Ok(RateLimitDecision::Allowed)
+    }
+
+    async fn tokens_taken_in_redis(&self, key: &str, bucket: &TokenBucket) -> Result<u32, RateLimitStoreError> {
+        let full_at_millis: Option<i64> = self.pool
+            .get()
+            .get(get_key(key))
+            .await
+            .wrap_err("failed to read rate limit bucket from Redis")
+            .map_err(RateLimitStoreError::UnexpectedError)?;
+
+        let full_at = full_at_millis.map(from_millis).transpose()?;
+        Ok(bucket.taken(full_at, self.clock.now()))
+    }
+
+    async fn restart(&self, key: &str, now: DateTime<Utc>, bucket: &TokenBucket) -> Result<bool, RateLimitStoreError> {
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::PX(bucket.refill_millis as usize));
+
+        let started: Option<String> = self.pool
+            .get()
+            .set_options(key, (now + bucket.refill_interval()).timestamp_millis(), options)
+            .await
+            .wrap_err("failed to start rate limit bucket in Redis")
+            .map_err(RateLimitStoreError::UnexpectedError)?;
+
+        Ok(started.is_some())
+    }
+
+    async fn overwrite(&self, key: &str, now: DateTime<Utc>, bucket: &TokenBucket) -> Result<(), RateLimitStoreError> {
+        self.pool
+            .get()
+            .pset_ex(key, (now + bucket.refill_interval()).timestamp_millis(), bucket.refill_millis as u64)
+            .await
+            .wrap_err("failed to restart rate limit bucket in Redis")
+            .map_err(RateLimitStoreError::UnexpectedError)
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    #[tracing::instrument(name = "TakeRateLimitToken", skip_all)]
+    async fn take_token(&self, key: &str, bucket: &TokenBucket) -> Result<RateLimitDecision, RateLimitStoreError> {
+        match self.take_token_in_redis(key, bucket).await {
+            Ok(decision) => Ok(decision),
+            Err(e) => {
+                tracing::warn!(error = ?e, "Rate limiting in memory, Redis is unavailable");
+                self.fallback.take_token(key, bucket).await
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "CountRateLimitTokens", skip_all)]
+    async fn tokens_taken(&self, key: &str, bucket: &TokenBucket) -> Result<u32, RateLimitStoreError> {
+        match self.tokens_taken_in_redis(key, bucket).await {
+            Ok(taken) => Ok(taken),
+            Err(e) => {
+                tracing::warn!(error = ?e, "Counting rate limit tokens in memory, Redis is unavailable");
+                self.fallback.tokens_taken(key, bucket).await
+            }
+        }
+    }
+}
+
+fn from_millis(millis: i64) -> Result<DateTime<Utc>, RateLimitStoreError> {
+    DateTime::from_timestamp_millis(millis)
+        .ok_or_else(|| RateLimitStoreError::UnexpectedError(eyre!("invalid rate limit time {}", millis)))
+}
+
+const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit:";
+
+fn get_key(key: &str) -> String {
+    format!("{}{}", RATE_LIMIT_KEY_PREFIX, key)
+}
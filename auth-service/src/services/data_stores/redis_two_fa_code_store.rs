@@ -1,22 +1,107 @@
 use std::sync::Arc;
+
 use secrecy::{ExposeSecret, Secret};
 use color_eyre::eyre::Context;
-use redis::{Commands, Connection};
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
 
-use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    Email,
+use crate::{
+    domain::{
+        data_stores::{
+            LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, TWO_FA_CODE_TTL_SECONDS,
+        },
+        Clock, Email, PhoneNumber, SystemClock,
+    },
+    services::data_stores::RedisConnectionPool,
 };
 
 pub struct RedisTwoFACodeStore {
-    conn: Arc<RwLock<Connection>>,
+    pool: RedisConnectionPool,
+    max_attempts_per_user: usize,
+    clock: Arc<dyn Clock>,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(pool: RedisConnectionPool, max_attempts_per_user: usize) -> Self {
+        Self { pool, max_attempts_per_user: max_attempts_per_user.max(1), clock: Arc::new(SystemClock) }
+    }
+
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    fn ttl_seconds() -> Result<u64, TwoFACodeStoreError> {
+        TWO_FA_CODE_TTL_SECONDS
+            .try_into()
+            .wrap_err("failed to cast TWO_FA_CODE_TTL_SECONDS to u64")
+            .map_err(TwoFACodeStoreError::UnexpectedError)
+    }
+
+    // The user's pending attempts, oldest first and without the expired ones
+    async fn get_attempts(&self, email: &Email) -> Result<Vec<(String, i64)>, TwoFACodeStoreError> {
+        let attempts_raw: Option<String> = self.pool
+            .get()
+            .get(get_attempts_key(email))
+            .await
+            .wrap_err("failed to get 2FA attempts from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let Some(attempts_raw) = attempts_raw else {
+            return Ok(Vec::new());
+        };
+        let mut attempts: Vec<(String, i64)> =
+            serde_json::from_str(&attempts_raw)
+                .wrap_err("failed to deserialize 2FA attempts")
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let now = self.clock.now().timestamp();
+        attempts.retain(|(_, expires_at)| *expires_at > now);
+        Ok(attempts)
+    }
+
+    // GETDEL, so a login attempt is taken by one caller only
+    async fn take_login_attempt(&self, id: &str) -> Result<Option<TwoFARecord>, TwoFACodeStoreError> {
+        let stored_value_raw: Option<String> = self.pool
+            .get()
+            .get_del(get_code_key(id))
+            .await
+            .wrap_err("failed to delete 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let Some(stored_value_raw) = stored_value_raw else {
+            return Ok(None);
+        };
+        let two_fa_record: TwoFARecord = 
+            serde_json::from_str(&stored_value_raw)
+                .wrap_err("failed to deserialize 2FA record") 
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let (email, _) = two_fa_record.split()?;
+
+        // So the removed attempt no longer counts towards the cap
+        let mut attempts = self.get_attempts(&email).await?;
+        attempts.retain(|(attempt_id, _)| attempt_id != id);
+        self.set_attempts(&email, &attempts).await?;
+
+        Ok(Some(two_fa_record))
+    }
+
+    async fn set_attempts(&self, email: &Email, attempts: &[(String, i64)]) -> Result<(), TwoFACodeStoreError> {
+        let key = get_attempts_key(email);
+        if attempts.is_empty() {
+            return self.pool
+                .get()
+                .del(key)
+                .await
+                .wrap_err("failed to delete 2FA attempts from Redis")
+                .map_err(TwoFACodeStoreError::UnexpectedError);
+        }
+        let attempts_json = 
+            serde_json::to_string(attempts)
+                .wrap_err("failed to serialize 2FA attempts")
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        self.pool
+            .get()
+            .set_ex(key, attempts_json, Self::ttl_seconds()?)
+            .await
+            .wrap_err("failed to set 2FA attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)
     }
 }
 
@@ -24,71 +109,187 @@ impl RedisTwoFACodeStore {
 impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "AddCode", skip_all)]
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
         login_attempt_id: LoginAttemptId,
+        email: Email,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
-        let two_fa_tuple_json = 
-            serde_json::to_string(&TwoFATuple::new(login_attempt_id, code))
-            .wrap_err("failed to serialize 2FA tuple")
-            .map_err(TwoFACodeStoreError::UnexpectedError)?;
-        let mut connection = self.conn.write().await;
-        let ttl_seconds: u64 = 
-            TEN_MINUTES_IN_SECONDS
-                .try_into()
-                .wrap_err("failed to serialize 2FA tuple")
+        // The expiry is stored too, so the code dies by our clock and not only by Redis'
+        let expires_at = self.clock.now().timestamp() + TWO_FA_CODE_TTL_SECONDS;
+
+        // Read then written back, two logins racing may leave the user one attempt over the cap
+        // until the extra one expires
+        let mut attempts = self.get_attempts(&email).await?;
+        let excess = (attempts.len() + 1).saturating_sub(self.max_attempts_per_user);
+        for (dropped_id, _) in attempts.drain(..excess) {
+            let _: () = self.pool
+                .get()
+                .del(get_code_key(&dropped_id))
+                .await
+                .wrap_err("failed to delete 2FA code from Redis")
                 .map_err(TwoFACodeStoreError::UnexpectedError)?;
-        connection
-            .set_ex(key, two_fa_tuple_json, ttl_seconds)
+        }
+
+        let id = login_attempt_id.as_ref().expose_secret().to_owned();
+        let two_fa_record_json = 
+            serde_json::to_string(&TwoFARecord::new(email.clone(), code, expires_at))
+            .wrap_err("failed to serialize 2FA record")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let _: () = self.pool
+            .get()
+            .set_ex(get_code_key(&id), two_fa_record_json, Self::ttl_seconds()?)
+            .await
             .wrap_err("failed to set 2FA code in Redis")
-            .map_err(TwoFACodeStoreError::UnexpectedError)
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        attempts.push((id, expires_at));
+        self.set_attempts(&email, &attempts).await
     }
 
     #[tracing::instrument(name = "RemoveCode", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
-        let mut connection = self.conn.write().await;
-        connection
-            .del(key)
-            .wrap_err("failed to delete 2FA code from Redis")
+    async fn remove_code(&self, login_attempt_id: &LoginAttemptId) -> Result<(), TwoFACodeStoreError> {
+        let id = login_attempt_id.as_ref().expose_secret();
+        if self.take_login_attempt(id).await?.is_some() {
+            return Ok(());
+        }
+
+        // Phone number verifications do not count towards the cap
+        self.pool
+            .get()
+            .del(get_phone_number_code_key(id))
+            .await
+            .wrap_err("failed to delete phone number code from Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)
     }
 
     #[tracing::instrument(name = "GetCode", skip_all)]
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let key = get_key(&email);
-        let mut connection = self.conn.write().await;
-        let stored_value_raw:String = connection.get(key).map_err(|_| TwoFACodeStoreError::LoginAttemptIdNotFound)?;
-        let two_fa_tuple: TwoFATuple = 
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError> {
+        let key = get_code_key(login_attempt_id.as_ref().expose_secret());
+        let stored_value_raw: Option<String> = self.pool
+            .get()
+            .get(key)
+            .await
+            .wrap_err("failed to get 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let stored_value_raw = stored_value_raw.ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        let two_fa_record: TwoFARecord = 
             serde_json::from_str(&stored_value_raw)
-                .wrap_err("failed to deserialize 2FA tuple") 
+                .wrap_err("failed to deserialize 2FA record") 
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if two_fa_record.2 <= self.clock.now().timestamp() {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+        two_fa_record.split()
+    }
+
+    #[tracing::instrument(name = "TakeCode", skip_all)]
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError> {
+        let two_fa_record = self.take_login_attempt(login_attempt_id.as_ref().expose_secret())
+            .await?
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        if two_fa_record.2 <= self.clock.now().timestamp() {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+        two_fa_record.split()
+    }
+
+    #[tracing::instrument(name = "AddPhoneNumberCode", skip_all)]
+    async fn add_phone_number_code(
+        &self,
+        verification_id: LoginAttemptId,
+        email: Email,
+        phone_number: PhoneNumber,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let record = PhoneNumberCodeRecord {
+            email: email.as_ref().expose_secret().to_owned(),
+            phone_number: phone_number.as_ref().expose_secret().to_owned(),
+            code: code.as_ref().expose_secret().to_owned(),
+            expires_at: self.clock.now().timestamp() + TWO_FA_CODE_TTL_SECONDS,
+        };
+        let record_json =
+            serde_json::to_string(&record)
+                .wrap_err("failed to serialize phone number code record")
                 .map_err(TwoFACodeStoreError::UnexpectedError)?;
-        Ok(two_fa_tuple.split())
+
+        self.pool
+            .get()
+            .set_ex(get_phone_number_code_key(verification_id.as_ref().expose_secret()), record_json, Self::ttl_seconds()?)
+            .await
+            .wrap_err("failed to set phone number code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "GetPhoneNumberCode", skip_all)]
+    async fn get_phone_number_code(
+        &self,
+        verification_id: &LoginAttemptId,
+    ) -> Result<(Email, PhoneNumber, TwoFACode), TwoFACodeStoreError> {
+        let stored_value_raw: Option<String> = self.pool
+            .get()
+            .get(get_phone_number_code_key(verification_id.as_ref().expose_secret()))
+            .await
+            .wrap_err("failed to get phone number code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let stored_value_raw = stored_value_raw.ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        let record: PhoneNumberCodeRecord =
+            serde_json::from_str(&stored_value_raw)
+                .wrap_err("failed to deserialize phone number code record")
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if record.expires_at <= self.clock.now().timestamp() {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+
+        let email = Email::parse(Secret::new(record.email)).map_err(TwoFACodeStoreError::UnexpectedError)?;
+        let phone_number = PhoneNumber::parse(Secret::new(record.phone_number)).map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok((email, phone_number, TwoFACode(Secret::new(record.code))))
     }
 }
 
 
 //Class to manipulate the output of the class, contains sensible information, not to log!
 #[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+struct TwoFARecord(pub String, pub String, pub i64);
 
-impl TwoFATuple {
-    pub fn new(login_attempt_id:LoginAttemptId, code: TwoFACode) -> Self  {
-        TwoFATuple(login_attempt_id.0.expose_secret().to_owned(), code.0.expose_secret().to_owned())
+impl TwoFARecord {
+    pub fn new(email: Email, code: TwoFACode, expires_at: i64) -> Self  {
+        TwoFARecord(email.as_ref().expose_secret().to_owned(), code.0.expose_secret().to_owned(), expires_at)
     }
-    pub fn split(&self) -> (LoginAttemptId, TwoFACode) {
-        (LoginAttemptId(Secret::new(self.0.to_owned())), TwoFACode(Secret::new(self.1.clone())))
+    pub fn split(&self) -> Result<(Email, TwoFACode), TwoFACodeStoreError> {
+        let email = Email::parse(Secret::new(self.0.to_owned()))
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok((email, TwoFACode(Secret::new(self.1.clone()))))
     }
 }
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
-const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+// Holds sensible information too, not to log!
+#[derive(Serialize, Deserialize)]
+struct PhoneNumberCodeRecord {
+    email: String,
+    phone_number: String,
+    code: String,
+    expires_at: i64,
+}
+
+// Codes used to be keyed by email under two_fa_code:, those expire on their own
+const TWO_FA_CODE_PREFIX: &str = "two_fa_login_attempt:";
+const TWO_FA_ATTEMPTS_PREFIX: &str = "two_fa_user_attempts:";
+const PHONE_NUMBER_CODE_PREFIX: &str = "phone_number_verification:";
+
+fn get_code_key(login_attempt_id: &str) -> String {
+    format!("{}{}", TWO_FA_CODE_PREFIX, login_attempt_id)
+}
+
+fn get_phone_number_code_key(verification_id: &str) -> String {
+    format!("{}{}", PHONE_NUMBER_CODE_PREFIX, verification_id)
+}
 
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref().expose_secret())
+fn get_attempts_key(email: &Email) -> String {
+    format!("{}{}", TWO_FA_ATTEMPTS_PREFIX, email.as_ref().expose_secret())
 }
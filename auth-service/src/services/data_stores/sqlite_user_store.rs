@@ -0,0 +1,320 @@
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::Result;
+use sqlx::SqlitePool;
+use secrecy::{ExposeSecret, Secret}; 
+use crate::domain::{
+    data_stores::{UserStore, UserStoreError},
+    AccountStatus, Email, Password, PhoneNumber, TwoFAMethod, User, UserHashed, UserId
+};
+use super::{PasswordHashingError, PasswordHashingExecutor};
+
+// Single file database for single-node deployments and local runs without Postgres
+pub struct SqliteUserStore {
+    pool: SqlitePool,
+    password_hashing: PasswordHashingExecutor,
+    enumeration_protection: bool,
+}
+
+impl SqliteUserStore {
+    pub fn new(pool: SqlitePool, password_hashing: PasswordHashingExecutor) -> Self {
+        Self { pool, password_hashing, enumeration_protection: true }
+    }
+
+    // When on, validating an unknown email takes as long as a wrong password
+    pub fn with_enumeration_protection(mut self, enabled: bool) -> Self {
+        self.enumeration_protection = enabled;
+        self
+    }
+
+    // Best effort, the login already succeeded with the old hash
+    #[tracing::instrument(name = "Rehashing outdated password hash in SQLite", skip_all)]
+    async fn rehash_password(&self, id: &UserId, old_password_hash: &Secret<String>, password: &Password) {
+        let password_hash = match self.password_hashing.hash(password.as_ref().to_owned()).await {
+            Ok(password_hash) => password_hash,
+            Err(e) => {
+                tracing::warn!(error = %e, "Could not rehash password");
+                return;
+            }
+        };
+
+        // Only replaces the hash just verified, a concurrent password change wins
+        let result = sqlx::query("UPDATE users SET password_hash = ?3 WHERE id = ?1 AND password_hash = ?2")
+            .bind(id.to_string())
+            .bind(old_password_hash.expose_secret())
+            .bind(password_hash.expose_secret())
+            .execute(&self.pool)
+            .await;
+
+        if let Err(e) = result {
+            tracing::warn!(error = %e, "Could not store rehashed password");
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for SqliteUserStore {
+    #[tracing::instrument(name = "Adding user to SQLite", skip_all)] 
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let password_hash = self.password_hashing.hash(user.password.as_ref().to_owned()).await?;
+        sqlx::query("INSERT INTO users (id, email, password_hash, requires_2fa, password_changed_at, password_max_age_seconds, status, status_until) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")
+        .bind(user.id.to_string())
+        .bind(user.email.as_ref().expose_secret())
+        .bind(password_hash.expose_secret())
+        .bind(user.requires_2fa)
+        .bind(user.password_changed_at.timestamp())
+        .bind(user.password_max_age.map(|max_age| max_age.num_seconds()))
+        .bind(user.status.as_str())
+        .bind(user.status.until().map(|until| until.timestamp()))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_error) if db_error.is_unique_violation() => UserStoreError::UserAlreadyExists,
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Delete user from SQLite", skip_all)]
+    async fn delete_user(&self, id: &UserId) -> Result<(), UserStoreError>{
+        let result = sqlx::query(
+            "DELETE FROM users where id = ?1"
+        )
+        .bind(id.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving user from SQLite", skip_all)] 
+    async fn get_user(&self, email: &Email) -> Result<UserHashed, UserStoreError> {
+        sqlx::query_as::<_, UserHashed>(
+            "SELECT id, email, password_hash, requires_2fa, phone_number, phone_number_verified, two_fa_method, password_changed_at, password_max_age_seconds, status, status_until FROM users WHERE email = ?1"
+        )
+        .bind(email.as_ref().expose_secret())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)
+    }
+
+    #[tracing::instrument(name = "Retrieving user by id from SQLite", skip_all)]
+    async fn get_user_by_id(&self, id: &UserId) -> Result<UserHashed, UserStoreError> {
+        sqlx::query_as::<_, UserHashed>(
+            "SELECT id, email, password_hash, requires_2fa, phone_number, phone_number_verified, two_fa_method, password_changed_at, password_max_age_seconds, status, status_until FROM users WHERE id = ?1"
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)
+    }
+
+    #[tracing::instrument(name = "Validating user credentials in SQLite", skip_all)] 
+    async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError>{
+        let user = match self.get_user(email).await {
+            Ok(user) => user,
+            Err(UserStoreError::UserNotFound) if self.enumeration_protection => {
+                self.password_hashing.verify_dummy(password.as_ref()).await?;
+                return Err(UserStoreError::UserNotFound);
+            }
+            Err(error) => return Err(error), //we return the same UserNotFound error required
+        };
+
+        self.password_hashing
+            .verify(user.password_hash.as_ref(), password.as_ref())
+            .await?;
+
+        if self.password_hashing.needs_rehash(user.password_hash.as_ref()) {
+            self.rehash_password(&user.id, user.password_hash.as_ref(), password).await;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting phone number in SQLite", skip_all)]
+    async fn set_phone_number(&self, id: &UserId, phone_number: PhoneNumber) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            "UPDATE users SET phone_number = ?2, phone_number_verified = FALSE, two_fa_method = ?3 WHERE id = ?1"
+        )
+        .bind(id.to_string())
+        .bind(phone_number.as_ref().expose_secret())
+        .bind(TwoFAMethod::Email.as_str())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Verifying phone number in SQLite", skip_all)]
+    async fn verify_phone_number(&self, id: &UserId, phone_number: &PhoneNumber) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            "UPDATE users SET phone_number_verified = TRUE WHERE id = ?1 AND phone_number = ?2"
+        )
+        .bind(id.to_string())
+        .bind(phone_number.as_ref().expose_secret())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            // Either the user is gone or the number changed since the code was sent
+            0 => self.get_user_by_id(id).await.and(Err(UserStoreError::InvalidCredentials)),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Setting 2FA method in SQLite", skip_all)]
+    async fn set_two_fa_method(&self, id: &UserId, two_fa_method: TwoFAMethod) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            "UPDATE users SET two_fa_method = ?2 WHERE id = ?1 AND (?2 <> 'sms' OR (phone_number IS NOT NULL AND phone_number_verified))"
+        )
+        .bind(id.to_string())
+        .bind(two_fa_method.as_str())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => self.get_user_by_id(id).await.and(Err(UserStoreError::PhoneNumberNotVerified)),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Updating password in SQLite", skip_all)]
+    async fn update_password(&self, id: &UserId, password: Password, changed_at: DateTime<Utc>, history_size: usize) -> Result<(), UserStoreError> {
+        let user = self.get_user_by_id(id).await?;
+
+        let history: Vec<String> = sqlx::query_scalar(
+            "SELECT password_hash FROM password_history WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2"
+        )
+        .bind(id.to_string())
+        .bind(history_limit(history_size))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        let recent_password_hashes = std::iter::once(user.password_hash.as_ref().clone())
+            .chain(history.into_iter().map(Secret::new))
+            .take(history_size);
+        for password_hash in recent_password_hashes {
+            match self.password_hashing.verify(&password_hash, password.as_ref()).await {
+                Ok(()) => return Err(UserStoreError::PasswordReused),
+                Err(PasswordHashingError::Mismatch) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let password_hash = self.password_hashing.hash(password.as_ref().to_owned()).await?;
+
+        let mut transaction = self.pool.begin().await.map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        let result = sqlx::query("UPDATE users SET password_hash = ?2, password_changed_at = ?3 WHERE id = ?1")
+            .bind(id.to_string())
+            .bind(password_hash.expose_secret())
+            .bind(changed_at.timestamp())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        sqlx::query("INSERT INTO password_history (user_id, password_hash, changed_at) VALUES (?1, ?2, ?3)")
+            .bind(id.to_string())
+            .bind(user.password_hash.as_ref().expose_secret())
+            .bind(user.password_changed_at.timestamp())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query(
+            "DELETE FROM password_history WHERE user_id = ?1 AND id NOT IN (SELECT id FROM password_history WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2)"
+        )
+        .bind(id.to_string())
+        .bind(history_limit(history_size))
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        transaction.commit().await.map_err(|e| UserStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Setting password max age in SQLite", skip_all)]
+    async fn set_password_max_age(&self, id: &UserId, max_age: Option<Duration>) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET password_max_age_seconds = ?2 WHERE id = ?1")
+            .bind(id.to_string())
+            .bind(max_age.map(|max_age| max_age.num_seconds()))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Setting account status in SQLite", skip_all)]
+    async fn set_status(&self, id: &UserId, status: AccountStatus) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET status = ?2, status_until = ?3 WHERE id = ?1")
+            .bind(id.to_string())
+            .bind(status.as_str())
+            .bind(status.until().map(|until| until.timestamp()))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    // Everything else of the users goes with them, their tables cascade on delete
+    #[tracing::instrument(name = "Purging deleted users from SQLite", skip_all)]
+    async fn purge_deleted_users(&self, now: DateTime<Utc>) -> Result<u64, UserStoreError> {
+        let result = sqlx::query("DELETE FROM users WHERE status = ?1 AND status_until <= ?2")
+            .bind(AccountStatus::PendingDeletion { delete_at: now }.as_str())
+            .bind(now.timestamp())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Updating email in SQLite", skip_all)]
+    async fn update_email(&self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET email = ?2 WHERE id = ?1")
+            .bind(id.to_string())
+            .bind(new_email.as_ref().expose_secret())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match e {
+                // The email is unique, so a taken one fails the update
+                sqlx::Error::Database(db_error) if db_error.is_unique_violation() => UserStoreError::UserAlreadyExists,
+                e => UserStoreError::UnexpectedError(e.into()),
+            })?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+}
+
+// How many of the previous passwords are kept, the current one is the last of the history_size
+fn history_limit(history_size: usize) -> i64 {
+    i64::try_from(history_size.saturating_sub(1)).unwrap_or(i64::MAX)
+}
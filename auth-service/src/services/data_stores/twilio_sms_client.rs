@@ -0,0 +1,168 @@
+use color_eyre::eyre::Result;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::domain::{PhoneNumber, SmsClient};
+
+// Sends SMS through Twilio's Messages API, or any provider exposing the same interface
+pub struct TwilioSmsClient {
+    http_client: Client,
+    base_url: String,
+    sender: PhoneNumber,
+    account_sid: String,
+    auth_token: Secret<String>,
+}
+
+impl TwilioSmsClient {
+    pub fn new(
+        base_url: String,
+        sender: PhoneNumber,
+        account_sid: String,
+        auth_token: Secret<String>,
+        http_client: Client,
+    ) -> Self {
+        Self {
+            http_client,
+            base_url,
+            sender,
+            account_sid,
+            auth_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsClient for TwilioSmsClient {
+    #[tracing::instrument(name = "Sending SMS", skip_all)]
+    async fn send_sms(&self, recipient: &PhoneNumber, content: &Secret<String>) -> Result<()> {
+        let base = Url::parse(&self.base_url)?;
+        let url = base.join(&format!("/2010-04-01/Accounts/{}/Messages.json", self.account_sid))?;
+
+        // Twilio expects a form encoded body, see: https://www.twilio.com/docs/messaging/api/message-resource#create-a-message-resource
+        let request_body = SendSmsRequest {
+            from: self.sender.as_ref().expose_secret(),
+            to: recipient.as_ref().expose_secret(),
+            body: content.expose_secret(),
+        };
+
+        let request = self
+            .http_client
+            .post(url)
+            .basic_auth(&self.account_sid, Some(self.auth_token.expose_secret()))
+            .form(&request_body);
+
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct SendSmsRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    body: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use fake::faker::lorem::en::Sentence;
+    use fake::Fake;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    use super::*;
+
+    const ACCOUNT_SID: &str = "AC00000000000000000000000000000000";
+
+    fn content() -> Secret<String> {
+        Secret::new(Sentence(1..2).fake())
+    }
+
+    fn phone_number() -> PhoneNumber {
+        PhoneNumber::parse(Secret::new("+34600123456".to_owned())).unwrap()
+    }
+
+    fn sms_client(base_url: String) -> TwilioSmsClient {
+        let http_client = Client::builder()
+            .timeout(std::time::Duration::from_millis(200))
+            .build()
+            .unwrap();
+        TwilioSmsClient::new(
+            base_url,
+            PhoneNumber::parse(Secret::new("+15005550006".to_owned())).unwrap(),
+            ACCOUNT_SID.to_owned(),
+            Secret::new("auth_token".to_owned()),
+            http_client,
+        )
+    }
+
+    struct SendSmsBodyMatcher;
+
+    impl wiremock::Match for SendSmsBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let body = String::from_utf8_lossy(&request.body);
+            let keys: Vec<&str> = body
+                .split('&')
+                .filter_map(|pair| pair.split('=').next())
+                .collect();
+            ["From", "To", "Body"]
+                .iter()
+                .all(|field| keys.contains(field))
+        }
+    }
+
+    #[tokio::test]
+    async fn send_sms_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(header_exists("Authorization"))
+            .and(header("Content-Type", "application/x-www-form-urlencoded"))
+            .and(path(format!("/2010-04-01/Accounts/{}/Messages.json", ACCOUNT_SID)))
+            .and(method("POST"))
+            .and(SendSmsBodyMatcher)
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &content()).await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_sms_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &content()).await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_sms_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(201).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &content()).await;
+
+        assert!(outcome.is_err());
+    }
+}
@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+
+use crate::app_state::{ClockType, UserStoreType};
+
+// Hard deletes the accounts whose deletion grace period is over, for as long as the app runs.
+// A failed run is only logged, the next one picks up what it missed.
+#[tracing::instrument(name = "PurgeDeletedAccounts", skip_all)]
+pub async fn purge_deleted_accounts(user_store: UserStoreType, clock: ClockType, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        match user_store.purge_deleted_users(clock.now()).await {
+            Ok(0) => (),
+            Ok(purged) => tracing::info!(purged, "Purged deleted accounts"),
+            Err(e) => tracing::warn!(error = %e, "Could not purge deleted accounts"),
+        }
+    }
+}
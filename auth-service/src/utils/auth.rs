@@ -1,30 +1,42 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
-use chrono::Utc;
+use axum_extra::extract::{cookie::{Cookie, SameSite}, CookieJar};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
-use crate::{app_state::BannedTokenStoreType, domain::email::Email};
+use crate::{
+    app_state::BannedTokenStoreType,
+    domain::{data_stores::JWT_LEEWAY_SECONDS, AuthAPIError, Clock, UserId},
+    utils::HttpSettings,
+};
 use color_eyre::eyre::{eyre, Context, Result};
+use uuid::Uuid;
 
 #[tracing::instrument(name = "GenerateAuthCookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email, jwt_secret:Secret<String>, jwt_cookie_name:String, token_ttl_millis:i64) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email, jwt_secret, token_ttl_millis)?;
+pub fn generate_auth_cookie(user_id: &UserId, amr: &[AuthMethod], jwt_secret:Secret<String>, jwt_cookie_name:String, token_ttl_millis:i64, clock: &dyn Clock) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(user_id, None, amr, jwt_secret, token_ttl_millis, clock)?;
     Ok(create_auth_cookie(token, false, jwt_cookie_name))
 }
 
 #[tracing::instrument(name = "GenerateAuthCookieWithoutDomain", skip_all)]
-pub fn generate_auth_cookie_without_domain(email: &Email, jwt_secret:Secret<String>, jwt_cookie_name:String, token_ttl_millis:i64) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email, jwt_secret, token_ttl_millis)?;
+pub fn generate_auth_cookie_without_domain(user_id: &UserId, amr: &[AuthMethod], jwt_secret:Secret<String>, jwt_cookie_name:String, token_ttl_millis:i64, clock: &dyn Clock) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(user_id, None, amr, jwt_secret, token_ttl_millis, clock)?;
     Ok(create_auth_cookie(token, true, jwt_cookie_name))
 }
 
+// A new token for the same session, the user did not prove who they are again so
+// auth_time and amr are carried over
+#[tracing::instrument(name = "GenerateRefreshedAuthCookie", skip_all)]
+pub fn generate_refreshed_auth_cookie(user_id: &UserId, previous: &Claims, jwt_secret:Secret<String>, jwt_cookie_name:String, token_ttl_millis:i64, clock: &dyn Clock) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(user_id, Some(previous.auth_time), &previous.amr, jwt_secret, token_ttl_millis, clock)?;
+    Ok(create_auth_cookie(token, false, jwt_cookie_name))
+}
+
 #[tracing::instrument(name = "GenerateAuthCookieEmpty", skip_all)]
 pub fn generate_auth_cookie_empty(jwt_cookie_name:String) -> Cookie<'static> {
     create_auth_cookie("".to_owned(), true, jwt_cookie_name)
 }
 
 #[tracing::instrument(name = "CreateAuthCookie", skip_all)]
-fn create_auth_cookie(token: String, without_domain:bool, jwt_cookie_name:String) -> Cookie<'static> {
+pub(crate) fn create_auth_cookie(token: String, without_domain:bool, jwt_cookie_name:String) -> Cookie<'static> {
 
     let cookie_build = Cookie::build((jwt_cookie_name, token))
         .path("/") // apply cookie to all URLs on the server
@@ -41,18 +53,26 @@ fn create_auth_cookie(token: String, without_domain:bool, jwt_cookie_name:String
 }
 
 
+// Without an auth_time the user is taken to have just authenticated
 #[tracing::instrument(name = "GenerateAuthToken", skip_all)]
-fn generate_auth_token(email: &Email, jwt_secret: Secret<String>, token_ttl_millis:i64) -> Result<String> {
+fn generate_auth_token(user_id: &UserId, auth_time: Option<usize>, amr: &[AuthMethod], jwt_secret: Secret<String>, token_ttl_millis:i64, clock: &dyn Clock) -> Result<String> {
     let delta = 
         chrono::Duration::try_milliseconds(token_ttl_millis)
             .ok_or(eyre!("failed to create 10 minute time delta"))?;
 
+    let now = clock.now();
+
     // Create JWT expiration time
-    let exp = Utc::now()
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(eyre!("failed to add 10 minutes to current time"))?
         .timestamp();
 
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .wrap_err("failed to cast iat time to usize")?;
+
     // Cast exp to a usize, which is what Claims expects
     let exp: usize = exp
         .try_into()
@@ -61,27 +81,87 @@ fn generate_auth_token(email: &Email, jwt_secret: Secret<String>, token_ttl_mill
             exp
         ))?;
 
-    let sub = email.as_ref().expose_secret().to_owned();
+    let sub = user_id.to_string();
 
-    let claims = Claims { sub, exp };
+    let claims = Claims {
+        sub,
+        exp,
+        iat,
+        jti: generate_jti(),
+        auth_time: auth_time.unwrap_or(iat),
+        amr: amr.to_vec(),
+    };
 
     create_token(&claims, jwt_secret)
 }
 
 #[tracing::instrument(name = "ValidateToken", skip_all)]
-pub async fn validate_token(banned_token_store: BannedTokenStoreType, token: &Secret<String>, jwt_secret:Secret<String>) -> Result<Claims> {
-    let _ = match banned_token_store.read().await.contains_token(token).await {
-        Ok(false) => (),
-        Ok(true) => return Err(eyre!("token is banned")),
-        Err(e) => return Err(e.into()),
-    };
-    decode::<Claims>(
+pub async fn validate_token(banned_token_store: BannedTokenStoreType, token: &Secret<String>, jwt_secret:Secret<String>, clock: &dyn Clock) -> Result<Claims> {
+    let claims = decode::<Claims>(
         token.expose_secret(),
         &DecodingKey::from_secret(jwt_secret.expose_secret().as_bytes()),
-        &Validation::default(),
+        &clock_validation(Validation::default()),
     )
     .map(|data| data.claims)
-    .wrap_err("failed to decode token")
+    .wrap_err("failed to decode token")?;
+
+    check_not_expired(claims.exp, clock)?;
+
+    if banned_token_store.contains_token(&claims.jti).await? {
+        return Err(eyre!("token is banned"));
+    }
+
+    let user_id = UserId::parse(&claims.sub).wrap_err("token subject is not a user id")?;
+
+    match banned_token_store.tokens_revoked_before(&user_id).await? {
+        Some(revoked_before) if claims.iat < revoked_before => Err(eyre!("token was revoked")),
+        _ => Ok(claims),
+    }
+}
+
+// For routes only available to logged in users, returns who owns the auth cookie
+#[tracing::instrument(name = "GetAuthenticatedUserId", skip_all)]
+pub async fn get_authenticated_user_id(jar: &CookieJar, banned_token_store: BannedTokenStoreType, http_settings: &HttpSettings, clock: &dyn Clock) -> Result<UserId, AuthAPIError> {
+    get_authenticated_claims(jar, banned_token_store, http_settings, clock)
+        .await
+        .map(|(user_id, _)| user_id)
+}
+
+// Same as get_authenticated_user_id, for routes that also look at how the user logged in
+#[tracing::instrument(name = "GetAuthenticatedClaims", skip_all)]
+pub async fn get_authenticated_claims(jar: &CookieJar, banned_token_store: BannedTokenStoreType, http_settings: &HttpSettings, clock: &dyn Clock) -> Result<(UserId, Claims), AuthAPIError> {
+    let cookie = jar.get(&http_settings.jwt_cookie_name).ok_or(AuthAPIError::MissingToken)?;
+    let token = Secret::new(cookie.value().to_owned());
+
+    let claims = validate_token(banned_token_store, &token, http_settings.jwt_token.clone(), clock)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    Ok((user_id, claims))
+}
+
+// Unique id of a token, what the banned token store keys bans on
+pub fn generate_jti() -> String {
+    Uuid::new_v4().to_string()
+}
+
+// jsonwebtoken checks exp against the system time, our tokens are checked against the clock
+// with check_not_expired instead
+pub fn clock_validation(mut validation: Validation) -> Validation {
+    validation.validate_exp = false;
+    validation
+}
+
+// Same leeway as jsonwebtoken's
+pub fn check_not_expired(exp: usize, clock: &dyn Clock) -> Result<()> {
+    let exp: i64 = exp.try_into().wrap_err("failed to cast exp time to i64")?;
+
+    if exp + JWT_LEEWAY_SECONDS < clock.now().timestamp() {
+        return Err(eyre!("token has expired"));
+    }
+    Ok(())
 }
 
 #[tracing::instrument(name = "CreateToken", skip_all)]
@@ -96,8 +176,38 @@ fn create_token(claims: &Claims, jwt_secret: Secret<String>) -> Result<String> {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
+    // The user id, emails can change and should not end up in every token
     pub sub: String,
     pub exp: usize,
+    // Tokens issued before this field existed count as issued at the epoch
+    #[serde(default)]
+    pub iat: usize,
+    // Required, a token without one could not be banned on logout
+    pub jti: String,
+    // When the user last proved who they are, refreshing a token keeps it. Older tokens
+    // count as authenticated at the epoch, so they never pass a step-up check
+    #[serde(default)]
+    pub auth_time: usize,
+    #[serde(default)]
+    pub amr: Vec<AuthMethod>,
+}
+
+// How the user proved who they are, the values of the amr claim. RFC 8176 names where it has them
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    #[serde(rename = "pwd")]
+    Password,
+    #[serde(rename = "otp")]
+    OneTimeCode,
+    // Set along with the second factor
+    #[serde(rename = "mfa")]
+    MultiFactor,
+    // A magic link followed from the user's inbox
+    #[serde(rename = "email")]
+    EmailLink,
+    // Logged in through an identity provider
+    #[serde(rename = "fed")]
+    Federated,
 }
 
 #[cfg(test)]
@@ -105,9 +215,13 @@ mod tests {
     use std::sync::Arc;
 
     use secrecy::Secret;
-    use tokio::sync::RwLock;
 
-    use crate::{domain::data_stores::banned_token_store::BannedTokenStore, services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore};
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        domain::{data_stores::banned_token_store::BannedTokenStore, ManualClock, SystemClock},
+        services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+    };
 
     use super::*;
 
@@ -115,9 +229,9 @@ mod tests {
     async fn test_generate_auth_cookie() {
         let jwt_token = Secret::new("secret".to_owned());
         let jwt_cookie_name = "jwt".to_owned();
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let user_id = UserId::default();
         let token_ttl_millis:i64 = 100;
-        let cookie = generate_auth_cookie(&email, jwt_token, jwt_cookie_name.clone(), token_ttl_millis).unwrap();
+        let cookie = generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name.clone(), token_ttl_millis, &SystemClock).unwrap();
         assert_eq!(cookie.name(), &jwt_cookie_name);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -140,24 +254,43 @@ mod tests {
     #[tokio::test]
     async fn test_generate_auth_token() {
         let jwt_token = Secret::new("secret".to_owned());
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let user_id = UserId::default();
         let token_ttl_millis:i64 = 100;
-        let result = generate_auth_token(&email, jwt_token, token_ttl_millis).unwrap();
+        let result = generate_auth_token(&user_id, None, &[AuthMethod::Password], jwt_token, token_ttl_millis, &SystemClock).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
+    #[tokio::test]
+    async fn test_refreshed_token_keeps_authentication() {
+        let jwt_token = Secret::new("secret".to_owned());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let clock = ManualClock::default();
+        let user_id = UserId::default();
+        let cookie = generate_auth_cookie(&user_id, &[AuthMethod::OneTimeCode, AuthMethod::MultiFactor], jwt_token.clone(), "jwt".to_owned(), 600 * 1000, &clock).unwrap();
+        let claims = validate_token(banned_token_store.clone(), &Secret::new(cookie.value().to_owned()), jwt_token.clone(), &clock).await.unwrap();
+        assert_eq!(claims.auth_time, claims.iat);
+
+        clock.advance(Duration::seconds(60));
+
+        let cookie = generate_refreshed_auth_cookie(&user_id, &claims, jwt_token.clone(), "jwt".to_owned(), 600 * 1000, &clock).unwrap();
+        let refreshed = validate_token(banned_token_store, &Secret::new(cookie.value().to_owned()), jwt_token, &clock).await.unwrap();
+        assert_eq!(refreshed.iat, claims.iat + 60);
+        assert_eq!(refreshed.auth_time, claims.auth_time);
+        assert_eq!(refreshed.amr, vec![AuthMethod::OneTimeCode, AuthMethod::MultiFactor]);
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
         let jwt_token = Secret::new("secret".to_owned());
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let user_id = UserId::default();
         let token_ttl_millis:i64 = 600 * 1000;
-        let token = generate_auth_token(&email, jwt_token.clone(), token_ttl_millis).unwrap();
-        let result = validate_token(banned_token_store, &Secret::new(token), jwt_token).await.unwrap();
-        assert_eq!(result.sub, "test@example.com");
+        let token = generate_auth_token(&user_id, None, &[AuthMethod::Password], jwt_token.clone(), token_ttl_millis, &SystemClock).unwrap();
+        let result = validate_token(banned_token_store, &Secret::new(token), jwt_token, &SystemClock).await.unwrap();
+        assert_eq!(result.sub, user_id.to_string());
 
         let exp = Utc::now()
-            .checked_add_signed(chrono::Duration::try_milliseconds(token_ttl_millis - 60 * 1000).expect("valid duration"))
+            .checked_add_signed(Duration::try_milliseconds(token_ttl_millis - 60 * 1000).expect("valid duration"))
             .expect("valid timestamp")
             .timestamp();
 
@@ -167,19 +300,88 @@ mod tests {
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let jwt_token = Secret::new("secret".to_owned());
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
         let token = Secret::new("invalid_token".to_owned());
-        let result = validate_token(banned_token_store, &token, jwt_token).await;
+        let result = validate_token(banned_token_store, &token, jwt_token, &SystemClock).await;
         assert!(result.is_err());
     }
 
         #[tokio::test]
     async fn test_validate_token_with_banned_token() {
         let jwt_token = Secret::new("secret".to_owned());
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let token = Secret::new("banned_token".to_owned());
-        let _ = banned_token_store.write().await.add_token(token.clone());
-        let result = validate_token(banned_token_store, &token, jwt_token).await;
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let token = Secret::new(generate_auth_token(&UserId::default(), None, &[AuthMethod::Password], jwt_token.clone(), 600 * 1000, &SystemClock).unwrap());
+        let claims = validate_token(banned_token_store.clone(), &token, jwt_token.clone(), &SystemClock).await.unwrap();
+
+        banned_token_store.add_token(&claims.jti, claims.exp).await.unwrap();
+
+        let result = validate_token(banned_token_store, &token, jwt_token, &SystemClock).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_without_jti() {
+        let jwt_token = Secret::new("secret".to_owned());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let claims = serde_json::json!({
+            "sub": UserId::default().to_string(),
+            "exp": Utc::now().timestamp() + 600,
+            "iat": Utc::now().timestamp(),
+        });
+        let token = encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &EncodingKey::from_secret(jwt_token.expose_secret().as_bytes()),
+        ).unwrap();
+        let result = validate_token(banned_token_store, &Secret::new(token), jwt_token, &SystemClock).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_email_subject() {
+        // Tokens issued before subjects became user ids
+        let jwt_token = Secret::new("secret".to_owned());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            exp: (Utc::now().timestamp() + 600) as usize,
+            iat: Utc::now().timestamp() as usize,
+            jti: generate_jti(),
+            auth_time: Utc::now().timestamp() as usize,
+            amr: vec![AuthMethod::Password],
+        };
+        let token = Secret::new(create_token(&claims, jwt_token.clone()).unwrap());
+        let result = validate_token(banned_token_store, &token, jwt_token, &SystemClock).await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_validate_token_issued_before_revocation() {
+        let jwt_token = Secret::new("secret".to_owned());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let user_id = UserId::default();
+        let token = Secret::new(generate_auth_token(&user_id, None, &[AuthMethod::Password], jwt_token.clone(), 600 * 1000, &SystemClock).unwrap());
+
+        let issued_at = validate_token(banned_token_store.clone(), &token, jwt_token.clone(), &SystemClock).await.unwrap().iat;
+
+        let _ = banned_token_store.revoke_tokens_before(&user_id, issued_at).await;
+        assert!(validate_token(banned_token_store.clone(), &token, jwt_token.clone(), &SystemClock).await.is_ok());
+
+        let _ = banned_token_store.revoke_tokens_before(&user_id, issued_at + 1).await;
+        assert!(validate_token(banned_token_store, &token, jwt_token, &SystemClock).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_expires_by_the_clock() {
+        let jwt_token = Secret::new("secret".to_owned());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let clock = ManualClock::default();
+        let token = Secret::new(generate_auth_token(&UserId::default(), None, &[AuthMethod::Password], jwt_token.clone(), 600 * 1000, &clock).unwrap());
+
+        clock.advance(Duration::seconds(600 + JWT_LEEWAY_SECONDS));
+        assert!(validate_token(banned_token_store.clone(), &token, jwt_token.clone(), &clock).await.is_ok());
+
+        clock.advance(Duration::seconds(1));
+        assert!(validate_token(banned_token_store, &token, jwt_token, &clock).await.is_err());
+    }
 }
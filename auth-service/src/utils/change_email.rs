@@ -0,0 +1,83 @@
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::BannedTokenStoreType,
+    domain::{email::Email, Clock, UserId},
+    utils::scoped_token,
+};
+
+const CHANGE_EMAIL_AUDIENCE: &str = "change-email";
+
+#[tracing::instrument(name = "GenerateChangeEmailToken", skip_all)]
+pub fn generate_change_email_token(user_id: &UserId, new_email: &Email, jwt_secret: Secret<String>, token_ttl_millis: i64, clock: &dyn Clock) -> Result<Secret<String>> {
+    let claims = ChangeEmailClaims {
+        sub: user_id.to_string(),
+        new_email: new_email.as_ref().expose_secret().to_owned(),
+    };
+
+    scoped_token::issue(CHANGE_EMAIL_AUDIENCE, claims, scoped_token::expires_in(token_ttl_millis, clock)?, jwt_secret)
+}
+
+// A confirmation is accepted once, validating it uses it up
+#[tracing::instrument(name = "ValidateChangeEmailToken", skip_all)]
+pub async fn validate_change_email_token(
+    banned_token_store: BannedTokenStoreType,
+    token: &Secret<String>,
+    jwt_secret: Secret<String>,
+    clock: &dyn Clock,
+) -> Result<ChangeEmailClaims> {
+    scoped_token::validate_once(CHANGE_EMAIL_AUDIENCE, token, jwt_secret, banned_token_store, clock).await
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChangeEmailClaims {
+    pub sub: String,
+    pub new_email: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{domain::SystemClock, services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validate_change_email_token() {
+        let jwt_secret = Secret::new("secret".to_owned());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let user_id = UserId::default();
+        let new_email = Email::parse(Secret::new("new@example.com".to_owned())).unwrap();
+
+        let token = generate_change_email_token(&user_id, &new_email, jwt_secret.clone(), 60 * 1000, &SystemClock).unwrap();
+        let claims = validate_change_email_token(banned_token_store, &token, jwt_secret, &SystemClock).await.unwrap();
+
+        assert_eq!(claims.sub, user_id.to_string());
+        assert_eq!(claims.new_email, "new@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_change_email_token_signed_with_another_secret() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let new_email = Email::parse(Secret::new("new@example.com".to_owned())).unwrap();
+
+        let token = generate_change_email_token(&UserId::default(), &new_email, Secret::new("other".to_owned()), 60 * 1000, &SystemClock).unwrap();
+        let result = validate_change_email_token(banned_token_store, &token, Secret::new("secret".to_owned()), &SystemClock).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_change_email_token_is_accepted_once() {
+        let jwt_secret = Secret::new("secret".to_owned());
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let new_email = Email::parse(Secret::new("new@example.com".to_owned())).unwrap();
+
+        let token = generate_change_email_token(&UserId::default(), &new_email, jwt_secret.clone(), 60 * 1000, &SystemClock).unwrap();
+        assert!(validate_change_email_token(banned_token_store.clone(), &token, jwt_secret.clone(), &SystemClock).await.is_ok());
+        assert!(validate_change_email_token(banned_token_store, &token, jwt_secret, &SystemClock).await.is_err());
+    }
+}
@@ -1,14 +1,48 @@
+use ipnet::IpNet;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use std::env as std_env;
 
+use crate::domain::data_stores::TokenBucket;
+
 #[derive(Deserialize, Clone)]
 pub struct AuthSettings {
+    #[serde(default)]
+    pub profile: RunProfile,
     pub http: HttpSettings,
     pub grpc: GrpcSettings,
     pub database: DatabaseSettings,
     pub redis: RedisSettings,
     pub email: EmailSettings,
+    pub sms: SmsSettings,
+    pub magic_link: MagicLinkSettings,
+    pub oidc: OidcSettings,
+    pub change_email: ChangeEmailSettings,
+    pub password_reset: PasswordResetSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub password_rotation: PasswordRotationSettings,
+    pub account_deletion: AccountDeletionSettings,
+    pub enumeration_protection: EnumerationProtectionSettings,
+    pub idempotency: IdempotencySettings,
+    pub two_fa: TwoFASettings,
+    pub trusted_device: TrustedDeviceSettings,
+    pub login_alert: LoginAlertSettings,
+    pub rate_limit: RateLimitSettings,
+    pub proof_of_work: ProofOfWorkSettings,
+    #[serde(default)]
+    pub dev: DevSettings,
+}
+
+// What the app stores its data in and sends messages through, RUN_PROFILE overrides it
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunProfile {
+    // Postgres or SQLite, Redis, Postmark and Twilio
+    #[default]
+    Services,
+    // Everything in process, data is lost on restart and emails only reach the dev mailbox
+    Memory,
 }
 
 #[derive(Deserialize, Clone)]
@@ -25,13 +59,155 @@ pub struct GrpcSettings {
 
 #[derive(Deserialize, Clone)]
 pub struct DatabaseSettings {
+    // postgres://... or sqlite:path/to/file.db, the scheme picks the user store
     pub url: Secret<String>,
 }
 
+impl DatabaseSettings {
+    pub fn is_sqlite(&self) -> bool {
+        self.url.expose_secret().starts_with("sqlite:")
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct RedisSettings {
     pub host_name: String,
     pub ttl_millis: i64,
+    // Multiplexed connections shared by the Redis stores
+    pub pool_size: usize,
+    pub connection_timeout_millis: u64,
+    pub response_timeout_millis: u64,
+    // Attempts with exponential backoff before a dropped connection gives up
+    pub reconnect_retries: usize,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PasswordHashingSettings {
+    // Argon2id parameters for new hashes, stored hashes made with others are redone on login
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    // Hashes allowed to run at once, the others wait for a slot
+    pub max_concurrency: usize,
+    // Longest wait for a slot before the request is turned away with a 503
+    pub queue_timeout_millis: u64,
+}
+
+// Checked when a password is chosen, logging in with one chosen before a change keeps working
+#[derive(Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    // Never below the 8 characters every password needs
+    pub min_length: usize,
+    // Estimated from the kinds of characters used, runs like "aaa" or "123" count as one character
+    pub min_entropy_bits: f64,
+    // One common password per line, compared ignoring case. Empty to skip the check.
+    #[serde(default)]
+    pub blocklist_path: String,
+    // Directory of SHA-1 range files like the ones of Have I Been Pwned, 00000.txt to FFFFF.txt with
+    // a SUFFIX:COUNT line per breached password. Empty to skip the check.
+    #[serde(default)]
+    pub breached_corpus_dir: String,
+    // Refuses passwords containing the email or the part before the @
+    pub reject_email: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PasswordRotationSettings {
+    // A new password can not be any of the last history_size ones, the current one included
+    pub history_size: usize,
+    // How long a login with an expired password has to change it, the token it gets is only good for that
+    pub change_token_ttl_millis: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AccountDeletionSettings {
+    // A deleted account can not log in but keeps its data this long, then the purge job removes it
+    pub grace_period_millis: i64,
+    // How often the purge job looks for accounts past their grace period
+    pub purge_interval_millis: u64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EnumerationProtectionSettings {
+    // Login and signup answer the same way, and just as fast, whether or not the email has an account
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct IdempotencySettings {
+    // How long a response is replayed to retries sent with the same Idempotency-Key
+    pub ttl_millis: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TwoFASettings {
+    // Logins a user can have waiting for a code at once, e.g. one per device, a new one drops the oldest
+    pub max_attempts_per_user: usize,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TrustedDeviceSettings {
+    // Set after a 2FA login when the user asks to remember the browser
+    pub cookie_name: String,
+    // How long logins from the browser skip 2FA
+    pub ttl_millis: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LoginAlertSettings {
+    // Page the "this wasn't me" link points to, the token is appended as the secure_account_token query parameter
+    pub base_url: String,
+    pub ttl_millis: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    // Proxies whose X-Forwarded-For tells who the client is, anyone else connecting is the client.
+    // Login alerts take the address from the same list, also with the rate limiter disabled.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+    // Routes not listed here are not limited
+    #[serde(default)]
+    pub routes: Vec<RouteRateLimitSettings>,
+}
+
+impl RateLimitSettings {
+    pub fn route(&self, path: &str) -> Option<&RouteRateLimitSettings> {
+        self.routes.iter().find(|route| route.path == path)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RouteRateLimitSettings {
+    // Under /auth, e.g. /login
+    pub path: String,
+    // Shared by the requests from each client address
+    pub per_ip: Option<TokenBucket>,
+    // Shared by the requests for each email in the JSON body, wherever they come from
+    pub per_email: Option<TokenBucket>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProofOfWorkSettings {
+    pub enabled: bool,
+    // Under /auth, requests to them must carry a solved challenge from /auth/challenge
+    #[serde(default)]
+    pub routes: Vec<String>,
+    // Time to solve and use a challenge, used ones are banned by jti until they expire
+    pub ttl_millis: i64,
+    // Leading zero bits the hash of a solution needs, each one doubles the work
+    pub base_difficulty: u32,
+    // Added for every token the rate limiter took from the abuse bucket of the client
+    pub difficulty_step: u32,
+    // Loses a token whenever the rate limiter turns the client away, its capacity caps the difficulty
+    pub abuse: TokenBucket,
+}
+
+impl ProofOfWorkSettings {
+    pub fn protects(&self, path: &str) -> bool {
+        self.enabled && self.routes.iter().any(|route| route == path)
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -42,6 +218,67 @@ pub struct EmailSettings {
     pub timeout_milliseconds: u64,
 }
 
+#[derive(Deserialize, Clone)]
+pub struct SmsSettings {
+    pub base_url: String,
+    pub account_sid: String,
+    pub auth_token: Secret<String>,
+    pub sender: String,
+    pub timeout_milliseconds: u64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MagicLinkSettings {
+    // Page the emailed link points to, the token is appended as the magic_token query parameter
+    pub base_url: String,
+    pub ttl_millis: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ChangeEmailSettings {
+    // Page the confirmation link points to, the token is appended as the change_email_token query parameter
+    pub base_url: String,
+    pub ttl_millis: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PasswordResetSettings {
+    // Page the emailed link points to, the token is appended as the password_reset_token query parameter
+    pub base_url: String,
+    pub ttl_millis: i64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct OidcSettings {
+    // Each provider calls back at {redirect_base_url}/{name}/callback
+    pub redirect_base_url: String,
+    // Where the browser lands once logged in through a provider
+    pub post_login_redirect: String,
+    pub timeout_milliseconds: u64,
+    pub jwks_cache_ttl_millis: i64,
+    #[serde(default)]
+    pub providers: Vec<OidcProviderSettings>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct OidcProviderSettings {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Secret<String>,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    pub scopes: String,
+}
+
+// Tooling that must never be enabled in production
+#[derive(Deserialize, Clone, Default)]
+pub struct DevSettings {
+    // Captures sent emails in memory and serves them at /auth/dev/mailbox
+    pub mailbox: bool,
+}
+
 
 
 impl AuthSettings {
@@ -52,9 +289,13 @@ impl AuthSettings {
         let run_env = std_env::var("RUN_ENV").unwrap_or_else(|_| "default".into());
 
         let jwt = std::env::var(env::JWT_SECRET_ENV_VAR).expect(&format!("{} must be set.", env::JWT_SECRET_ENV_VAR));
-        let postmark_auth_token = std::env::var(env::POSTMARK_AUTH_TOKEN_ENV_VAR).expect(&format!("{} must be set.", env::POSTMARK_AUTH_TOKEN_ENV_VAR));
-        let db_url = std::env::var(env::DATABASE_URL_ENV_VAR).expect(&format!("{} must be set.", env::DATABASE_URL_ENV_VAR));
+        // Checked below, the memory profile runs without them
+        let postmark_auth_token = std::env::var(env::POSTMARK_AUTH_TOKEN_ENV_VAR).ok();
+        let db_url = std::env::var(env::DATABASE_URL_ENV_VAR).ok();
+        let run_profile = std::env::var(env::RUN_PROFILE_ENV_VAR).ok();
         let redis_host = std::env::var(env::REDIS_HOST_NAME_ENV_VAR).ok();
+        // Optional so environments without SMS 2FA do not need a provider account
+        let sms_auth_token = std::env::var(env::SMS_AUTH_TOKEN_ENV_VAR).unwrap_or_default();
 
         let builder = config::Config::builder()
             // Load base config
@@ -63,13 +304,37 @@ impl AuthSettings {
             .add_source(config::File::with_name(&format!("config/{}", run_env)).required(false))
             //load variables from env
             .set_override("http.jwt_token", jwt).unwrap()
-            .set_override("email.postmark_auth_token", postmark_auth_token).unwrap()
-            .set_override("database.url", db_url).unwrap()
-            .set_override_option("redis.host_name", redis_host).unwrap();
+            .set_override_option("email.postmark_auth_token", postmark_auth_token.clone()).unwrap()
+            .set_override_option("database.url", db_url.clone()).unwrap()
+            .set_override_option("profile", run_profile).unwrap()
+            .set_override_option("redis.host_name", redis_host).unwrap()
+            .set_override("sms.auth_token", sms_auth_token).unwrap();
 
         let cfg = builder.build().unwrap();
 
-        let auth_settings: AuthSettings = cfg.try_deserialize().unwrap();
+        let mut auth_settings: AuthSettings = cfg.try_deserialize().unwrap();
+
+        match auth_settings.profile {
+            RunProfile::Services => {
+                if db_url.is_none() {
+                    panic!("{} must be set.", env::DATABASE_URL_ENV_VAR);
+                }
+                if postmark_auth_token.is_none() {
+                    panic!("{} must be set.", env::POSTMARK_AUTH_TOKEN_ENV_VAR);
+                }
+            }
+            // Emails have nowhere else to go
+            RunProfile::Memory => auth_settings.dev.mailbox = true,
+        }
+
+        // Provider secrets stay out of the config files, e.g. OIDC_GOOGLE_CLIENT_SECRET
+        for provider in auth_settings.oidc.providers.iter_mut() {
+            let env_var = format!("{}{}{}", env::OIDC_CLIENT_SECRET_ENV_VAR_PREFIX, provider.name.to_uppercase(), env::OIDC_CLIENT_SECRET_ENV_VAR_SUFFIX);
+            if let Ok(client_secret) = std::env::var(env_var) {
+                provider.client_secret = Secret::new(client_secret);
+            }
+        }
+
         auth_settings.validate()
     }
 
@@ -77,7 +342,7 @@ impl AuthSettings {
         if self.http.jwt_token.expose_secret().trim().is_empty() {
             panic!("JWT_SECRET must be set and not empty.");
         }
-        if self.database.url.expose_secret().trim().is_empty() {
+        if self.profile == RunProfile::Services && self.database.url.expose_secret().trim().is_empty() {
             panic!("DATABASE_URL must be set and not empty.");
         }
         // Optional: ensure Redis is always present
@@ -94,4 +359,8 @@ mod env {
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME"; 
     pub const POSTMARK_AUTH_TOKEN_ENV_VAR: &str = "POSTMARK_AUTH_TOKEN";
+    pub const SMS_AUTH_TOKEN_ENV_VAR: &str = "SMS_AUTH_TOKEN";
+    pub const RUN_PROFILE_ENV_VAR: &str = "RUN_PROFILE";
+    pub const OIDC_CLIENT_SECRET_ENV_VAR_PREFIX: &str = "OIDC_";
+    pub const OIDC_CLIENT_SECRET_ENV_VAR_SUFFIX: &str = "_CLIENT_SECRET";
 }
\ No newline at end of file
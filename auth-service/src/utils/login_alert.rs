@@ -0,0 +1,117 @@
+use std::net::IpAddr;
+
+use askama::Template;
+use axum::http::{header::USER_AGENT, HeaderMap};
+use color_eyre::eyre::Result;
+use ipnet::IpNet;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{data_stores::LoginFingerprint, Clock, UserId},
+    utils::{rate_limit::client_ip, scoped_token::{self, ScopedToken}},
+};
+
+const SECURE_ACCOUNT_AUDIENCE: &str = "secure-account";
+
+const UNKNOWN: &str = "unknown";
+
+// Where a login came from, the address as the rate limiter tells it so clients can not pick their own
+pub fn login_fingerprint(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> LoginFingerprint {
+    let ip_address = client_ip(peer, headers, trusted_proxies);
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .unwrap_or(UNKNOWN);
+
+    LoginFingerprint::new(ip_address.to_string(), user_agent.to_owned())
+}
+
+// Postmark gets the content as HTML too, so what the client sent is escaped
+#[derive(Template)]
+#[template(path = "login_alert.txt", escape = "html")]
+pub struct LoginAlertTemplate<'a> {
+    pub logged_in_at: String,
+    pub ip_address: &'a str,
+    pub user_agent: &'a str,
+    pub link_ttl_hours: i64,
+    pub secure_account_link: String,
+}
+
+#[tracing::instrument(name = "GenerateSecureAccountToken", skip_all)]
+pub fn generate_secure_account_token(user_id: &UserId, jwt_secret: Secret<String>, token_ttl_millis: i64, clock: &dyn Clock) -> Result<Secret<String>> {
+    let claims = SecureAccountClaims { sub: user_id.to_string() };
+
+    scoped_token::issue(SECURE_ACCOUNT_AUDIENCE, claims, scoped_token::expires_in(token_ttl_millis, clock)?, jwt_secret)
+}
+
+// A link is followed once, the caller uses the token up once the new password was accepted
+#[tracing::instrument(name = "ValidateSecureAccountToken", skip_all)]
+pub fn validate_secure_account_token(token: &Secret<String>, jwt_secret: Secret<String>, clock: &dyn Clock) -> Result<ScopedToken<SecureAccountClaims>> {
+    scoped_token::validate(SECURE_ACCOUNT_AUDIENCE, token, jwt_secret, clock)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecureAccountClaims {
+    pub sub: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::{app_state::BannedTokenStoreType, domain::SystemClock, services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore};
+
+    #[test]
+    fn test_login_fingerprint_takes_the_client_address() {
+        let trusted_proxies: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("198.51.100.1, 203.0.113.7"));
+        headers.insert(USER_AGENT, HeaderValue::from_static("Firefox"));
+
+        // The proxy appended the address it got the request from, the rest came from the client
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(login_fingerprint(proxy, &headers, &trusted_proxies), LoginFingerprint::new("203.0.113.7".to_owned(), "Firefox".to_owned()));
+
+        // Without a proxy in front the header is the client's own word
+        let client: IpAddr = "192.0.2.9".parse().unwrap();
+        assert_eq!(login_fingerprint(client, &headers, &trusted_proxies), LoginFingerprint::new("192.0.2.9".to_owned(), "Firefox".to_owned()));
+        assert_eq!(login_fingerprint(client, &HeaderMap::new(), &trusted_proxies), LoginFingerprint::new("192.0.2.9".to_owned(), UNKNOWN.to_owned()));
+    }
+
+    #[test]
+    fn test_login_alert_escapes_what_the_client_sent() {
+        let content = LoginAlertTemplate {
+            logged_in_at: "2026-10-19 13:00 UTC".to_owned(),
+            ip_address: "203.0.113.7",
+            user_agent: "<a href=\"https://evil.example\">Firefox</a>",
+            link_ttl_hours: 168,
+            secure_account_link: "http://127.0.0.1/auth/?secure_account_token=token".to_owned(),
+        }
+        .render()
+        .unwrap();
+
+        assert!(!content.contains("<a href"));
+        assert!(content.contains("secure_account_token=token"));
+    }
+
+    #[tokio::test]
+    async fn test_secure_account_token_is_accepted_once() {
+        let banned_token_store: BannedTokenStoreType = Arc::new(HashsetBannedTokenStore::default());
+        let jwt_secret = Secret::new("secret".to_owned());
+        let user_id = UserId::default();
+
+        let token = generate_secure_account_token(&user_id, jwt_secret.clone(), 60 * 1000, &SystemClock).unwrap();
+        let validated = validate_secure_account_token(&token, jwt_secret.clone(), &SystemClock).unwrap();
+        assert_eq!(validated.claims.sub, user_id.to_string());
+        assert!(validated.use_once(banned_token_store.clone()).await.is_ok());
+
+        let validated = validate_secure_account_token(&token, jwt_secret, &SystemClock).unwrap();
+        assert!(validated.use_once(banned_token_store).await.is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use color_eyre::eyre::{eyre, Result};
+
+use crate::{
+    app_state::BannedTokenStoreType,
+    domain::{email::Email, Clock},
+    utils::scoped_token,
+};
+
+pub const MAGIC_LINK_NONCE_COOKIE_NAME: &str = "magic_link_nonce";
+
+const MAGIC_LINK_AUDIENCE: &str = "magic-link";
+
+// Random value kept in a cookie of the browser asking for the link, the token only stores its hash
+#[tracing::instrument(name = "GenerateBrowserNonce", skip_all)]
+pub fn generate_browser_nonce() -> Secret<String> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Secret::new(to_hex(&bytes))
+}
+
+#[tracing::instrument(name = "CreateMagicLinkNonceCookie", skip_all)]
+pub fn create_magic_link_nonce_cookie(browser_nonce: &Secret<String>) -> Cookie<'static> {
+    Cookie::build((MAGIC_LINK_NONCE_COOKIE_NAME, browser_nonce.expose_secret().to_owned()))
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .build()
+}
+
+#[tracing::instrument(name = "GenerateMagicLinkToken", skip_all)]
+pub fn generate_magic_link_token(email: &Email, browser_nonce: &Secret<String>, jwt_secret: Secret<String>, token_ttl_millis: i64, clock: &dyn Clock) -> Result<Secret<String>> {
+    let claims = MagicLinkClaims {
+        sub: email.as_ref().expose_secret().to_owned(),
+        nonce_hash: hash_nonce(browser_nonce),
+    };
+
+    scoped_token::issue(MAGIC_LINK_AUDIENCE, claims, scoped_token::expires_in(token_ttl_millis, clock)?, jwt_secret)
+}
+
+// A link is accepted once, and only by the browser holding the nonce it was issued for
+#[tracing::instrument(name = "ValidateMagicLinkToken", skip_all)]
+pub async fn validate_magic_link_token(
+    banned_token_store: BannedTokenStoreType,
+    token: &Secret<String>,
+    browser_nonce: &Secret<String>,
+    jwt_secret: Secret<String>,
+    clock: &dyn Clock,
+) -> Result<MagicLinkClaims> {
+    let token = scoped_token::validate::<MagicLinkClaims>(MAGIC_LINK_AUDIENCE, token, jwt_secret, clock)?;
+
+    // Checked first, so opening the link in another browser does not use it up
+    if token.claims.nonce_hash != hash_nonce(browser_nonce) {
+        return Err(eyre!("magic link opened from another browser"));
+    }
+
+    token.use_once(banned_token_store).await
+}
+
+fn hash_nonce(browser_nonce: &Secret<String>) -> String {
+    to_hex(&Sha256::digest(browser_nonce.expose_secret().as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MagicLinkClaims {
+    pub sub: String,
+    pub nonce_hash: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{domain::SystemClock, services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore};
+
+    use super::*;
+
+    fn email() -> Email {
+        Email::parse(Secret::new("test@example.com".to_owned())).unwrap()
+    }
+
+    fn jwt_secret() -> Secret<String> {
+        Secret::new("secret".to_owned())
+    }
+
+    #[tokio::test]
+    async fn test_validate_magic_link_token_from_same_browser() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let nonce = generate_browser_nonce();
+        let token = generate_magic_link_token(&email(), &nonce, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let claims = validate_magic_link_token(banned_token_store, &token, &nonce, jwt_secret(), &SystemClock).await.unwrap();
+        assert_eq!(claims.sub, "test@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_validate_magic_link_token_from_another_browser() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let token = generate_magic_link_token(&email(), &generate_browser_nonce(), jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let result = validate_magic_link_token(banned_token_store, &token, &generate_browser_nonce(), jwt_secret(), &SystemClock).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_magic_link_token_already_used() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let nonce = generate_browser_nonce();
+        let token = generate_magic_link_token(&email(), &nonce, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        assert!(validate_magic_link_token(banned_token_store.clone(), &token, &nonce, jwt_secret(), &SystemClock).await.is_ok());
+        let result = validate_magic_link_token(banned_token_store, &token, &nonce, jwt_secret(), &SystemClock).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_link_opened_from_another_browser_is_not_used_up() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let nonce = generate_browser_nonce();
+        let token = generate_magic_link_token(&email(), &nonce, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        assert!(validate_magic_link_token(banned_token_store.clone(), &token, &generate_browser_nonce(), jwt_secret(), &SystemClock).await.is_err());
+        assert!(validate_magic_link_token(banned_token_store, &token, &nonce, jwt_secret(), &SystemClock).await.is_ok());
+    }
+}
@@ -1,5 +1,18 @@
+pub mod account_deletion;
 pub mod auth;
+pub mod change_email;
 pub mod config;
+pub mod login_alert;
+pub mod magic_link;
+pub mod oidc;
+pub mod password_policy;
+pub mod password_reset;
+pub mod password_rotation;
 pub use config::*;
+pub mod proof_of_work;
+pub mod rate_limit;
+pub mod scoped_token;
+pub mod step_up;
 pub mod tracing;
+pub mod trusted_device;
 pub use tracing::*;
\ No newline at end of file
@@ -0,0 +1,131 @@
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::eyre::Result;
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{domain::Clock, utils::scoped_token};
+
+pub const OIDC_FLOW_COOKIE_NAME: &str = "oidc_flow";
+
+// Time the user has to log in at the provider and come back
+const OIDC_FLOW_TTL_MILLIS: i64 = 600 * 1000;
+
+const OIDC_FLOW_AUDIENCE: &str = "oidc-flow";
+
+// What the callback needs to trust the provider's answer, kept signed in the browser that started the login
+#[derive(Debug, Clone)]
+pub struct OidcFlow {
+    pub provider: String,
+    pub state: Secret<String>,
+    pub nonce: Secret<String>,
+    pub code_verifier: Secret<String>,
+}
+
+impl OidcFlow {
+    pub fn new(provider: String) -> Self {
+        Self {
+            provider,
+            state: generate_random_value(),
+            nonce: generate_random_value(),
+            code_verifier: generate_random_value(),
+        }
+    }
+
+    // PKCE S256 challenge, see: https://datatracker.ietf.org/doc/html/rfc7636#section-4.2
+    pub fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.expose_secret().as_bytes()))
+    }
+}
+
+// 32 random bytes, base64url encoded so it is also a valid PKCE code verifier
+fn generate_random_value() -> Secret<String> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Secret::new(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+#[derive(Serialize, Deserialize)]
+struct OidcFlowClaims {
+    provider: String,
+    state: String,
+    nonce: String,
+    code_verifier: String,
+}
+
+#[tracing::instrument(name = "CreateOidcFlowCookie", skip_all)]
+pub fn create_oidc_flow_cookie(flow: &OidcFlow, jwt_secret: Secret<String>, clock: &dyn Clock) -> Result<Cookie<'static>> {
+    let claims = OidcFlowClaims {
+        provider: flow.provider.clone(),
+        state: flow.state.expose_secret().to_owned(),
+        nonce: flow.nonce.expose_secret().to_owned(),
+        code_verifier: flow.code_verifier.expose_secret().to_owned(),
+    };
+
+    let token = scoped_token::issue(OIDC_FLOW_AUDIENCE, claims, scoped_token::expires_in(OIDC_FLOW_TTL_MILLIS, clock)?, jwt_secret)?;
+
+    Ok(Cookie::build((OIDC_FLOW_COOKIE_NAME, token.expose_secret().to_owned()))
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        // The provider redirects back with a top level navigation, which Lax still allows
+        .same_site(SameSite::Lax)
+        .build())
+}
+
+// The state and nonce already make a flow single use, so the cookie is not banned once used
+#[tracing::instrument(name = "ValidateOidcFlowCookie", skip_all)]
+pub fn validate_oidc_flow_cookie(token: &Secret<String>, jwt_secret: Secret<String>, clock: &dyn Clock) -> Result<OidcFlow> {
+    let claims = scoped_token::validate::<OidcFlowClaims>(OIDC_FLOW_AUDIENCE, token, jwt_secret, clock)?.claims;
+
+    Ok(OidcFlow {
+        provider: claims.provider,
+        state: Secret::new(claims.state),
+        nonce: Secret::new(claims.nonce),
+        code_verifier: Secret::new(claims.code_verifier),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SystemClock;
+
+    use super::*;
+
+    fn jwt_secret() -> Secret<String> {
+        Secret::new("secret".to_owned())
+    }
+
+    #[test]
+    fn test_oidc_flow_cookie_round_trip() {
+        let flow = OidcFlow::new("google".to_owned());
+        let cookie = create_oidc_flow_cookie(&flow, jwt_secret(), &SystemClock).unwrap();
+        assert_eq!(cookie.name(), OIDC_FLOW_COOKIE_NAME);
+        assert_eq!(cookie.http_only(), Some(true));
+
+        let validated = validate_oidc_flow_cookie(&Secret::new(cookie.value().to_owned()), jwt_secret(), &SystemClock).unwrap();
+        assert_eq!(validated.provider, "google");
+        assert_eq!(validated.state.expose_secret(), flow.state.expose_secret());
+        assert_eq!(validated.code_challenge(), flow.code_challenge());
+    }
+
+    #[test]
+    fn test_oidc_flow_cookie_signed_with_another_secret() {
+        let flow = OidcFlow::new("google".to_owned());
+        let cookie = create_oidc_flow_cookie(&flow, Secret::new("other".to_owned()), &SystemClock).unwrap();
+        assert!(validate_oidc_flow_cookie(&Secret::new(cookie.value().to_owned()), jwt_secret(), &SystemClock).is_err());
+    }
+
+    #[test]
+    fn test_code_challenge_matches_rfc7636_example() {
+        let flow = OidcFlow {
+            provider: "google".to_owned(),
+            state: Secret::new("state".to_owned()),
+            nonce: Secret::new("nonce".to_owned()),
+            code_verifier: Secret::new("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_owned()),
+        };
+        assert_eq!(flow.code_challenge(), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+}
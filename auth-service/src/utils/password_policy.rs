@@ -0,0 +1,238 @@
+use std::{collections::HashSet, io::ErrorKind, path::PathBuf};
+
+use color_eyre::eyre::{Context, Result};
+use secrecy::{ExposeSecret, Secret};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    domain::{password::weaknesses, Email, Password, PasswordWeakness},
+    utils::PasswordPolicySettings,
+};
+
+// Characters of a k-anonymity range, the rest of the hash is looked up in its file
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+// Email parts shorter than this turn up in too many good passwords
+const MIN_EMAIL_PART_LENGTH: usize = 3;
+
+// Rules for passwords being chosen, on top of the ones Password::parse applies to every password.
+// The blocklist is loaded once, the breached corpus is too big for that and is read a range at a time.
+pub struct PasswordPolicy {
+    settings: PasswordPolicySettings,
+    blocklist: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn new(settings: &PasswordPolicySettings) -> Result<Self> {
+        let blocklist = if settings.blocklist_path.is_empty() {
+            HashSet::new()
+        } else {
+            std::fs::read_to_string(&settings.blocklist_path)
+                .wrap_err(format!("failed to read password blocklist {}", settings.blocklist_path))?
+                .lines()
+                .map(|line| line.trim().to_lowercase())
+                .filter(|line| !line.is_empty())
+                .collect()
+        };
+
+        Ok(Self { settings: settings.clone(), blocklist })
+    }
+
+    // Every reason the password is refused for, so the user can fix them all at once
+    #[tracing::instrument(name = "CheckPasswordPolicy", skip_all)]
+    pub async fn parse(&self, password: Secret<String>, email: &Email) -> Result<Password, Vec<PasswordWeakness>> {
+        let mut weaknesses = weaknesses(&password);
+        let raw = password.expose_secret();
+
+        if raw.len() < self.settings.min_length && !weaknesses.contains(&PasswordWeakness::TooShort) {
+            weaknesses.push(PasswordWeakness::TooShort);
+        }
+        if entropy_bits(raw) < self.settings.min_entropy_bits {
+            weaknesses.push(PasswordWeakness::TooPredictable);
+        }
+        if self.blocklist.contains(&raw.to_lowercase()) {
+            weaknesses.push(PasswordWeakness::Common);
+        }
+        if self.is_breached(raw).await {
+            weaknesses.push(PasswordWeakness::Breached);
+        }
+        if self.settings.reject_email && contains_email(raw, email) {
+            weaknesses.push(PasswordWeakness::ContainsEmail);
+        }
+
+        if !weaknesses.is_empty() {
+            return Err(weaknesses);
+        }
+        Password::parse(password).map_err(|_| weaknesses)
+    }
+
+    // Only the file of the range is read. A missing file means the corpus has no password there,
+    // a corpus that can not be read lets the password through rather than blocking every signup.
+    async fn is_breached(&self, password: &str) -> bool {
+        if self.settings.breached_corpus_dir.is_empty() {
+            return false;
+        }
+
+        let hash = to_upper_hex(&Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+        let range_path: PathBuf = [self.settings.breached_corpus_dir.as_str(), &format!("{}.txt", prefix)].iter().collect();
+
+        match tokio::fs::read_to_string(&range_path).await {
+            Ok(range) => range
+                .lines()
+                .filter_map(|line| line.split(':').next())
+                .any(|breached_suffix| breached_suffix.trim().eq_ignore_ascii_case(suffix)),
+            Err(e) if e.kind() == ErrorKind::NotFound => false,
+            Err(e) => {
+                tracing::warn!(error = ?e, "Could not read the breached password corpus");
+                false
+            }
+        }
+    }
+}
+
+// Length times the bits of the kinds of characters used. A character repeating the one before it,
+// or next to it like "b" after "a", adds nothing.
+pub fn entropy_bits(password: &str) -> f64 {
+    let mut pool = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password.chars().any(|c| c.is_ascii_punctuation() || c == ' ') {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+    if pool == 0 {
+        return 0.0;
+    }
+
+    let mut previous: Option<char> = None;
+    let mut length = 0;
+    for c in password.chars() {
+        let predictable = previous.is_some_and(|previous| (c as i64 - previous as i64).abs() <= 1);
+        if !predictable {
+            length += 1;
+        }
+        previous = Some(c);
+    }
+
+    length as f64 * (pool as f64).log2()
+}
+
+fn contains_email(password: &str, email: &Email) -> bool {
+    let password = password.to_lowercase();
+    let email = email.as_ref().expose_secret().to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+
+    [email.as_str(), local_part]
+        .iter()
+        .any(|part| part.len() >= MIN_EMAIL_PART_LENGTH && password.contains(part))
+}
+
+fn to_upper_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn settings() -> PasswordPolicySettings {
+        PasswordPolicySettings {
+            min_length: 10,
+            min_entropy_bits: 40.0,
+            blocklist_path: String::new(),
+            breached_corpus_dir: String::new(),
+            reject_email: true,
+        }
+    }
+
+    fn email() -> Email {
+        Email::parse(Secret::new("jane.doe@example.com".to_owned())).unwrap()
+    }
+
+    async fn check(policy: &PasswordPolicy, password: &str) -> Result<Password, Vec<PasswordWeakness>> {
+        policy.parse(Secret::new(password.to_owned()), &email()).await
+    }
+
+    #[test]
+    fn test_entropy_counts_runs_as_one_character() {
+        assert_eq!(entropy_bits(""), 0.0);
+        assert_eq!(entropy_bits("aaaaaaaa"), entropy_bits("a"));
+        assert_eq!(entropy_bits("abcdefgh"), entropy_bits("a"));
+        assert!(entropy_bits("Tr0ub4dor&3") > entropy_bits("Password"));
+    }
+
+    #[tokio::test]
+    async fn test_accepts_strong_passwords() {
+        let policy = PasswordPolicy::new(&settings()).unwrap();
+
+        assert!(check(&policy, "CorrectHorseBattery9").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_lists_every_weakness() {
+        let policy = PasswordPolicy::new(&settings()).unwrap();
+
+        assert_eq!(check(&policy, "Aaaaaaaaa").await.err(), Some(vec![PasswordWeakness::TooShort, PasswordWeakness::TooPredictable]));
+        assert_eq!(check(&policy, "Jane.Doe-Rocks-2024").await.err(), Some(vec![PasswordWeakness::ContainsEmail]));
+    }
+
+    #[tokio::test]
+    async fn test_refuses_blocklisted_passwords() {
+        let blocklist_path = env::temp_dir().join(format!("{}.txt", Uuid::new_v4()));
+        fs::write(&blocklist_path, "iloveyou\nQwertyUiop123\n").unwrap();
+        let policy = PasswordPolicy::new(&PasswordPolicySettings {
+            blocklist_path: blocklist_path.to_string_lossy().into_owned(),
+            ..settings()
+        })
+        .unwrap();
+
+        assert_eq!(check(&policy, "qwertyuiop123").await.err().map(|w| w.contains(&PasswordWeakness::Common)), Some(true));
+        assert!(check(&policy, "CorrectHorseBattery9").await.is_ok());
+
+        fs::remove_file(blocklist_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refuses_breached_passwords() {
+        let corpus_dir = env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir(&corpus_dir).unwrap();
+        let hash = to_upper_hex(&Sha1::digest(b"CorrectHorseBattery9"));
+        let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+        fs::write(corpus_dir.join(format!("{}.txt", prefix)), format!("0000000000000000000000000000000000A:3\r\n{}:12\r\n", suffix)).unwrap();
+        let policy = PasswordPolicy::new(&PasswordPolicySettings {
+            breached_corpus_dir: corpus_dir.to_string_lossy().into_owned(),
+            ..settings()
+        })
+        .unwrap();
+
+        assert_eq!(check(&policy, "CorrectHorseBattery9").await.err(), Some(vec![PasswordWeakness::Breached]));
+        // Its range file is missing from the corpus
+        assert!(check(&policy, "Tr0ub4dor&3Staple").await.is_ok());
+
+        fs::remove_dir_all(corpus_dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_blocklist_is_an_error() {
+        let result = PasswordPolicy::new(&PasswordPolicySettings {
+            blocklist_path: "does/not/exist.txt".to_owned(),
+            ..settings()
+        });
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{Clock, UserId},
+    utils::scoped_token::{self, ScopedToken},
+};
+
+const PASSWORD_RESET_AUDIENCE: &str = "password-reset";
+
+#[tracing::instrument(name = "GeneratePasswordResetToken", skip_all)]
+pub fn generate_password_reset_token(user_id: &UserId, jwt_secret: Secret<String>, token_ttl_millis: i64, clock: &dyn Clock) -> Result<Secret<String>> {
+    let claims = PasswordResetClaims { sub: user_id.to_string() };
+
+    scoped_token::issue(PASSWORD_RESET_AUDIENCE, claims, scoped_token::expires_in(token_ttl_millis, clock)?, jwt_secret)
+}
+
+// A link is followed once, the caller uses the token up once the new password was accepted
+#[tracing::instrument(name = "ValidatePasswordResetToken", skip_all)]
+pub fn validate_password_reset_token(token: &Secret<String>, jwt_secret: Secret<String>, clock: &dyn Clock) -> Result<ScopedToken<PasswordResetClaims>> {
+    scoped_token::validate(PASSWORD_RESET_AUDIENCE, token, jwt_secret, clock)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordResetClaims {
+    pub sub: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{app_state::BannedTokenStoreType, domain::SystemClock, services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore};
+
+    #[tokio::test]
+    async fn test_password_reset_token_is_accepted_once() {
+        let banned_token_store: BannedTokenStoreType = Arc::new(HashsetBannedTokenStore::default());
+        let jwt_secret = Secret::new("secret".to_owned());
+        let user_id = UserId::default();
+
+        let token = generate_password_reset_token(&user_id, jwt_secret.clone(), 60 * 1000, &SystemClock).unwrap();
+        let validated = validate_password_reset_token(&token, jwt_secret.clone(), &SystemClock).unwrap();
+        assert_eq!(validated.claims.sub, user_id.to_string());
+        assert!(validated.use_once(banned_token_store.clone()).await.is_ok());
+
+        let validated = validate_password_reset_token(&token, jwt_secret, &SystemClock).unwrap();
+        assert!(validated.use_once(banned_token_store).await.is_err());
+    }
+}
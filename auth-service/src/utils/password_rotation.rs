@@ -0,0 +1,112 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Clock, UserId},
+    utils::{
+        auth::create_auth_cookie,
+        scoped_token::{self, ScopedToken},
+        step_up::RecentlyAuthenticated,
+    },
+};
+
+const PASSWORD_CHANGE_AUDIENCE: &str = "password-change";
+
+// Goes in the auth cookie after a login with an expired password, /change-password is all it opens
+#[tracing::instrument(name = "GeneratePasswordChangeCookie", skip_all)]
+pub fn generate_password_change_cookie(user_id: &UserId, jwt_secret: Secret<String>, jwt_cookie_name: String, token_ttl_millis: i64, clock: &dyn Clock) -> Result<Cookie<'static>> {
+    let claims = PasswordChangeClaims { sub: user_id.to_string() };
+
+    let token = scoped_token::issue(PASSWORD_CHANGE_AUDIENCE, claims, scoped_token::expires_in(token_ttl_millis, clock)?, jwt_secret)?;
+
+    Ok(create_auth_cookie(token.expose_secret().to_owned(), false, jwt_cookie_name))
+}
+
+// A token changes the password once, the caller uses it up right before changing it
+#[tracing::instrument(name = "ValidatePasswordChangeToken", skip_all)]
+pub fn validate_password_change_token(token: &Secret<String>, jwt_secret: Secret<String>, clock: &dyn Clock) -> Result<ScopedToken<PasswordChangeClaims>> {
+    scoped_token::validate(PASSWORD_CHANGE_AUDIENCE, token, jwt_secret, clock)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordChangeClaims {
+    pub sub: String,
+}
+
+// Lets in a recently authenticated user like RecentlyAuthenticated does, or one holding a password
+// change token. Anything else gets the rejection of RecentlyAuthenticated.
+pub struct PasswordChangeAllowed<const MAX_AGE_SECONDS: i64> {
+    pub user_id: UserId,
+    pub password_change_token: Option<ScopedToken<PasswordChangeClaims>>,
+}
+
+impl<const MAX_AGE_SECONDS: i64> FromRequestParts<AppState> for PasswordChangeAllowed<MAX_AGE_SECONDS> {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let rejection = match RecentlyAuthenticated::<MAX_AGE_SECONDS>::from_request_parts(parts, state).await {
+            Ok(RecentlyAuthenticated { user_id, .. }) => return Ok(PasswordChangeAllowed { user_id, password_change_token: None }),
+            Err(rejection) => rejection,
+        };
+
+        let jar = CookieJar::from_headers(&parts.headers);
+        let Some(cookie) = jar.get(&state.auth_settings.http.jwt_cookie_name) else {
+            return Err(rejection);
+        };
+
+        let token = validate_password_change_token(
+            &Secret::new(cookie.value().to_owned()),
+            state.auth_settings.http.jwt_token.clone(),
+            state.clock.as_ref(),
+        )
+        .map_err(|_| rejection)?;
+
+        let user_id = UserId::parse(&token.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+        Ok(PasswordChangeAllowed { user_id, password_change_token: Some(token) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        app_state::BannedTokenStoreType,
+        domain::SystemClock,
+        services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+        utils::auth::validate_token,
+    };
+
+    #[tokio::test]
+    async fn test_password_change_token_is_used_once() {
+        let banned_token_store: BannedTokenStoreType = Arc::new(HashsetBannedTokenStore::default());
+        let jwt_secret = Secret::new("secret".to_owned());
+        let user_id = UserId::default();
+
+        let cookie = generate_password_change_cookie(&user_id, jwt_secret.clone(), "jwt".to_owned(), 60 * 1000, &SystemClock).unwrap();
+        let token = Secret::new(cookie.value().to_owned());
+        let validated = validate_password_change_token(&token, jwt_secret.clone(), &SystemClock).unwrap();
+        assert_eq!(validated.claims.sub, user_id.to_string());
+        assert!(validated.use_once(banned_token_store.clone()).await.is_ok());
+
+        let validated = validate_password_change_token(&token, jwt_secret, &SystemClock).unwrap();
+        assert!(validated.use_once(banned_token_store).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_password_change_token_is_not_an_auth_token() {
+        let banned_token_store: BannedTokenStoreType = Arc::new(HashsetBannedTokenStore::default());
+        let jwt_secret = Secret::new("secret".to_owned());
+
+        let cookie = generate_password_change_cookie(&UserId::default(), jwt_secret.clone(), "jwt".to_owned(), 60 * 1000, &SystemClock).unwrap();
+        let token = Secret::new(cookie.value().to_owned());
+
+        assert!(validate_token(banned_token_store, &token, jwt_secret, &SystemClock).await.is_err());
+    }
+}
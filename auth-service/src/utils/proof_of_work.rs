@@ -0,0 +1,263 @@
+use std::net::IpAddr;
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use color_eyre::eyre::{eyre, Result};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_state::{AppState, BannedTokenStoreType},
+    domain::{AuthAPIError, Clock},
+    utils::{
+        rate_limit::{abuse_key, request_client_ip},
+        scoped_token, ProofOfWorkSettings,
+    },
+};
+
+pub const PROOF_OF_WORK_CHALLENGE_HEADER: &str = "x-pow-challenge";
+pub const PROOF_OF_WORK_SOLUTION_HEADER: &str = "x-pow-solution";
+
+const PROOF_OF_WORK_AUDIENCE: &str = "proof-of-work";
+
+// Bots pay for every request to the protected routes by solving a challenge first, see
+// ProofOfWorkSettings for the routes and difficulty
+#[tracing::instrument(name = "ProofOfWork", skip_all)]
+pub async fn require_proof_of_work(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let settings = &state.auth_settings.proof_of_work;
+
+    if !settings.protects(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let ip_address = request_client_ip(&request, &state.auth_settings.rate_limit.trusted_proxies);
+    // A challenge issued before the client was turned away is too easy now
+    let difficulty = challenge_difficulty(&state, ip_address).await;
+
+    if let Err(e) = validate_solution(&state, request.headers(), difficulty).await {
+        tracing::debug!(error = ?e, "Rejected proof of work");
+        return AuthAPIError::ProofOfWorkRequired.into_response();
+    }
+
+    next.run(request).await
+}
+
+async fn validate_solution(state: &AppState, headers: &HeaderMap, difficulty: u32) -> Result<ProofOfWorkClaims> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| Secret::new(value.to_owned()))
+            .ok_or_else(|| eyre!("missing {} header", name))
+    };
+    let challenge = header(PROOF_OF_WORK_CHALLENGE_HEADER)?;
+    let solution = header(PROOF_OF_WORK_SOLUTION_HEADER)?;
+
+    validate_challenge(
+        state.banned_token_store.clone(),
+        &challenge,
+        &solution,
+        difficulty,
+        state.auth_settings.http.jwt_token.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+}
+
+// Base difficulty plus a step for every token missing from the abuse bucket of the client
+#[tracing::instrument(name = "ChallengeDifficulty", skip_all)]
+pub async fn challenge_difficulty(state: &AppState, ip_address: Option<IpAddr>) -> u32 {
+    let settings = &state.auth_settings.proof_of_work;
+
+    let Some(ip_address) = ip_address else {
+        return settings.base_difficulty;
+    };
+
+    let abuse = state
+        .rate_limit_store
+        .tokens_taken(&abuse_key(ip_address), &settings.abuse)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!(error = ?e, "Could not read the abuse of the client");
+            0
+        });
+
+    difficulty_for(settings, abuse)
+}
+
+fn difficulty_for(settings: &ProofOfWorkSettings, abuse: u32) -> u32 {
+    settings.base_difficulty + settings.difficulty_step * abuse
+}
+
+// The difficulty is signed into the challenge, so clients can not pick an easier one
+#[tracing::instrument(name = "GenerateProofOfWorkChallenge", skip_all)]
+pub fn generate_challenge(difficulty: u32, jwt_secret: Secret<String>, ttl_millis: i64, clock: &dyn Clock) -> Result<Secret<String>> {
+    scoped_token::issue(PROOF_OF_WORK_AUDIENCE, ProofOfWorkClaims { difficulty }, scoped_token::expires_in(ttl_millis, clock)?, jwt_secret)
+}
+
+// A challenge is accepted once, solved for at least the difficulty asked
+#[tracing::instrument(name = "ValidateProofOfWorkChallenge", skip_all)]
+pub async fn validate_challenge(
+    banned_token_store: BannedTokenStoreType,
+    challenge: &Secret<String>,
+    solution: &Secret<String>,
+    difficulty: u32,
+    jwt_secret: Secret<String>,
+    clock: &dyn Clock,
+) -> Result<ProofOfWorkClaims> {
+    let token = scoped_token::validate::<ProofOfWorkClaims>(PROOF_OF_WORK_AUDIENCE, challenge, jwt_secret, clock)?;
+
+    if token.claims.difficulty < difficulty {
+        return Err(eyre!("challenge easier than the client is asked for"));
+    }
+
+    if leading_zero_bits(&solution_hash(challenge, solution)) < token.claims.difficulty {
+        return Err(eyre!("challenge not solved"));
+    }
+
+    token.use_once(banned_token_store).await
+}
+
+// The client looks for a solution whose hash after the challenge starts with enough zero bits
+pub fn solution_hash(challenge: &Secret<String>, solution: &Secret<String>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(challenge.expose_secret().as_bytes());
+    hasher.update(solution.expose_secret().as_bytes());
+    hasher.finalize().into()
+}
+
+pub fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProofOfWorkClaims {
+    pub difficulty: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        domain::{data_stores::TokenBucket, SystemClock},
+        services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+        utils::auth::validate_token,
+    };
+
+    use super::*;
+
+    const DIFFICULTY: u32 = 8;
+
+    fn jwt_secret() -> Secret<String> {
+        Secret::new("secret".to_owned())
+    }
+
+    fn solve(challenge: &Secret<String>, difficulty: u32) -> Secret<String> {
+        (0u64..)
+            .map(|counter| Secret::new(counter.to_string()))
+            .find(|solution| leading_zero_bits(&solution_hash(challenge, solution)) >= difficulty)
+            .unwrap()
+    }
+
+    fn unsolved(challenge: &Secret<String>, difficulty: u32) -> Secret<String> {
+        (0u64..)
+            .map(|counter| Secret::new(counter.to_string()))
+            .find(|solution| leading_zero_bits(&solution_hash(challenge, solution)) < difficulty)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0xff, 0x00]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x10, 0x00]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn test_difficulty_grows_with_abuse() {
+        let settings = ProofOfWorkSettings {
+            enabled: true,
+            routes: vec![],
+            ttl_millis: 60 * 1000,
+            base_difficulty: 12,
+            difficulty_step: 2,
+            abuse: TokenBucket { capacity: 4, refill_millis: 60 * 1000 },
+        };
+
+        assert_eq!(difficulty_for(&settings, 0), 12);
+        assert_eq!(difficulty_for(&settings, 4), 20);
+    }
+
+    #[tokio::test]
+    async fn test_validate_solved_challenge() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let challenge = generate_challenge(DIFFICULTY, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let solution = solve(&challenge, DIFFICULTY);
+
+        let claims = validate_challenge(banned_token_store, &challenge, &solution, DIFFICULTY, jwt_secret(), &SystemClock).await.unwrap();
+        assert_eq!(claims.difficulty, DIFFICULTY);
+    }
+
+    #[tokio::test]
+    async fn test_validate_unsolved_challenge() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let challenge = generate_challenge(DIFFICULTY, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let solution = unsolved(&challenge, DIFFICULTY);
+
+        let result = validate_challenge(banned_token_store, &challenge, &solution, DIFFICULTY, jwt_secret(), &SystemClock).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unsolved_attempt_does_not_use_the_challenge_up() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let challenge = generate_challenge(DIFFICULTY, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let unsolved_attempt = unsolved(&challenge, DIFFICULTY);
+        assert!(validate_challenge(banned_token_store.clone(), &challenge, &unsolved_attempt, DIFFICULTY, jwt_secret(), &SystemClock).await.is_err());
+
+        let solution = solve(&challenge, DIFFICULTY);
+        assert!(validate_challenge(banned_token_store, &challenge, &solution, DIFFICULTY, jwt_secret(), &SystemClock).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_challenge_easier_than_asked() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let challenge = generate_challenge(DIFFICULTY, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let solution = solve(&challenge, DIFFICULTY);
+
+        let result = validate_challenge(banned_token_store, &challenge, &solution, DIFFICULTY + 1, jwt_secret(), &SystemClock).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_challenge_already_used() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let challenge = generate_challenge(DIFFICULTY, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let solution = solve(&challenge, DIFFICULTY);
+        assert!(validate_challenge(banned_token_store.clone(), &challenge, &solution, DIFFICULTY, jwt_secret(), &SystemClock).await.is_ok());
+
+        let result = validate_challenge(banned_token_store, &challenge, &solution, DIFFICULTY, jwt_secret(), &SystemClock).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_challenge_is_not_an_auth_token() {
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let challenge = generate_challenge(DIFFICULTY, jwt_secret(), 60 * 1000, &SystemClock).unwrap();
+        let result = validate_token(banned_token_store, &challenge, jwt_secret(), &SystemClock).await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Duration;
+use ipnet::IpNet;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::{RateLimitDecision, TokenBucket}, AuthAPIError},
+};
+
+// Login bodies are tiny, anything bigger is not worth reading for the email
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+// Turns requests over the limits of their route away with a 429 before they reach the handler,
+// see RateLimitSettings for the limits
+#[tracing::instrument(name = "RateLimit", skip_all)]
+pub async fn rate_limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let settings = &state.auth_settings.rate_limit;
+    let path = request.uri().path().to_owned();
+
+    let Some(route) = settings.route(&path).filter(|_| settings.enabled) else {
+        return next.run(request).await;
+    };
+
+    let ip_address = request_client_ip(&request, &settings.trusted_proxies);
+
+    if let (Some(bucket), Some(ip_address)) = (&route.per_ip, ip_address) {
+        if let Err(e) = take_token(&state, &format!("{}:ip:{}", path, ip_address), bucket, ip_address).await {
+            return e.into_response();
+        }
+    }
+
+    let Some(bucket) = &route.per_email else {
+        return next.run(request).await;
+    };
+
+    // The handler still needs the body once the email is read from it
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_BODY_BYTES).await else {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    };
+
+    if let (Some(email), Some(ip_address)) = (body_email(&bytes), ip_address) {
+        if let Err(e) = take_token(&state, &format!("{}:email:{}", path, email), bucket, ip_address).await {
+            return e.into_response();
+        }
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+// The address the request comes from, only missing when the app is not served with connect info
+pub fn request_client_ip(request: &Request, trusted_proxies: &[IpNet]) -> Option<IpAddr> {
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| client_ip(address.ip(), request.headers(), trusted_proxies))
+}
+
+// Clients the rate limiter keeps turning away get harder proof of work challenges
+pub fn abuse_key(ip_address: IpAddr) -> String {
+    format!("abuse:ip:{}", ip_address)
+}
+
+async fn take_token(state: &AppState, key: &str, bucket: &TokenBucket, ip_address: IpAddr) -> Result<(), AuthAPIError> {
+    match state.rate_limit_store.take_token(key, bucket).await {
+        Ok(RateLimitDecision::Allowed) => Ok(()),
+        Ok(RateLimitDecision::Limited { retry_after }) => {
+            record_abuse(state, ip_address).await;
+            Err(AuthAPIError::TooManyRequests { retry_after_seconds: retry_after_seconds(retry_after) })
+        }
+        // Better to let the request through than to turn everyone away
+        Err(e) => {
+            tracing::warn!(error = ?e, "Could not rate limit the request");
+            Ok(())
+        }
+    }
+}
+
+async fn record_abuse(state: &AppState, ip_address: IpAddr) {
+    let proof_of_work = &state.auth_settings.proof_of_work;
+    if !proof_of_work.enabled {
+        return;
+    }
+
+    // A full abuse bucket only means the difficulty is already at its highest
+    if let Err(e) = state.rate_limit_store.take_token(&abuse_key(ip_address), &proof_of_work.abuse).await {
+        tracing::warn!(error = ?e, "Could not record the abuse");
+    }
+}
+
+// Retry-After is in whole seconds, rounded up so a client waiting that long finds a token
+fn retry_after_seconds(retry_after: Duration) -> u64 {
+    let millis = retry_after.num_milliseconds().max(1) as u64;
+    millis.div_ceil(1000)
+}
+
+// Proxies append the address they got the request from to X-Forwarded-For, so the client is the
+// last address no trusted proxy added. What untrusted peers send in the header is ignored.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip_address: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip_address));
+
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let forwarded_for = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| entry.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+
+    let mut client = peer;
+    for ip_address in forwarded_for.into_iter().rev() {
+        // Whatever comes before a garbled entry could have been made up by anyone
+        let Some(ip_address) = ip_address else {
+            break;
+        };
+        client = ip_address;
+        if !is_trusted(&ip_address) {
+            break;
+        }
+    }
+    client
+}
+
+#[derive(Deserialize)]
+struct EmailField {
+    email: Option<String>,
+}
+
+// Hashed so the keys do not hold the emails themselves, bodies without one are left to the handler
+fn body_email(body: &[u8]) -> Option<String> {
+    let email = serde_json::from_slice::<EmailField>(body).ok()?.email?;
+    let email = email.trim().to_lowercase();
+    Some(URL_SAFE_NO_PAD.encode(Sha256::digest(email.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn trusted_proxies() -> Vec<IpNet> {
+        vec!["127.0.0.1/32".parse().unwrap(), "172.16.0.0/12".parse().unwrap()]
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_client_ip_ignores_header_from_untrusted_peer() {
+        let peer: IpAddr = "203.0.113.7".parse().unwrap();
+
+        assert_eq!(client_ip(peer, &forwarded_for("198.51.100.23"), &trusted_proxies()), peer);
+    }
+
+    #[test]
+    fn test_client_ip_skips_trusted_proxies() {
+        let peer: IpAddr = "172.18.0.2".parse().unwrap();
+        let headers = forwarded_for("10.0.0.1, 198.51.100.23, 127.0.0.1");
+
+        assert_eq!(client_ip(peer, &headers, &trusted_proxies()), "198.51.100.23".parse::<IpAddr>().unwrap());
+        assert_eq!(client_ip(peer, &HeaderMap::new(), &trusted_proxies()), peer);
+    }
+
+    #[test]
+    fn test_client_ip_stops_at_garbled_entries() {
+        let peer: IpAddr = "127.0.0.1".parse().unwrap();
+        let headers = forwarded_for("198.51.100.23, not-an-ip, 172.18.0.2");
+
+        assert_eq!(client_ip(peer, &headers, &trusted_proxies()), "172.18.0.2".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_body_email_is_normalized_and_hashed() {
+        let email = body_email(br#"{"email": " User@Example.com ", "password": "Password123"}"#).unwrap();
+
+        assert_eq!(Some(email.clone()), body_email(br#"{"email": "user@example.com"}"#));
+        assert!(!email.contains("example"));
+        assert_eq!(body_email(br#"{"password": "Password123"}"#), None);
+        assert_eq!(body_email(b"not json"), None);
+    }
+
+    #[test]
+    fn test_retry_after_rounds_up_to_seconds() {
+        assert_eq!(retry_after_seconds(Duration::milliseconds(1)), 1);
+        assert_eq!(retry_after_seconds(Duration::seconds(60)), 60);
+        assert_eq!(retry_after_seconds(Duration::milliseconds(60_001)), 61);
+    }
+}
@@ -0,0 +1,189 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
+use secrecy::{ExposeSecret, Secret};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    app_state::BannedTokenStoreType,
+    domain::Clock,
+    utils::auth::{check_not_expired, clock_validation, generate_jti},
+};
+
+// Tokens that each open one thing, like a magic link or an email change. Every kind has its own
+// audience and auth tokens carry none, so validate_token rejects all of them and they reject each other.
+#[derive(Serialize, Deserialize)]
+struct ScopedClaims<T> {
+    exp: usize,
+    aud: String,
+    jti: String,
+    #[serde(flatten)]
+    claims: T,
+}
+
+// A token whose signature, audience and expiry were checked
+pub struct ScopedToken<T> {
+    pub claims: T,
+    pub exp: usize,
+    jti: String,
+}
+
+impl<T> ScopedToken<T> {
+    // Bans the token, of requests racing with it only one gets the claims. Callers use the token up
+    // before doing what it opens, so that is done once at most.
+    pub async fn use_once(self, banned_token_store: BannedTokenStoreType) -> Result<T> {
+        if !banned_token_store.add_token_if_absent(&self.jti, self.exp).await? {
+            return Err(eyre!("token already used"));
+        }
+
+        Ok(self.claims)
+    }
+}
+
+// When a token issued now with the TTL expires
+pub fn expires_in(ttl_millis: i64, clock: &dyn Clock) -> Result<DateTime<Utc>> {
+    let delta = chrono::Duration::try_milliseconds(ttl_millis)
+        .ok_or(eyre!("failed to create token time delta"))?;
+
+    clock.now()
+        .checked_add_signed(delta)
+        .ok_or(eyre!("failed to add token ttl to current time"))
+}
+
+#[tracing::instrument(name = "IssueScopedToken", skip_all, fields(audience = audience))]
+pub fn issue<T: Serialize>(audience: &str, claims: T, expires_at: DateTime<Utc>, jwt_secret: Secret<String>) -> Result<Secret<String>> {
+    let claims = ScopedClaims {
+        exp: expires_at.timestamp().try_into().wrap_err("failed to cast exp time to usize")?,
+        aud: audience.to_owned(),
+        jti: generate_jti(),
+        claims,
+    };
+
+    encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.expose_secret().as_bytes()),
+    )
+    .map(Secret::new)
+    .wrap_err(format!("failed to create {} token", audience))
+}
+
+// Tokens that may be used many times stop here, single use ones go on to ScopedToken::use_once
+#[tracing::instrument(name = "ValidateScopedToken", skip_all, fields(audience = audience))]
+pub fn validate<T: DeserializeOwned>(audience: &str, token: &Secret<String>, jwt_secret: Secret<String>, clock: &dyn Clock) -> Result<ScopedToken<T>> {
+    let mut validation = clock_validation(Validation::default());
+    validation.set_audience(&[audience]);
+
+    let claims = decode::<ScopedClaims<T>>(
+        token.expose_secret(),
+        &DecodingKey::from_secret(jwt_secret.expose_secret().as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .wrap_err(format!("failed to decode {} token", audience))?;
+
+    check_not_expired(claims.exp, clock)?;
+
+    Ok(ScopedToken { claims: claims.claims, exp: claims.exp, jti: claims.jti })
+}
+
+#[tracing::instrument(name = "ValidateScopedTokenOnce", skip_all, fields(audience = audience))]
+pub async fn validate_once<T: DeserializeOwned>(
+    audience: &str,
+    token: &Secret<String>,
+    jwt_secret: Secret<String>,
+    banned_token_store: BannedTokenStoreType,
+    clock: &dyn Clock,
+) -> Result<T> {
+    validate(audience, token, jwt_secret, clock)?
+        .use_once(banned_token_store)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{
+        domain::{ManualClock, UserId},
+        services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+        utils::auth::{generate_auth_cookie, validate_token, AuthMethod},
+    };
+
+    const AUDIENCE: &str = "test";
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+    }
+
+    fn jwt_secret() -> Secret<String> {
+        Secret::new("secret".to_owned())
+    }
+
+    fn issue_test_token(clock: &ManualClock) -> Secret<String> {
+        issue(AUDIENCE, TestClaims { sub: "user".to_owned() }, expires_in(60 * 1000, clock).unwrap(), jwt_secret()).unwrap()
+    }
+
+    #[test]
+    fn test_scoped_token_round_trips_until_it_expires() {
+        let clock = ManualClock::default();
+        let token = issue_test_token(&clock);
+
+        let validated = validate::<TestClaims>(AUDIENCE, &token, jwt_secret(), &clock).unwrap();
+        assert_eq!(validated.claims, TestClaims { sub: "user".to_owned() });
+
+        clock.advance(Duration::minutes(3));
+        assert!(validate::<TestClaims>(AUDIENCE, &token, jwt_secret(), &clock).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scoped_tokens_are_only_accepted_for_their_audience() {
+        let clock = ManualClock::default();
+        let banned_token_store: BannedTokenStoreType = Arc::new(HashsetBannedTokenStore::default());
+        let token = issue_test_token(&clock);
+
+        assert!(validate::<TestClaims>("other", &token, jwt_secret(), &clock).is_err());
+        assert!(validate_token(banned_token_store, &token, jwt_secret(), &clock).await.is_err());
+
+        let auth_cookie = generate_auth_cookie(&UserId::default(), &[AuthMethod::Password], jwt_secret(), "jwt".to_owned(), 60 * 1000, &clock).unwrap();
+        assert!(validate::<TestClaims>(AUDIENCE, &Secret::new(auth_cookie.value().to_owned()), jwt_secret(), &clock).is_err());
+    }
+
+    #[test]
+    fn test_scoped_token_without_jti_is_rejected() {
+        let clock = ManualClock::default();
+        let exp = expires_in(60 * 1000, &clock).unwrap().timestamp();
+        let token = encode(
+            &jsonwebtoken::Header::default(),
+            &serde_json::json!({ "exp": exp, "aud": AUDIENCE, "sub": "user" }),
+            &EncodingKey::from_secret(jwt_secret().expose_secret().as_bytes()),
+        )
+        .unwrap();
+
+        assert!(validate::<TestClaims>(AUDIENCE, &Secret::new(token), jwt_secret(), &clock).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scoped_token_is_used_once_by_concurrent_requests() {
+        let clock = Arc::new(ManualClock::default());
+        let banned_token_store: BannedTokenStoreType = Arc::new(HashsetBannedTokenStore::default());
+        let token = issue_test_token(&clock);
+
+        let uses = (0..8).map(|_| {
+            let (token, banned_token_store, clock) = (token.clone(), banned_token_store.clone(), clock.clone());
+            tokio::spawn(async move {
+                validate_once::<TestClaims>(AUDIENCE, &token, jwt_secret(), banned_token_store, clock.as_ref()).await
+            })
+        }).collect::<Vec<_>>();
+
+        let mut used = 0;
+        for used_once in uses {
+            used += used_once.await.unwrap().is_ok() as usize;
+        }
+        assert_eq!(used, 1);
+    }
+}
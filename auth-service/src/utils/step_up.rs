@@ -0,0 +1,93 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Clock, UserId},
+    utils::auth::{get_authenticated_claims, AuthMethod, Claims},
+};
+
+// Guards sensitive routes, the user must have entered their password or a 2FA code in the last MAX_AGE_SECONDS.
+// Past that, or after a login by magic link or identity provider, they get ReauthenticationRequired and can go through /reauthenticate without logging out
+pub struct RecentlyAuthenticated<const MAX_AGE_SECONDS: i64> {
+    pub user_id: UserId,
+    pub claims: Claims,
+}
+
+impl<const MAX_AGE_SECONDS: i64> FromRequestParts<AppState> for RecentlyAuthenticated<MAX_AGE_SECONDS> {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+
+        let (user_id, claims) = get_authenticated_claims(&jar, state.banned_token_store.clone(), &state.auth_settings.http, state.clock.as_ref()).await?;
+
+        if !proved_password_or_code(&claims) || !authenticated_within(&claims, MAX_AGE_SECONDS, state.clock.as_ref()) {
+            return Err(AuthAPIError::ReauthenticationRequired);
+        }
+
+        Ok(RecentlyAuthenticated { user_id, claims })
+    }
+}
+
+// A magic link or an identity provider only shows access to the inbox or the provider account
+fn proved_password_or_code(claims: &Claims) -> bool {
+    claims.amr
+        .iter()
+        .any(|method| matches!(method, AuthMethod::Password | AuthMethod::OneTimeCode))
+}
+
+fn authenticated_within(claims: &Claims, max_age_seconds: i64, clock: &dyn Clock) -> bool {
+    i64::try_from(claims.auth_time)
+        .map(|auth_time| clock.now().timestamp() - auth_time <= max_age_seconds)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::domain::ManualClock;
+
+    use super::*;
+
+    fn claims_at(auth_time: usize) -> Claims {
+        Claims {
+            sub: UserId::default().to_string(),
+            exp: auth_time + 600,
+            iat: auth_time,
+            jti: "jti".to_owned(),
+            auth_time,
+            amr: vec![AuthMethod::Password],
+        }
+    }
+
+    #[test]
+    fn test_authenticated_within_the_max_age() {
+        let clock = ManualClock::default();
+        let claims = claims_at(clock.now().timestamp() as usize);
+
+        clock.advance(Duration::seconds(300));
+        assert!(authenticated_within(&claims, 300, &clock));
+
+        clock.advance(Duration::seconds(1));
+        assert!(!authenticated_within(&claims, 300, &clock));
+    }
+
+    #[test]
+    fn test_only_a_password_or_code_counts_for_step_up() {
+        let claims_with = |amr: Vec<AuthMethod>| Claims { amr, ..claims_at(0) };
+
+        assert!(proved_password_or_code(&claims_with(vec![AuthMethod::Password])));
+        assert!(proved_password_or_code(&claims_with(vec![AuthMethod::OneTimeCode, AuthMethod::MultiFactor])));
+        assert!(!proved_password_or_code(&claims_with(vec![AuthMethod::EmailLink])));
+        assert!(!proved_password_or_code(&claims_with(vec![AuthMethod::Federated])));
+    }
+
+    #[test]
+    fn test_tokens_without_auth_time_are_never_recent() {
+        let clock = ManualClock::default();
+
+        assert!(!authenticated_within(&claims_at(0), 300, &clock));
+    }
+}
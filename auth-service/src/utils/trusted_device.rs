@@ -0,0 +1,99 @@
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{data_stores::{TrustedDevice, TrustedDeviceId}, Clock, UserId},
+    utils::scoped_token,
+};
+
+const TRUSTED_DEVICE_AUDIENCE: &str = "trusted-device";
+
+// Lasts as long as the device record, the record decides whether it is still trusted
+#[tracing::instrument(name = "GenerateTrustedDeviceCookie", skip_all)]
+pub fn generate_trusted_device_cookie(device: &TrustedDevice, jwt_secret: Secret<String>, cookie_name: String) -> Result<Cookie<'static>> {
+    let claims = TrustedDeviceClaims {
+        sub: device.user_id.to_string(),
+        device_id: device.id.to_string(),
+    };
+
+    let token = scoped_token::issue(TRUSTED_DEVICE_AUDIENCE, claims, device.expires_at, jwt_secret)?;
+
+    let max_age = (device.expires_at - device.created_at).num_seconds();
+
+    Ok(Cookie::build((cookie_name, token.expose_secret().to_owned()))
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(max_age))
+        .build())
+}
+
+// Only checks the signature and expiry, the caller looks the device up to know it was not revoked
+#[tracing::instrument(name = "ValidateTrustedDeviceToken", skip_all)]
+pub fn validate_trusted_device_token(token: &str, jwt_secret: Secret<String>, clock: &dyn Clock) -> Result<TrustedDeviceClaims> {
+    scoped_token::validate(TRUSTED_DEVICE_AUDIENCE, &Secret::new(token.to_owned()), jwt_secret, clock)
+        .map(|token| token.claims)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrustedDeviceClaims {
+    pub sub: String,
+    pub device_id: String,
+}
+
+impl TrustedDeviceClaims {
+    pub fn user_id(&self) -> Result<UserId> {
+        UserId::parse(&self.sub)
+    }
+
+    pub fn device_id(&self) -> Result<TrustedDeviceId> {
+        TrustedDeviceId::parse(&self.device_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::domain::ManualClock;
+
+    fn jwt_secret() -> Secret<String> {
+        Secret::new("secret".to_owned())
+    }
+
+    #[test]
+    fn test_trusted_device_token_round_trips() {
+        let clock = ManualClock::default();
+        let device = TrustedDevice::new(UserId::default(), "Firefox".to_owned(), clock.now(), Duration::days(30));
+
+        let cookie = generate_trusted_device_cookie(&device, jwt_secret(), "trusted_device".to_owned()).unwrap();
+        assert_eq!(cookie.max_age(), Some(time::Duration::days(30)));
+
+        let claims = validate_trusted_device_token(cookie.value(), jwt_secret(), &clock).unwrap();
+        assert_eq!(claims.user_id().unwrap(), device.user_id);
+        assert_eq!(claims.device_id().unwrap(), device.id);
+    }
+
+    #[test]
+    fn test_trusted_device_token_expires_with_the_device() {
+        let clock = ManualClock::default();
+        let device = TrustedDevice::new(UserId::default(), "Firefox".to_owned(), clock.now(), Duration::days(30));
+        let cookie = generate_trusted_device_cookie(&device, jwt_secret(), "trusted_device".to_owned()).unwrap();
+
+        clock.advance(Duration::days(31));
+
+        assert!(validate_trusted_device_token(cookie.value(), jwt_secret(), &clock).is_err());
+    }
+
+    #[test]
+    fn test_auth_tokens_are_not_device_tokens() {
+        let clock = ManualClock::default();
+        let auth_cookie = crate::utils::auth::generate_auth_cookie(&UserId::default(), &[crate::utils::auth::AuthMethod::Password], jwt_secret(), "jwt".to_owned(), 60_000, &clock).unwrap();
+
+        assert!(validate_trusted_device_token(auth_cookie.value(), jwt_secret(), &clock).is_err());
+    }
+}
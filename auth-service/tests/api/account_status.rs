@@ -0,0 +1,137 @@
+use auth_service::{
+    domain::{AccountStatus, Clock, Email},
+    ErrorResponse,
+};
+use chrono::Duration;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup(app: &TestApp) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    random_email
+}
+
+async fn set_status(app: &TestApp, email: &str, status: AccountStatus) {
+    let user = app.user_store.get_user(&Email::parse(Secret::new(email.to_owned())).unwrap()).await.unwrap();
+    app.user_store.set_status(&user.id, status).await.unwrap();
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password,
+    })).await
+}
+
+async fn assert_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        error.to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_return_403_if_account_disabled() {
+    let mut app = TestApp::new(None).await;
+    let random_email = signup(&app).await;
+
+    set_status(&app, &random_email, AccountStatus::Disabled).await;
+
+    assert_error(login(&app, &random_email, "Password123").await, 403, "Account disabled").await;
+
+    // Without the password nobody learns the account is disabled
+    assert_eq!(login(&app, &random_email, "WrongPassword123").await.status().as_u16(), 401);
+
+    set_status(&app, &random_email, AccountStatus::Active).await;
+    assert_eq!(login(&app, &random_email, "Password123").await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_allow_login_again_once_the_suspension_is_over() {
+    let mut app = TestApp::new(None).await;
+    let random_email = signup(&app).await;
+
+    set_status(&app, &random_email, AccountStatus::SuspendedUntil(app.clock.now() + Duration::days(1))).await;
+
+    assert_error(login(&app, &random_email, "Password123").await, 403, "Account suspended, try again later").await;
+
+    app.clock.advance(Duration::days(1));
+    assert_eq!(login(&app, &random_email, "Password123").await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_refresh_the_token_of_a_disabled_account() {
+    let mut app = TestApp::new(None).await;
+    let random_email = signup(&app).await;
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+
+    set_status(&app, &random_email, AccountStatus::Disabled).await;
+
+    assert_error(app.post_refresh_token().await, 403, "Account disabled").await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_renew_the_session_of_a_disabled_account() {
+    let mut app = TestApp::new(None).await;
+    let random_email = signup(&app).await;
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+
+    set_status(&app, &random_email, AccountStatus::Disabled).await;
+
+    assert_error(app.post_reauthenticate(&serde_json::json!({ "password": "Password123" })).await, 403, "Account disabled").await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_error(response, 403, "Account disabled").await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_purge_a_deleted_account_after_the_grace_period() {
+    let mut app = TestApp::new(None).await;
+    let random_email = signup(&app).await;
+    let email = Email::parse(Secret::new(random_email.clone())).unwrap();
+    app.log_in_as(&email).await;
+
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The session that deleted the account ends with it
+    assert_eq!(app.post_refresh_token().await.status().as_u16(), 401);
+    assert_error(login(&app, &random_email, "Password123").await, 403, "Account scheduled for deletion").await;
+
+    let grace_period = Duration::milliseconds(app.auth_settings.account_deletion.grace_period_millis);
+    app.clock.advance(grace_period - Duration::seconds(1));
+    assert_eq!(app.user_store.purge_deleted_users(app.clock.now()).await, Ok(0));
+    assert!(app.user_store.get_user(&email).await.is_ok());
+
+    app.clock.advance(Duration::seconds(1));
+    assert_eq!(app.user_store.purge_deleted_users(app.clock.now()).await, Ok(1));
+    assert!(app.user_store.get_user(&email).await.is_err());
+    assert_error(login(&app, &random_email, "Password123").await, 401, "Incorrect credentials").await;
+
+    app.clean_up().await;
+}
@@ -0,0 +1,162 @@
+use auth_service::{domain::Email, routes::ChangeEmailResponse, ErrorResponse};
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a user without 2FA and leaves the http client logged in as them
+async fn signup_and_log_in(app: &TestApp) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+
+    random_email
+}
+
+async fn mount_email_server(app: &TestApp, expected_calls: u64) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(expected_calls)
+        .mount(&app.email_server)
+        .await;
+}
+
+// Reads the confirmation token back from the email sent to the new address
+async fn change_email_token(app: &TestApp, new_email: &str) -> String {
+    let message = app.get_last_email_for(new_email).await.expect("No confirmation email captured");
+    assert_eq!(message.subject, "Confirm your new email");
+
+    message
+        .content
+        .split("change_email_token=")
+        .nth(1)
+        .expect("No token in the confirmation email")
+        .to_owned()
+}
+
+#[tokio::test]
+async fn should_move_account_to_new_email_once_confirmed() {
+    let mut app = TestApp::new(None).await;
+
+    let old_email = signup_and_log_in(&app).await;
+    let new_email = get_random_email().expose_secret().to_owned();
+
+    // Confirmation to the new address and notice to the old one
+    mount_email_server(&app, 2).await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": new_email,
+        "password": "Password123"
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<ChangeEmailResponse>()
+        .await
+        .expect("Could not deserialize response body to ChangeEmailResponse");
+
+    // Nothing changes before the confirmation
+    let response = app.post_login(&serde_json::json!({ "email": old_email, "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = change_email_token(&app, &new_email).await;
+
+    let response = app.post_confirm_email_change(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let notice = app.get_last_email_for(&old_email).await.expect("No notice captured");
+    assert_eq!(notice.subject, "Your email was changed");
+
+    let response = app.post_login(&serde_json::json!({ "email": new_email, "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Tokens carry the user id, so the session survives the change
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = app.post_login(&serde_json::json!({ "email": old_email, "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The link only works once
+    let response = app.post_confirm_email_change(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_new_email_is_taken() {
+    let mut app = TestApp::new(None).await;
+
+    let taken_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": taken_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+
+    let _ = signup_and_log_in(&app).await;
+
+    mount_email_server(&app, 0).await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": taken_email,
+        "password": "Password123"
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "User already exists".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_wrong() {
+    let mut app = TestApp::new(None).await;
+
+    let _ = signup_and_log_in(&app).await;
+
+    mount_email_server(&app, 0).await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": get_random_email().expose_secret(),
+        "password": "WrongPassword123"
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_confirmation_token_is_invalid() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.post_confirm_email_change(&serde_json::json!({ "token": "invalid" })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,168 @@
+use auth_service::{domain::Email, ErrorResponse};
+use reqwest::{cookie::CookieStore, Url};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a user without 2FA and leaves the http client logged in as them
+async fn signup_and_log_in(app: &TestApp) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+
+    random_email
+}
+
+fn current_token(app: &TestApp) -> String {
+    let cookies = app
+        .cookie_jar
+        .cookies(&Url::parse("http://127.0.0.1").expect("Failed to parse URL"))
+        .expect("No auth cookie found");
+
+    cookies
+        .to_str()
+        .unwrap()
+        .split("; ")
+        .find_map(|cookie| cookie.strip_prefix(&format!("{}=", app.auth_settings.http.jwt_cookie_name)))
+        .expect("No auth cookie found")
+        .to_owned()
+}
+
+#[tokio::test]
+async fn should_return_200_and_revoke_other_sessions() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup_and_log_in(&app).await;
+    let other_session_token = current_token(&app);
+
+    // Revocation works at the second granularity of the tokens
+    app.clock.advance(chrono::Duration::seconds(1));
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": other_session_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": new_token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "NewPassword456",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_wrong() {
+    let mut app = TestApp::new(None).await;
+
+    let _ = signup_and_log_in(&app).await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "WrongPassword123",
+        "newPassword": "NewPassword456"
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Incorrect credentials".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let mut app = TestApp::new(None).await;
+
+    let _ = signup_and_log_in(&app).await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "short"
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_was_used_recently() {
+    let mut app = TestApp::new(None).await;
+
+    let _ = signup_and_log_in(&app).await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "NewPassword456",
+        "newPassword": "Password123"
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Password was used recently, choose another one".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_not_logged_in() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,169 @@
+use auth_service::{routes::{MailboxMessage, TwoFactorAuthResponse}, ErrorResponse};
+use secrecy::ExposeSecret;
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+#[tokio::test]
+async fn should_return_empty_mailbox_if_no_email_was_sent() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.get_dev_mailbox(None).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let messages = response
+        .json::<Vec<MailboxMessage>>()
+        .await
+        .expect("Could not deserialize response body to Vec<MailboxMessage>");
+
+    assert!(messages.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_capture_the_2fa_code_sent_on_login() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let email = app.get_last_email_for(&random_email).await.expect("No email captured for the user");
+
+    assert_eq!(email.recipient, random_email);
+    assert_eq!(email.subject, "Verify code for login");
+
+    let test_case = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": email.content
+    });
+
+    let response = app.post_verify_2fa(&test_case).await;
+
+    assert_eq!(
+        response.status().as_u16(),
+        200,
+        "Failed for input: {:?}",
+        test_case
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_return_emails_of_the_requested_recipient() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+    let other_random_email = get_random_email().expose_secret().to_owned();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    for email in [&random_email, &other_random_email] {
+        let signup_body = serde_json::json!({
+            "email": email,
+            "password": "Password123",
+            "requires2FA": true
+        });
+        let _ = app.post_signup(&signup_body).await;
+
+        let login_body = serde_json::json!({
+            "email": email,
+            "password": "Password123",
+        });
+        let response = app.post_login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 206);
+    }
+
+    let messages = app
+        .get_dev_mailbox(Some(&random_email))
+        .await
+        .json::<Vec<MailboxMessage>>()
+        .await
+        .expect("Could not deserialize response body to Vec<MailboxMessage>");
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].recipient, random_email);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_html_if_json_not_requested() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.http_client
+        .get(format!("{}/auth/dev/mailbox", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_recipient() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.get_dev_mailbox(Some("not_an_email")).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Invalid credentials".to_owned()
+    );
+
+    app.clean_up().await;
+}
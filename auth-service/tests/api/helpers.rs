@@ -1,20 +1,39 @@
 use auth_service::app_state::BannedTokenStoreType;
 use auth_service::app_state::TwoFACodeStoreType;
 use auth_service::app_state::UserStoreType;
+use auth_service::app_state::IdempotencyStoreType;
+use auth_service::app_state::TrustedDeviceStoreType;
+use auth_service::app_state::LoginFingerprintStoreType;
 use auth_service::app_state::AppState;
 use auth_service::auth::VerifyTokenRequest;
 use auth_service::auth::VerifyTokenResponse;
-use auth_service::domain::Email;
+use auth_service::domain::{data_stores::{LoginAttemptId, TwoFACode}, Email, ManualClock, PhoneNumber};
+use auth_service::utils::password_policy::PasswordPolicy;
+use auth_service::utils::auth::{generate_auth_cookie_without_domain, validate_token, AuthMethod};
+use auth_service::routes::{MailboxMessage, TwoFactorAuthResponse, IDEMPOTENCY_KEY_HEADER};
+use auth_service::services::data_stores::capturing_email_client::{CapturingEmailClient, Mailbox};
 use auth_service::services::data_stores::postmark_email_client::PostmarkEmailClient;
-use auth_service::services::data_stores::{RedisBannedTokenStore, RedisTwoFACodeStore};
+use auth_service::services::data_stores::twilio_sms_client::TwilioSmsClient;
+use auth_service::services::data_stores::{RedisBannedTokenStore, RedisConnectionPool, RedisIdempotencyStore, RedisTwoFACodeStore};
 use auth_service::get_postgres_pool;
-use auth_service::get_redis_client;
+use auth_service::get_redis_pool;
+use auth_service::services::data_stores::postgres_login_fingerprint_store::PostgresLoginFingerprintStore;
+use auth_service::services::data_stores::postgres_trusted_device_store::PostgresTrustedDeviceStore;
 use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
-use auth_service::utils::AuthSettings;
+use auth_service::services::data_stores::PasswordHashingExecutor;
+use auth_service::services::data_stores::{
+    hashmap_idempotency_store::HashmapIdempotencyStore, hashmap_login_fingerprint_store::HashmapLoginFingerprintStore,
+    hashmap_rate_limit_store::HashmapRateLimitStore, hashmap_trusted_device_store::HashmapTrustedDeviceStore,
+    hashmap_two_fa_code_store::HashmapTwoFACodeStore,
+    hashmap_user_store::HashmapUserStore, hashset_banned_token_store::HashsetBannedTokenStore,
+};
+use auth_service::utils::proof_of_work::{PROOF_OF_WORK_CHALLENGE_HEADER, PROOF_OF_WORK_SOLUTION_HEADER};
+use auth_service::utils::{AuthSettings, OidcProviderSettings, RedisSettings, RouteRateLimitSettings, RunProfile};
 use auth_service::Application;
 use auth_service::auth::auth_grpc_service_client::AuthGrpcServiceClient;
 use reqwest::cookie::Jar;
 use reqwest::Client;
+use reqwest::Url;
 use secrecy::ExposeSecret;
 use secrecy::Secret;
 use sqlx::postgres::PgConnectOptions;
@@ -30,6 +49,9 @@ use std::str::FromStr;
 use std::sync::Arc;
 use uuid::Uuid;
 
+pub const MOCK_IDP_NAME: &str = "mock";
+pub const MOCK_IDP_CLIENT_ID: &str = "auth-service";
+
 
 /*
 For me to understand:
@@ -44,10 +66,17 @@ helper methods.
 pub struct TestApp {
     pub address: String,
     pub cookie_jar: Arc<Jar>, 
+    pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType, 
+    pub trusted_device_store: TrustedDeviceStoreType,
+    // Only moves when a test advances it
+    pub clock: Arc<ManualClock>,
     pub http_client: reqwest::Client,
     pub email_server: MockServer,
+    pub mailbox: Mailbox,
+    pub sms_server: MockServer,
+    pub idp_server: MockServer,
     pub db_name: String,
     pub clean_up_called: bool,
     pub grpc_address: String,
@@ -57,44 +86,143 @@ pub struct TestApp {
 
 impl TestApp {
     pub async fn new(mock_user_store:Option<UserStoreType>) -> Self {
+        let builder = Self::builder();
+        match mock_user_store {
+            Some(mock_user_store) => builder.user_store(mock_user_store),
+            None => builder,
+        }
+        .build()
+        .await
+    }
+
+    pub fn builder() -> TestAppBuilder {
+        TestAppBuilder::default()
+    }
+}
+
+type ConfigureSettings = Box<dyn FnOnce(&mut AuthSettings)>;
+
+// What a test changes about the app it runs against, TestApp::new keeps the test config as is
+#[derive(Default)]
+pub struct TestAppBuilder {
+    user_store: Option<UserStoreType>,
+    configure: Vec<ConfigureSettings>,
+    rate_limit_routes: Vec<RouteRateLimitSettings>,
+}
+
+impl TestAppBuilder {
+    // In place of the store of the run profile, e.g. a mock failing on purpose
+    pub fn user_store(self, user_store: UserStoreType) -> Self {
+        Self { user_store: Some(user_store), ..self }
+    }
+
+    // Changes are applied in the order they were given, before the app starts
+    pub fn settings(mut self, configure: impl FnOnce(&mut AuthSettings) + 'static) -> Self {
+        self.configure.push(Box::new(configure));
+        self
+    }
+
+    // Turns the rate limiter on for the routes given this way only
+    pub fn rate_limit(mut self, route: RouteRateLimitSettings) -> Self {
+        self.rate_limit_routes.push(route);
+        self
+    }
+
+    pub async fn build(self) -> TestApp {
         env::set_var("RUN_ENV", "test");
-        let auth_settings = AuthSettings::new();
+        let mut auth_settings = AuthSettings::new();
+        if !self.rate_limit_routes.is_empty() {
+            auth_settings.rate_limit.enabled = true;
+            auth_settings.rate_limit.routes = self.rate_limit_routes;
+        }
+        for configure in self.configure {
+            configure(&mut auth_settings);
+        }
+
+        // Local identity provider for the OIDC login, see tests/api/oidc.rs
+        let idp_server = MockServer::start().await;
+        auth_settings.oidc.providers.push(configure_mock_idp(idp_server.uri()));
 
         let (
                 user_store, 
+                trusted_device_store,
+                login_fingerprint_store,
                 db_name,
                 clean_up_called
-            ):(UserStoreType, String, bool) = 
-            match mock_user_store {
+            ):(UserStoreType, TrustedDeviceStoreType, LoginFingerprintStoreType, String, bool) = 
+            match self.user_store {
                 Some(mock_user_store) => 
                     (
                         mock_user_store, 
+                        Arc::new(HashmapTrustedDeviceStore::default()),
+                        Arc::new(HashmapLoginFingerprintStore::default()),
                         "mock_database".to_owned(), 
                         true
                     ),
+                // RUN_PROFILE=memory runs the whole suite without Postgres and Redis
+                None if auth_settings.profile == RunProfile::Memory =>
+                    (
+                        Arc::new(HashmapUserStore::default()),
+                        Arc::new(HashmapTrustedDeviceStore::default()),
+                        Arc::new(HashmapLoginFingerprintStore::default()),
+                        "memory_database".to_owned(),
+                        true
+                    ),
                 None => {
-                    let (pg_pool, db_name) = Self::configure_postgresql(auth_settings.database.url.clone()).await;
+                    let (pg_pool, db_name) = TestApp::configure_postgresql(auth_settings.database.url.clone()).await;
                     (
-                        Arc::new(RwLock::new(PostgresUserStore::new(pg_pool))), 
+                        Arc::new(PostgresUserStore::new(
+                            pg_pool.clone(),
+                            PasswordHashingExecutor::new(&auth_settings.password_hashing)
+                                .expect("Invalid password hashing settings"),
+                        ).with_enumeration_protection(auth_settings.enumeration_protection.enabled)),
+                        Arc::new(PostgresTrustedDeviceStore::new(pg_pool.clone())),
+                        Arc::new(PostgresLoginFingerprintStore::new(pg_pool)),
                         db_name, 
                         false
                     )
                 },
             };
 
-        let redis_connection = Arc::new(RwLock::new(configure_redis(auth_settings.redis.host_name.clone())));
-        let banned_token_store: BannedTokenStoreType = Arc::new(RwLock::new(RedisBannedTokenStore::new(Arc::clone(&redis_connection), auth_settings.redis.ttl_millis)));
-        let two_fa_code_store: TwoFACodeStoreType = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection)));
+        let clock = Arc::new(ManualClock::default());
+        let (banned_token_store, two_fa_code_store, idempotency_store): (BannedTokenStoreType, TwoFACodeStoreType, IdempotencyStoreType) =
+            match auth_settings.profile {
+                RunProfile::Memory => (
+                    Arc::new(HashsetBannedTokenStore::new(auth_settings.redis.ttl_millis).with_clock(clock.clone())),
+                    Arc::new(HashmapTwoFACodeStore::new(auth_settings.two_fa.max_attempts_per_user).with_clock(clock.clone())),
+                    Arc::new(HashmapIdempotencyStore::default()),
+                ),
+                RunProfile::Services => {
+                    let redis_pool = configure_redis(&auth_settings.redis).await;
+                    (
+                        Arc::new(RedisBannedTokenStore::new(redis_pool.clone(), auth_settings.redis.ttl_millis).with_clock(clock.clone())),
+                        Arc::new(RedisTwoFACodeStore::new(redis_pool.clone(), auth_settings.two_fa.max_attempts_per_user).with_clock(clock.clone())),
+                        Arc::new(RedisIdempotencyStore::new(redis_pool, auth_settings.idempotency.ttl_millis)),
+                    )
+                }
+            };
         let email_server = MockServer::start().await; 
         let base_url = email_server.uri(); 
-        let email_client = Arc::new(RwLock::new(configure_postmark_email_client(base_url)));
+        let mailbox = Mailbox::default();
+        let email_client = Arc::new(RwLock::new(CapturingEmailClient::new(configure_postmark_email_client(base_url), mailbox.clone())));
+        let sms_server = MockServer::start().await;
+        let sms_client = Arc::new(RwLock::new(configure_twilio_sms_client(sms_server.uri())));
         let app_state = AppState::new(
-            user_store, 
+            Arc::clone(&user_store),
             Arc::clone(&banned_token_store),
             Arc::clone(&two_fa_code_store),
+            idempotency_store,
+            Arc::clone(&trusted_device_store),
+            login_fingerprint_store,
             email_client,
+            sms_client,
+            Arc::new(PasswordPolicy::new(&auth_settings.password_policy).expect("Invalid password policy settings")),
             auth_settings
-        );
+        )
+        // Buckets of their own, so no test is limited by the requests of another
+        .with_rate_limit_store(Arc::new(HashmapRateLimitStore::default().with_clock(clock.clone())))
+        .with_clock(clock.clone())
+        .with_mailbox(mailbox.clone());
 
         let http_address = app_state.auth_settings.http.address.clone();
         let grpc_address = app_state.auth_settings.grpc.address.clone();
@@ -120,8 +248,10 @@ impl TestApp {
 
 
         let cookie_jar = Arc::new(Jar::default());
+        // Redirects are asserted on, like the one sending the browser to the identity provider
         let http_client = reqwest::Client::builder()
             .cookie_provider(cookie_jar.clone())
+            .redirect(reqwest::redirect::Policy::none())
             .build()
             .unwrap();
         let grpc_client = AuthGrpcServiceClient::connect(grpc_address.clone())
@@ -131,10 +261,16 @@ impl TestApp {
         TestApp {
             address,
             cookie_jar,
+            user_store,
             banned_token_store,
             two_fa_code_store,
+            trusted_device_store,
+            clock,
             http_client,
             email_server,
+            mailbox,
+            sms_server,
+            idp_server,
             db_name,
             clean_up_called,
             grpc_address,
@@ -142,7 +278,9 @@ impl TestApp {
             auth_settings,
         }
     }
+}
 
+impl TestApp {
     async fn configure_postgresql(postgresql_conn_url:Secret<String>) -> (PgPool, String) {
         // We are creating a new database for each test case, and we need to ensure each database has a unique name!
         let db_name = Uuid::new_v4().to_string();
@@ -186,9 +324,63 @@ impl TestApp {
             .expect("Failed to migrate the database");
     }
         
+    // Stores a valid auth cookie for the user, as if they had just logged in
+    pub async fn log_in_as(&self, email: &Email) {
+        let user = self.user_store
+            .get_user(email)
+            .await
+            .expect("Failed to find the user to log in as");
+
+        let cookie = generate_auth_cookie_without_domain(
+            &user.id,
+            &[AuthMethod::Password],
+            self.auth_settings.http.jwt_token.clone(),
+            self.auth_settings.http.jwt_cookie_name.clone(),
+            self.auth_settings.redis.ttl_millis,
+            self.clock.as_ref(),
+        ).expect("Failed to generate auth cookie");
+
+        self.cookie_jar.add_cookie_str(
+            &cookie.to_string(),
+            &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+        );
+    }
+
+    // The code sent for the login attempt a 206 login response points to
+    pub async fn get_2fa_code(&self, login_response: reqwest::Response) -> (LoginAttemptId, TwoFACode) {
+        let login_attempt_id = login_response
+            .json::<TwoFactorAuthResponse>()
+            .await
+            .expect("Could not deserialize response body to TwoFactorAuthResponse")
+            .login_attempt_id;
+        let login_attempt_id = LoginAttemptId::parse(Secret::new(login_attempt_id)).expect("Invalid login attempt id");
+
+        let (_, code) = self.two_fa_code_store
+            .get_code(&login_attempt_id)
+            .await
+            .expect("No 2FA code stored for the login attempt");
+
+        (login_attempt_id, code)
+    }
+
+    // Bans an auth token by its jti, as logout does
+    pub async fn ban_token(&self, token: &str) {
+        let claims = validate_token(
+            self.banned_token_store.clone(),
+            &Secret::new(token.to_owned()),
+            self.auth_settings.http.jwt_token.clone(),
+            self.clock.as_ref(),
+        ).await.expect("Failed to validate the token to ban");
+
+        self.banned_token_store
+            .add_token(&claims.jti, claims.exp)
+            .await
+            .expect("Failed to ban the token");
+    }
+
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/auth", &self.address))
+            .get(format!("{}/auth", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -196,7 +388,7 @@ impl TestApp {
 
     pub async fn post_refresh_token(&self) -> reqwest::Response  {
         self.http_client
-            .post(&format!("{}/auth/refresh-token", &self.address))
+            .post(format!("{}/auth/refresh-token", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -207,7 +399,20 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/auth/signup", &self.address))
+            .post(format!("{}/auth/signup", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_signup_with_idempotency_key<Body>(&self, body: &Body, idempotency_key: &str) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/signup", &self.address))
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
             .json(body)
             .send()
             .await
@@ -219,7 +424,22 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/auth/login", &self.address))
+            .post(format!("{}/auth/login", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Logs in as a browser at the given address, as nginx would forward it
+    pub async fn post_login_from<Body>(&self, body: &Body, ip_address: &str, user_agent: &str) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/login", &self.address))
+            .header("X-Forwarded-For", ip_address)
+            .header("User-Agent", user_agent)
             .json(body)
             .send()
             .await
@@ -228,7 +448,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/auth/logout", &self.address))
+            .post(format!("{}/auth/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -246,18 +466,270 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_verify_2fa_from<Body>(&self, body: &Body, ip_address: &str, user_agent: &str) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/verify-2fa", &self.address))
+            .header("X-Forwarded-For", ip_address)
+            .header("User-Agent", user_agent)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_secure_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/secure-account", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/password-reset", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/confirm-password-reset", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response 
         where
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/auth/verify-token", &self.address))
+            .post(format!("{}/auth/verify-token", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/phone-number", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/verify-phone-number", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_2fa_method<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/2fa-method", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_magic_link<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/magic-link", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_magic_link<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/verify-magic-link", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_oidc_authorize(&self, provider: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/auth/oidc/{}/authorize", &self.address, provider))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_oidc_callback(&self, provider: &str, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/auth/oidc/{}/callback", &self.address, provider))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/change-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/change-email", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_email_change<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/confirm-email-change", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reauthenticate<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth/reauthenticate", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_challenge(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/auth/challenge", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Sends the body to a route protected by proof of work, with the challenge and its solution
+    pub async fn post_with_proof_of_work<Body>(&self, path: &str, body: &Body, challenge: &str, solution: &str) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/auth{}", &self.address, path))
+            .header(PROOF_OF_WORK_CHALLENGE_HEADER, challenge)
+            .header(PROOF_OF_WORK_SOLUTION_HEADER, solution)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .delete(format!("{}/auth/delete-account", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_trusted_devices(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/auth/trusted-devices", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_trusted_device(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/auth/trusted-devices/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_dev_mailbox(&self, recipient: Option<&str>) -> reqwest::Response {
+        let mut request = self.http_client
+            .get(format!("{}/auth/dev/mailbox", &self.address))
+            .header("Accept", "application/json");
+
+        if let Some(recipient) = recipient {
+            request = request.query(&[("recipient", recipient)]);
+        }
+
+        request
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Goes through the dev mailbox route, the same way a developer would read the 2FA code
+    pub async fn get_last_email_for(&self, recipient: &str) -> Option<MailboxMessage> {
+        self.get_dev_mailbox(Some(recipient))
+            .await
+            .json::<Vec<MailboxMessage>>()
+            .await
+            .expect("Could not deserialize response body to Vec<MailboxMessage>")
+            .into_iter()
+            .next()
+    }
+
     pub async fn grpc_verify_token(&mut self, request: impl tonic::IntoRequest<VerifyTokenRequest>) -> tonic::Response<VerifyTokenResponse> {
         self.grpc_client
             .verify_token(request)
@@ -287,7 +759,7 @@ pub fn get_random_email() -> Secret<String> {
 }
 
 async fn delete_database(db_name: &str, postgresql_conn_url: Secret<String>) {
-    let connection_options = PgConnectOptions::from_str(&postgresql_conn_url.expose_secret())
+    let connection_options = PgConnectOptions::from_str(postgresql_conn_url.expose_secret())
         .expect("Failed to parse PostgreSQL connection string");
 
     let mut connection = PgConnection::connect_with(&connection_options)
@@ -318,11 +790,10 @@ async fn delete_database(db_name: &str, postgresql_conn_url: Secret<String>) {
         .expect("Failed to drop the database.");
 }
 
-fn configure_redis(redis_host_name:String) -> redis::Connection {
-    get_redis_client(redis_host_name)
-        .expect("Failed to get Redis client")
-        .get_connection()
-        .expect("Failed to get Redis connection")
+async fn configure_redis(redis_settings: &RedisSettings) -> RedisConnectionPool {
+    get_redis_pool(redis_settings)
+        .await
+        .expect("Failed to create Redis connection pool")
 }
 
 fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
@@ -339,4 +810,31 @@ fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
         .expect("Failed to build HTTP client");
 
     PostmarkEmailClient::new(base_url, sender, postmark_auth_token, http_client)
-}
\ No newline at end of file
+}
+
+fn configure_twilio_sms_client(base_url: String) -> TwilioSmsClient {
+    let sms_settings = AuthSettings::new().sms;
+    let timeout = std::time::Duration::from_millis(sms_settings.timeout_milliseconds);
+
+    let sender = PhoneNumber::parse(Secret::new(sms_settings.sender)).unwrap();
+
+    let http_client = Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    TwilioSmsClient::new(base_url, sender, sms_settings.account_sid, Secret::new("auth_token".to_owned()), http_client)
+}
+
+fn configure_mock_idp(base_url: String) -> OidcProviderSettings {
+    OidcProviderSettings {
+        name: MOCK_IDP_NAME.to_owned(),
+        issuer: base_url.clone(),
+        client_id: MOCK_IDP_CLIENT_ID.to_owned(),
+        client_secret: Secret::new("client_secret".to_owned()),
+        authorization_endpoint: format!("{}/authorize", base_url),
+        token_endpoint: format!("{}/token", base_url),
+        jwks_uri: format!("{}/jwks", base_url),
+        scopes: "openid email".to_owned(),
+    }
+}
@@ -1,6 +1,8 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{domain::email::Email, routes::TwoFactorAuthResponse, ErrorResponse};
+use auth_service::{domain::{data_stores::LoginAttemptId, email::Email}, routes::{PasswordChangeRequiredResponse, TwoFactorAuthResponse}, ErrorResponse};
+use reqwest::Url;
 use secrecy::{ExposeSecret, Secret};
+use std::time::{Duration, Instant};
 use wiremock::{matchers::*, Mock, ResponseTemplate};
 
 #[tokio::test]
@@ -30,7 +32,7 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
 
     let auth_cookie = response
         .cookies()
-        .find(|cookie| cookie.name() == &app.auth_settings.http.jwt_cookie_name)
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
         .expect("No auth cookie found");
 
     assert!(!auth_cookie.value().is_empty());
@@ -70,17 +72,19 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     assert_eq!(response.status().as_u16(), 206);
 
+    let response = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    assert_eq!(response.message, "2FA required".to_owned());
+
+    let login_attempt_id = LoginAttemptId::parse(Secret::new(response.login_attempt_id)).unwrap();
     assert_eq!(
-        response
-            .json::<TwoFactorAuthResponse>()
-            .await
-            .expect("Could not deserialize response body to TwoFactorAuthResponse")
-            .message,
-        "2FA required".to_owned()
+        app.two_fa_code_store.get_code(&login_attempt_id).await.map(|(email, _)| email),
+        Ok(random_email_typed)
     );
 
-    assert!(app.two_fa_code_store.read().await.get_code(&random_email_typed).await.is_ok());
-
     app.clean_up().await;
 }
 
@@ -202,3 +206,205 @@ async fn should_return_401_if_incorrect_credentials() {
     app.clean_up().await;
 }
 
+#[tokio::test]
+async fn should_answer_unknown_emails_like_wrong_passwords() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let sign_up_request = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&sign_up_request).await.status().as_u16(), 201);
+
+    let mut wrong_password_time = Duration::ZERO;
+    let mut unknown_email_time = Duration::ZERO;
+
+    for _ in 0..5 {
+        let start = Instant::now();
+        let wrong_password = app.post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "NotQuiteThePassword123"
+        })).await;
+        wrong_password_time += start.elapsed();
+
+        let start = Instant::now();
+        let unknown_email = app.post_login(&serde_json::json!({
+            "email": get_random_email().expose_secret(),
+            "password": "NotQuiteThePassword123"
+        })).await;
+        unknown_email_time += start.elapsed();
+
+        assert_eq!(wrong_password.status().as_u16(), 401);
+        assert_eq!(unknown_email.status().as_u16(), 401);
+        assert_eq!(wrong_password.text().await.unwrap(), unknown_email.text().await.unwrap());
+    }
+
+    // Both verify a hash, an unknown email skipping it would be several times faster
+    assert!(unknown_email_time * 2 > wrong_password_time, "unknown {:?}, wrong {:?}", unknown_email_time, wrong_password_time);
+    assert!(wrong_password_time * 2 > unknown_email_time, "unknown {:?}, wrong {:?}", unknown_email_time, wrong_password_time);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_and_only_allow_a_password_change_if_password_expired() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email.clone())).unwrap()).await.unwrap();
+    app.user_store.set_password_max_age(&user.id, Some(chrono::Duration::days(90))).await.unwrap();
+    app.clock.advance(chrono::Duration::days(91));
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let password_change_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+        .expect("No password change cookie found")
+        .value()
+        .to_owned();
+
+    assert_eq!(
+        response
+            .json::<PasswordChangeRequiredResponse>()
+            .await
+            .expect("Could not deserialize response body to PasswordChangeRequiredResponse")
+            .message,
+        "Password change required".to_owned()
+    );
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": password_change_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}", app.auth_settings.http.jwt_cookie_name, password_change_token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "NewPassword456",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_age_passwords_by_the_app_clock() {
+    let mut app = TestApp::new(None).await;
+
+    // Signing up at a time far from the wall clock, the password is still new then
+    app.clock.advance(chrono::Duration::days(365));
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email.clone())).unwrap()).await.unwrap();
+    app.user_store.set_password_max_age(&user.id, Some(chrono::Duration::days(90))).await.unwrap();
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+
+    app.clock.advance(chrono::Duration::days(89));
+    assert_eq!(app.post_login(&login_body).await.status().as_u16(), 200);
+
+    app.clock.advance(chrono::Duration::days(1));
+    assert_eq!(app.post_login(&login_body).await.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_change_token_used_twice() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email.clone())).unwrap()).await.unwrap();
+    app.user_store.set_password_max_age(&user.id, Some(chrono::Duration::days(90))).await.unwrap();
+    app.clock.advance(chrono::Duration::days(91));
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let password_change_cookie = format!(
+        "{}={}",
+        app.auth_settings.http.jwt_cookie_name,
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+            .expect("No password change cookie found")
+            .value()
+    );
+    let url = Url::parse("http://127.0.0.1").expect("Failed to parse URL");
+    app.cookie_jar.add_cookie_str(&password_change_cookie, &url);
+
+    // A refused password leaves the token usable
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "short"
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The change set an auth cookie in its place, put the used token back
+    app.cookie_jar.add_cookie_str(&password_change_cookie, &url);
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "NewPassword456",
+        "newPassword": "OtherPassword789"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
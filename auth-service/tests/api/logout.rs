@@ -1,6 +1,6 @@
-use auth_service::{domain::email::Email, utils::{auth::generate_auth_cookie_without_domain, HttpSettings}, ErrorResponse};
+use auth_service::{domain::UserId, utils::{auth::{generate_auth_cookie_without_domain, validate_token, AuthMethod}, HttpSettings}, ErrorResponse};
 use secrecy::Secret;
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::TestApp;
 use reqwest::Url;
 
 #[tokio::test]
@@ -55,18 +55,25 @@ async fn should_return_401_if_invalid_token() {
 async fn should_return_200_if_valid_jwt_cookie() {
     let mut app = TestApp::new(None).await;
 
-    let email = Email::parse(Secret::new("user@domain.com".to_owned())).unwrap();
+    let user_id = UserId::default();
 
     let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
     let token_ttl_millis = app.auth_settings.redis.ttl_millis;
 
-    let cookie = generate_auth_cookie_without_domain(&email, jwt_token, jwt_cookie_name, token_ttl_millis).unwrap();
+    let cookie = generate_auth_cookie_without_domain(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
 
     app.cookie_jar.add_cookie_str(
         &format!("{}", cookie),
         &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
     );
 
+    let claims = validate_token(
+        app.banned_token_store.clone(),
+        &Secret::new(cookie.value().to_owned()),
+        app.auth_settings.http.jwt_token.clone(),
+        app.clock.as_ref(),
+    ).await.unwrap();
+
     let response = app.post_logout().await;
 
     assert_eq!(
@@ -76,9 +83,9 @@ async fn should_return_200_if_valid_jwt_cookie() {
     );
 
     {
-        let banned_token_store = app.banned_token_store.read().await;
+        let banned_token_store = &app.banned_token_store;
         assert_eq!(
-            banned_token_store.contains_token(&Secret::new(cookie.value().to_string())).await, 
+            banned_token_store.contains_token(&claims.jti).await, 
             Ok(true), 
                 "Missing token from the store: {}", cookie.value()
         );
@@ -91,12 +98,12 @@ async fn should_return_200_if_valid_jwt_cookie() {
 async fn should_return_400_if_logout_called_twice_in_a_row() {
     let mut app = TestApp::new(None).await;
 
-    let email = Email::parse(get_random_email()).unwrap();
+    let user_id = UserId::default();
 
     let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
     let token_ttl_millis = app.auth_settings.redis.ttl_millis;
 
-    let cookie = generate_auth_cookie_without_domain(&email, jwt_token, jwt_cookie_name, token_ttl_millis).unwrap();
+    let cookie = generate_auth_cookie_without_domain(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
 
     app.cookie_jar.add_cookie_str(
         &format!("{}", cookie),
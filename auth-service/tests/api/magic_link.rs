@@ -0,0 +1,261 @@
+use auth_service::{domain::Email, routes::{MagicLinkResponse, PasswordChangeRequiredResponse, TwoFactorAuthResponse}, ErrorResponse};
+use reqwest::{cookie::CookieStore, Url};
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": requires_2fa
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    random_email
+}
+
+// Requests a link for the email and reads the token back from the dev mailbox
+async fn request_magic_token(app: &TestApp, email: &str) -> String {
+    let response = app.post_magic_link(&serde_json::json!({ "email": email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let message = app.get_last_email_for(email).await.expect("No magic link email captured");
+    assert_eq!(message.subject, "Your login link");
+
+    message
+        .content
+        .split("magic_token=")
+        .nth(1)
+        .expect("No token in the magic link email")
+        .to_owned()
+}
+
+async fn mount_email_server(app: &TestApp, expected_calls: u64) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(expected_calls)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_set_auth_cookie_if_valid_magic_link() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup(&app, false).await;
+
+    mount_email_server(&app, 1).await;
+
+    let token = request_magic_token(&app, &random_email).await;
+
+    let response = app.post_verify_magic_link(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_206_if_valid_magic_link_and_2fa_enabled() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup(&app, true).await;
+
+    // The login link and then the 2FA code
+    mount_email_server(&app, 2).await;
+
+    let token = request_magic_token(&app, &random_email).await;
+
+    let response = app.post_verify_magic_link(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    assert_eq!(json_body.message, "2FA required".to_owned());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_without_sending_email_if_unknown_email() {
+    let mut app = TestApp::new(None).await;
+
+    mount_email_server(&app, 0).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_magic_link(&serde_json::json!({ "email": random_email })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<MagicLinkResponse>()
+        .await
+        .expect("Could not deserialize response body to MagicLinkResponse");
+
+    assert!(app.get_last_email_for(&random_email).await.is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_email() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.post_magic_link(&serde_json::json!({ "email": "not-an-email" })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_and_only_allow_a_password_change_if_password_expired() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup(&app, false).await;
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email.clone())).unwrap()).await.unwrap();
+    app.user_store.set_password_max_age(&user.id, Some(chrono::Duration::days(90))).await.unwrap();
+    app.clock.advance(chrono::Duration::days(91));
+
+    mount_email_server(&app, 1).await;
+
+    let token = request_magic_token(&app, &random_email).await;
+
+    let response = app.post_verify_magic_link(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let password_change_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+        .expect("No password change cookie found")
+        .value()
+        .to_owned();
+
+    assert_eq!(
+        response
+            .json::<PasswordChangeRequiredResponse>()
+            .await
+            .expect("Could not deserialize response body to PasswordChangeRequiredResponse")
+            .message,
+        "Password change required".to_owned()
+    );
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": password_change_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_magic_link_used_twice() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup(&app, false).await;
+
+    mount_email_server(&app, 1).await;
+
+    let token = request_magic_token(&app, &random_email).await;
+
+    // Logging in drops the nonce cookie, keep a copy to replay the link from the same browser
+    let url = Url::parse("http://127.0.0.1").expect("Failed to parse URL");
+    let nonce_cookie = app
+        .cookie_jar
+        .cookies(&url)
+        .expect("No nonce cookie found")
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let response = app.post_verify_magic_link(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.cookie_jar.add_cookie_str(&nonce_cookie, &url);
+
+    let response = app.post_verify_magic_link(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Invalid token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_magic_link_opened_in_another_browser() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup(&app, false).await;
+
+    mount_email_server(&app, 2).await;
+
+    let token = request_magic_token(&app, &random_email).await;
+
+    // Another browser with a nonce of its own, e.g. someone the email was forwarded to
+    let other_browser = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    let response = other_browser
+        .post(format!("{}/auth/magic-link", &app.address))
+        .json(&serde_json::json!({ "email": random_email }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = other_browser
+        .post(format!("{}/auth/verify-magic-link", &app.address))
+        .json(&serde_json::json!({ "token": token }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_magic_link_is_used_as_auth_cookie() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup(&app, false).await;
+
+    mount_email_server(&app, 1).await;
+
+    let token = request_magic_token(&app, &random_email).await;
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -1,8 +1,21 @@
+mod account_status;
+mod change_email;
+mod change_password;
+mod dev_mailbox;
 mod helpers;
 mod login;
 mod logout;
+mod magic_link;
+mod oidc;
+mod password_reset;
+mod phone_number;
+mod proof_of_work;
+mod rate_limit;
+mod reauthenticate;
 mod refresh_token;
 mod root;
+mod secure_account;
 mod signup;
+mod trusted_devices;
 mod verify_2fa;
 mod verify_token;
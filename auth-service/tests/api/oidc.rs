@@ -0,0 +1,454 @@
+use std::sync::Arc;
+
+use auth_service::{
+    domain::{data_stores::user_store::{MockUserStore, UserStoreError}, AccountStatus, Clock, Email, Password, TwoFAMethod, UserHashed, UserId},
+    ErrorResponse,
+};
+use chrono::Duration;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use mockall::Sequence;
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp, MOCK_IDP_CLIENT_ID, MOCK_IDP_NAME};
+
+const MOCK_IDP_KEY: &str = include_str!("fixtures/mock_idp_key.pem");
+const MOCK_IDP_JWKS: &str = include_str!("fixtures/mock_idp_jwks.json");
+
+// Starts the login and returns the state and nonce the provider would receive
+async fn start_login(app: &TestApp) -> (String, String) {
+    let response = app.get_oidc_authorize(MOCK_IDP_NAME).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = Url::parse(
+        response.headers()["location"].to_str().expect("Invalid location header"),
+    )
+    .expect("Failed to parse location");
+
+    let query_value = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_else(|| panic!("No {} in the authorization url", name))
+    };
+
+    (query_value("state"), query_value("nonce"))
+}
+
+fn id_token(app: &TestApp, email: &str, email_verified: bool, nonce: &str) -> String {
+    id_token_signed_as(app, Some("mock-idp-key"), email, email_verified, nonce)
+}
+
+// Signed with the mock provider's key, announced under the given kid
+fn id_token_signed_as(app: &TestApp, kid: Option<&str>, email: &str, email_verified: bool, nonce: &str) -> String {
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = kid.map(str::to_owned);
+
+    let claims = serde_json::json!({
+        "iss": app.idp_server.uri(),
+        "aud": MOCK_IDP_CLIENT_ID,
+        "sub": "248289761001",
+        "exp": app.clock.now().timestamp() + 600,
+        "email": email,
+        "email_verified": email_verified,
+        "nonce": nonce,
+    });
+
+    encode(
+        &header,
+        &claims,
+        &EncodingKey::from_rsa_pem(MOCK_IDP_KEY.as_bytes()).expect("Invalid mock IdP key"),
+    )
+    .expect("Failed to sign id_token")
+}
+
+// The provider hands out the given id_token for the code, as long as the PKCE verifier is sent
+async fn mount_idp(app: &TestApp, id_token: String) {
+    Mock::given(path("/jwks"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(MOCK_IDP_JWKS, "application/json"))
+        .mount(&app.idp_server)
+        .await;
+
+    mount_token_endpoint(app, id_token).await;
+}
+
+// Answers a single code exchange, so each login mounts its own
+async fn mount_token_endpoint(app: &TestApp, id_token: String) {
+    Mock::given(path("/token"))
+        .and(method("POST"))
+        .and(body_string_contains("grant_type=authorization_code"))
+        .and(body_string_contains("code=auth-code"))
+        .and(body_string_contains("code_verifier="))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "access_token": "access-token",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.idp_server)
+        .await;
+}
+
+// Logs a new user in through the provider, with the id_token the closure signs for the login's nonce
+async fn log_in_with_provider(app: &TestApp, sign_id_token: impl FnOnce(&str, &str) -> String) -> reqwest::Response {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let (state, nonce) = start_login(app).await;
+    mount_token_endpoint(app, sign_id_token(&random_email, &nonce)).await;
+
+    app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", &state)]).await
+}
+
+fn auth_cookie_is_set(app: &TestApp, response: &reqwest::Response) -> bool {
+    response
+        .cookies()
+        .any(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name && !cookie.value().is_empty())
+}
+
+#[tokio::test]
+async fn should_redirect_to_provider_with_pkce_challenge() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.get_oidc_authorize(MOCK_IDP_NAME).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = response.headers()["location"].to_str().unwrap().to_owned();
+
+    assert!(location.starts_with(&format!("{}/authorize?", app.idp_server.uri())));
+    assert!(location.contains("code_challenge_method=S256"));
+    assert!(location.contains(&format!("client_id={}", MOCK_IDP_CLIENT_ID)));
+    assert!(response.cookies().any(|cookie| cookie.name() == "oidc_flow"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_if_unknown_provider() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.get_oidc_authorize("unknown").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Unknown identity provider".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_create_user_and_log_in_if_new_verified_email() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let (state, nonce) = start_login(&app).await;
+    mount_idp(&app, id_token(&app, &random_email, true, &nonce)).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", &state)]).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers()["location"], "/auth/");
+    assert!(auth_cookie_is_set(&app, &response));
+
+    // The account now exists
+    let email = Email::parse(Secret::new(random_email)).unwrap();
+    assert!(app.user_store.get_user(&email).await.is_ok());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_link_existing_user_and_continue_with_2fa() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (state, nonce) = start_login(&app).await;
+    mount_idp(&app, id_token(&app, &random_email, true, &nonce)).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", &state)]).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(!auth_cookie_is_set(&app, &response));
+
+    let location = response.headers()["location"].to_str().unwrap();
+    assert!(location.starts_with("/auth/#loginAttemptId="));
+
+    let message = app.get_last_email_for(&random_email).await.expect("No 2FA email captured");
+    assert_eq!(message.subject, "Verify code for login");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_redirect_to_the_password_change_if_password_expired() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    assert_eq!(app.post_signup(&signup_body).await.status().as_u16(), 201);
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email.clone())).unwrap()).await.unwrap();
+    app.user_store.set_password_max_age(&user.id, Some(Duration::days(90))).await.unwrap();
+    app.clock.advance(Duration::days(91));
+
+    let (state, nonce) = start_login(&app).await;
+    mount_idp(&app, id_token(&app, &random_email, true, &nonce)).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", &state)]).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers()["location"], "/auth/#passwordChangeRequired=true");
+
+    // The cookie only opens /change-password
+    let password_change_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+        .expect("No password change cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": password_change_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}", app.auth_settings.http.jwt_cookie_name, password_change_token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_state_does_not_match() {
+    let mut app = TestApp::new(None).await;
+
+    let _ = start_login(&app).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", "forged-state")]).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_nonce_does_not_match() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let (state, _) = start_login(&app).await;
+    mount_idp(&app, id_token(&app, &random_email, true, "replayed-nonce")).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", &state)]).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(!auth_cookie_is_set(&app, &response));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_email_not_verified() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let (state, nonce) = start_login(&app).await;
+    mount_idp(&app, id_token(&app, &random_email, false, &nonce)).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", &state)]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_callback_without_flow_cookie() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", "state")]).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_fetch_provider_keys_once_while_cached() {
+    let mut app = TestApp::new(None).await;
+
+    Mock::given(path("/jwks"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(MOCK_IDP_JWKS, "application/json"))
+        .expect(1)
+        .mount(&app.idp_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = log_in_with_provider(&app, |email, nonce| id_token(&app, email, true, nonce)).await;
+        assert_eq!(response.status().as_u16(), 303);
+        assert!(auth_cookie_is_set(&app, &response));
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_refetch_provider_keys_for_a_token_signed_with_an_unknown_key() {
+    let mut app = TestApp::new(None).await;
+
+    Mock::given(path("/jwks"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(MOCK_IDP_JWKS, "application/json"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.idp_server)
+        .await;
+
+    // The provider rotated to a new key after the first login
+    Mock::given(path("/jwks"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(MOCK_IDP_JWKS.replace("mock-idp-key", "rotated-key"), "application/json"))
+        .expect(1)
+        .mount(&app.idp_server)
+        .await;
+
+    let response = log_in_with_provider(&app, |email, nonce| id_token(&app, email, true, nonce)).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    app.clock.advance(Duration::minutes(1));
+
+    let response = log_in_with_provider(&app, |email, nonce| id_token_signed_as(&app, Some("rotated-key"), email, true, nonce)).await;
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(auth_cookie_is_set(&app, &response));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_id_token_has_no_kid_and_provider_has_several_keys() {
+    let mut app = TestApp::new(None).await;
+
+    let mut jwks: serde_json::Value = serde_json::from_str(MOCK_IDP_JWKS).unwrap();
+    let mut other_key = jwks["keys"][0].clone();
+    other_key["kid"] = "other-key".into();
+    jwks["keys"].as_array_mut().unwrap().push(other_key);
+
+    Mock::given(path("/jwks"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(jwks))
+        .mount(&app.idp_server)
+        .await;
+
+    let response = log_in_with_provider(&app, |email, nonce| id_token_signed_as(&app, None, email, true, nonce)).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(!auth_cookie_is_set(&app, &response));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_log_in_if_a_concurrent_callback_created_the_user_first() {
+    let random_email = get_random_email().expose_secret().to_owned();
+    let email = Email::parse(Secret::new(random_email.clone())).unwrap();
+
+    let user = UserHashed {
+        id: UserId::default(),
+        email: email.clone(),
+        password_hash: Password::parse(Secret::new("Password123".to_owned())).unwrap(),
+        requires_2fa: false,
+        phone_number: None,
+        phone_number_verified: false,
+        two_fa_method: TwoFAMethod::Email,
+        password_changed_at: chrono::Utc::now(),
+        password_max_age: None,
+        status: AccountStatus::Active,
+    };
+
+    let mut mock_user_store = MockUserStore::new();
+    let mut seq = Sequence::new();
+
+    mock_user_store
+        .expect_get_user()
+        .once()
+        .in_sequence(&mut seq)
+        .returning(|_| Err(UserStoreError::UserNotFound));
+
+    // The other callback inserted the same email in between
+    mock_user_store
+        .expect_add_user()
+        .once()
+        .in_sequence(&mut seq)
+        .returning(|_| Err(UserStoreError::UserAlreadyExists));
+
+    mock_user_store
+        .expect_get_user()
+        .once()
+        .in_sequence(&mut seq)
+        .returning(move |_| Ok(user.clone()));
+
+    let mut app = TestApp::new(Some(Arc::new(mock_user_store))).await;
+
+    let (state, nonce) = start_login(&app).await;
+    mount_idp(&app, id_token(&app, &random_email, true, &nonce)).await;
+
+    let response = app.get_oidc_callback(MOCK_IDP_NAME, &[("code", "auth-code"), ("state", &state)]).await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(auth_cookie_is_set(&app, &response));
+
+    app.clean_up().await;
+}
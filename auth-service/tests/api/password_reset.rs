@@ -0,0 +1,180 @@
+use auth_service::{routes::{MailboxMessage, PasswordResetResponse}, ErrorResponse};
+use secrecy::ExposeSecret;
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+const RESET_SUBJECT: &str = "Reset your password";
+
+async fn signup(app: &TestApp) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    random_email
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn request_reset_link(app: &TestApp, email: &str) -> String {
+    let response = app.post_password_reset(&serde_json::json!({ "email": email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.get_dev_mailbox(Some(email))
+        .await
+        .json::<Vec<MailboxMessage>>()
+        .await
+        .expect("Could not deserialize response body to Vec<MailboxMessage>")
+        .into_iter()
+        .rev()
+        .find(|message| message.subject == RESET_SUBJECT)
+        .and_then(|message| {
+            message.content
+                .split("password_reset_token=")
+                .nth(1)
+                .and_then(|rest| rest.split_whitespace().next())
+                .map(str::to_owned)
+        })
+        .expect("No password reset link was sent")
+}
+
+async fn log_in_with(app: &TestApp, email: &str, password: &str) -> u16 {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password,
+    }))
+    .await
+    .status()
+    .as_u16()
+}
+
+#[tokio::test]
+async fn should_answer_the_same_for_unknown_emails() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let known_email = signup(&app).await;
+    let unknown_email = get_random_email().expose_secret().to_owned();
+
+    let mut messages = Vec::new();
+    for email in [&known_email, &unknown_email] {
+        let response = app.post_password_reset(&serde_json::json!({ "email": email })).await;
+        assert_eq!(response.status().as_u16(), 200);
+        messages.push(
+            response
+                .json::<PasswordResetResponse>()
+                .await
+                .expect("Could not deserialize response body to PasswordResetResponse")
+                .message,
+        );
+    }
+    assert_eq!(messages[0], messages[1]);
+
+    let mailbox = app.get_dev_mailbox(Some(&unknown_email))
+        .await
+        .json::<Vec<MailboxMessage>>()
+        .await
+        .expect("Could not deserialize response body to Vec<MailboxMessage>");
+    assert!(mailbox.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_replace_the_password() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app).await;
+    let token = request_reset_link(&app, &random_email).await;
+
+    let response = app.post_confirm_password_reset(&serde_json::json!({
+        "token": token,
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(log_in_with(&app, &random_email, "Password123").await, 401);
+    assert_eq!(log_in_with(&app, &random_email, "NewPassword456").await, 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_link_used_twice() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app).await;
+    let token = request_reset_link(&app, &random_email).await;
+
+    let response = app.post_confirm_password_reset(&serde_json::json!({
+        "token": token,
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_confirm_password_reset(&serde_json::json!({
+        "token": token,
+        "newPassword": "OtherPassword789"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Invalid token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app).await;
+    let token = request_reset_link(&app, &random_email).await;
+
+    let response = app.post_confirm_password_reset(&serde_json::json!({
+        "token": token,
+        "newPassword": "short"
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The refused password did not use the link up
+    let response = app.post_confirm_password_reset(&serde_json::json!({
+        "token": token,
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_link_expired() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app).await;
+    let token = request_reset_link(&app, &random_email).await;
+
+    app.clock.advance(chrono::Duration::milliseconds(app.auth_settings.password_reset.ttl_millis) + chrono::Duration::minutes(2));
+    let response = app.post_confirm_password_reset(&serde_json::json!({
+        "token": token,
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,270 @@
+use auth_service::{domain::{data_stores::LoginAttemptId, Email}, routes::SetPhoneNumberResponse, ErrorResponse};
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PHONE_NUMBER: &str = "+34600123456";
+
+// Signs up a user with 2FA and leaves the http client logged in as them
+async fn signup_and_log_in(app: &TestApp) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+
+    random_email
+}
+
+async fn mount_sms_server(app: &TestApp, expected_calls: u64) {
+    Mock::given(path_regex(r"^/2010-04-01/Accounts/.+/Messages\.json$"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(expected_calls)
+        .mount(&app.sms_server)
+        .await;
+}
+
+// Goes through the whole verification of PHONE_NUMBER for the logged in user
+async fn verify_phone_number(app: &TestApp, random_email: &str) {
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let verification_id = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse")
+        .verification_id;
+
+    let (email, phone_number, code) = app.two_fa_code_store
+        .get_phone_number_code(&LoginAttemptId::parse(Secret::new(verification_id.clone())).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(email, Email::parse(Secret::new(random_email.to_owned())).unwrap());
+    assert_eq!(phone_number.as_ref().expose_secret(), PHONE_NUMBER);
+
+    let response = app.post_verify_phone_number(&serde_json::json!({
+        "verificationId": verification_id,
+        "code": code.as_ref().expose_secret()
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_not_logged_in() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_phone_number_is_not_e164() {
+    let mut app = TestApp::new(None).await;
+
+    let _ = signup_and_log_in(&app).await;
+
+    for phone_number in ["600123456", "+34 600 123 456", "+0600123456"] {
+        let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": phone_number })).await;
+
+        assert_eq!(response.status().as_u16(), 400, "Failed for input: {}", phone_number);
+
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Invalid phone number".to_owned()
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_2fa_code_by_sms_once_phone_number_is_verified() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup_and_log_in(&app).await;
+
+    // One SMS to verify the number and one more on login
+    mount_sms_server(&app, 2).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    verify_phone_number(&app, &random_email).await;
+
+    let response = app.post_2fa_method(&serde_json::json!({ "method": "sms" })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_sms_chosen_before_verifying_phone_number() {
+    let mut app = TestApp::new(None).await;
+
+    let _ = signup_and_log_in(&app).await;
+
+    mount_sms_server(&app, 1).await;
+
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_2fa_method(&serde_json::json!({ "method": "sms" })).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Phone number not verified".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_verification_code() {
+    let mut app = TestApp::new(None).await;
+
+    signup_and_log_in(&app).await;
+
+    mount_sms_server(&app, 1).await;
+
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER })).await;
+
+    let verification_id = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse")
+        .verification_id;
+
+    let (_, _, code) = app.two_fa_code_store
+        .get_phone_number_code(&LoginAttemptId::parse(Secret::new(verification_id.clone())).unwrap())
+        .await
+        .unwrap();
+    let wrong_code = if code.as_ref().expose_secret() == "123456" { "654321" } else { "123456" };
+
+    let response = app.post_verify_phone_number(&serde_json::json!({
+        "verificationId": verification_id,
+        "code": wrong_code
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_fall_back_to_email_when_phone_number_changes() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup_and_log_in(&app).await;
+
+    // Verification of the first number and the unverified second one
+    mount_sms_server(&app, 2).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    verify_phone_number(&app, &random_email).await;
+
+    let response = app.post_2fa_method(&serde_json::json!({ "method": "sms" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": "+34600654321" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_the_code_was_sent_to_another_number() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = signup_and_log_in(&app).await;
+
+    mount_sms_server(&app, 2).await;
+
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": PHONE_NUMBER })).await;
+    let verification_id = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse")
+        .verification_id;
+    let (_, _, code) = app.two_fa_code_store
+        .get_phone_number_code(&LoginAttemptId::parse(Secret::new(verification_id.clone())).unwrap())
+        .await
+        .unwrap();
+
+    // The pending number changes before the code of the first one comes back
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": "+34600654321" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_phone_number(&serde_json::json!({
+        "verificationId": verification_id,
+        "code": code.as_ref().expose_secret()
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email)).unwrap()).await.unwrap();
+    assert!(!user.phone_number_verified);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,170 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    domain::data_stores::TokenBucket,
+    routes::ChallengeResponse,
+    utils::{
+        proof_of_work::{leading_zero_bits, solution_hash},
+        AuthSettings, RouteRateLimitSettings,
+    },
+    ErrorResponse,
+};
+use chrono::Duration;
+use secrecy::{ExposeSecret, Secret};
+
+const BASE_DIFFICULTY: u32 = 4;
+const DIFFICULTY_STEP: u32 = 2;
+
+// Easy challenges on /signup and /login, whose rate limit feeds the abuse bucket
+fn easy_proof_of_work(settings: &mut AuthSettings) {
+    settings.proof_of_work.enabled = true;
+    settings.proof_of_work.routes = vec!["/signup".to_owned(), "/login".to_owned()];
+    settings.proof_of_work.base_difficulty = BASE_DIFFICULTY;
+    settings.proof_of_work.difficulty_step = DIFFICULTY_STEP;
+    settings.proof_of_work.abuse = TokenBucket { capacity: 2, refill_millis: 60_000 };
+    settings.rate_limit.enabled = true;
+    settings.rate_limit.routes = vec![RouteRateLimitSettings {
+        path: "/login".to_owned(),
+        per_ip: Some(TokenBucket { capacity: 1, refill_millis: 60_000 }),
+        per_email: None,
+    }];
+}
+
+async fn get_challenge(app: &TestApp) -> ChallengeResponse {
+    let response = app.get_challenge().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json::<ChallengeResponse>().await.expect("Could not deserialize response body to ChallengeResponse")
+}
+
+fn solve(challenge: &ChallengeResponse) -> String {
+    let challenge_secret = Secret::new(challenge.challenge.clone());
+    (0u64..)
+        .map(|counter| counter.to_string())
+        .find(|solution| leading_zero_bits(&solution_hash(&challenge_secret, &Secret::new(solution.clone()))) >= challenge.difficulty)
+        .unwrap()
+}
+
+// A fixed string would solve an easy challenge every now and then
+fn unsolved(challenge: &ChallengeResponse) -> String {
+    let challenge_secret = Secret::new(challenge.challenge.clone());
+    (0u64..)
+        .map(|counter| counter.to_string())
+        .find(|solution| leading_zero_bits(&solution_hash(&challenge_secret, &Secret::new(solution.clone()))) < challenge.difficulty)
+        .unwrap()
+}
+
+fn signup_body() -> serde_json::Value {
+    serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "Password123",
+        "requires2FA": false
+    })
+}
+
+fn login_body() -> serde_json::Value {
+    serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "Password123",
+    })
+}
+
+#[tokio::test]
+async fn should_return_428_without_a_solved_challenge() {
+    let mut app = TestApp::builder().settings(easy_proof_of_work).build().await;
+
+    let response = app.post_signup(&signup_body()).await;
+    assert_eq!(response.status().as_u16(), 428);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.expect("Could not deserialize response body to ErrorResponse").error,
+        "Solve a challenge from /auth/challenge first".to_owned()
+    );
+
+    let challenge = get_challenge(&app).await;
+    let response = app.post_with_proof_of_work("/signup", &signup_body(), &challenge.challenge, &unsolved(&challenge)).await;
+    assert_eq!(response.status().as_u16(), 428);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_a_solved_challenge_once() {
+    let mut app = TestApp::builder().settings(easy_proof_of_work).build().await;
+    let challenge = get_challenge(&app).await;
+    assert_eq!(challenge.difficulty, BASE_DIFFICULTY);
+    let solution = solve(&challenge);
+
+    let response = app.post_with_proof_of_work("/signup", &signup_body(), &challenge.challenge, &solution).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_with_proof_of_work("/signup", &signup_body(), &challenge.challenge, &solution).await;
+    assert_eq!(response.status().as_u16(), 428);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_expired_challenges() {
+    let mut app = TestApp::builder().settings(easy_proof_of_work).build().await;
+    let challenge = get_challenge(&app).await;
+    let solution = solve(&challenge);
+
+    app.clock.advance(Duration::milliseconds(app.auth_settings.proof_of_work.ttl_millis) + Duration::minutes(2));
+    let response = app.post_with_proof_of_work("/signup", &signup_body(), &challenge.challenge, &solution).await;
+    assert_eq!(response.status().as_u16(), 428);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_raise_the_difficulty_for_clients_being_rate_limited() {
+    let mut app = TestApp::builder().settings(easy_proof_of_work).build().await;
+    let before_abuse = get_challenge(&app).await;
+
+    // The first login takes the only token, the next ones are turned away
+    for _ in 0..3 {
+        let challenge = get_challenge(&app).await;
+        let _ = app.post_with_proof_of_work("/login", &login_body(), &challenge.challenge, &solve(&challenge)).await;
+    }
+
+    let challenge = get_challenge(&app).await;
+    assert_eq!(challenge.difficulty, BASE_DIFFICULTY + 2 * DIFFICULTY_STEP);
+
+    // Challenges handed out before the abuse are no longer hard enough
+    let response = app.post_with_proof_of_work("/signup", &signup_body(), &before_abuse.challenge, &solve(&before_abuse)).await;
+    assert_eq!(response.status().as_u16(), 428);
+
+    let response = app.post_with_proof_of_work("/signup", &signup_body(), &challenge.challenge, &solve(&challenge)).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // And the difficulty comes back down as the abuse bucket refills
+    app.clock.advance(Duration::minutes(1));
+    assert_eq!(get_challenge(&app).await.difficulty, BASE_DIFFICULTY + DIFFICULTY_STEP);
+
+    app.clean_up().await;
+}
+
+// Keeps the routes from config/default.toml, so those stay covered as the config changes
+#[tokio::test]
+async fn should_require_proof_of_work_on_the_configured_routes() {
+    let mut app = TestApp::builder().settings(|settings| settings.proof_of_work.enabled = true).build().await;
+
+    let response = app.post_signup(&signup_body()).await;
+    assert_eq!(response.status().as_u16(), 428);
+
+    let response = app.post_login(&login_body()).await;
+    assert_eq!(response.status().as_u16(), 428);
+
+    let response = app.post_password_reset(&serde_json::json!({ "email": get_random_email().expose_secret() })).await;
+    assert_eq!(response.status().as_u16(), 428);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_require_proof_of_work_on_other_routes() {
+    let mut app = TestApp::builder().settings(easy_proof_of_work).build().await;
+
+    let response = app.post_magic_link(&serde_json::json!({ "email": get_random_email().expose_secret() })).await;
+    assert_ne!(response.status().as_u16(), 428);
+
+    app.clean_up().await;
+}
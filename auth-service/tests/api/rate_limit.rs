@@ -0,0 +1,121 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{domain::data_stores::TokenBucket, utils::RouteRateLimitSettings, ErrorResponse};
+use chrono::Duration;
+use secrecy::ExposeSecret;
+
+const BUCKET: TokenBucket = TokenBucket { capacity: 2, refill_millis: 60_000 };
+
+// Limits /login only, per client address or per email
+fn login_limit(per_ip: Option<TokenBucket>, per_email: Option<TokenBucket>) -> RouteRateLimitSettings {
+    RouteRateLimitSettings { path: "/login".to_owned(), per_ip, per_email }
+}
+
+fn login_body(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": "Password123",
+    })
+}
+
+#[tokio::test]
+async fn should_return_429_with_retry_after_once_ip_is_over_the_limit() {
+    let mut app = TestApp::builder().rate_limit(login_limit(Some(BUCKET), None)).build().await;
+
+    for _ in 0..BUCKET.capacity {
+        let response = app.post_login(&login_body(get_random_email().expose_secret())).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app.post_login(&login_body(get_random_email().expose_secret())).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers().get("retry-after").unwrap(), "60");
+    assert_eq!(
+        response.json::<ErrorResponse>().await.expect("Could not deserialize response body to ErrorResponse").error,
+        "Too many requests, try again later".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_let_requests_through_once_the_bucket_refills() {
+    let mut app = TestApp::builder().rate_limit(login_limit(Some(BUCKET), None)).build().await;
+    for _ in 0..=BUCKET.capacity {
+        let _ = app.post_login(&login_body(get_random_email().expose_secret())).await;
+    }
+
+    app.clock.advance(Duration::seconds(45));
+    let response = app.post_login(&login_body(get_random_email().expose_secret())).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers().get("retry-after").unwrap(), "15");
+
+    app.clock.advance(Duration::seconds(15));
+    let response = app.post_login(&login_body(get_random_email().expose_secret())).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_limit_each_forwarded_client_on_its_own() {
+    let mut app = TestApp::builder().rate_limit(login_limit(Some(BUCKET), None)).build().await;
+    let email = get_random_email().expose_secret().to_owned();
+
+    for _ in 0..BUCKET.capacity {
+        let _ = app.post_login_from(&login_body(&email), "198.51.100.23", "test").await;
+    }
+    let response = app.post_login_from(&login_body(&email), "198.51.100.23", "test").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // The test client connects from 127.0.0.1, a trusted proxy, so the header tells the clients apart
+    let response = app.post_login_from(&login_body(&email), "203.0.113.7", "test").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_limit_each_email_wherever_the_requests_come_from() {
+    let mut app = TestApp::builder().rate_limit(login_limit(None, Some(BUCKET))).build().await;
+    let email = get_random_email().expose_secret().to_owned();
+
+    for ip_address in ["198.51.100.1", "198.51.100.2"] {
+        let response = app.post_login_from(&login_body(&email), ip_address, "test").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app.post_login_from(&login_body(&email.to_uppercase()), "198.51.100.3", "test").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_login(&login_body(get_random_email().expose_secret())).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_limit_other_routes() {
+    let mut app = TestApp::builder().rate_limit(login_limit(Some(BUCKET), None)).build().await;
+
+    for _ in 0..=BUCKET.capacity {
+        let response = app.post_magic_link(&serde_json::json!({ "email": get_random_email().expose_secret() })).await;
+        assert_ne!(response.status().as_u16(), 429);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_limit_when_disabled() {
+    let mut app = TestApp::builder()
+        .settings(|settings| settings.rate_limit.routes = vec![login_limit(Some(BUCKET), None)])
+        .build()
+        .await;
+
+    for _ in 0..=BUCKET.capacity {
+        let response = app.post_login(&login_body(get_random_email().expose_secret())).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    app.clean_up().await;
+}
@@ -0,0 +1,250 @@
+use auth_service::{domain::Email, routes::PasswordChangeRequiredResponse, utils::AuthSettings, ErrorResponse};
+use chrono::Duration;
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Longer than any route's max age
+const STALE: Duration = Duration::minutes(11);
+
+// Sessions outlive STALE, otherwise the auth cookie would expire first
+fn long_sessions(settings: &mut AuthSettings) {
+    settings.redis.ttl_millis = 60 * 60 * 1000;
+}
+
+async fn signup_and_log_in(app: &TestApp) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+
+    random_email
+}
+
+// The auth cookie is set for the production domain, the test client needs it for the test server
+fn keep_auth_cookie(app: &TestApp, response: &reqwest::Response) {
+    let cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+        .expect("No auth cookie found");
+
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}", cookie.name(), cookie.value()),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+async fn assert_reauthentication_required(response: reqwest::Response) {
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Reauthentication required".to_owned()
+    );
+}
+
+#[tokio::test]
+async fn should_delete_account_right_after_logging_in() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    let random_email = signup_and_log_in(&app).await;
+
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_authentication_is_stale() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    let random_email = signup_and_log_in(&app).await;
+
+    app.clock.advance(STALE);
+
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_reauthentication_required(response).await;
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_reauthentication_required(response).await;
+
+    let response = app.post_change_email(&serde_json::json!({
+        "newEmail": get_random_email().expose_secret(),
+        "password": "Password123"
+    })).await;
+    assert_reauthentication_required(response).await;
+
+    let response = app.post_phone_number(&serde_json::json!({ "phoneNumber": "+34600000000" })).await;
+    assert_reauthentication_required(response).await;
+
+    let response = app.post_2fa_method(&serde_json::json!({ "method": "email" })).await;
+    assert_reauthentication_required(response).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_right_after_a_magic_link_login() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_magic_link(&serde_json::json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let message = app.get_last_email_for(&random_email).await.expect("No magic link email captured");
+    let token = message.content.split("magic_token=").nth(1).expect("No token in the magic link email").to_owned();
+
+    let response = app.post_verify_magic_link(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    keep_auth_cookie(&app, &response);
+
+    // The link shows access to the inbox, not the password
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_reauthentication_required(response).await;
+
+    let response = app.post_reauthenticate(&serde_json::json!({ "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    keep_auth_cookie(&app, &response);
+
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_allow_sensitive_routes_again_after_reauthenticating() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    let random_email = signup_and_log_in(&app).await;
+
+    app.clock.advance(STALE);
+
+    let response = app.post_reauthenticate(&serde_json::json!({ "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    keep_auth_cookie(&app, &response);
+
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_allow_a_password_change_if_password_expired() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    let random_email = signup_and_log_in(&app).await;
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email.clone())).unwrap()).await.unwrap();
+    app.user_store.set_password_max_age(&user.id, Some(Duration::minutes(5))).await.unwrap();
+    app.clock.advance(STALE);
+
+    let response = app.post_reauthenticate(&serde_json::json!({ "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 403);
+    keep_auth_cookie(&app, &response);
+    assert_eq!(
+        response
+            .json::<PasswordChangeRequiredResponse>()
+            .await
+            .expect("Could not deserialize response body to PasswordChangeRequiredResponse")
+            .message,
+        "Password change required".to_owned()
+    );
+
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_change_password(&serde_json::json!({
+        "currentPassword": "Password123",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_authentication_time_when_refreshing() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    let random_email = signup_and_log_in(&app).await;
+
+    app.clock.advance(STALE);
+
+    let response = app.post_refresh_token().await;
+    assert_eq!(response.status().as_u16(), 204);
+    keep_auth_cookie(&app, &response);
+
+    let response = app.delete_account(&serde_json::json!({ "email": random_email })).await;
+    assert_reauthentication_required(response).await;
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_reauthenticating_with_wrong_password() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    signup_and_log_in(&app).await;
+
+    let response = app.post_reauthenticate(&serde_json::json!({ "password": "WrongPassword123" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_reauthenticating_without_token() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+
+    let response = app.post_reauthenticate(&serde_json::json!({ "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_deleting_another_users_account() {
+    let mut app = TestApp::builder().settings(long_sessions).build().await;
+    let other_email = signup_and_log_in(&app).await;
+    signup_and_log_in(&app).await;
+
+    let response = app.delete_account(&serde_json::json!({ "email": other_email })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": other_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
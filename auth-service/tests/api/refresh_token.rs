@@ -1,7 +1,7 @@
 use crate::helpers::{get_random_email, TestApp};
 use auth_service::{
-    domain::email::Email, 
-    utils::{auth::{generate_auth_cookie_empty, generate_auth_cookie_without_domain}, HttpSettings},
+    domain::{Email, UserId}, 
+    utils::{auth::{generate_auth_cookie_empty, generate_auth_cookie_without_domain, AuthMethod}, HttpSettings},
 };
 use reqwest::{cookie::CookieStore, Url};
 use secrecy::{ExposeSecret, Secret};
@@ -10,18 +10,6 @@ use secrecy::{ExposeSecret, Secret};
 async fn should_return_204_if_the_token_is_valid_and_get_a_new_token() {
     let mut app = TestApp::new(None).await;
 
-    let email = Email::parse(get_random_email()).unwrap();
-
-    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
-    let token_ttl_millis = app.auth_settings.redis.ttl_millis;
-
-    let cookie = generate_auth_cookie_without_domain(&email, jwt_token, jwt_cookie_name, token_ttl_millis).unwrap();
-
-    app.cookie_jar.add_cookie_str(
-        &format!("{}", cookie),
-        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
-    );
-
     let random_email = get_random_email().expose_secret().to_owned();
 
     let signup_body = serde_json::json!({
@@ -34,6 +22,8 @@ async fn should_return_204_if_the_token_is_valid_and_get_a_new_token() {
 
     assert_eq!(response.status().as_u16(), 201);
 
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+
     let login_body = serde_json::json!({
         "email": random_email,
         "password": "Password123",
@@ -48,7 +38,7 @@ async fn should_return_204_if_the_token_is_valid_and_get_a_new_token() {
 
     //If hitting refresh creating the cookie right away will get exactly the same cookie and 
     //cannot compare if the cookie was refreshed
-    std::thread::sleep(std::time::Duration::from_millis(1000));
+    app.clock.advance(chrono::Duration::seconds(1));
 
     let response = app.post_refresh_token().await;
     let new_cookie = 
@@ -103,20 +93,16 @@ async fn should_return_400_if_the_token_is_expired() {
 #[tokio::test]
 async fn should_return_401_if_the_token_is_banned() {
     let mut app = TestApp::new(None).await;
-    let email = Email::parse(get_random_email()).unwrap();
+    let user_id = UserId::default();
     let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
     let token_ttl_millis = app.auth_settings.redis.ttl_millis;
-    let cookie = generate_auth_cookie_without_domain(&email, jwt_token, jwt_cookie_name, token_ttl_millis).unwrap();
+    let cookie = generate_auth_cookie_without_domain(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
     app.cookie_jar.add_cookie_str(
         &format!("{}", cookie),
         &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
     );
     
-    // This is an inner scope to drop banned_token_store write reference once the add_token operation is finished
-    {
-        let mut banned_token_store = app.banned_token_store.write().await;
-        let _ = banned_token_store.add_token(Secret::new(cookie.value().to_owned())).await;
-    } // dropping write lock here
+    app.ban_token(cookie.value()).await;
     
     let response = app.post_refresh_token().await;
 
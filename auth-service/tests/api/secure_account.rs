@@ -0,0 +1,264 @@
+use auth_service::{domain::Email, routes::MailboxMessage, ErrorResponse};
+use reqwest::{cookie::CookieStore, Url};
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+const HOME: (&str, &str) = ("203.0.113.7", "Firefox on Linux");
+const ELSEWHERE: (&str, &str) = ("198.51.100.23", "Chrome on Windows");
+
+const ALERT_SUBJECT: &str = "New login to your account";
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": requires_2fa
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    random_email
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn log_in_from(app: &TestApp, email: &str, (ip_address, user_agent): (&str, &str)) {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123",
+    });
+
+    let response = app.post_login_from(&login_body, ip_address, user_agent).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn alerts_for(app: &TestApp, email: &str) -> Vec<String> {
+    app.get_dev_mailbox(Some(email))
+        .await
+        .json::<Vec<MailboxMessage>>()
+        .await
+        .expect("Could not deserialize response body to Vec<MailboxMessage>")
+        .into_iter()
+        .filter(|message| message.subject == ALERT_SUBJECT)
+        .map(|message| message.content)
+        .collect()
+}
+
+fn secure_account_token(alert: &str) -> String {
+    alert
+        .split("secure_account_token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .expect("No secure account link in the alert")
+        .to_owned()
+}
+
+#[tokio::test]
+async fn should_not_alert_on_the_first_login() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app, false).await;
+
+    log_in_from(&app, &random_email, HOME).await;
+
+    assert!(alerts_for(&app, &random_email).await.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_alert_on_a_known_device() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app, false).await;
+
+    log_in_from(&app, &random_email, HOME).await;
+    log_in_from(&app, &random_email, HOME).await;
+
+    assert!(alerts_for(&app, &random_email).await.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_alert_on_a_new_device() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app, false).await;
+
+    log_in_from(&app, &random_email, HOME).await;
+    log_in_from(&app, &random_email, ELSEWHERE).await;
+
+    let alerts = alerts_for(&app, &random_email).await;
+    assert_eq!(alerts.len(), 1);
+    assert!(alerts[0].contains(ELSEWHERE.0));
+    assert!(alerts[0].contains(ELSEWHERE.1));
+
+    // Once seen the device is known
+    log_in_from(&app, &random_email, ELSEWHERE).await;
+    assert_eq!(alerts_for(&app, &random_email).await.len(), 1);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_alert_on_a_new_device_after_2fa() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app, true).await;
+
+    for (ip_address, user_agent) in [HOME, ELSEWHERE] {
+        let login_body = serde_json::json!({
+            "email": random_email,
+            "password": "Password123",
+        });
+        let response = app.post_login_from(&login_body, ip_address, user_agent).await;
+        assert_eq!(response.status().as_u16(), 206);
+
+        let (login_attempt_id, two_fa_code) = app.get_2fa_code(response).await;
+        let verify_body = serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+            "2FACode": two_fa_code.as_ref().expose_secret(),
+        });
+        let response = app.post_verify_2fa_from(&verify_body, ip_address, user_agent).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let alerts = alerts_for(&app, &random_email).await;
+    assert_eq!(alerts.len(), 1);
+    assert!(alerts[0].contains(ELSEWHERE.1));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_revoke_sessions_and_password() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app, false).await;
+
+    log_in_from(&app, &random_email, HOME).await;
+    log_in_from(&app, &random_email, ELSEWHERE).await;
+    let token = secure_account_token(&alerts_for(&app, &random_email).await[0]);
+
+    app.log_in_as(&Email::parse(Secret::new(random_email.clone())).unwrap()).await;
+    let session_cookie = app
+        .cookie_jar
+        .cookies(&Url::parse("http://127.0.0.1").expect("Failed to parse URL"))
+        .expect("No auth cookie found")
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let session_token = session_cookie
+        .strip_prefix(&format!("{}=", app.auth_settings.http.jwt_cookie_name))
+        .expect("No auth cookie found")
+        .to_owned();
+
+    // Revocation works at the second granularity of the tokens
+    app.clock.advance(chrono::Duration::seconds(1));
+
+    let response = app.post_secure_account(&serde_json::json!({
+        "token": token,
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": session_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "NewPassword456",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_link_used_twice() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app, false).await;
+
+    log_in_from(&app, &random_email, HOME).await;
+    log_in_from(&app, &random_email, ELSEWHERE).await;
+    let token = secure_account_token(&alerts_for(&app, &random_email).await[0]);
+
+    let response = app.post_secure_account(&serde_json::json!({
+        "token": token,
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_secure_account(&serde_json::json!({
+        "token": token,
+        "newPassword": "OtherPassword789"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Invalid token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup(&app, false).await;
+
+    log_in_from(&app, &random_email, HOME).await;
+    log_in_from(&app, &random_email, ELSEWHERE).await;
+    let token = secure_account_token(&alerts_for(&app, &random_email).await[0]);
+
+    let response = app.post_secure_account(&serde_json::json!({
+        "token": token,
+        "newPassword": "short"
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The refused password did not use the link up
+    let response = app.post_secure_account(&serde_json::json!({
+        "token": token,
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_is_invalid() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.post_secure_account(&serde_json::json!({
+        "token": "invalid",
+        "newPassword": "NewPassword456"
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
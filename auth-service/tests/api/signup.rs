@@ -1,9 +1,10 @@
 use std::sync::Arc;
 use color_eyre::eyre::Report;
-use auth_service::{domain::{data_stores::user_store::{UserStoreError}, email::Email, User}, routes::SignupResponse, ErrorResponse};
+use auth_service::{domain::{data_stores::user_store::{UserStoreError}, email::Email, PasswordWeakness, User}, routes::SignupResponse, ErrorResponse};
 use auth_service::domain::data_stores::user_store::MockUserStore;
 use secrecy::{ExposeSecret, Secret};
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
 
 use crate::helpers::{get_random_email, TestApp};
 
@@ -87,8 +88,6 @@ async fn should_return_400_if_invalid_input() {
 
     let bad_email_1 = "";
     let bad_email_2 = "user_name_a_domain";
-    let short_password = "short!";
-    let random_email = get_random_email().expose_secret().to_owned();
 
     let test_cases = [
         serde_json::json!({
@@ -101,11 +100,6 @@ async fn should_return_400_if_invalid_input() {
             "password": "password123",
             "requires2FA": true
         }),
-        serde_json::json!({
-            "email": random_email,
-            "password": short_password,
-            "requires2FA": true
-        }),
     ];
 
     for test_case in test_cases.iter() {
@@ -130,10 +124,60 @@ async fn should_return_400_if_invalid_input() {
     app.clean_up().await;
 }
 
+#[tokio::test]
+async fn should_return_400_with_reasons_if_password_is_weak() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "short!",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let error = response.json::<ErrorResponse>().await.expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error.error, "Password is too weak".to_owned());
+    assert_eq!(error.reasons, vec![PasswordWeakness::TooShort, PasswordWeakness::MissingUppercase, PasswordWeakness::TooPredictable]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_password_is_common_or_contains_the_email() {
+    let mut app = TestApp::builder()
+        .settings(|settings| settings.password_policy.blocklist_path = "config/common_passwords.txt".to_owned())
+        .build()
+        .await;
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "Password123",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.expect("Could not deserialize response body to ErrorResponse").reasons,
+        vec![PasswordWeakness::Common]
+    );
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": "maria.garcia@example.com",
+        "password": "Maria.Garcia-1987",
+        "requires2FA": false
+    })).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.expect("Could not deserialize response body to ErrorResponse").reasons,
+        vec![PasswordWeakness::ContainsEmail]
+    );
+
+    app.clean_up().await;
+}
+
 #[tokio::test]
 async fn should_return_409_if_email_already_exists() {
     // Call the signup route twice. The second request should fail with a 409 HTTP status code    
-    let mut app = TestApp::new(None).await;
+    let mut app = TestApp::builder().settings(|settings| settings.enumeration_protection.enabled = false).build().await;
 
     let random_email = get_random_email().expose_secret().to_owned();
 
@@ -211,7 +255,7 @@ async fn should_return_500_if_store_has_unexpected_error() {
         .returning(|_| Err(UserStoreError::UnexpectedError(Report::msg("Mock error for exepect add"))));
 
 
-    let mut app = TestApp::new(Some(Arc::new(RwLock::new(mock_user_store)))).await;
+    let mut app = TestApp::new(Some(Arc::new(mock_user_store))).await;
 
     let response_no_connections = app.post_signup(&sign_up_request_no_connections).await;
 
@@ -250,4 +294,246 @@ async fn should_return_500_if_store_has_unexpected_error() {
     );
 
     app.clean_up().await;
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn should_return_409_if_a_concurrent_signup_wins() {
+    let mut mock_user_store = MockUserStore::new();
+
+    // Another signup inserted the same email first
+    mock_user_store
+        .expect_add_user()
+        .once()
+        .returning(|_| Err(UserStoreError::UserAlreadyExists));
+
+    let mut app = TestApp::builder()
+        .user_store(Arc::new(mock_user_store))
+        .settings(|settings| settings.enumeration_protection.enabled = false)
+        .build()
+        .await;
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "Password123",
+        "requires2FA": true
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_201_and_email_the_owner_if_email_already_exists() {
+    let mut app = TestApp::new(None).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The notice and the 2FA code of the login at the end
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let sign_up_request = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+
+    let first_response = app.post_signup(&sign_up_request).await;
+    assert_eq!(first_response.status().as_u16(), 201);
+    let first_body = first_response.text().await.unwrap();
+
+    let second_response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "OtherPassword456",
+        "requires2FA": false
+    })).await;
+    assert_eq!(second_response.status().as_u16(), 201);
+    assert_eq!(second_response.text().await.unwrap(), first_body);
+
+    // The notice goes out in the background
+    let mut notice = None;
+    for _ in 0..50 {
+        notice = app.get_last_email_for(&random_email).await;
+        if notice.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(notice.expect("No notice captured").subject, "Sign up attempt with your email");
+
+    // The account keeps its password
+    let response = app.post_login(&serde_json::json!({ "email": random_email, "password": "Password123" })).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_take_as_long_for_a_taken_email_as_for_a_new_one() {
+    let mut app = TestApp::new(None).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let taken_email = get_random_email().expose_secret().to_owned();
+    let sign_up_request = |email: &str| serde_json::json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    assert_eq!(app.post_signup(&sign_up_request(&taken_email)).await.status().as_u16(), 201);
+
+    let mut new_email_time = Duration::ZERO;
+    let mut taken_email_time = Duration::ZERO;
+
+    for _ in 0..5 {
+        let start = Instant::now();
+        let response = app.post_signup(&sign_up_request(get_random_email().expose_secret())).await;
+        new_email_time += start.elapsed();
+        assert_eq!(response.status().as_u16(), 201);
+
+        let start = Instant::now();
+        let response = app.post_signup(&sign_up_request(&taken_email)).await;
+        taken_email_time += start.elapsed();
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    // Both hash the password, a taken email skipping it would be several times faster
+    assert!(taken_email_time * 2 > new_email_time, "taken {:?}, new {:?}", taken_email_time, new_email_time);
+    assert!(new_email_time * 2 > taken_email_time, "taken {:?}, new {:?}", taken_email_time, new_email_time);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_create_one_account_if_signups_race_for_an_email() {
+    let mut app = TestApp::builder().settings(|settings| settings.enumeration_protection.enabled = false).build().await;
+
+    let sign_up_request = serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    let responses = tokio::join!(
+        app.post_signup(&sign_up_request),
+        app.post_signup(&sign_up_request),
+        app.post_signup(&sign_up_request),
+        app.post_signup(&sign_up_request),
+    );
+
+    let mut statuses: Vec<u16> = [responses.0, responses.1, responses.2, responses.3]
+        .iter()
+        .map(|response| response.status().as_u16())
+        .collect();
+    statuses.sort();
+
+    assert_eq!(statuses, vec![201, 409, 409, 409]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_replay_the_response_to_a_retry_with_the_same_idempotency_key() {
+    let mut app = TestApp::builder().settings(|settings| settings.enumeration_protection.enabled = false).build().await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let random_email = get_random_email().expose_secret().to_owned();
+    let sign_up_request = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    let first_response = app.post_signup_with_idempotency_key(&sign_up_request, &idempotency_key).await;
+    assert_eq!(first_response.status().as_u16(), 201);
+    let first_body = first_response.text().await.unwrap();
+
+    // Without the key this would be a 409, the account already exists
+    let retry = app.post_signup_with_idempotency_key(&sign_up_request, &idempotency_key).await;
+    assert_eq!(retry.status().as_u16(), 201);
+    assert_eq!(retry.text().await.unwrap(), first_body);
+
+    let other_key = app.post_signup_with_idempotency_key(&sign_up_request, &uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(other_key.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_email_the_owner_again_on_a_retry_with_the_same_idempotency_key() {
+    let mut app = TestApp::new(None).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let sign_up_request = serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    for _ in 0..2 {
+        let response = app.post_signup_with_idempotency_key(&sign_up_request, &idempotency_key).await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_idempotency_key_is_reused_for_another_email() {
+    let mut app = TestApp::new(None).await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let sign_up_request = |email: &str| serde_json::json!({
+        "email": email,
+        "password": "Password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup_with_idempotency_key(&sign_up_request(get_random_email().expose_secret()), &idempotency_key).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_signup_with_idempotency_key(&sign_up_request(get_random_email().expose_secret()), &idempotency_key).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Idempotency key already used for another request".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_idempotency_key_is_invalid() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.post_signup_with_idempotency_key(&serde_json::json!({
+        "email": get_random_email().expose_secret(),
+        "password": "Password123",
+        "requires2FA": false
+    }), &"a".repeat(256)).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
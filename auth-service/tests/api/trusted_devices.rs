@@ -0,0 +1,205 @@
+use auth_service::{domain::Email, routes::TrustedDeviceResponse, ErrorResponse};
+use chrono::Duration;
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{matchers::*, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup_with_2fa(app: &TestApp) -> String {
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    random_email
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn post_login(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "Password123",
+    }))
+    .await
+}
+
+// Logs in through 2FA, remembering the browser when asked to
+async fn log_in_with_2fa(app: &TestApp, email: &str, remember_device: bool) -> reqwest::Response {
+    let response = post_login(app, email).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (login_attempt_id, two_fa_code) = app.get_2fa_code(response).await;
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": two_fa_code.as_ref().expose_secret(),
+        "rememberDevice": remember_device
+    }))
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+}
+
+fn parse_email(email: &str) -> Email {
+    Email::parse(Secret::new(email.to_owned())).unwrap()
+}
+
+async fn get_trusted_devices(app: &TestApp) -> Vec<TrustedDeviceResponse> {
+    let response = app.get_trusted_devices().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<Vec<TrustedDeviceResponse>>()
+        .await
+        .expect("Could not deserialize response body to Vec<TrustedDeviceResponse>")
+}
+
+#[tokio::test]
+async fn should_skip_2fa_on_a_remembered_device() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup_with_2fa(&app).await;
+
+    let response = log_in_with_2fa(&app, &random_email, true).await;
+
+    let device_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.trusted_device.cookie_name)
+        .expect("No trusted device cookie found");
+    assert!(device_cookie.http_only());
+    assert!(device_cookie.secure());
+
+    let response = post_login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_ask_for_2fa_if_device_not_remembered() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup_with_2fa(&app).await;
+
+    let response = log_in_with_2fa(&app, &random_email, false).await;
+
+    assert!(response
+        .cookies()
+        .all(|cookie| cookie.name() != app.auth_settings.trusted_device.cookie_name));
+
+    let response = post_login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_ask_for_2fa_once_the_device_expired() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup_with_2fa(&app).await;
+
+    log_in_with_2fa(&app, &random_email, true).await;
+
+    app.clock.advance(Duration::milliseconds(app.auth_settings.trusted_device.ttl_millis));
+
+    let response = post_login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_ask_for_2fa_if_the_device_is_another_users() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let first_email = signup_with_2fa(&app).await;
+    let second_email = signup_with_2fa(&app).await;
+
+    log_in_with_2fa(&app, &first_email, true).await;
+
+    let response = post_login(&app, &second_email).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_list_and_revoke_trusted_devices() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let random_email = signup_with_2fa(&app).await;
+
+    log_in_with_2fa(&app, &random_email, true).await;
+    app.log_in_as(&parse_email(&random_email)).await;
+
+    let devices = get_trusted_devices(&app).await;
+    assert_eq!(devices.len(), 1);
+    assert!(devices[0].current);
+
+    let response = app.delete_trusted_device(&devices[0].id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert_eq!(get_trusted_devices(&app).await, vec![]);
+
+    let response = post_login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_if_the_device_is_another_users() {
+    let mut app = TestApp::new(None).await;
+    mount_email_server(&app).await;
+    let first_email = signup_with_2fa(&app).await;
+    let second_email = signup_with_2fa(&app).await;
+
+    log_in_with_2fa(&app, &first_email, true).await;
+    app.log_in_as(&parse_email(&first_email)).await;
+    let device_id = get_trusted_devices(&app).await[0].id.clone();
+
+    app.log_in_as(&parse_email(&second_email)).await;
+
+    let response = app.delete_trusted_device(&device_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Trusted device not found".to_owned()
+    );
+
+    // The first user's browser stays trusted
+    let response = post_login(&app, &first_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_listing_without_token() {
+    let mut app = TestApp::new(None).await;
+
+    let response = app.get_trusted_devices().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
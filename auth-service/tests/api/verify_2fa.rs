@@ -1,4 +1,4 @@
-use auth_service::{domain::{data_stores::two_fa_code_store::{LoginAttemptId, TwoFACode}, Email}};
+use auth_service::domain::{data_stores::two_fa_code_store::{LoginAttemptId, TwoFACode, TWO_FA_CODE_TTL_SECONDS}, Email};
 use secrecy::{ExposeSecret, Secret};
 use wiremock::{matchers::*, Mock, ResponseTemplate};
 use crate::helpers::{get_random_email, TestApp};
@@ -36,9 +36,7 @@ async fn should_return_200_if_correct_code() {
 
     assert_eq!(response.status().as_u16(), 206);
 
-    let email_typed = Email::parse(Secret::new(random_email.clone())).unwrap();
-
-    let (login_attempt_id, two_fa_code_store) = app.two_fa_code_store.read().await.get_code(&email_typed).await.unwrap();
+    let (login_attempt_id, two_fa_code_store) = app.get_2fa_code(response).await;
 
     let test_case = serde_json::json!({
             "email": random_email,
@@ -236,9 +234,7 @@ async fn should_return_401_if_old_code() {
 
     assert_eq!(response.status().as_u16(), 206);
 
-    let email_typed = Email::parse(Secret::new(random_email.clone())).unwrap();
-
-    let (login_attempt_id, two_fa_code_store) = app.two_fa_code_store.read().await.get_code(&email_typed).await.unwrap();
+    let (login_attempt_id, two_fa_code_store) = app.get_2fa_code(response).await;
 
     let verify_new_code = serde_json::json!({
             "email": random_email,
@@ -304,9 +300,7 @@ async fn should_return_401_if_same_code_twice() {
 
     assert_eq!(response.status().as_u16(), 206);
 
-    let email_typed = Email::parse(Secret::new(random_email.clone())).unwrap();
-
-    let (login_attempt_id, two_fa_code_store) = app.two_fa_code_store.read().await.get_code(&email_typed).await.unwrap();
+    let (login_attempt_id, two_fa_code_store) = app.get_2fa_code(response).await;
 
     let test_case = serde_json::json!({
             "email": random_email,
@@ -340,4 +334,233 @@ async fn should_return_401_if_same_code_twice() {
 
     app.clean_up().await;
     
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn should_log_in_once_if_the_same_code_is_sent_concurrently() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (login_attempt_id, two_fa_code) = app.get_2fa_code(response).await;
+    let verify_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": two_fa_code.as_ref().expose_secret()
+    });
+
+    let responses = tokio::join!(
+        app.post_verify_2fa(&verify_body),
+        app.post_verify_2fa(&verify_body),
+        app.post_verify_2fa(&verify_body),
+    );
+
+    let mut statuses = [responses.0.status().as_u16(), responses.1.status().as_u16(), responses.2.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 401, 401]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_the_code_expired() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (login_attempt_id, code) = app.get_2fa_code(response).await;
+
+    app.clock.advance(chrono::Duration::seconds(TWO_FA_CODE_TTL_SECONDS));
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": code.as_ref().expose_secret()
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_for_logins_pending_on_two_devices() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let (first_login_attempt_id, first_code) = app.get_2fa_code(response).await;
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let (second_login_attempt_id, second_code) = app.get_2fa_code(response).await;
+
+    // The second login does not replace the first one's code
+    for (login_attempt_id, code) in [(first_login_attempt_id, first_code), (second_login_attempt_id, second_code)] {
+        let response = app.post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+            "2FACode": code.as_ref().expose_secret()
+        })).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_the_login_attempt_is_another_users() {
+    let mut app = TestApp::new(None).await;
+
+    let (random_email, other_email) = (
+        get_random_email().expose_secret().to_owned(),
+        get_random_email().expose_secret().to_owned(),
+    );
+
+    for email in [&random_email, &other_email] {
+        let response = app.post_signup(&serde_json::json!({
+            "email": email,
+            "password": "Password123",
+            "requires2FA": true
+        })).await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let (login_attempt_id, code) = app.get_2fa_code(response).await;
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": other_email,
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": code.as_ref().expose_secret()
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_correct_code_but_password_expired() {
+    let mut app = TestApp::new(None).await;
+
+    let random_email = get_random_email().expose_secret().to_owned();
+
+    let response = app.post_signup(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+        "requires2FA": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let user = app.user_store.get_user(&Email::parse(Secret::new(random_email.clone())).unwrap()).await.unwrap();
+    app.user_store.set_password_max_age(&user.id, Some(chrono::Duration::days(90))).await.unwrap();
+    app.clock.advance(chrono::Duration::days(91));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_login(&serde_json::json!({
+        "email": random_email,
+        "password": "Password123",
+    })).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (login_attempt_id, two_fa_code) = app.get_2fa_code(response).await;
+
+    let response = app.post_verify_2fa(&serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": two_fa_code.as_ref().expose_secret(),
+        "rememberDevice": true
+    })).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // A password that is no longer good does not get a device skipping 2FA
+    assert!(!response.cookies().any(|cookie| cookie.name() == app.auth_settings.trusted_device.cookie_name));
+    assert_eq!(app.trusted_device_store.get_devices(&user.id).await.unwrap().len(), 0);
+
+    let password_change_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.auth_settings.http.jwt_cookie_name)
+        .expect("No password change cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": password_change_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -1,7 +1,15 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{auth::{verify_token_response::VerifyTokenStatus, VerifyTokenRequest}, domain::email::Email, utils::{auth::generate_auth_cookie, HttpSettings}};
+use auth_service::{auth::{verify_token_response::VerifyTokenStatus, VerifyTokenRequest}, domain::{data_stores::JWT_LEEWAY_SECONDS, AccountStatus, Clock, Email, Password, User, UserId}, utils::{auth::{generate_auth_cookie, AuthMethod}, HttpSettings}};
 use secrecy::Secret;
 
+// Tokens are only valid while their user exists and may log in
+async fn add_user(app: &TestApp) -> UserId {
+    let email = Email::parse(get_random_email()).unwrap();
+    let password = Password::parse(Secret::new("Password123".to_owned())).unwrap();
+    app.user_store.add_user(User::new(email.clone(), password, false, app.clock.now())).await.unwrap();
+    app.user_store.get_user(&email).await.unwrap().id
+}
+
 
 //grpc counterpart doesn't make sense here since the client does not accept just any request but 
 //something that is already of the right type
@@ -36,12 +44,12 @@ async fn should_return_422_if_malformed_input() {
 async fn should_return_200_valid_token() {
     let mut app = TestApp::new(None).await;
 
-    let random_email = Email::parse(get_random_email()).unwrap();
+    let user_id = add_user(&app).await;
 
     let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
     let token_ttl_millis = app.auth_settings.redis.ttl_millis;
 
-    let token = generate_auth_cookie(&random_email, jwt_token, jwt_cookie_name, token_ttl_millis).unwrap();
+    let token = generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
 
     let test_case = serde_json::json!({
         "token": token.value(),
@@ -58,12 +66,12 @@ async fn should_return_200_valid_token() {
 async fn should_return_200_valid_token_in_grpc() {
     let mut app = TestApp::new(None).await;
 
-    let random_email = Email::parse(get_random_email()).unwrap();
+    let user_id = add_user(&app).await;
 
     let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
     let token_ttl_millis = app.auth_settings.redis.ttl_millis;
 
-    let token = generate_auth_cookie(&random_email, jwt_token, jwt_cookie_name, token_ttl_millis).unwrap();
+    let token = generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
 
     let test_case = VerifyTokenRequest { token: token.value().to_owned() } ;
 
@@ -95,14 +103,14 @@ async fn should_return_401_if_invalid_token() {
 async fn should_return_401_if_banned_token() {
     let mut app = TestApp::new(None).await;
 
-    let random_email = Email::parse(get_random_email()).unwrap();
+    let user_id = UserId::default();
 
     let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
     let token_ttl_millis = app.auth_settings.redis.ttl_millis;
 
-    let cookie = generate_auth_cookie(&random_email, jwt_token, jwt_cookie_name, token_ttl_millis).unwrap();
+    let cookie = generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
 
-    let _ = app.banned_token_store.write().await.add_token(Secret::new(cookie.value().to_owned())).await;
+    app.ban_token(cookie.value()).await;
 
     let test_case = serde_json::json!({
         "token": cookie.value(),
@@ -128,4 +136,63 @@ async fn should_return_401_if_invalid_token_in_grpc() {
     assert_eq!(status, Ok(VerifyTokenStatus::Invalid));
     
     app.clean_up().await;
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn should_return_401_once_the_token_expired() {
+    let mut app = TestApp::new(None).await;
+
+    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
+    let token_ttl_millis = app.auth_settings.redis.ttl_millis;
+
+    let user_id = add_user(&app).await;
+
+    let token = generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
+    let test_case = serde_json::json!({
+        "token": token.value(),
+    });
+
+    // Still accepted within the leeway
+    app.clock.advance(chrono::Duration::milliseconds(token_ttl_millis) + chrono::Duration::seconds(JWT_LEEWAY_SECONDS));
+    assert_eq!(app.post_verify_token(&test_case).await.status().as_u16(), 200);
+
+    app.clock.advance(chrono::Duration::seconds(1));
+    assert_eq!(app.post_verify_token(&test_case).await.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_once_the_account_is_disabled() {
+    let mut app = TestApp::new(None).await;
+
+    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
+    let token_ttl_millis = app.auth_settings.redis.ttl_millis;
+
+    let user_id = add_user(&app).await;
+    let token = generate_auth_cookie(&user_id, &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
+
+    app.user_store.set_status(&user_id, AccountStatus::Disabled).await.unwrap();
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": token.value() })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.grpc_verify_token(VerifyTokenRequest { token: token.value().to_owned() }).await.into_inner();
+    assert_eq!(VerifyTokenStatus::try_from(response.token_status), Ok(VerifyTokenStatus::Invalid));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_the_user_is_gone() {
+    let mut app = TestApp::new(None).await;
+
+    let HttpSettings { address: _, jwt_token, jwt_cookie_name} = app.auth_settings.http.clone();
+    let token_ttl_millis = app.auth_settings.redis.ttl_millis;
+
+    let token = generate_auth_cookie(&UserId::default(), &[AuthMethod::Password], jwt_token, jwt_cookie_name, token_ttl_millis, app.clock.as_ref()).unwrap();
+
+    let response = app.grpc_verify_token(VerifyTokenRequest { token: token.value().to_owned() }).await.into_inner();
+    assert_eq!(VerifyTokenStatus::try_from(response.token_status), Ok(VerifyTokenStatus::Invalid));
+
+    app.clean_up().await;
+}
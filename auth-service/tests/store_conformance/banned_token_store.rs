@@ -0,0 +1,137 @@
+use auth_service::domain::{
+    data_stores::{BannedTokenStore, JWT_LEEWAY_SECONDS},
+    Clock, ManualClock, UserId,
+};
+use chrono::Duration;
+use uuid::Uuid;
+
+use crate::helpers::TOKEN_TTL_MILLIS;
+
+// Every case runs against each implementation, see the modules at the bottom
+
+fn random_jti() -> String {
+    Uuid::new_v4().to_string()
+}
+
+// The exp claim of a token issued now
+fn token_exp(clock: &ManualClock) -> usize {
+    (clock.now() + Duration::milliseconds(TOKEN_TTL_MILLIS)).timestamp() as usize
+}
+
+fn ban_ttl() -> Duration {
+    Duration::milliseconds(TOKEN_TTL_MILLIS) + Duration::seconds(JWT_LEEWAY_SECONDS)
+}
+
+async fn bans_tokens(store: &dyn BannedTokenStore, clock: &ManualClock) {
+    let jti = random_jti();
+
+    assert_eq!(store.contains_token(&jti).await, Ok(false));
+    assert_eq!(store.add_token(&jti, token_exp(clock)).await, Ok(()));
+    assert_eq!(store.contains_token(&jti).await, Ok(true));
+    assert_eq!(store.contains_token(&random_jti()).await, Ok(false));
+}
+
+async fn forgets_bans_once_tokens_expired(store: &dyn BannedTokenStore, clock: &ManualClock) {
+    let jti = random_jti();
+    let _ = store.add_token(&jti, token_exp(clock)).await;
+
+    clock.advance(ban_ttl() - Duration::seconds(1));
+    assert_eq!(store.contains_token(&jti).await, Ok(true));
+
+    clock.advance(Duration::seconds(1));
+    assert_eq!(store.contains_token(&jti).await, Ok(false));
+}
+
+async fn keeps_each_ban_for_its_own_token(store: &dyn BannedTokenStore, clock: &ManualClock) {
+    let (short_lived, long_lived) = (random_jti(), random_jti());
+    let exp = token_exp(clock);
+    let _ = store.add_token(&short_lived, exp - 300).await;
+    let _ = store.add_token(&long_lived, exp).await;
+
+    clock.advance(ban_ttl() - Duration::seconds(300));
+    assert_eq!(store.contains_token(&short_lived).await, Ok(false));
+    assert_eq!(store.contains_token(&long_lived).await, Ok(true));
+}
+
+async fn skips_tokens_already_expired(store: &dyn BannedTokenStore, clock: &ManualClock) {
+    let jti = random_jti();
+    let exp = (clock.now() - Duration::seconds(JWT_LEEWAY_SECONDS + 1)).timestamp() as usize;
+
+    assert_eq!(store.add_token(&jti, exp).await, Ok(()));
+    assert_eq!(store.contains_token(&jti).await, Ok(false));
+}
+
+async fn bans_tokens_once(store: &dyn BannedTokenStore, clock: &ManualClock) {
+    let jti = random_jti();
+    let exp = token_exp(clock);
+
+    assert_eq!(store.add_token_if_absent(&jti, exp).await, Ok(true));
+    assert_eq!(store.add_token_if_absent(&jti, exp).await, Ok(false));
+    assert_eq!(store.contains_token(&jti).await, Ok(true));
+
+    // A ban added the other way counts too
+    let other_jti = random_jti();
+    let _ = store.add_token(&other_jti, exp).await;
+    assert_eq!(store.add_token_if_absent(&other_jti, exp).await, Ok(false));
+
+    let expired = (clock.now() - Duration::seconds(JWT_LEEWAY_SECONDS + 1)).timestamp() as usize;
+    assert_eq!(store.add_token_if_absent(&random_jti(), expired).await, Ok(false));
+}
+
+async fn revokes_tokens_before(store: &dyn BannedTokenStore, _clock: &ManualClock) {
+    let (user_id, other_user_id) = (UserId::default(), UserId::default());
+
+    assert_eq!(store.tokens_revoked_before(&user_id).await, Ok(None));
+
+    assert_eq!(store.revoke_tokens_before(&user_id, 1000).await, Ok(()));
+    assert_eq!(store.tokens_revoked_before(&user_id).await, Ok(Some(1000)));
+    assert_eq!(store.tokens_revoked_before(&other_user_id).await, Ok(None));
+
+    // The latest revocation wins
+    assert_eq!(store.revoke_tokens_before(&user_id, 2000).await, Ok(()));
+    assert_eq!(store.tokens_revoked_before(&user_id).await, Ok(Some(2000)));
+}
+
+async fn forgets_revocations_once_tokens_expired(store: &dyn BannedTokenStore, clock: &ManualClock) {
+    let user_id = UserId::default();
+    let _ = store.revoke_tokens_before(&user_id, 1000).await;
+
+    clock.advance(ban_ttl() - Duration::seconds(1));
+    assert_eq!(store.tokens_revoked_before(&user_id).await, Ok(Some(1000)));
+
+    clock.advance(Duration::seconds(1));
+    assert_eq!(store.tokens_revoked_before(&user_id).await, Ok(None));
+}
+
+macro_rules! banned_token_store_conformance_tests {
+    ($make_store:expr) => {
+        banned_token_store_conformance_tests!(
+            $make_store;
+            bans_tokens,
+            forgets_bans_once_tokens_expired,
+            keeps_each_ban_for_its_own_token,
+            skips_tokens_already_expired,
+            bans_tokens_once,
+            revokes_tokens_before,
+            forgets_revocations_once_tokens_expired
+        );
+    };
+    ($make_store:expr; $($case:ident),+) => {
+        $(
+            #[tokio::test]
+            async fn $case() {
+                let test_store = $make_store.await;
+                super::$case(test_store.store.as_ref(), &test_store.clock).await;
+                test_store.clean_up().await;
+            }
+        )+
+    };
+}
+
+mod hashset {
+    banned_token_store_conformance_tests!(crate::helpers::TestBannedTokenStore::hashset());
+}
+
+mod redis {
+    banned_token_store_conformance_tests!(crate::helpers::TestBannedTokenStore::redis());
+}
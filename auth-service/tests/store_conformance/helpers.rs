@@ -0,0 +1,246 @@
+use std::{env, sync::Arc};
+
+use auth_service::{
+    domain::{
+        data_stores::{BannedTokenStore, LoginFingerprintStore, RateLimitStore, TrustedDeviceStore, TwoFACodeStore, UserStore},
+        Email, ManualClock, Password,
+    },
+    get_postgres_pool, get_redis_pool, get_sqlite_pool,
+    services::data_stores::{
+        hashmap_login_fingerprint_store::HashmapLoginFingerprintStore, hashmap_rate_limit_store::HashmapRateLimitStore, hashmap_trusted_device_store::HashmapTrustedDeviceStore, hashmap_two_fa_code_store::HashmapTwoFACodeStore,
+        hashmap_user_store::HashmapUserStore, hashset_banned_token_store::HashsetBannedTokenStore,
+        postgres_login_fingerprint_store::PostgresLoginFingerprintStore, postgres_trusted_device_store::PostgresTrustedDeviceStore, postgres_user_store::PostgresUserStore,
+        sqlite_login_fingerprint_store::SqliteLoginFingerprintStore, sqlite_trusted_device_store::SqliteTrustedDeviceStore, sqlite_user_store::SqliteUserStore,
+        PasswordHashingExecutor, RedisBannedTokenStore, RedisConnectionPool, RedisRateLimitStore, RedisTwoFACodeStore,
+    },
+    utils::{AuthSettings, PasswordHashingSettings},
+};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Connection, Executor, PgConnection, PgPool, SqlitePool};
+use uuid::Uuid;
+
+// A store under test, Postgres ones get a database of their own that clean_up drops
+pub struct TestUserStore {
+    pub store: Box<dyn UserStore + Send + Sync>,
+    postgres_database: Option<(Secret<String>, String)>,
+}
+
+impl TestUserStore {
+    pub async fn hashmap() -> Self {
+        Self { store: Box::new(HashmapUserStore::default()), postgres_database: None }
+    }
+
+    pub async fn sqlite() -> Self {
+        Self::on_sqlite(sqlite_pool().await)
+    }
+
+    pub async fn postgres() -> Self {
+        let (pool, postgres_database) = create_postgres_database().await;
+        Self::on_postgres(pool, postgres_database)
+    }
+
+    fn on_sqlite(pool: SqlitePool) -> Self {
+        Self { store: Box::new(SqliteUserStore::new(pool, password_hashing())), postgres_database: None }
+    }
+
+    fn on_postgres(pool: PgPool, postgres_database: (Secret<String>, String)) -> Self {
+        Self {
+            store: Box::new(PostgresUserStore::new(pool, password_hashing())),
+            postgres_database: Some(postgres_database),
+        }
+    }
+
+    pub async fn clean_up(self) {
+        drop(self.store);
+
+        if let Some((database_url, db_name)) = self.postgres_database {
+            let mut connection = PgConnection::connect(database_url.expose_secret()).await.expect("Failed to connect to Postgres");
+            connection
+                .execute(format!(r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE);"#, db_name).as_str())
+                .await
+                .expect("Failed to drop database");
+        }
+    }
+}
+
+// Devices belong to users of the same database, the cases create them through the user store
+pub struct TestTrustedDeviceStore {
+    pub store: Box<dyn TrustedDeviceStore + Send + Sync>,
+    pub users: TestUserStore,
+}
+
+impl TestTrustedDeviceStore {
+    pub async fn hashmap() -> Self {
+        Self { store: Box::new(HashmapTrustedDeviceStore::default()), users: TestUserStore::hashmap().await }
+    }
+
+    pub async fn sqlite() -> Self {
+        let pool = sqlite_pool().await;
+        Self { store: Box::new(SqliteTrustedDeviceStore::new(pool.clone())), users: TestUserStore::on_sqlite(pool) }
+    }
+
+    pub async fn postgres() -> Self {
+        let (pool, postgres_database) = create_postgres_database().await;
+        Self {
+            store: Box::new(PostgresTrustedDeviceStore::new(pool.clone())),
+            users: TestUserStore::on_postgres(pool, postgres_database),
+        }
+    }
+
+    pub async fn clean_up(self) {
+        drop(self.store);
+        self.users.clean_up().await;
+    }
+}
+
+// Fingerprints belong to users of the same database too
+pub struct TestLoginFingerprintStore {
+    pub store: Box<dyn LoginFingerprintStore + Send + Sync>,
+    pub users: TestUserStore,
+}
+
+impl TestLoginFingerprintStore {
+    pub async fn hashmap() -> Self {
+        Self { store: Box::new(HashmapLoginFingerprintStore::default()), users: TestUserStore::hashmap().await }
+    }
+
+    pub async fn sqlite() -> Self {
+        let pool = sqlite_pool().await;
+        Self { store: Box::new(SqliteLoginFingerprintStore::new(pool.clone())), users: TestUserStore::on_sqlite(pool) }
+    }
+
+    pub async fn postgres() -> Self {
+        let (pool, postgres_database) = create_postgres_database().await;
+        Self {
+            store: Box::new(PostgresLoginFingerprintStore::new(pool.clone())),
+            users: TestUserStore::on_postgres(pool, postgres_database),
+        }
+    }
+
+    pub async fn clean_up(self) {
+        drop(self.store);
+        self.users.clean_up().await;
+    }
+}
+
+async fn sqlite_pool() -> SqlitePool {
+    let pool = get_sqlite_pool(&Secret::new("sqlite::memory:".to_owned()))
+        .await
+        .expect("Failed to create SQLite connection pool");
+    sqlx::migrate!("./migrations_sqlite").run(&pool).await.expect("Failed to migrate the database");
+    pool
+}
+
+async fn create_postgres_database() -> (PgPool, (Secret<String>, String)) {
+    env::set_var("RUN_ENV", "test");
+    let database_url = AuthSettings::new().database.url;
+    let db_name = Uuid::new_v4().to_string();
+
+    let mut connection = PgConnection::connect(database_url.expose_secret()).await.expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, db_name).as_str())
+        .await
+        .expect("Failed to create database");
+
+    let pool = get_postgres_pool(&Secret::new(format!("{}/{}", database_url.expose_secret(), db_name)))
+        .await
+        .expect("Failed to create Postgres connection pool");
+    sqlx::migrate!().run(&pool).await.expect("Failed to migrate the database");
+
+    (pool, (database_url, db_name))
+}
+
+pub const TOKEN_TTL_MILLIS: i64 = 600_000;
+pub const MAX_ATTEMPTS_PER_USER: usize = 3;
+
+// Expiring stores run on a clock the cases move by hand. Redis entries need no cleaning up,
+// every case uses random keys.
+pub struct TestBannedTokenStore {
+    pub store: Box<dyn BannedTokenStore + Send + Sync>,
+    pub clock: Arc<ManualClock>,
+}
+
+impl TestBannedTokenStore {
+    pub async fn hashset() -> Self {
+        let clock = Arc::new(ManualClock::default());
+        let store = HashsetBannedTokenStore::new(TOKEN_TTL_MILLIS).with_clock(clock.clone());
+        Self { store: Box::new(store), clock }
+    }
+
+    pub async fn redis() -> Self {
+        let clock = Arc::new(ManualClock::default());
+        let store = RedisBannedTokenStore::new(redis_pool().await, TOKEN_TTL_MILLIS).with_clock(clock.clone());
+        Self { store: Box::new(store), clock }
+    }
+
+    pub async fn clean_up(self) {}
+}
+
+pub struct TestTwoFACodeStore {
+    pub store: Box<dyn TwoFACodeStore + Send + Sync>,
+    pub clock: Arc<ManualClock>,
+}
+
+impl TestTwoFACodeStore {
+    pub async fn hashmap() -> Self {
+        let clock = Arc::new(ManualClock::default());
+        let store = HashmapTwoFACodeStore::new(MAX_ATTEMPTS_PER_USER).with_clock(clock.clone());
+        Self { store: Box::new(store), clock }
+    }
+
+    pub async fn redis() -> Self {
+        let clock = Arc::new(ManualClock::default());
+        let store = RedisTwoFACodeStore::new(redis_pool().await, MAX_ATTEMPTS_PER_USER).with_clock(clock.clone());
+        Self { store: Box::new(store), clock }
+    }
+
+    pub async fn clean_up(self) {}
+}
+
+pub struct TestRateLimitStore {
+    pub store: Box<dyn RateLimitStore + Send + Sync>,
+    pub clock: Arc<ManualClock>,
+}
+
+impl TestRateLimitStore {
+    pub async fn hashmap() -> Self {
+        let clock = Arc::new(ManualClock::default());
+        let store = HashmapRateLimitStore::default().with_clock(clock.clone());
+        Self { store: Box::new(store), clock }
+    }
+
+    pub async fn redis() -> Self {
+        let clock = Arc::new(ManualClock::default());
+        let store = RedisRateLimitStore::new(redis_pool().await).with_clock(clock.clone());
+        Self { store: Box::new(store), clock }
+    }
+
+    pub async fn clean_up(self) {}
+}
+
+async fn redis_pool() -> RedisConnectionPool {
+    env::set_var("RUN_ENV", "test");
+    get_redis_pool(&AuthSettings::new().redis)
+        .await
+        .expect("Failed to create Redis connection pool")
+}
+
+// Cheap parameters, the suite checks behaviour and not hashing cost
+fn password_hashing() -> PasswordHashingExecutor {
+    PasswordHashingExecutor::new(&PasswordHashingSettings {
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+        max_concurrency: 4,
+        queue_timeout_millis: 5000,
+    })
+    .expect("Invalid password hashing settings")
+}
+
+pub fn random_email() -> Email {
+    Email::parse(Secret::new(format!("{}@example.com", Uuid::new_v4()))).unwrap()
+}
+
+pub fn password(raw: &str) -> Password {
+    Password::parse(Secret::new(raw.to_owned())).unwrap()
+}
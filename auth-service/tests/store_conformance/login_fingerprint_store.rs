@@ -0,0 +1,94 @@
+use auth_service::domain::{
+    data_stores::{FingerprintStatus, LoginFingerprint, LoginFingerprintStore, UserStore},
+    User, UserId,
+};
+use chrono::{Duration, Utc};
+
+use crate::helpers::{password, random_email};
+
+// Every case runs against each implementation, see the modules at the bottom
+
+async fn add_user(users: &dyn UserStore) -> UserId {
+    let user = User::new(random_email(), password("Password123"), false, Utc::now());
+    let id = user.id;
+    users.add_user(user).await.expect("Failed to add user");
+    id
+}
+
+fn fingerprint(ip_address: &str, user_agent: &str) -> LoginFingerprint {
+    LoginFingerprint::new(ip_address.to_owned(), user_agent.to_owned())
+}
+
+async fn reports_the_first_login(store: &dyn LoginFingerprintStore, users: &dyn UserStore) {
+    let user_id = add_user(users).await;
+
+    assert_eq!(
+        store.record_fingerprint(&user_id, &fingerprint("203.0.113.7", "Firefox"), Utc::now()).await,
+        Ok(FingerprintStatus::FirstLogin)
+    );
+}
+
+async fn recognises_known_fingerprints(store: &dyn LoginFingerprintStore, users: &dyn UserStore) {
+    let user_id = add_user(users).await;
+    let home = fingerprint("203.0.113.7", "Firefox");
+    let now = Utc::now();
+
+    let _ = store.record_fingerprint(&user_id, &home, now).await;
+
+    assert_eq!(store.record_fingerprint(&user_id, &home, now + Duration::days(1)).await, Ok(FingerprintStatus::Known));
+}
+
+async fn reports_new_addresses_and_browsers(store: &dyn LoginFingerprintStore, users: &dyn UserStore) {
+    let user_id = add_user(users).await;
+    let now = Utc::now();
+
+    let _ = store.record_fingerprint(&user_id, &fingerprint("203.0.113.7", "Firefox"), now).await;
+
+    assert_eq!(store.record_fingerprint(&user_id, &fingerprint("198.51.100.23", "Firefox"), now).await, Ok(FingerprintStatus::New));
+    assert_eq!(store.record_fingerprint(&user_id, &fingerprint("203.0.113.7", "Chrome"), now).await, Ok(FingerprintStatus::New));
+    assert_eq!(store.record_fingerprint(&user_id, &fingerprint("203.0.113.7", "Chrome"), now).await, Ok(FingerprintStatus::Known));
+}
+
+async fn keeps_fingerprints_per_user(store: &dyn LoginFingerprintStore, users: &dyn UserStore) {
+    let (user_id, other_user_id) = (add_user(users).await, add_user(users).await);
+    let home = fingerprint("203.0.113.7", "Firefox");
+    let now = Utc::now();
+
+    let _ = store.record_fingerprint(&user_id, &home, now).await;
+
+    assert_eq!(store.record_fingerprint(&other_user_id, &home, now).await, Ok(FingerprintStatus::FirstLogin));
+}
+
+macro_rules! login_fingerprint_store_conformance_tests {
+    ($make_store:expr) => {
+        login_fingerprint_store_conformance_tests!(
+            $make_store;
+            reports_the_first_login,
+            recognises_known_fingerprints,
+            reports_new_addresses_and_browsers,
+            keeps_fingerprints_per_user
+        );
+    };
+    ($make_store:expr; $($case:ident),+) => {
+        $(
+            #[tokio::test]
+            async fn $case() {
+                let test_store = $make_store.await;
+                super::$case(test_store.store.as_ref(), test_store.users.store.as_ref()).await;
+                test_store.clean_up().await;
+            }
+        )+
+    };
+}
+
+mod hashmap {
+    login_fingerprint_store_conformance_tests!(crate::helpers::TestLoginFingerprintStore::hashmap());
+}
+
+mod sqlite {
+    login_fingerprint_store_conformance_tests!(crate::helpers::TestLoginFingerprintStore::sqlite());
+}
+
+mod postgres {
+    login_fingerprint_store_conformance_tests!(crate::helpers::TestLoginFingerprintStore::postgres());
+}
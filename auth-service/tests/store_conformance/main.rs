@@ -0,0 +1,7 @@
+mod banned_token_store;
+mod helpers;
+mod login_fingerprint_store;
+mod rate_limit_store;
+mod trusted_device_store;
+mod two_fa_code_store;
+mod user_store;
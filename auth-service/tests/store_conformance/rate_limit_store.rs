@@ -0,0 +1,127 @@
+use auth_service::domain::{
+    data_stores::{RateLimitDecision, RateLimitStore, RateLimitStoreError, TokenBucket},
+    ManualClock,
+};
+use chrono::Duration;
+use uuid::Uuid;
+
+// Every case runs against each implementation, see the modules at the bottom
+
+const BUCKET: TokenBucket = TokenBucket { capacity: 3, refill_millis: 60_000 };
+
+fn random_key() -> String {
+    Uuid::new_v4().to_string()
+}
+
+fn limited_for(retry_after: Duration) -> Result<RateLimitDecision, RateLimitStoreError> {
+    Ok(RateLimitDecision::Limited { retry_after })
+}
+
+async fn allows_bursts_up_to_capacity(store: &dyn RateLimitStore, _clock: &ManualClock) {
+    let key = random_key();
+
+    for _ in 0..BUCKET.capacity {
+        assert_eq!(store.take_token(&key, &BUCKET).await, Ok(RateLimitDecision::Allowed));
+    }
+    assert_eq!(store.take_token(&key, &BUCKET).await, limited_for(BUCKET.refill_interval()));
+}
+
+async fn keeps_each_key_apart(store: &dyn RateLimitStore, _clock: &ManualClock) {
+    let (key, other_key) = (random_key(), random_key());
+    for _ in 0..BUCKET.capacity {
+        let _ = store.take_token(&key, &BUCKET).await;
+    }
+
+    assert_eq!(store.take_token(&other_key, &BUCKET).await, Ok(RateLimitDecision::Allowed));
+}
+
+async fn refills_one_token_per_interval(store: &dyn RateLimitStore, clock: &ManualClock) {
+    let key = random_key();
+    for _ in 0..BUCKET.capacity {
+        let _ = store.take_token(&key, &BUCKET).await;
+    }
+
+    clock.advance(Duration::seconds(20));
+    assert_eq!(store.take_token(&key, &BUCKET).await, limited_for(Duration::seconds(40)));
+
+    clock.advance(Duration::seconds(40));
+    assert_eq!(store.take_token(&key, &BUCKET).await, Ok(RateLimitDecision::Allowed));
+    assert_eq!(store.take_token(&key, &BUCKET).await, limited_for(BUCKET.refill_interval()));
+}
+
+// Turned away requests must not push the next token further back
+async fn does_not_charge_limited_requests(store: &dyn RateLimitStore, clock: &ManualClock) {
+    let key = random_key();
+    for _ in 0..BUCKET.capacity {
+        let _ = store.take_token(&key, &BUCKET).await;
+    }
+    for _ in 0..5 {
+        let _ = store.take_token(&key, &BUCKET).await;
+    }
+
+    clock.advance(BUCKET.refill_interval());
+    assert_eq!(store.take_token(&key, &BUCKET).await, Ok(RateLimitDecision::Allowed));
+}
+
+async fn fills_up_when_left_alone(store: &dyn RateLimitStore, clock: &ManualClock) {
+    let key = random_key();
+    for _ in 0..BUCKET.capacity {
+        let _ = store.take_token(&key, &BUCKET).await;
+    }
+
+    clock.advance(Duration::hours(1));
+    for _ in 0..BUCKET.capacity {
+        assert_eq!(store.take_token(&key, &BUCKET).await, Ok(RateLimitDecision::Allowed));
+    }
+    assert!(matches!(store.take_token(&key, &BUCKET).await, Ok(RateLimitDecision::Limited { .. })));
+}
+
+async fn counts_tokens_taken(store: &dyn RateLimitStore, clock: &ManualClock) {
+    let key = random_key();
+    assert_eq!(store.tokens_taken(&key, &BUCKET).await, Ok(0));
+
+    for taken in 1..=BUCKET.capacity {
+        let _ = store.take_token(&key, &BUCKET).await;
+        assert_eq!(store.tokens_taken(&key, &BUCKET).await, Ok(taken));
+    }
+    let _ = store.take_token(&key, &BUCKET).await;
+    assert_eq!(store.tokens_taken(&key, &BUCKET).await, Ok(BUCKET.capacity));
+
+    clock.advance(BUCKET.refill_interval());
+    assert_eq!(store.tokens_taken(&key, &BUCKET).await, Ok(BUCKET.capacity - 1));
+
+    clock.advance(Duration::hours(1));
+    assert_eq!(store.tokens_taken(&key, &BUCKET).await, Ok(0));
+}
+
+macro_rules! rate_limit_store_conformance_tests {
+    ($make_store:expr) => {
+        rate_limit_store_conformance_tests!(
+            $make_store;
+            allows_bursts_up_to_capacity,
+            keeps_each_key_apart,
+            refills_one_token_per_interval,
+            does_not_charge_limited_requests,
+            fills_up_when_left_alone,
+            counts_tokens_taken
+        );
+    };
+    ($make_store:expr; $($case:ident),+) => {
+        $(
+            #[tokio::test]
+            async fn $case() {
+                let test_store = $make_store.await;
+                super::$case(test_store.store.as_ref(), &test_store.clock).await;
+                test_store.clean_up().await;
+            }
+        )+
+    };
+}
+
+mod hashmap {
+    rate_limit_store_conformance_tests!(crate::helpers::TestRateLimitStore::hashmap());
+}
+
+mod redis {
+    rate_limit_store_conformance_tests!(crate::helpers::TestRateLimitStore::redis());
+}
@@ -0,0 +1,134 @@
+use auth_service::domain::{
+    data_stores::{TrustedDevice, TrustedDeviceId, TrustedDeviceStore, TrustedDeviceStoreError, UserStore},
+    User, UserId,
+};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::helpers::{password, random_email};
+
+// Every case runs against each implementation, see the modules at the bottom
+
+const TTL_DAYS: i64 = 30;
+
+async fn add_user(users: &dyn UserStore) -> UserId {
+    let user = User::new(random_email(), password("Password123"), true, Utc::now());
+    let id = user.id;
+    users.add_user(user).await.expect("Failed to add user");
+    id
+}
+
+fn device_at(user_id: UserId, now: DateTime<Utc>) -> TrustedDevice {
+    TrustedDevice::new(user_id, "Firefox on Linux".to_owned(), now, Duration::days(TTL_DAYS))
+}
+
+async fn adds_and_gets_devices(store: &dyn TrustedDeviceStore, users: &dyn UserStore) {
+    let user_id = add_user(users).await;
+    let device = device_at(user_id, Utc::now());
+
+    assert_eq!(store.add_device(device.clone()).await, Ok(()));
+    assert_eq!(store.get_device(&device.id).await, Ok(device.clone()));
+    assert_eq!(store.get_devices(&user_id).await, Ok(vec![device]));
+}
+
+async fn reports_unknown_devices(store: &dyn TrustedDeviceStore, users: &dyn UserStore) {
+    let user_id = add_user(users).await;
+    let id = TrustedDeviceId::default();
+
+    assert_eq!(store.get_device(&id).await, Err(TrustedDeviceStoreError::DeviceNotFound));
+    assert_eq!(store.mark_used(&id, Utc::now()).await, Err(TrustedDeviceStoreError::DeviceNotFound));
+    assert_eq!(store.revoke_device(&user_id, &id).await, Err(TrustedDeviceStoreError::DeviceNotFound));
+    assert_eq!(store.get_devices(&user_id).await, Ok(vec![]));
+}
+
+async fn lists_each_users_devices_newest_first(store: &dyn TrustedDeviceStore, users: &dyn UserStore) {
+    let (user_id, other_user_id) = (add_user(users).await, add_user(users).await);
+    let now = Utc::now();
+    let older = device_at(user_id, now);
+    let newer = device_at(user_id, now + Duration::seconds(1));
+    let others = device_at(other_user_id, now);
+
+    for device in [older.clone(), newer.clone(), others.clone()] {
+        let _ = store.add_device(device).await;
+    }
+
+    assert_eq!(store.get_devices(&user_id).await, Ok(vec![newer, older]));
+    assert_eq!(store.get_devices(&other_user_id).await, Ok(vec![others]));
+}
+
+async fn marks_devices_used(store: &dyn TrustedDeviceStore, users: &dyn UserStore) {
+    let device = device_at(add_user(users).await, Utc::now());
+    let _ = store.add_device(device.clone()).await;
+    let used_at = device.created_at + Duration::hours(1);
+
+    assert_eq!(store.mark_used(&device.id, used_at).await, Ok(()));
+
+    let stored = store.get_device(&device.id).await.expect("Device not found");
+    assert_eq!(stored.last_used_at, used_at);
+    assert_eq!(stored.created_at, device.created_at);
+}
+
+async fn revokes_only_the_owners_devices(store: &dyn TrustedDeviceStore, users: &dyn UserStore) {
+    let (user_id, other_user_id) = (add_user(users).await, add_user(users).await);
+    let device = device_at(user_id, Utc::now());
+    let _ = store.add_device(device.clone()).await;
+
+    assert_eq!(store.revoke_device(&other_user_id, &device.id).await, Err(TrustedDeviceStoreError::DeviceNotFound));
+    assert_eq!(store.get_device(&device.id).await, Ok(device.clone()));
+
+    assert_eq!(store.revoke_device(&user_id, &device.id).await, Ok(()));
+    assert_eq!(store.get_device(&device.id).await, Err(TrustedDeviceStoreError::DeviceNotFound));
+    assert_eq!(store.revoke_device(&user_id, &device.id).await, Err(TrustedDeviceStoreError::DeviceNotFound));
+}
+
+async fn drops_expired_devices_when_adding(store: &dyn TrustedDeviceStore, users: &dyn UserStore) {
+    let (user_id, other_user_id) = (add_user(users).await, add_user(users).await);
+    let now = Utc::now();
+    let expired = device_at(user_id, now);
+    let others_expired = device_at(other_user_id, now);
+    let _ = store.add_device(expired.clone()).await;
+    let _ = store.add_device(others_expired.clone()).await;
+
+    let later = device_at(user_id, now + Duration::days(TTL_DAYS));
+    assert_eq!(store.add_device(later.clone()).await, Ok(()));
+
+    assert_eq!(store.get_device(&expired.id).await, Err(TrustedDeviceStoreError::DeviceNotFound));
+    assert_eq!(store.get_devices(&user_id).await, Ok(vec![later]));
+    // Only the user adding a device gets theirs cleaned up
+    assert_eq!(store.get_device(&others_expired.id).await, Ok(others_expired));
+}
+
+macro_rules! trusted_device_store_conformance_tests {
+    ($make_store:expr) => {
+        trusted_device_store_conformance_tests!(
+            $make_store;
+            adds_and_gets_devices,
+            reports_unknown_devices,
+            lists_each_users_devices_newest_first,
+            marks_devices_used,
+            revokes_only_the_owners_devices,
+            drops_expired_devices_when_adding
+        );
+    };
+    ($make_store:expr; $($case:ident),+) => {
+        $(
+            #[tokio::test]
+            async fn $case() {
+                let test_store = $make_store.await;
+                super::$case(test_store.store.as_ref(), test_store.users.store.as_ref()).await;
+                test_store.clean_up().await;
+            }
+        )+
+    };
+}
+
+mod hashmap {
+    trusted_device_store_conformance_tests!(crate::helpers::TestTrustedDeviceStore::hashmap());
+}
+
+mod sqlite {
+    trusted_device_store_conformance_tests!(crate::helpers::TestTrustedDeviceStore::sqlite());
+}
+
+mod postgres {
+    trusted_device_store_conformance_tests!(crate::helpers::TestTrustedDeviceStore::postgres());
+}
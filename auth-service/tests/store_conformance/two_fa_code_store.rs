@@ -0,0 +1,181 @@
+use auth_service::domain::{
+    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, TWO_FA_CODE_TTL_SECONDS},
+    ManualClock, PhoneNumber,
+};
+use chrono::Duration;
+use secrecy::Secret;
+
+use crate::helpers::{random_email, MAX_ATTEMPTS_PER_USER};
+
+// Every case runs against each implementation, see the modules at the bottom
+
+async fn adds_and_gets_codes(store: &dyn TwoFACodeStore, _clock: &ManualClock) {
+    let email = random_email();
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::default();
+
+    assert_eq!(store.add_code(login_attempt_id.clone(), email.clone(), code.clone()).await, Ok(()));
+    assert_eq!(store.get_code(&login_attempt_id).await, Ok((email, code)));
+}
+
+async fn keeps_phone_number_codes_apart(store: &dyn TwoFACodeStore, _clock: &ManualClock) {
+    let email = random_email();
+    let phone_number = PhoneNumber::parse(Secret::new("+15005550006".to_owned())).unwrap();
+    let (verification_id, login_attempt_id) = (LoginAttemptId::default(), LoginAttemptId::default());
+    let code = TwoFACode::default();
+
+    assert_eq!(
+        store.add_phone_number_code(verification_id.clone(), email.clone(), phone_number.clone(), code.clone()).await,
+        Ok(())
+    );
+    let _ = store.add_code(login_attempt_id.clone(), email.clone(), TwoFACode::default()).await;
+
+    assert_eq!(store.get_phone_number_code(&verification_id).await, Ok((email, phone_number, code)));
+    // Neither kind is found as the other
+    assert_eq!(store.get_code(&verification_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    assert_eq!(store.get_phone_number_code(&login_attempt_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+    assert_eq!(store.remove_code(&verification_id).await, Ok(()));
+    assert_eq!(store.get_phone_number_code(&verification_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    assert!(store.get_code(&login_attempt_id).await.is_ok());
+}
+
+async fn reports_missing_codes(store: &dyn TwoFACodeStore, _clock: &ManualClock) {
+    assert_eq!(
+        store.get_code(&LoginAttemptId::default()).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+}
+
+async fn keeps_concurrent_attempts(store: &dyn TwoFACodeStore, _clock: &ManualClock) {
+    let email = random_email();
+    let (first_id, first_code) = (LoginAttemptId::default(), TwoFACode::default());
+    let (second_id, second_code) = (LoginAttemptId::default(), TwoFACode::default());
+
+    let _ = store.add_code(first_id.clone(), email.clone(), first_code.clone()).await;
+    let _ = store.add_code(second_id.clone(), email.clone(), second_code.clone()).await;
+
+    assert_eq!(store.get_code(&first_id).await, Ok((email.clone(), first_code)));
+    assert_eq!(store.get_code(&second_id).await, Ok((email, second_code)));
+}
+
+async fn drops_the_oldest_attempt_past_the_cap(store: &dyn TwoFACodeStore, clock: &ManualClock) {
+    let (email, other_email) = (random_email(), random_email());
+    let other_id = LoginAttemptId::default();
+    let _ = store.add_code(other_id.clone(), other_email, TwoFACode::default()).await;
+
+    let ids: Vec<LoginAttemptId> = (0..=MAX_ATTEMPTS_PER_USER).map(|_| LoginAttemptId::default()).collect();
+    for id in &ids {
+        assert_eq!(store.add_code(id.clone(), email.clone(), TwoFACode::default()).await, Ok(()));
+        clock.advance(Duration::seconds(1));
+    }
+
+    assert_eq!(store.get_code(&ids[0]).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    for id in &ids[1..] {
+        assert!(store.get_code(id).await.is_ok());
+    }
+    // Other users keep theirs
+    assert!(store.get_code(&other_id).await.is_ok());
+}
+
+async fn removes_codes(store: &dyn TwoFACodeStore, _clock: &ManualClock) {
+    let email = random_email();
+    let (login_attempt_id, other_id) = (LoginAttemptId::default(), LoginAttemptId::default());
+    let _ = store.add_code(login_attempt_id.clone(), email.clone(), TwoFACode::default()).await;
+    let _ = store.add_code(other_id.clone(), email, TwoFACode::default()).await;
+
+    assert_eq!(store.remove_code(&login_attempt_id).await, Ok(()));
+    assert_eq!(store.get_code(&login_attempt_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    assert!(store.get_code(&other_id).await.is_ok());
+
+    assert_eq!(store.remove_code(&login_attempt_id).await, Ok(()));
+}
+
+async fn takes_codes_once(store: &dyn TwoFACodeStore, _clock: &ManualClock) {
+    let email = random_email();
+    let (login_attempt_id, other_id) = (LoginAttemptId::default(), LoginAttemptId::default());
+    let code = TwoFACode::default();
+    let _ = store.add_code(login_attempt_id.clone(), email.clone(), code.clone()).await;
+    let _ = store.add_code(other_id.clone(), email.clone(), TwoFACode::default()).await;
+
+    assert_eq!(store.take_code(&login_attempt_id).await, Ok((email, code)));
+    assert_eq!(store.take_code(&login_attempt_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    assert_eq!(store.get_code(&login_attempt_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    assert!(store.get_code(&other_id).await.is_ok());
+}
+
+async fn does_not_take_phone_number_or_expired_codes(store: &dyn TwoFACodeStore, clock: &ManualClock) {
+    let email = random_email();
+    let phone_number = PhoneNumber::parse(Secret::new("+15005550006".to_owned())).unwrap();
+    let (verification_id, login_attempt_id) = (LoginAttemptId::default(), LoginAttemptId::default());
+    let _ = store.add_phone_number_code(verification_id.clone(), email.clone(), phone_number, TwoFACode::default()).await;
+    let _ = store.add_code(login_attempt_id.clone(), email, TwoFACode::default()).await;
+
+    assert_eq!(store.take_code(&verification_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    assert!(store.get_phone_number_code(&verification_id).await.is_ok());
+
+    clock.advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS));
+    assert_eq!(store.take_code(&login_attempt_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+}
+
+async fn removed_attempts_leave_room_under_the_cap(store: &dyn TwoFACodeStore, clock: &ManualClock) {
+    let email = random_email();
+    let kept_id = LoginAttemptId::default();
+    let _ = store.add_code(kept_id.clone(), email.clone(), TwoFACode::default()).await;
+
+    for _ in 1..=MAX_ATTEMPTS_PER_USER {
+        clock.advance(Duration::seconds(1));
+        let id = LoginAttemptId::default();
+        let _ = store.add_code(id.clone(), email.clone(), TwoFACode::default()).await;
+        let _ = store.remove_code(&id).await;
+    }
+
+    assert!(store.get_code(&kept_id).await.is_ok());
+}
+
+async fn expires_codes(store: &dyn TwoFACodeStore, clock: &ManualClock) {
+    let login_attempt_id = LoginAttemptId::default();
+    let _ = store.add_code(login_attempt_id.clone(), random_email(), TwoFACode::default()).await;
+
+    clock.advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS - 1));
+    assert!(store.get_code(&login_attempt_id).await.is_ok());
+
+    clock.advance(Duration::seconds(1));
+    assert_eq!(store.get_code(&login_attempt_id).await, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+}
+
+macro_rules! two_fa_code_store_conformance_tests {
+    ($make_store:expr) => {
+        two_fa_code_store_conformance_tests!(
+            $make_store;
+            adds_and_gets_codes,
+            keeps_phone_number_codes_apart,
+            reports_missing_codes,
+            keeps_concurrent_attempts,
+            drops_the_oldest_attempt_past_the_cap,
+            removes_codes,
+            takes_codes_once,
+            does_not_take_phone_number_or_expired_codes,
+            removed_attempts_leave_room_under_the_cap,
+            expires_codes
+        );
+    };
+    ($make_store:expr; $($case:ident),+) => {
+        $(
+            #[tokio::test]
+            async fn $case() {
+                let test_store = $make_store.await;
+                super::$case(test_store.store.as_ref(), &test_store.clock).await;
+                test_store.clean_up().await;
+            }
+        )+
+    };
+}
+
+mod hashmap {
+    two_fa_code_store_conformance_tests!(crate::helpers::TestTwoFACodeStore::hashmap());
+}
+
+mod redis {
+    two_fa_code_store_conformance_tests!(crate::helpers::TestTwoFACodeStore::redis());
+}
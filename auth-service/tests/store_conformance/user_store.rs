@@ -0,0 +1,303 @@
+use auth_service::domain::{
+    data_stores::{UserStore, UserStoreError},
+    AccountStatus, PhoneNumber, TwoFAMethod, User, UserId,
+};
+use chrono::{DateTime, Duration, Utc};
+use secrecy::Secret;
+
+use crate::helpers::{password, random_email};
+
+// Every case runs against each implementation, see the modules at the bottom
+
+async fn adds_and_gets_users(store: &dyn UserStore) {
+    let user = User::new(random_email(), password("Password123"), true, Utc::now());
+    let (id, email) = (user.id, user.email.clone());
+
+    assert_eq!(store.add_user(user).await, Ok(()));
+
+    let by_email = store.get_user(&email).await.expect("User not found by email");
+    assert_eq!(by_email.id, id);
+    assert_eq!(by_email.email, email);
+    assert!(by_email.requires_2fa);
+    assert_eq!(by_email.phone_number, None);
+    assert!(!by_email.phone_number_verified);
+    assert_eq!(by_email.two_fa_method, TwoFAMethod::Email);
+
+    assert_eq!(store.get_user_by_id(&id).await, Ok(by_email));
+}
+
+async fn rejects_taken_emails(store: &dyn UserStore) {
+    let email = random_email();
+
+    assert_eq!(store.add_user(User::new(email.clone(), password("Password123"), false, Utc::now())).await, Ok(()));
+    assert_eq!(
+        store.add_user(User::new(email, password("Password456"), true, Utc::now())).await,
+        Err(UserStoreError::UserAlreadyExists)
+    );
+}
+
+async fn reports_unknown_users(store: &dyn UserStore) {
+    assert_eq!(store.get_user(&random_email()).await, Err(UserStoreError::UserNotFound));
+    assert_eq!(store.get_user_by_id(&UserId::default()).await, Err(UserStoreError::UserNotFound));
+    assert_eq!(store.delete_user(&UserId::default()).await, Err(UserStoreError::UserNotFound));
+}
+
+async fn deletes_users(store: &dyn UserStore) {
+    let user = User::new(random_email(), password("Password123"), false, Utc::now());
+    let (id, email) = (user.id, user.email.clone());
+    let _ = store.add_user(user).await;
+
+    assert_eq!(store.delete_user(&id).await, Ok(()));
+    assert_eq!(store.get_user(&email).await, Err(UserStoreError::UserNotFound));
+    assert_eq!(store.delete_user(&id).await, Err(UserStoreError::UserNotFound));
+}
+
+async fn validates_credentials(store: &dyn UserStore) {
+    let email = random_email();
+    let _ = store.add_user(User::new(email.clone(), password("Password123"), false, Utc::now())).await;
+
+    assert_eq!(store.validate_user(&email, &password("Password123")).await, Ok(()));
+    assert_eq!(
+        store.validate_user(&email, &password("Password456")).await,
+        Err(UserStoreError::InvalidCredentials)
+    );
+    assert_eq!(
+        store.validate_user(&random_email(), &password("Password123")).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+async fn sets_and_verifies_phone_numbers(store: &dyn UserStore) {
+    let phone_number = PhoneNumber::parse(Secret::new("+15005550006".to_owned())).unwrap();
+    let other_phone_number = PhoneNumber::parse(Secret::new("+15005550007".to_owned())).unwrap();
+    let user = User::new(random_email(), password("Password123"), true, Utc::now());
+    let id = user.id;
+    let _ = store.add_user(user).await;
+
+    assert_eq!(store.set_phone_number(&id, phone_number.clone()).await, Ok(()));
+    let stored = store.get_user_by_id(&id).await.unwrap();
+    assert_eq!(stored.phone_number, Some(phone_number.clone()));
+    assert!(!stored.phone_number_verified);
+
+    assert_eq!(
+        store.verify_phone_number(&id, &other_phone_number).await,
+        Err(UserStoreError::InvalidCredentials)
+    );
+    assert_eq!(store.verify_phone_number(&id, &phone_number).await, Ok(()));
+    assert!(store.get_user_by_id(&id).await.unwrap().phone_number_verified);
+
+    assert_eq!(
+        store.set_phone_number(&UserId::default(), phone_number.clone()).await,
+        Err(UserStoreError::UserNotFound)
+    );
+    assert_eq!(
+        store.verify_phone_number(&UserId::default(), &phone_number).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+async fn needs_a_verified_phone_number_for_sms(store: &dyn UserStore) {
+    let phone_number = PhoneNumber::parse(Secret::new("+15005550006".to_owned())).unwrap();
+    let user = User::new(random_email(), password("Password123"), true, Utc::now());
+    let id = user.id;
+    let _ = store.add_user(user).await;
+
+    assert_eq!(
+        store.set_two_fa_method(&id, TwoFAMethod::Sms).await,
+        Err(UserStoreError::PhoneNumberNotVerified)
+    );
+
+    let _ = store.set_phone_number(&id, phone_number.clone()).await;
+    let _ = store.verify_phone_number(&id, &phone_number).await;
+
+    assert_eq!(store.set_two_fa_method(&id, TwoFAMethod::Sms).await, Ok(()));
+    assert_eq!(store.get_user_by_id(&id).await.unwrap().two_fa_method, TwoFAMethod::Sms);
+
+    // A new number needs verifying again and falls back to email
+    let _ = store.set_phone_number(&id, phone_number).await;
+    assert_eq!(store.get_user_by_id(&id).await.unwrap().two_fa_method, TwoFAMethod::Email);
+
+    assert_eq!(
+        store.set_two_fa_method(&UserId::default(), TwoFAMethod::Email).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+async fn updates_passwords(store: &dyn UserStore) {
+    let email = random_email();
+    let user = User::new(email.clone(), password("Password123"), false, Utc::now());
+    let id = user.id;
+    let _ = store.add_user(user).await;
+
+    assert_eq!(store.update_password(&id, password("Password456"), Utc::now(), 3).await, Ok(()));
+    assert_eq!(
+        store.validate_user(&email, &password("Password123")).await,
+        Err(UserStoreError::InvalidCredentials)
+    );
+    assert_eq!(store.validate_user(&email, &password("Password456")).await, Ok(()));
+
+    assert_eq!(
+        store.update_password(&UserId::default(), password("Password456"), Utc::now(), 3).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+async fn rejects_recent_passwords(store: &dyn UserStore) {
+    let email = random_email();
+    let user = User::new(email.clone(), password("Password1"), false, Utc::now());
+    let id = user.id;
+    let _ = store.add_user(user).await;
+
+    assert_eq!(store.update_password(&id, password("Password1"), Utc::now(), 3).await, Err(UserStoreError::PasswordReused));
+    assert_eq!(store.update_password(&id, password("Password2"), Utc::now(), 3).await, Ok(()));
+    assert_eq!(store.update_password(&id, password("Password3"), Utc::now(), 3).await, Ok(()));
+    assert_eq!(store.update_password(&id, password("Password1"), Utc::now(), 3).await, Err(UserStoreError::PasswordReused));
+    assert_eq!(store.update_password(&id, password("Password2"), Utc::now(), 3).await, Err(UserStoreError::PasswordReused));
+    assert_eq!(store.validate_user(&email, &password("Password3")).await, Ok(()));
+
+    // Password1 is now older than the last 3
+    assert_eq!(store.update_password(&id, password("Password4"), Utc::now(), 3).await, Ok(()));
+    assert_eq!(store.update_password(&id, password("Password1"), Utc::now(), 3).await, Ok(()));
+
+    // Without a history only the current password is refused
+    assert_eq!(store.update_password(&id, password("Password1"), Utc::now(), 1).await, Err(UserStoreError::PasswordReused));
+    assert_eq!(store.update_password(&id, password("Password4"), Utc::now(), 1).await, Ok(()));
+}
+
+async fn tracks_password_age(store: &dyn UserStore) {
+    let user = User::new(random_email(), password("Password123"), false, Utc::now());
+    let id = user.id;
+    let _ = store.add_user(user).await;
+
+    let user = store.get_user_by_id(&id).await.expect("User not found");
+    assert_eq!(user.password_max_age, None);
+    assert!(!user.password_expired(user.password_changed_at + Duration::days(1000)));
+
+    assert_eq!(store.set_password_max_age(&id, Some(Duration::days(90))).await, Ok(()));
+    let user = store.get_user_by_id(&id).await.expect("User not found");
+    assert_eq!(user.password_max_age, Some(Duration::days(90)));
+    assert!(user.password_expired(user.password_changed_at + Duration::days(90)));
+
+    let changed_at = user.password_changed_at + Duration::days(100);
+    assert_eq!(store.update_password(&id, password("Password456"), changed_at, 3).await, Ok(()));
+    let user = store.get_user_by_id(&id).await.expect("User not found");
+    assert_eq!(user.password_changed_at, changed_at);
+    assert!(!user.password_expired(changed_at + Duration::days(89)));
+
+    assert_eq!(store.set_password_max_age(&id, None).await, Ok(()));
+    assert_eq!(store.get_user_by_id(&id).await.map(|user| user.password_max_age), Ok(None));
+    assert_eq!(
+        store.set_password_max_age(&UserId::default(), None).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+async fn sets_account_status(store: &dyn UserStore) {
+    let user = User::new(random_email(), password("Password123"), false, Utc::now());
+    let id = user.id;
+    let _ = store.add_user(user).await;
+
+    assert_eq!(store.get_user_by_id(&id).await.map(|user| user.status), Ok(AccountStatus::Active));
+
+    // Stores keep whole seconds
+    let until = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap() + Duration::days(7);
+    for status in [
+        AccountStatus::Disabled,
+        AccountStatus::SuspendedUntil(until),
+        AccountStatus::PendingDeletion { delete_at: until },
+        AccountStatus::Active,
+    ] {
+        assert_eq!(store.set_status(&id, status).await, Ok(()));
+        assert_eq!(store.get_user_by_id(&id).await.map(|user| user.status), Ok(status));
+    }
+
+    assert_eq!(
+        store.set_status(&UserId::default(), AccountStatus::Disabled).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+async fn purges_users_past_their_deletion_time(store: &dyn UserStore) {
+    let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+    let (due, not_due, disabled) = (
+        User::new(random_email(), password("Password123"), false, Utc::now()),
+        User::new(random_email(), password("Password123"), false, Utc::now()),
+        User::new(random_email(), password("Password123"), false, Utc::now()),
+    );
+    let (due_id, not_due_id, disabled_id) = (due.id, not_due.id, disabled.id);
+    for user in [due, not_due, disabled] {
+        let _ = store.add_user(user).await;
+    }
+
+    let _ = store.update_password(&due_id, password("Password456"), now, 3).await;
+    let _ = store.set_status(&due_id, AccountStatus::PendingDeletion { delete_at: now }).await;
+    let _ = store.set_status(&not_due_id, AccountStatus::PendingDeletion { delete_at: now + Duration::seconds(1) }).await;
+    let _ = store.set_status(&disabled_id, AccountStatus::Disabled).await;
+
+    // Other cases may share the store, so only these users are checked
+    assert!(store.purge_deleted_users(now).await.is_ok_and(|purged| purged >= 1));
+    assert_eq!(store.get_user_by_id(&due_id).await, Err(UserStoreError::UserNotFound));
+    assert!(store.get_user_by_id(&not_due_id).await.is_ok());
+    assert!(store.get_user_by_id(&disabled_id).await.is_ok());
+}
+
+async fn updates_emails(store: &dyn UserStore) {
+    let (old_email, new_email, taken_email) = (random_email(), random_email(), random_email());
+    let user = User::new(old_email.clone(), password("Password123"), false, Utc::now());
+    let id = user.id;
+    let _ = store.add_user(user).await;
+    let _ = store.add_user(User::new(taken_email.clone(), password("Password123"), false, Utc::now())).await;
+
+    assert_eq!(store.update_email(&id, new_email.clone()).await, Ok(()));
+    assert_eq!(store.get_user(&new_email).await.map(|user| user.id), Ok(id));
+    assert_eq!(store.get_user(&old_email).await, Err(UserStoreError::UserNotFound));
+
+    assert_eq!(store.update_email(&id, taken_email).await, Err(UserStoreError::UserAlreadyExists));
+    assert_eq!(
+        store.update_email(&UserId::default(), random_email()).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+macro_rules! user_store_conformance_tests {
+    ($make_store:expr) => {
+        user_store_conformance_tests!(
+            $make_store;
+            adds_and_gets_users,
+            rejects_taken_emails,
+            reports_unknown_users,
+            deletes_users,
+            validates_credentials,
+            sets_and_verifies_phone_numbers,
+            needs_a_verified_phone_number_for_sms,
+            updates_passwords,
+            rejects_recent_passwords,
+            tracks_password_age,
+            sets_account_status,
+            purges_users_past_their_deletion_time,
+            updates_emails
+        );
+    };
+    ($make_store:expr; $($case:ident),+) => {
+        $(
+            #[tokio::test]
+            async fn $case() {
+                let test_store = $make_store.await;
+                super::$case(test_store.store.as_ref()).await;
+                test_store.clean_up().await;
+            }
+        )+
+    };
+}
+
+mod hashmap {
+    user_store_conformance_tests!(crate::helpers::TestUserStore::hashmap());
+}
+
+mod sqlite {
+    user_store_conformance_tests!(crate::helpers::TestUserStore::sqlite());
+}
+
+mod postgres {
+    user_store_conformance_tests!(crate::helpers::TestUserStore::postgres());
+}